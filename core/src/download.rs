@@ -0,0 +1,813 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    job::{JobMessage, JobSender},
+    model::is_music_file,
+    util,
+};
+
+/// Prefix of the lines that yt-dlp prints for each finished file
+const FILE_LINE_PREFIX: &str = "#file#";
+
+/// Extensions of internet shortcut files whose links are downloaded when dropped
+const URL_SHORTCUT_EXTENSIONS: [&str; 3] = ["url", "desktop", "webloc"];
+
+/// Audio format of downloaded songs
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DownloadFormat {
+    /// Best m4a or mp3 audio stream without conversion
+    Original,
+    M4a,
+    Mp3,
+    Vorbis,
+}
+
+impl DownloadFormat {
+    pub const ALL: [DownloadFormat; 4] = [
+        DownloadFormat::Original,
+        DownloadFormat::M4a,
+        DownloadFormat::Mp3,
+        DownloadFormat::Vorbis,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DownloadFormat::Original => "Original (m4a/mp3)",
+            DownloadFormat::M4a => "M4A",
+            DownloadFormat::Mp3 => "MP3",
+            DownloadFormat::Vorbis => "Vorbis (ogg)",
+        }
+    }
+
+    /// Returns the value of `--audio-format` for formats that are converted after downloading.
+    fn audio_format(&self) -> Option<&'static str> {
+        match self {
+            DownloadFormat::Original => None,
+            DownloadFormat::M4a => Some("m4a"),
+            DownloadFormat::Mp3 => Some("mp3"),
+            DownloadFormat::Vorbis => Some("vorbis"),
+        }
+    }
+}
+
+/// Bitrates in kbit/s that converted downloads can be encoded with
+pub const DOWNLOAD_BITRATES: [u32; 4] = [128, 192, 256, 320];
+
+/// Options of a download that are chosen in the download popup
+#[derive(Clone)]
+pub struct DownloadOptions {
+    /// Download all songs of the playlist behind the URL
+    pub whole_playlist: bool,
+    pub format: DownloadFormat,
+    /// Target bitrate of converted formats in kbit/s, the best quality if `None`
+    pub bitrate: Option<u32>,
+    /// Embed the video thumbnail as cover art
+    pub embed_thumbnail: bool,
+    /// Embed tags from the video metadata
+    pub embed_metadata: bool,
+    /// Split titles like "Artist - Title" into the artist and title tags
+    pub parse_artist_title: bool,
+    /// Split videos with chapters into a file per chapter
+    pub split_chapters: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            whole_playlist: false,
+            format: DownloadFormat::Original,
+            bitrate: None,
+            embed_thumbnail: false,
+            embed_metadata: false,
+            parse_artist_title: false,
+            split_chapters: false,
+        }
+    }
+}
+
+/// Options from the settings that apply to all downloads, empty values are not passed to yt-dlp
+#[derive(Clone, Default)]
+pub struct NetworkOptions {
+    pub proxy: String,
+    /// Maximum download rate like `50K` or `4.2M`
+    pub rate_limit: String,
+    /// Path of a cookies file in Netscape format
+    pub cookies_file: String,
+}
+
+pub fn download_command(
+    yt_dlp: &str,
+    base_path: &str,
+    url: &str,
+    options: &DownloadOptions,
+    network_options: &NetworkOptions,
+) -> Command {
+    let mut command = Command::new(yt_dlp);
+    command
+        .arg("-o")
+        .arg(format!("{}/%(title)s.%(ext)s", base_path))
+        .arg("-q")
+        .arg("--no-simulate")
+        // The path is printed after conversion, since the extension may change
+        .arg("--print")
+        .arg(format!("after_move:{FILE_LINE_PREFIX}%(filepath)s"))
+        .arg("--progress")
+        .arg("--newline")
+        .arg("--progress-template");
+    if options.whole_playlist {
+        command
+            .arg("#status#%(info.playlist_index)s/%(info.n_entries)s %(progress._percent_str)s")
+            .arg("--yes-playlist")
+            .arg("--ignore-errors");
+    } else {
+        command
+            .arg("#status#%(progress._percent_str)s")
+            .arg("--no-playlist");
+    }
+    match options.format.audio_format() {
+        None => {
+            command.arg("-f").arg("ba[ext=m4a] / ba[ext=mp3]");
+        }
+        Some(audio_format) => {
+            command
+                .arg("-f")
+                .arg("ba")
+                .arg("-x")
+                .arg("--audio-format")
+                .arg(audio_format);
+            if let Some(bitrate) = options.bitrate {
+                command.arg("--audio-quality").arg(format!("{bitrate}K"));
+            }
+        }
+    }
+    if options.embed_thumbnail {
+        command.arg("--embed-thumbnail");
+    }
+    if options.embed_metadata {
+        command.arg("--embed-metadata");
+        if options.parse_artist_title {
+            command
+                .arg("--parse-metadata")
+                .arg("title:%(artist)s - %(title)s");
+        }
+    }
+    if options.split_chapters {
+        // The chapter files start with the title of the download so they can be found afterwards
+        command.arg("--split-chapters").arg("-o").arg(format!(
+            "chapter:{base_path}/%(title)s - %(section_number)03d %(section_title)s.%(ext)s"
+        ));
+    }
+    let network_options = [
+        ("--proxy", &network_options.proxy),
+        ("--limit-rate", &network_options.rate_limit),
+        ("--cookies", &network_options.cookies_file),
+    ];
+    for (option, value) in network_options {
+        if !value.trim().is_empty() {
+            command.arg(option).arg(value.trim());
+        }
+    }
+    command.arg(url);
+    command
+}
+
+/// Returns the files that yt-dlp split the download into sorted by chapter and removes the
+/// download itself. Downloads without chapters are returned unchanged.
+pub fn find_chapter_files(path: &str) -> Vec<String> {
+    let file_path = Path::new(path);
+    let (Some(directory), Some(stem)) = (file_path.parent(), file_path.file_stem()) else {
+        return vec![path.to_string()];
+    };
+    let prefix = format!("{} - ", stem.to_string_lossy());
+    let is_chapter = |name: &str| {
+        let Some(rest) = name.strip_prefix(&prefix) else {
+            return false;
+        };
+        rest.chars().take(3).filter(char::is_ascii_digit).count() == 3
+            && is_music_file(Path::new(name))
+    };
+    let mut chapter_paths: Vec<String> = fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|x| is_chapter(&x.file_name().to_string_lossy()))
+        .map(|x| x.path().to_string_lossy().to_string())
+        .collect();
+    if chapter_paths.is_empty() {
+        return vec![path.to_string()];
+    }
+    chapter_paths.sort();
+    let _ = fs::remove_file(path);
+    chapter_paths
+}
+
+/// File types supported by aacgain
+const POSTPROCESSING_EXTENSIONS: [&str; 3] = ["m4a", "mp4", "mp3"];
+
+/// Loudness target of the loudnorm filter following EBU R128
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// Removes silence below -50 dB at the start and, by reversing the audio, at the end
+const TRIM_SILENCE_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB,areverse,silenceremove=start_periods=1:start_threshold=-50dB,areverse";
+
+fn is_postprocessing_supported(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|x| POSTPROCESSING_EXTENSIONS.contains(&x.to_string_lossy().as_ref()))
+}
+
+/// Paths of the external programs, `None` if a program was not found
+#[derive(Default, Clone)]
+pub struct Programs {
+    pub yt_dlp: Option<String>,
+    pub aacgain: Option<String>,
+    pub mp3gain: Option<String>,
+    pub ffmpeg: Option<String>,
+}
+
+/// A step that is run on each downloaded song. The steps are configured in the settings and run
+/// in their configured order.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Postprocessor {
+    /// Adjusts the volume level of mp3 and m4a files without reencoding
+    Aacgain,
+    /// Adjusts the volume level of mp3 files without reencoding
+    Mp3gain,
+    /// Normalizes the loudness with ffmpeg
+    Loudnorm,
+    /// Removes silence at the start and end with ffmpeg
+    TrimSilence,
+    /// Converts to mp3 V0 with ffmpeg
+    ConvertMp3,
+    /// Converts to AAC in m4a with ffmpeg
+    ConvertM4a,
+    /// Converts to Vorbis in ogg with ffmpeg
+    ConvertVorbis,
+}
+
+impl Postprocessor {
+    pub const ALL: [Postprocessor; 7] = [
+        Postprocessor::ConvertMp3,
+        Postprocessor::ConvertM4a,
+        Postprocessor::ConvertVorbis,
+        Postprocessor::Aacgain,
+        Postprocessor::Mp3gain,
+        Postprocessor::Loudnorm,
+        Postprocessor::TrimSilence,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Postprocessor::Aacgain => "Adjust volume level (aacgain)",
+            Postprocessor::Mp3gain => "Adjust volume level (mp3gain)",
+            Postprocessor::Loudnorm => "Normalize loudness (ffmpeg)",
+            Postprocessor::TrimSilence => "Trim silence (ffmpeg)",
+            Postprocessor::ConvertMp3 => "Convert to mp3 V0 (ffmpeg)",
+            Postprocessor::ConvertM4a => "Convert to m4a (ffmpeg)",
+            Postprocessor::ConvertVorbis => "Convert to ogg vorbis (ffmpeg)",
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Postprocessor::Aacgain => "aacgain",
+            Postprocessor::Mp3gain => "mp3gain",
+            Postprocessor::Loudnorm => "loudnorm",
+            Postprocessor::TrimSilence => "trim_silence",
+            Postprocessor::ConvertMp3 => "convert_mp3",
+            Postprocessor::ConvertM4a => "convert_m4a",
+            Postprocessor::ConvertVorbis => "convert_vorbis",
+        }
+    }
+
+    /// Returns the path of the program that runs this step.
+    pub fn program<'a>(&self, programs: &'a Programs) -> Option<&'a str> {
+        match self {
+            Postprocessor::Aacgain => programs.aacgain.as_deref(),
+            Postprocessor::Mp3gain => programs.mp3gain.as_deref(),
+            _ => programs.ffmpeg.as_deref(),
+        }
+    }
+
+    fn supports(&self, path: &str) -> bool {
+        match self {
+            Postprocessor::Aacgain => is_postprocessing_supported(path),
+            Postprocessor::Mp3gain => Path::new(path).extension().is_some_and(|x| x == "mp3"),
+            Postprocessor::Loudnorm | Postprocessor::TrimSilence => true,
+            // Files that already have the target format are not converted again
+            _ => self.get_output_path(path).is_some_and(|x| x != path),
+        }
+    }
+
+    /// Returns the extension of the format that the step converts to.
+    fn get_target_extension(&self) -> Option<&'static str> {
+        match self {
+            Postprocessor::ConvertMp3 => Some("mp3"),
+            Postprocessor::ConvertM4a => Some("m4a"),
+            Postprocessor::ConvertVorbis => Some("ogg"),
+            _ => None,
+        }
+    }
+
+    /// Returns the path of the processed file for steps that write a new file with ffmpeg and
+    /// `None` for steps that change the file in place.
+    fn get_output_path(&self, path: &str) -> Option<String> {
+        match self {
+            Postprocessor::Aacgain | Postprocessor::Mp3gain => None,
+            Postprocessor::Loudnorm | Postprocessor::TrimSilence => Some(path.to_string()),
+            _ => Some(
+                Path::new(path)
+                    .with_extension(self.get_target_extension()?)
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// Returns the command that processes the file. Steps that reencode the file write it to a
+    /// temporary file, which then replaces the original.
+    fn command(&self, program: &str, path: &str) -> Command {
+        match self {
+            Postprocessor::Aacgain => return postprocessing_command(program, path),
+            Postprocessor::Mp3gain => {
+                let mut command = Command::new(program);
+                command.arg("-r").arg("-k").arg(path);
+                return command;
+            }
+            _ => (),
+        }
+        let mut command = Command::new(program);
+        command.arg("-y").arg("-v").arg("error").arg("-i").arg(path);
+        command.arg("-map").arg("0:a");
+        // Keep embedded cover art, which ogg doesn't support as a stream
+        if *self != Postprocessor::ConvertVorbis {
+            command.arg("-map").arg("0:v?").arg("-c:v").arg("copy");
+        }
+        match self {
+            Postprocessor::Loudnorm => {
+                // loudnorm upsamples to 192 kHz
+                command
+                    .arg("-af")
+                    .arg(LOUDNORM_FILTER)
+                    .arg("-ar")
+                    .arg("44100");
+            }
+            Postprocessor::TrimSilence => {
+                command.arg("-af").arg(TRIM_SILENCE_FILTER);
+            }
+            Postprocessor::ConvertMp3 => {
+                command.arg("-c:a").arg("libmp3lame").arg("-q:a").arg("0");
+            }
+            Postprocessor::ConvertM4a => {
+                command.arg("-c:a").arg("aac").arg("-b:a").arg("256k");
+            }
+            Postprocessor::ConvertVorbis => {
+                command.arg("-c:a").arg("libvorbis").arg("-q:a").arg("6");
+            }
+            Postprocessor::Aacgain | Postprocessor::Mp3gain => (),
+        }
+        let output_path = self.get_output_path(path).unwrap_or_default();
+        command.arg(get_temporary_path(&output_path));
+        command
+    }
+}
+
+/// Returns the path of the file that ffmpeg writes to, which keeps the extension so ffmpeg picks
+/// the same format.
+fn get_temporary_path(path: &str) -> String {
+    let path = Path::new(path);
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_extension(format!("postprocessing.{extension}"))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Adjusts the volume level of the music file
+fn postprocessing_command(aacgain: &str, path: &str) -> Command {
+    let mut command = Command::new(aacgain);
+    command.arg("-r").arg(path);
+    command
+}
+
+/// Sends the lines of the output to the receiver until the output is closed.
+fn start_listener<R: Read + std::marker::Send + 'static>(
+    output: R,
+    sender: Sender<String>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(output);
+        loop {
+            let mut buf = String::new();
+            match reader.read_line(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {
+                    if !buf.trim().is_empty() && sender.send(buf).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// A running process whose output is read in the background
+pub struct Process {
+    child: Child,
+    stdout_rx: Receiver<String>,
+    stderr_rx: Receiver<String>,
+    /// Threads that read stdout and stderr
+    listeners: Vec<JoinHandle<()>>,
+}
+
+impl Process {
+    pub fn start(mut command: Command) -> io::Result<Process> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (stdout_tx, stdout_rx) = mpsc::channel();
+        let stdout_listener = start_listener(child.stdout.take().unwrap(), stdout_tx);
+        let (stderr_tx, stderr_rx) = mpsc::channel();
+        let stderr_listener = start_listener(child.stderr.take().unwrap(), stderr_tx);
+        Ok(Process {
+            child,
+            stdout_rx,
+            stderr_rx,
+            listeners: vec![stdout_listener, stderr_listener],
+        })
+    }
+
+    /// Kills the process and waits until its output is closed.
+    pub fn kill(&mut self) {
+        let _ = self.child.kill();
+        self.wait();
+    }
+
+    /// Waits until the process has exited and its output is closed.
+    pub fn wait(&mut self) {
+        let _ = self.child.wait();
+        for listener in self.listeners.drain(..) {
+            let _ = listener.join();
+        }
+    }
+
+    /// Returns the exit status once the process has exited.
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().flatten()
+    }
+
+    /// Returns the output lines that were not received yet. After the process has exited, this
+    /// waits until the whole output has been read.
+    pub fn receive_output(&self, is_finished: bool) -> Vec<String> {
+        if is_finished {
+            self.stdout_rx.iter().collect()
+        } else {
+            util::receive_all(&self.stdout_rx)
+        }
+    }
+
+    pub fn get_error_output(&self) -> String {
+        let lines: Vec<String> = self.stderr_rx.iter().collect();
+        lines.join("").trim().to_string()
+    }
+}
+
+pub enum DownloadStage {
+    Queued,
+    Downloading(Process),
+    /// Runs the postprocessing step with the index
+    Postprocessing(Process, usize),
+}
+
+pub struct DownloadJob {
+    pub url: String,
+    /// Playlist the downloaded song is added to
+    pub playlist_name: String,
+    /// Path of the downloaded file as printed by yt-dlp
+    pub path: Option<String>,
+    pub options: DownloadOptions,
+    pub postprocessors: Vec<Postprocessor>,
+    pub stage: DownloadStage,
+    /// Progress of the current stage, like `45.3%`
+    pub progress: String,
+}
+
+impl DownloadJob {
+    /// Returns the file name once it is known, otherwise the URL.
+    pub fn get_title(&self) -> String {
+        match &self.path {
+            Some(path) => Path::new(path)
+                .file_name()
+                .map_or(path.clone(), |x| x.to_string_lossy().to_string()),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Stops the running process and removes the temporary file of the postprocessing step.
+    pub fn stop(&mut self) {
+        if let DownloadStage::Downloading(process) | DownloadStage::Postprocessing(process, _) =
+            &mut self.stage
+        {
+            process.kill();
+        }
+        if let (DownloadStage::Postprocessing(_, step), Some(path)) = (&self.stage, &self.path) {
+            if let Some(output_path) = self.postprocessors[*step].get_output_path(path) {
+                let _ = fs::remove_file(get_temporary_path(&output_path));
+            }
+        }
+    }
+
+    pub fn get_status(&self) -> String {
+        match self.stage {
+            DownloadStage::Queued => "Queued".to_string(),
+            DownloadStage::Downloading(_) => format!("Downloading {}", self.progress),
+            DownloadStage::Postprocessing(_, step) => {
+                format!("{} {}", self.postprocessors[step].name(), self.progress)
+            }
+        }
+    }
+
+    /// Returns the progress between 0 and 1 parsed from the last progress output. Playlist
+    /// downloads report their progress as "index/count percentage".
+    pub fn get_fraction(&self) -> f32 {
+        let mut parts = self.progress.split_whitespace();
+        let (position, percentage) = match (parts.next(), parts.next()) {
+            (Some(position), Some(percentage)) => (Some(position), percentage),
+            (Some(percentage), None) => (None, percentage),
+            _ => return 0.0,
+        };
+        let fraction = percentage
+            .trim_end_matches('%')
+            .parse::<f32>()
+            .map_or(0.0, |x| x / 100.0);
+        let playlist_position = position
+            .and_then(|x| x.split_once('/'))
+            .and_then(|(i, n)| Some((i.parse::<f32>().ok()?, n.parse::<f32>().ok()?)));
+        match playlist_position {
+            Some((i, n)) if n > 0.0 => ((i - 1.0 + fraction) / n).clamp(0.0, 1.0),
+            _ => fraction.clamp(0.0, 1.0),
+        }
+    }
+}
+
+pub enum DownloadEvent {
+    Error(String),
+    Finished(String),
+    /// A song of a playlist download or a download that is split into chapters was downloaded and
+    /// needs postprocessing
+    SongDownloaded(String),
+    /// All songs of a playlist download or a download that is split into chapters were downloaded
+    PlaylistFinished,
+}
+
+/// Returns the URL of an internet shortcut file as created by dragging a link out of a browser.
+/// Supports .url and .desktop files and .webloc property lists.
+pub fn read_url_shortcut(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    if !URL_SHORTCUT_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    let url = if extension == "webloc" {
+        let start = content.find("<string>")? + "<string>".len();
+        let end = start + content[start..].find("</string>")?;
+        content[start..end].replace("&amp;", "&")
+    } else {
+        content
+            .lines()
+            .find_map(|x| x.trim().strip_prefix("URL="))?
+            .to_string()
+    };
+    let url = url.trim();
+    (url.starts_with("http://") || url.starts_with("https://")).then(|| url.to_string())
+}
+
+/// Starts the first postprocessing step from `first_step` on that applies to the file. Steps whose
+/// program is missing are skipped. Returns false if no step is left.
+pub fn start_postprocessing(
+    job: &mut DownloadJob,
+    programs: &Programs,
+    first_step: usize,
+) -> Result<bool, String> {
+    let Some(path) = &job.path else {
+        return Ok(false);
+    };
+    for (i, postprocessor) in job.postprocessors.iter().enumerate().skip(first_step) {
+        let Some(program) = postprocessor.program(programs) else {
+            continue;
+        };
+        if !postprocessor.supports(path) {
+            continue;
+        }
+        let process = Process::start(postprocessor.command(program, path))
+            .map_err(|e| format!("Could not start postprocessing:\n{e}"))?;
+        job.stage = DownloadStage::Postprocessing(process, i);
+        job.progress = String::new();
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Reads the output of the running process of the job and starts the next stage once it exits.
+pub fn update_job(job: &mut DownloadJob, programs: &Programs) -> Vec<DownloadEvent> {
+    let mut events = Vec::new();
+    match &mut job.stage {
+        DownloadStage::Queued => (),
+        DownloadStage::Downloading(process) => {
+            let status = process.try_wait();
+            for line in process.receive_output(status.is_some()) {
+                let line = line.trim();
+                if let Some(progress) = line.strip_prefix("#status#") {
+                    job.progress = progress.trim().to_string();
+                } else if let Some(path) = line.strip_prefix(FILE_LINE_PREFIX) {
+                    if job.options.whole_playlist {
+                        events.push(DownloadEvent::SongDownloaded(path.to_string()));
+                    } else {
+                        job.path = Some(path.to_string());
+                    }
+                }
+            }
+            let status = match status {
+                Some(status) => status,
+                None => return events,
+            };
+            if !status.success() {
+                events.push(DownloadEvent::Error(format!(
+                    "Error while downloading {}:\n{}",
+                    job.url,
+                    process.get_error_output()
+                )));
+                return events;
+            }
+            if job.options.whole_playlist {
+                events.push(DownloadEvent::PlaylistFinished);
+                return events;
+            }
+            let path = match &job.path {
+                Some(path) => path.clone(),
+                None => {
+                    events.push(DownloadEvent::Error(format!(
+                        "yt-dlp did not report the file name of {}",
+                        job.url
+                    )));
+                    return events;
+                }
+            };
+            if job.options.split_chapters {
+                events.push(DownloadEvent::SongDownloaded(path));
+                events.push(DownloadEvent::PlaylistFinished);
+                return events;
+            }
+
+            match start_postprocessing(job, programs, 0) {
+                Ok(true) => (),
+                Ok(false) => events.push(DownloadEvent::Finished(path)),
+                Err(e) => events.push(DownloadEvent::Error(e)),
+            }
+        }
+        DownloadStage::Postprocessing(process, step) => {
+            let step = *step;
+            let status = process.try_wait();
+            let lines = process.receive_output(status.is_some());
+            for line in lines.iter() {
+                if line.chars().nth(2) == Some('%') {
+                    job.progress = line[..3].trim().to_string();
+                }
+            }
+            let Some(status) = status else {
+                return events;
+            };
+            let Some(path) = job.path.clone() else {
+                return events;
+            };
+            let postprocessor = job.postprocessors[step];
+            if !status.success() {
+                // aacgain reports errors on stdout
+                let error = [
+                    lines.join("").trim().to_string(),
+                    process.get_error_output(),
+                ]
+                .into_iter()
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<String>>()
+                .join("\n");
+                if let Some(output_path) = postprocessor.get_output_path(&path) {
+                    let _ = fs::remove_file(get_temporary_path(&output_path));
+                }
+                events.push(DownloadEvent::Error(format!(
+                    "Error during postprocessing ({}):\n{error}",
+                    postprocessor.name()
+                )));
+                return events;
+            }
+            let mut path = path;
+            if let Some(output_path) = postprocessor.get_output_path(&path) {
+                if let Err(e) = fs::rename(get_temporary_path(&output_path), &output_path) {
+                    events.push(DownloadEvent::Error(format!(
+                        "Could not replace {path} after postprocessing:\n{e}"
+                    )));
+                    return events;
+                }
+                // Converted files replace the file in the original format
+                if output_path != path {
+                    let _ = fs::remove_file(&path);
+                    path = output_path;
+                    job.path = Some(path.clone());
+                }
+            }
+
+            match start_postprocessing(job, programs, step + 1) {
+                Ok(true) => (),
+                Ok(false) => events.push(DownloadEvent::Finished(path)),
+                Err(e) => events.push(DownloadEvent::Error(e)),
+            }
+        }
+    }
+    events
+}
+
+/// A video found by the search
+pub struct SearchResult {
+    pub url: String,
+    pub title: String,
+    pub channel: String,
+    /// Duration in milliseconds
+    pub duration: Option<u64>,
+}
+
+/// A song of a playlist from a streaming service
+pub struct StreamingTrack {
+    pub artist: String,
+    pub title: String,
+    /// URL of the video, tracks without one are downloaded from the first search result
+    pub url: Option<String>,
+}
+
+impl StreamingTrack {
+    /// Splits the video title into artist and title. YouTube Music uploads are published by
+    /// channels named "Artist - Topic", other videos are usually named "Artist - Title".
+    pub fn from_video(url: &str, channel: &str, title: &str) -> StreamingTrack {
+        let (artist, title) = match channel.strip_suffix(" - Topic") {
+            Some(artist) => (artist, title),
+            None => title.split_once(" - ").unwrap_or((channel, title)),
+        };
+        StreamingTrack {
+            artist: artist.trim().to_string(),
+            title: title.trim().to_string(),
+            url: Some(url.to_string()),
+        }
+    }
+}
+
+/// Runs aacgain on each file, for songs that were downloaded before postprocessing was set up.
+pub fn run_postprocessing(aacgain: String, paths: Vec<String>, sender: JobSender<usize>) {
+    let mut success_count = 0;
+    for (i, path) in paths.iter().enumerate() {
+        let file_name = Path::new(path)
+            .file_name()
+            .map_or(String::new(), |x| x.to_string_lossy().to_string());
+        if sender
+            .send(JobMessage::Progress {
+                done: i + 1,
+                total: paths.len(),
+                item: Some(file_name.clone()),
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        let error = if !is_postprocessing_supported(path) {
+            Some("Unsupported file type".to_string())
+        } else {
+            match postprocessing_command(&aacgain, path).output() {
+                Ok(output) if output.status.success() => None,
+                Ok(output) => Some(
+                    [&output.stderr, &output.stdout]
+                        .iter()
+                        .map(|x| String::from_utf8_lossy(x).trim().to_string())
+                        .filter(|x| !x.is_empty())
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                ),
+                Err(e) => Some(e.to_string()),
+            }
+        };
+        match error {
+            Some(error) => {
+                let _ = sender.send(JobMessage::Error(format!(
+                    "Error during postprocessing of {file_name}:\n{error}"
+                )));
+            }
+            None => success_count += 1,
+        }
+    }
+    let _ = sender.send(JobMessage::Finished(success_count));
+}
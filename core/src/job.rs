@@ -0,0 +1,100 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+pub enum JobMessage<T> {
+    /// Number of the current item and the total number of items, with the name of the item
+    Progress {
+        done: usize,
+        total: usize,
+        item: Option<String>,
+    },
+    Error(String),
+    Finished(T),
+}
+
+pub type JobSender<T> = Sender<JobMessage<T>>;
+
+/// Work that runs in a background thread and reports its progress, its errors and finally its
+/// result through a channel. Dropping the job stops the thread once it fails to send its next
+/// message.
+pub struct Job<T> {
+    receiver: Option<Receiver<JobMessage<T>>>,
+}
+
+impl<T> Default for Job<T> {
+    fn default() -> Self {
+        Job { receiver: None }
+    }
+}
+
+impl<T> Job<T> {
+    /// Returns the sender that the thread of a new run sends its messages with. A run that is
+    /// still going is stopped.
+    pub fn start(&mut self) -> JobSender<T> {
+        let (sender, receiver) = mpsc::channel();
+        self.receiver = Some(receiver);
+        sender
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Stops the job once it sends its next message.
+    pub fn stop(&mut self) {
+        self.receiver = None;
+    }
+
+    /// Returns the messages that were sent since the last call. The job stops running once it
+    /// sent its result or if its thread ended without one.
+    pub fn receive(&mut self) -> Vec<JobMessage<T>> {
+        let mut messages = Vec::new();
+        let Some(receiver) = &self.receiver else {
+            return messages;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(message) => {
+                    let is_finished = matches!(message, JobMessage::Finished(_));
+                    messages.push(message);
+                    if is_finished {
+                        self.receiver = None;
+                        break;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_stops_after_its_result() {
+        let mut job = Job::default();
+        let sender = job.start();
+        sender.send(JobMessage::Error("error".to_string())).unwrap();
+        assert_eq!(job.receive().len(), 1);
+        assert!(job.is_running());
+        sender.send(JobMessage::Finished(3)).unwrap();
+        let messages = job.receive();
+        assert!(matches!(messages[..], [JobMessage::Finished(3)]));
+        assert!(!job.is_running());
+    }
+
+    #[test]
+    fn job_stops_when_its_thread_ends_without_result() {
+        let mut job: Job<()> = Job::default();
+        let sender = job.start();
+        drop(sender);
+        assert!(job.receive().is_empty());
+        assert!(!job.is_running());
+    }
+}
@@ -0,0 +1,16 @@
+//! Playlist model, playback and downloads of implayer without the user interface
+
+/// Downloads with yt-dlp and postprocessing of the downloaded songs
+pub mod download;
+/// Background jobs that report their progress and result through a channel
+pub mod job;
+/// Playlists and songs of the music directory
+pub mod model;
+mod output;
+/// Decoding and playback in a background thread
+pub mod player;
+/// Reading and writing of m3u, pls and xspf playlist files
+pub mod playlist_file;
+mod resampler;
+/// Helpers for text matching, paths and channels
+pub mod util;
@@ -0,0 +1,514 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{playlist_file::PlaylistFormat, util};
+
+pub const ALL_PLAYLIST_NAME: &str = "All";
+pub const ALL_UNUSED_PLAYLIST_NAME: &str = "All Unused";
+pub const HISTORY_PLAYLIST_NAME: &str = "History";
+pub const RECENTLY_ADDED_PLAYLIST_NAME: &str = "Recently Added";
+pub const MUSIC_EXTENSIONS: &[&str] = &["flac", "mp3", "m4a", "ogg", "wav"];
+
+/// Returns whether the file has the extension of a supported format, ignoring its case.
+pub fn is_music_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| MUSIC_EXTENSIONS.contains(&x.to_string_lossy().to_lowercase().as_str()))
+}
+
+/// Returns whether the playlist is one of the playlists that are created from the library instead
+/// of being stored in a file.
+pub fn is_default_playlist(playlist_name: &str) -> bool {
+    playlist_name == ALL_PLAYLIST_NAME
+        || playlist_name == ALL_UNUSED_PLAYLIST_NAME
+        || playlist_name == RECENTLY_ADDED_PLAYLIST_NAME
+        || playlist_name == HISTORY_PLAYLIST_NAME
+}
+
+/// Returns the paths of the songs in the playlists. Songs of the All playlist that are in none of
+/// the playlist files make up the All Unused playlist.
+pub fn get_song_paths<'a>(playlists: impl IntoIterator<Item = &'a Playlist>) -> HashSet<String> {
+    playlists
+        .into_iter()
+        .flat_map(|x| x.songs.iter().map(|x| x.path.clone()))
+        .collect()
+}
+
+/// Returns a hash of the songs and their order, which tells whether a playlist changed since it
+/// was saved.
+pub fn hash_songs(songs: &[Song]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for song in songs.iter() {
+        song.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Orders the default playlists first in a fixed order, then the playlists by name with the
+/// playlists of a folder below the playlists next to the folder.
+pub fn compare_playlist_names(a: &str, b: &str) -> Ordering {
+    let get_rank = |name: &str| match name {
+        ALL_PLAYLIST_NAME => 0,
+        ALL_UNUSED_PLAYLIST_NAME => 1,
+        RECENTLY_ADDED_PLAYLIST_NAME => 2,
+        HISTORY_PLAYLIST_NAME => 3,
+        _ => 4,
+    };
+    let rank_ordering = get_rank(a).cmp(&get_rank(b));
+    if rank_ordering != Ordering::Equal {
+        return rank_ordering;
+    }
+
+    let a_folders = util::get_playlist_folders(a);
+    let b_folders = util::get_playlist_folders(b);
+    for depth in 0..=a_folders.len().min(b_folders.len()) {
+        let ordering = match (a_folders.get(depth), b_folders.get(depth)) {
+            (Some(a), Some(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+pub struct Playlist {
+    pub name: String,
+    pub songs: Vec<Song>,
+    pub original_hash: u64,
+    /// Paths as written in the playlist file by song path, to keep them unchanged on save
+    pub file_paths: HashMap<String, String>,
+    /// Comment lines of the playlist file by the path of the song they are in front of
+    pub comments: HashMap<String, Vec<String>>,
+    pub format: PlaylistFormat,
+    /// Modification time of the playlist file when it was last read or written
+    pub modified_time: Option<SystemTime>,
+}
+
+impl Playlist {
+    pub fn new(name: String, songs: Vec<Song>) -> Playlist {
+        Playlist {
+            name,
+            original_hash: hash_songs(&songs),
+            songs,
+            file_paths: HashMap::new(),
+            comments: HashMap::new(),
+            format: PlaylistFormat::M3u,
+            modified_time: None,
+        }
+    }
+
+    /// Returns whether the songs differ from the saved playlist file.
+    pub fn has_changes(&self) -> bool {
+        if is_default_playlist(&self.name) {
+            return false;
+        }
+        self.original_hash != hash_songs(&self.songs)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Song {
+    pub path: String,
+    pub name: String,
+    pub artist: String,
+    /// Milliseconds
+    pub duration: Option<u64>,
+    pub exists: bool,
+}
+
+impl Song {
+    pub fn new(path: PathBuf, base_path: &str, duration: Option<u64>) -> Song {
+        let (artist, name) =
+            Song::parse_file_name(&path.file_stem().unwrap_or_default().to_string_lossy());
+
+        Song {
+            path: Song::get_relative_path(&path, base_path),
+            name,
+            artist,
+            duration,
+            exists: path.exists(),
+        }
+    }
+
+    /// Returns the path relative to the base path. Songs outside of the base path keep their
+    /// absolute path.
+    pub fn get_relative_path(path: &Path, base_path: &str) -> String {
+        path.strip_prefix(base_path)
+            .ok()
+            .map(|x| x.to_path_buf())
+            .or_else(|| {
+                let base_path = Path::new(base_path).canonicalize().ok()?;
+                path.strip_prefix(base_path).ok().map(|x| x.to_path_buf())
+            })
+            .unwrap_or(path.to_path_buf())
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Splits a file name without extension like `Artist - Song` into artist and song name.
+    pub fn parse_file_name(file_name: &str) -> (String, String) {
+        let name_info: Vec<&str> = file_name.splitn(2, " - ").collect();
+        let name = if name_info.len() > 1 {
+            name_info[1].trim().to_string()
+        } else {
+            String::new()
+        };
+        (name_info[0].trim().to_string(), name)
+    }
+
+    /// Returns the best fuzzy match score of the name, the artist or both together.
+    pub fn get_match_score(&self, search_text: &str) -> Option<i32> {
+        [
+            &self.name,
+            &self.artist,
+            &format!("{} {}", self.artist, self.name),
+        ]
+        .into_iter()
+        .filter_map(|x| util::fuzzy_match(search_text, x).map(|(score, _)| score))
+        .max()
+    }
+}
+
+/// Fields of a song that can be compared without its tags
+#[derive(Clone, Copy)]
+pub enum SongField {
+    Name,
+    Artist,
+    Duration,
+    Path,
+}
+
+impl Song {
+    /// Compares the field of the songs, texts are compared ignoring the case. Songs with an unknown
+    /// duration come first.
+    pub fn compare_by(&self, other: &Song, field: SongField) -> Ordering {
+        match field {
+            SongField::Name => self.name.to_lowercase().cmp(&other.name.to_lowercase()),
+            SongField::Artist => self.artist.to_lowercase().cmp(&other.artist.to_lowercase()),
+            SongField::Duration => self.duration.cmp(&other.duration),
+            SongField::Path => self.path.to_lowercase().cmp(&other.path.to_lowercase()),
+        }
+    }
+}
+
+/// Selected and playing songs of a playlist, which have to stay on the same songs when songs are
+/// inserted into or removed from the playlist
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct SongIndices {
+    pub selected: Vec<usize>,
+    pub playing: Option<usize>,
+}
+
+impl SongIndices {
+    /// Moves the indices after songs were inserted at the start of the playlist.
+    pub fn increment(&mut self, amount: usize) {
+        for i in self.selected.iter_mut() {
+            *i += amount;
+        }
+        if let Some(playing) = &mut self.playing {
+            *playing += amount;
+        }
+    }
+
+    /// Drops the indices of songs that were cut off the end of a playlist with the given length.
+    pub fn truncate(&mut self, len: usize) {
+        self.selected.retain(|x| *x < len);
+        self.playing = self.playing.filter(|x| *x < len);
+    }
+
+    /// Removes the selected songs and clears the selection. A removed playing song is replaced by
+    /// the song in front of it, so that playback continues after it.
+    pub fn remove_selected(&mut self, songs: &mut Vec<Song>) {
+        self.selected.sort_unstable();
+        self.selected.dedup();
+        for i in self.selected.iter().rev() {
+            if self.playing == Some(*i) {
+                self.playing = Some(i.saturating_sub(1));
+            } else if self.playing > Some(*i) {
+                self.playing = self.playing.map(|x| x - 1);
+            }
+            songs.remove(*i);
+        }
+        self.selected.clear();
+    }
+}
+
+impl Hash for Song {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.duration.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(path: &str) -> Song {
+        let (artist, name) = Song::parse_file_name(path.trim_end_matches(".mp3"));
+        Song {
+            path: path.to_string(),
+            name,
+            artist,
+            duration: None,
+            exists: true,
+        }
+    }
+
+    fn songs(paths: &[&str]) -> Vec<Song> {
+        paths.iter().map(|x| song(x)).collect()
+    }
+
+    fn paths(songs: &[Song]) -> Vec<&str> {
+        songs.iter().map(|x| x.path.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_file_name_splits_artist_and_name() {
+        assert_eq!(
+            Song::parse_file_name("Queen - Bohemian Rhapsody"),
+            ("Queen".to_string(), "Bohemian Rhapsody".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_file_name_splits_at_first_separator() {
+        assert_eq!(
+            Song::parse_file_name("AC/DC - Live - Thunderstruck "),
+            ("AC/DC".to_string(), "Live - Thunderstruck".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_file_name_without_separator() {
+        assert_eq!(
+            Song::parse_file_name("Untitled"),
+            ("Untitled".to_string(), String::new())
+        );
+        assert_eq!(
+            Song::parse_file_name("Artist-Name"),
+            ("Artist-Name".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn relative_path_strips_base_path() {
+        let path = Path::new("/music/Rock/A - B.mp3");
+        assert_eq!(Song::get_relative_path(path, "/music"), "Rock/A - B.mp3");
+    }
+
+    #[test]
+    fn relative_path_keeps_paths_outside_base_path() {
+        let path = Path::new("/other/A - B.mp3");
+        assert_eq!(
+            Song::get_relative_path(path, "/music/missing"),
+            "/other/A - B.mp3"
+        );
+    }
+
+    #[test]
+    fn has_changes_after_editing() {
+        let mut playlist = Playlist::new("Mix".to_string(), songs(&["a.mp3", "b.mp3"]));
+        assert!(!playlist.has_changes());
+        playlist.songs.swap(0, 1);
+        assert!(playlist.has_changes());
+        playlist.songs.swap(0, 1);
+        assert!(!playlist.has_changes());
+        // A song whose duration was read after saving is written again
+        playlist.songs[0].duration = Some(1000);
+        assert!(playlist.has_changes());
+    }
+
+    #[test]
+    fn default_playlists_have_no_changes() {
+        let mut playlist = Playlist::new(ALL_PLAYLIST_NAME.to_string(), songs(&["a.mp3"]));
+        playlist.songs.clear();
+        assert!(!playlist.has_changes());
+    }
+
+    #[test]
+    fn increment_moves_selection_and_playing_song() {
+        let mut indices = SongIndices {
+            selected: vec![0, 3],
+            playing: Some(2),
+        };
+        indices.increment(2);
+        assert_eq!(
+            indices,
+            SongIndices {
+                selected: vec![2, 5],
+                playing: Some(4),
+            }
+        );
+    }
+
+    #[test]
+    fn increment_without_playing_song() {
+        let mut indices = SongIndices::default();
+        indices.increment(3);
+        assert_eq!(indices, SongIndices::default());
+    }
+
+    #[test]
+    fn truncate_drops_indices_pushed_out_of_full_playlist() {
+        let mut songs = songs(&["a.mp3", "b.mp3", "c.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![0, 2],
+            playing: Some(2),
+        };
+        songs.insert(0, song("c.mp3"));
+        songs.truncate(3);
+        indices.increment(1);
+        indices.truncate(songs.len());
+        assert_eq!(
+            indices,
+            SongIndices {
+                selected: vec![1],
+                playing: None,
+            }
+        );
+    }
+
+    #[test]
+    fn remove_selected_before_playing_song() {
+        let mut songs = songs(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![1, 0],
+            playing: Some(3),
+        };
+        indices.remove_selected(&mut songs);
+        assert_eq!(paths(&songs), ["c.mp3", "d.mp3"]);
+        assert_eq!(
+            indices,
+            SongIndices {
+                selected: Vec::new(),
+                playing: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn remove_selected_after_playing_song() {
+        let mut songs = songs(&["a.mp3", "b.mp3", "c.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![2],
+            playing: Some(1),
+        };
+        indices.remove_selected(&mut songs);
+        assert_eq!(paths(&songs), ["a.mp3", "b.mp3"]);
+        assert_eq!(indices.playing, Some(1));
+    }
+
+    #[test]
+    fn remove_playing_song_moves_to_previous_song() {
+        let mut songs = songs(&["a.mp3", "b.mp3", "c.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![1],
+            playing: Some(1),
+        };
+        indices.remove_selected(&mut songs);
+        assert_eq!(paths(&songs), ["a.mp3", "c.mp3"]);
+        assert_eq!(indices.playing, Some(0));
+    }
+
+    #[test]
+    fn remove_first_playing_song_stays_at_start() {
+        let mut songs = songs(&["a.mp3", "b.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![0],
+            playing: Some(0),
+        };
+        indices.remove_selected(&mut songs);
+        assert_eq!(paths(&songs), ["b.mp3"]);
+        assert_eq!(indices.playing, Some(0));
+    }
+
+    #[test]
+    fn remove_duplicate_selection_once() {
+        let mut songs = songs(&["a.mp3", "b.mp3", "c.mp3"]);
+        let mut indices = SongIndices {
+            selected: vec![1, 1],
+            playing: None,
+        };
+        indices.remove_selected(&mut songs);
+        assert_eq!(paths(&songs), ["a.mp3", "c.mp3"]);
+        assert_eq!(indices, SongIndices::default());
+    }
+
+    #[test]
+    fn song_paths_of_several_playlists_are_combined() {
+        let a = Playlist::new("a".to_string(), vec![song("A - 1.mp3"), song("A - 2.mp3")]);
+        let b = Playlist::new("b".to_string(), vec![song("A - 2.mp3"), song("B - 1.mp3")]);
+        let paths = get_song_paths([&a, &b]);
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains("B - 1.mp3"));
+        assert!(get_song_paths([]).is_empty());
+    }
+
+    #[test]
+    fn default_playlists_are_sorted_first() {
+        let mut names = [
+            "Alpha",
+            HISTORY_PLAYLIST_NAME,
+            RECENTLY_ADDED_PLAYLIST_NAME,
+            ALL_UNUSED_PLAYLIST_NAME,
+            ALL_PLAYLIST_NAME,
+        ];
+        names.sort_by(|a, b| compare_playlist_names(a, b));
+        assert_eq!(
+            names,
+            [
+                ALL_PLAYLIST_NAME,
+                ALL_UNUSED_PLAYLIST_NAME,
+                RECENTLY_ADDED_PLAYLIST_NAME,
+                HISTORY_PLAYLIST_NAME,
+                "Alpha",
+            ]
+        );
+    }
+
+    #[test]
+    fn folders_are_sorted_below_playlists_next_to_them() {
+        let mut names = ["rock.Live.b", "Rock.a", "pop", "Rock", "Rock.Live.a", "Zed"];
+        names.sort_by(|a, b| compare_playlist_names(a, b));
+        assert_eq!(
+            names,
+            ["pop", "Rock", "Zed", "Rock.a", "Rock.Live.a", "rock.Live.b"]
+        );
+    }
+
+    #[test]
+    fn songs_are_compared_ignoring_case() {
+        let a = song("abba - b song.mp3");
+        let b = song("Beatles - A Song.mp3");
+        assert_eq!(a.compare_by(&b, SongField::Name), Ordering::Greater);
+        assert_eq!(a.compare_by(&b, SongField::Artist), Ordering::Less);
+        assert_eq!(a.compare_by(&b, SongField::Path), Ordering::Less);
+    }
+
+    #[test]
+    fn music_files_are_recognized_in_any_case() {
+        assert!(is_music_file(Path::new("a/b.mp3")));
+        assert!(is_music_file(Path::new("a/b.FLAC")));
+        assert!(!is_music_file(Path::new("a/b.txt")));
+        assert!(!is_music_file(Path::new("a/mp3")));
+    }
+
+    #[test]
+    fn unknown_durations_are_compared_first() {
+        let mut a = song("a.mp3");
+        let mut b = song("b.mp3");
+        b.duration = Some(1000);
+        assert_eq!(a.compare_by(&b, SongField::Duration), Ordering::Less);
+        a.duration = Some(2000);
+        assert_eq!(a.compare_by(&b, SongField::Duration), Ordering::Greater);
+    }
+}
@@ -115,10 +115,14 @@ mod cpal {
                 }
             } else {
                 // Use the default config for Windows.
-                device
-                    .default_output_config()
-                    .expect("Failed to get the default output config.")
-                    .config()
+                match device.default_output_config() {
+                    Ok(config) => config.config(),
+                    Err(err) => {
+                        println!("failed to get the default output config: {}", err);
+
+                        return Err(AudioOutputError::OpenStreamError);
+                    }
+                }
             };
 
             // Create a ring buffer with a capacity for up-to 200ms of audio.
@@ -231,6 +235,181 @@ mod cpal {
     }
 }
 
+#[cfg(all(feature = "jack", target_os = "linux"))]
+mod jack {
+    use crate::resampler::Resampler;
+
+    use super::{AudioOutput, AudioOutputError, Result};
+
+    use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec};
+    use symphonia::core::units::Duration;
+
+    use rb::*;
+
+    /// Name of the client in the session graph, its ports are named `ImPlayer:out_1` and so on
+    const CLIENT_NAME: &str = "ImPlayer";
+
+    /// Output that registers its own ports with a JACK server or with PipeWire's JACK interface,
+    /// so that it can be routed like other applications of the session.
+    pub struct JackAudioOutput {
+        ring_buf_producer: rb::Producer<f32>,
+        sample_buf: SampleBuffer<f32>,
+        resampler: Option<Resampler<f32>>,
+        // Deactivating the client removes its ports from the graph
+        _client: jack::AsyncClient<(), JackProcess>,
+    }
+
+    struct JackProcess {
+        ports: Vec<jack::Port<jack::AudioOut>>,
+        ring_buf_consumer: rb::Consumer<f32>,
+        /// Interleaved samples of one period, the ports take planar samples. It is sized for the
+        /// largest period outside of `process`, which must not allocate.
+        interleaved: Vec<f32>,
+    }
+
+    impl jack::ProcessHandler for JackProcess {
+        fn process(&mut self, _: &jack::Client, scope: &jack::ProcessScope) -> jack::Control {
+            let channel_count = self.ports.len();
+            let len = (scope.n_frames() as usize * channel_count).min(self.interleaved.len());
+            let interleaved = &mut self.interleaved[..len];
+            let written = self.ring_buf_consumer.read(interleaved).unwrap_or(0);
+            // Mute any remaining samples.
+            interleaved[written..].iter_mut().for_each(|s| *s = 0.0);
+            for (channel, port) in self.ports.iter_mut().enumerate() {
+                let samples = interleaved.iter().skip(channel).step_by(channel_count);
+                for (sample, value) in port.as_mut_slice(scope).iter_mut().zip(samples) {
+                    *sample = *value;
+                }
+            }
+            jack::Control::Continue
+        }
+
+        // Called before the first period and whenever the server changes the period size
+        fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+            self.interleaved
+                .resize(size as usize * self.ports.len(), 0.0);
+            jack::Control::Continue
+        }
+    }
+
+    impl JackAudioOutput {
+        pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
+            // Don't start a server, the default output is used if none is running
+            let (client, _) = jack::Client::new(CLIENT_NAME, jack::ClientOptions::NO_START_SERVER)
+                .map_err(|_| AudioOutputError::OpenStreamError)?;
+
+            let num_channels = spec.channels.count();
+            let mut ports = Vec::with_capacity(num_channels);
+            for i in 0..num_channels {
+                match client.register_port(&format!("out_{}", i + 1), jack::AudioOut::default()) {
+                    Ok(port) => ports.push(port),
+                    Err(err) => {
+                        println!("failed to register jack port: {}", err);
+                        return Err(AudioOutputError::OpenStreamError);
+                    }
+                }
+            }
+            let port_names: Vec<String> = ports.iter().filter_map(|x| x.name().ok()).collect();
+
+            let sample_rate = client.sample_rate();
+            // Create a ring buffer with a capacity for up-to 200ms of audio.
+            let ring_len = ((200 * sample_rate) / 1000) * num_channels;
+            let ring_buf = SpscRb::new(ring_len);
+            let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
+
+            let process = JackProcess {
+                ports,
+                ring_buf_consumer,
+                interleaved: vec![0.0; client.buffer_size() as usize * num_channels],
+            };
+            let client = match client.activate_async((), process) {
+                Ok(client) => client,
+                Err(err) => {
+                    println!("failed to activate jack client: {}", err);
+                    return Err(AudioOutputError::PlayStreamError);
+                }
+            };
+
+            // Connect to the speakers like other players, the connections can be changed in the
+            // session graph afterwards.
+            let playback_ports = client.as_client().ports(
+                None,
+                Some("32 bit float mono audio"),
+                jack::PortFlags::IS_INPUT | jack::PortFlags::IS_PHYSICAL,
+            );
+            if !playback_ports.is_empty() {
+                for (i, port_name) in port_names.iter().enumerate() {
+                    let playback_port = &playback_ports[i % playback_ports.len()];
+                    let _ = client
+                        .as_client()
+                        .connect_ports_by_name(port_name, playback_port);
+                }
+            }
+
+            let sample_buf = SampleBuffer::<f32>::new(duration, spec);
+
+            let resampler = if spec.rate as usize != sample_rate {
+                println!("resampling {} Hz to {} Hz", spec.rate, sample_rate);
+                Some(Resampler::new(spec, sample_rate, duration))
+            } else {
+                None
+            };
+
+            Ok(Box::new(JackAudioOutput {
+                ring_buf_producer,
+                sample_buf,
+                resampler,
+                _client: client,
+            }))
+        }
+    }
+
+    impl AudioOutput for JackAudioOutput {
+        fn write(&mut self, decoded: AudioBufferRef<'_>, volume: f32) -> Result<()> {
+            // Do nothing if there are no audio frames.
+            if decoded.frames() == 0 {
+                return Ok(());
+            }
+
+            let samples = if let Some(resampler) = &mut self.resampler {
+                match resampler.resample(decoded) {
+                    Some(resampled) => resampled,
+                    None => return Ok(()),
+                }
+            } else {
+                self.sample_buf.copy_interleaved_ref(decoded);
+
+                self.sample_buf.samples()
+            };
+
+            let adjusted_samples: Vec<f32> = samples.iter().map(|x| x * volume).collect();
+
+            let mut slice = &adjusted_samples[..];
+
+            while let Some(written) = self.ring_buf_producer.write_blocking(slice) {
+                slice = &slice[written..];
+            }
+
+            Ok(())
+        }
+
+        fn flush(&mut self) {
+            if let Some(resampler) = &mut self.resampler {
+                let mut remaining_samples = resampler.flush().unwrap_or_default();
+
+                while let Some(written) = self.ring_buf_producer.write_blocking(remaining_samples) {
+                    remaining_samples = &remaining_samples[written..];
+                }
+            }
+        }
+    }
+}
+
 pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
+    // Falls back to the default output if no JACK or PipeWire server is running
+    #[cfg(all(feature = "jack", target_os = "linux"))]
+    if let Ok(output) = jack::JackAudioOutput::try_open(spec, duration) {
+        return Ok(output);
+    }
     cpal::CpalAudioOutput::try_open(spec, duration)
 }
@@ -0,0 +1,219 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use symphonia::core::{
+    codecs::Decoder,
+    formats::{FormatReader, SeekMode, SeekTo},
+    units::{Time, TimeBase},
+};
+
+use crate::output;
+
+fn time_to_ms(time: Time) -> u64 {
+    time.seconds * 1000 + (time.frac * 1000.0) as u64
+}
+
+fn ms_to_time(ms: u64) -> Time {
+    Time {
+        seconds: ms / 1000,
+        frac: (ms % 1000) as f64 / 1000.0,
+    }
+}
+
+/// Opens the song file and probes its format.
+fn open(path: &Path, enable_gapless: bool) -> Result<Box<dyn FormatReader>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mss = symphonia::core::io::MediaSourceStream::new(Box::new(file), Default::default());
+    symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &symphonia::core::formats::FormatOptions {
+                enable_gapless,
+                ..Default::default()
+            },
+            &Default::default(),
+        )
+        .map(|x| x.format)
+        .map_err(|e| e.to_string())
+}
+
+pub fn get_duration(path: &Path) -> Result<u64, String> {
+    let reader = open(path, false)?;
+    let track = reader.tracks().first().ok_or("No audio track")?;
+
+    let tb = track.codec_params.time_base.ok_or("Unknown time base")?;
+    let frames = track.codec_params.n_frames.ok_or("Unknown length")?;
+    Ok(time_to_ms(
+        tb.calc_time(track.codec_params.start_ts + frames),
+    ))
+}
+
+pub enum PlayerAction {
+    Play(PathBuf),
+    Pause,
+    Resume,
+    Stop,
+    Seek(u64),
+    SetVolume(f32),
+    /// Stops playback and ends the thread
+    Quit,
+}
+
+/// Opens the song and creates the decoder for its first track.
+fn load(path: &Path) -> Result<(Box<dyn FormatReader>, Box<dyn Decoder>, TimeBase), String> {
+    let reader = open(path, true)?;
+    let track = reader.tracks().first().ok_or("No audio track")?;
+    let decoder = symphonia::default::get_codecs()
+        .make(
+            &track.codec_params,
+            &symphonia::core::codecs::DecoderOptions { verify: false },
+        )
+        .map_err(|e| e.to_string())?;
+    let time_base = track.codec_params.time_base.ok_or("Unknown time base")?;
+    Ok((reader, decoder, time_base))
+}
+
+/// Plays the songs of the actions. Songs that can't be played are reported through `error_tx` and
+/// playback stops.
+pub fn run(
+    action_rx: Receiver<PlayerAction>,
+    song_ended_tx: Sender<()>,
+    error_tx: Sender<String>,
+    position: Arc<Mutex<u64>>,
+) {
+    struct PlayerState {
+        reader: Box<dyn FormatReader>,
+        audio_output: Option<Box<dyn output::AudioOutput>>,
+        decoder: Box<dyn Decoder>,
+        time_base: TimeBase,
+    }
+
+    let mut state = None;
+    let mut is_playing = false;
+    let mut volume = 0.93_f32.powi(4);
+
+    loop {
+        let result = if is_playing {
+            match action_rx.try_recv() {
+                Ok(action) => Some(action),
+                Err(_) => None,
+            }
+        } else {
+            match action_rx.recv() {
+                Ok(action) => Some(action),
+                Err(_) => return,
+            }
+        };
+
+        match result {
+            Some(PlayerAction::Play(path)) => match load(&path) {
+                Ok((reader, decoder, time_base)) => {
+                    state = Some(PlayerState {
+                        reader,
+                        audio_output: None,
+                        decoder,
+                        time_base,
+                    });
+                    is_playing = true;
+                }
+                Err(e) => {
+                    state = None;
+                    is_playing = false;
+                    let _ = error_tx.send(format!("Could not play {}:\n{e}", path.display()));
+                }
+            },
+            Some(PlayerAction::Pause) => {
+                if state.is_some() {
+                    is_playing = false;
+                }
+            }
+            Some(PlayerAction::Resume) => {
+                if state.is_some() {
+                    is_playing = true;
+                }
+            }
+            Some(PlayerAction::Stop) => {
+                state = None;
+                is_playing = false;
+            }
+            Some(PlayerAction::Seek(ms)) => {
+                if let Some(s) = &mut state {
+                    let result = s.reader.seek(
+                        SeekMode::Accurate,
+                        SeekTo::Time {
+                            time: ms_to_time(ms),
+                            track_id: None,
+                        },
+                    );
+                    if let Err(e) = result {
+                        let _ = error_tx.send(format!("Could not seek:\n{e}"));
+                    }
+                }
+            }
+            Some(PlayerAction::SetVolume(v)) => {
+                volume = v;
+            }
+            Some(PlayerAction::Quit) => return,
+            None => (),
+        }
+
+        if state.is_none() || !is_playing {
+            continue;
+        }
+
+        let s = state.as_mut().unwrap();
+
+        let packet = match s.reader.next_packet() {
+            Ok(packet) => packet,
+            Err(_) => {
+                state = None;
+                is_playing = false;
+                let _ = song_ended_tx.send(());
+                continue;
+            }
+        };
+
+        match s.decoder.decode(&packet) {
+            Ok(decoded) => {
+                if s.audio_output.is_none() {
+                    let spec = *decoded.spec();
+                    let duration = decoded.capacity() as u64;
+                    match output::try_open(spec, duration) {
+                        Ok(audio_output) => s.audio_output = Some(audio_output),
+                        Err(e) => {
+                            state = None;
+                            is_playing = false;
+                            let _ = error_tx.send(format!("Could not open audio output: {e:?}"));
+                            continue;
+                        }
+                    }
+                }
+
+                *position.lock().unwrap() = time_to_ms(s.time_base.calc_time(packet.ts()));
+
+                if let Some(ref mut audio_output) = s.audio_output {
+                    if let Err(e) = audio_output.write(decoded, volume) {
+                        state = None;
+                        is_playing = false;
+                        let _ = error_tx.send(format!("Audio output failed: {e:?}"));
+                    }
+                }
+            }
+            Err(symphonia::core::errors::Error::DecodeError(err)) => {
+                println!("decode error: {}", err);
+            }
+            Err(_) => {
+                state = None;
+                is_playing = false;
+                let _ = song_ended_tx.send(());
+            }
+        }
+    }
+}
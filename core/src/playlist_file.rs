@@ -0,0 +1,504 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    model::{Playlist, Song},
+    player, util,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub const ALL: [PlaylistFormat; 3] = [
+        PlaylistFormat::M3u,
+        PlaylistFormat::Pls,
+        PlaylistFormat::Xspf,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "m3u",
+            PlaylistFormat::Pls => "pls",
+            PlaylistFormat::Xspf => "xspf",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "M3U",
+            PlaylistFormat::Pls => "PLS",
+            PlaylistFormat::Xspf => "XSPF",
+        }
+    }
+
+    /// Returns the format of a playlist file or a playlist backup like `Name.pls.1.bak`.
+    pub fn from_path(path: &Path) -> Option<PlaylistFormat> {
+        let mut path = path.to_path_buf();
+        if path.extension().is_some_and(|x| x == "bak") {
+            path = path.with_extension("").with_extension("");
+        }
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        PlaylistFormat::ALL
+            .into_iter()
+            .find(|x| x.extension() == extension)
+    }
+}
+
+/// Returns whether the file is a playlist backup like `Name.pls.1.bak`, which are stored next to
+/// the playlists but are not loaded as playlists.
+pub fn is_backup(path: &Path) -> bool {
+    path.extension().is_some_and(|x| x == "bak")
+}
+
+pub struct PlaylistEntry {
+    /// Path as written in the playlist file
+    pub path: String,
+    /// Milliseconds, 0 if unknown
+    pub duration: u64,
+    /// Comment lines in front of the entry, only supported by m3u files
+    pub comments: Vec<String>,
+}
+
+/// Parses the content of a playlist file. Comments after the last entry are returned separately.
+pub fn parse(content: &str, format: PlaylistFormat) -> (Vec<PlaylistEntry>, Vec<String>) {
+    let content = content.trim_start_matches('\u{feff}');
+    match format {
+        PlaylistFormat::M3u => parse_m3u(content),
+        PlaylistFormat::Pls => (parse_pls(content), Vec::new()),
+        PlaylistFormat::Xspf => (parse_xspf(content), Vec::new()),
+    }
+}
+
+/// Parses the playlist file at `path`. Durations that are missing in `songs` are filled in from
+/// the playlist or by reading the song file. Entries can be relative to the base path or absolute
+/// and use both slashes and backslashes. Returns an error message if the file can't be read.
+pub fn load(base_path: &str, path: &Path, songs: &mut [Song]) -> Result<Playlist, String> {
+    let playlist_name = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let format = PlaylistFormat::from_path(path).unwrap_or(PlaylistFormat::M3u);
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read playlist \"{playlist_name}\":\n{e}"))?;
+    let (entries, trailing_comments) = parse(&content, format);
+
+    let mut playlist_songs = Vec::<Song>::new();
+    let mut file_paths = HashMap::new();
+    let mut comments: HashMap<String, Vec<String>> = HashMap::new();
+    for entry in entries {
+        let full_path = Path::new(base_path).join(entry.path.replace('\\', "/"));
+        let relative_path = Song::get_relative_path(&full_path, base_path);
+        let song = songs
+            .iter_mut()
+            .find(|x| x.path == entry.path || x.path == relative_path);
+        let song = match song {
+            Some(s) => {
+                if s.duration.is_none() {
+                    if entry.duration == 0 {
+                        // Unreadable songs are kept without duration so the playlist is unchanged
+                        s.duration = player::get_duration(&Path::new(base_path).join(&s.path)).ok();
+                    } else {
+                        s.duration = Some(entry.duration);
+                    }
+                }
+                s.clone()
+            }
+            // Song will be added with exists = false
+            None => Song::new(full_path, base_path, Some(entry.duration)),
+        };
+        if song.path != entry.path {
+            file_paths.insert(song.path.clone(), entry.path);
+        }
+        if !entry.comments.is_empty() {
+            comments
+                .entry(song.path.clone())
+                .or_default()
+                .extend(entry.comments);
+        }
+        playlist_songs.push(song);
+    }
+    if !trailing_comments.is_empty() {
+        comments.insert(String::new(), trailing_comments);
+    }
+
+    let mut playlist = Playlist::new(playlist_name, playlist_songs);
+    playlist.file_paths = file_paths;
+    playlist.comments = comments;
+    playlist.format = format;
+    playlist.modified_time = fs::metadata(path).and_then(|x| x.modified()).ok();
+    Ok(playlist)
+}
+
+fn parse_m3u(content: &str) -> (Vec<PlaylistEntry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut comments = Vec::new();
+    let mut duration = 0;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line == "#EXTM3U" {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // Unknown durations are written as -1
+            duration = info
+                .split(',')
+                .next()
+                .and_then(|x| x.trim().parse::<f64>().ok())
+                .map_or(0, |x| (x.max(0.0) * 1000.0) as u64);
+            continue;
+        }
+        if line.starts_with('#') {
+            comments.push(line.to_string());
+            continue;
+        }
+        entries.push(PlaylistEntry {
+            path: line.to_string(),
+            duration,
+            comments: std::mem::take(&mut comments),
+        });
+        duration = 0;
+    }
+    (entries, comments)
+}
+
+fn parse_pls(content: &str) -> Vec<PlaylistEntry> {
+    let mut files: HashMap<usize, String> = HashMap::new();
+    let mut lengths: HashMap<usize, u64> = HashMap::new();
+    for line in content.lines() {
+        let (key, value) = match line.trim().split_once('=') {
+            Some(x) => x,
+            None => continue,
+        };
+        let key = key.trim().to_lowercase();
+        if let Some(number) = key.strip_prefix("file").and_then(|x| x.parse().ok()) {
+            files.insert(number, value.trim().to_string());
+        } else if let Some(number) = key.strip_prefix("length").and_then(|x| x.parse().ok()) {
+            let length = value.trim().parse::<i64>().unwrap_or(0).max(0) as u64;
+            lengths.insert(number, length * 1000);
+        }
+    }
+
+    let mut numbers: Vec<usize> = files.keys().copied().collect();
+    numbers.sort_unstable();
+    numbers
+        .iter()
+        .map(|number| PlaylistEntry {
+            path: files[number].clone(),
+            duration: lengths.get(number).copied().unwrap_or(0),
+            comments: Vec::new(),
+        })
+        .collect()
+}
+
+/// Returns the text of the first element with the given name.
+pub fn get_element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{name}>"))?;
+    Some(xml[start..end].trim())
+}
+
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn parse_xspf(content: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<track>") {
+        let end = match rest[start..].find("</track>") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let track = &rest[start..end];
+        rest = &rest[end..];
+
+        let location = match get_element_text(track, "location") {
+            Some(location) => unescape_xml(location),
+            None => continue,
+        };
+        let path = match location.strip_prefix("file://") {
+            // Windows paths are written as file:///C:/...
+            Some(path) if path.get(2..3) == Some(":") || path.get(3..4) == Some(":") => {
+                util::percent_decode(path.trim_start_matches('/'))
+            }
+            Some(path) => util::percent_decode(path),
+            None => util::percent_decode(&location),
+        };
+        let duration = get_element_text(track, "duration")
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(0);
+        entries.push(PlaylistEntry {
+            path,
+            duration,
+            comments: Vec::new(),
+        });
+    }
+    entries
+}
+
+/// Returns the path of the song as it is written to the playlist file.
+fn get_file_path<'a>(playlist: &'a Playlist, song_path: &'a String) -> &'a String {
+    playlist.file_paths.get(song_path).unwrap_or(song_path)
+}
+
+/// Serializes the playlist in its format.
+pub fn write(playlist: &Playlist) -> String {
+    match playlist.format {
+        PlaylistFormat::M3u => write_m3u(playlist),
+        PlaylistFormat::Pls => write_pls(playlist),
+        PlaylistFormat::Xspf => write_xspf(playlist),
+    }
+}
+
+fn write_m3u(playlist: &Playlist) -> String {
+    let mut result = "#EXTM3U".to_string();
+    let mut written_comments = HashSet::new();
+    for song in playlist.songs.iter() {
+        if written_comments.insert(&song.path) {
+            for comment in playlist.comments.get(&song.path).into_iter().flatten() {
+                result += &format!("\n{comment}");
+            }
+        }
+        result += &format!(
+            "\n#EXTINF:{},{} - {}\n{}",
+            song.duration.unwrap_or(0) / 1000,
+            song.artist,
+            song.name,
+            get_file_path(playlist, &song.path),
+        );
+    }
+    for comment in playlist.comments.get("").into_iter().flatten() {
+        result += &format!("\n{comment}");
+    }
+    result
+}
+
+fn write_pls(playlist: &Playlist) -> String {
+    let mut result = "[playlist]\n".to_string();
+    for (i, song) in playlist.songs.iter().enumerate() {
+        let number = i + 1;
+        result += &format!(
+            "File{number}={}\nTitle{number}={} - {}\nLength{number}={}\n",
+            get_file_path(playlist, &song.path),
+            song.artist,
+            song.name,
+            song.duration.map_or(-1, |x| (x / 1000) as i64),
+        );
+    }
+    result += &format!("NumberOfEntries={}\nVersion=2\n", playlist.songs.len());
+    result
+}
+
+fn write_xspf(playlist: &Playlist) -> String {
+    let mut result = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+    result += "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n";
+    result += &format!("  <title>{}</title>\n", escape_xml(&playlist.name));
+    result += "  <trackList>\n";
+    for song in playlist.songs.iter() {
+        let path = get_file_path(playlist, &song.path).replace('\\', "/");
+        // Relative paths are relative URIs, absolute ones need the file scheme
+        let location = if Path::new(&path).is_absolute() || path.get(1..2) == Some(":") {
+            format!(
+                "file:///{}",
+                util::percent_encode(path.trim_start_matches('/'))
+            )
+        } else {
+            util::percent_encode(&path)
+        };
+        result += "    <track>\n";
+        result += &format!("      <location>{}</location>\n", escape_xml(&location));
+        result += &format!("      <title>{}</title>\n", escape_xml(&song.name));
+        result += &format!("      <creator>{}</creator>\n", escape_xml(&song.artist));
+        if let Some(duration) = song.duration {
+            result += &format!("      <duration>{duration}</duration>\n");
+        }
+        result += "    </track>\n";
+    }
+    result += "  </trackList>\n</playlist>\n";
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(path: &str, duration: Option<u64>) -> Song {
+        let (artist, name) = Song::parse_file_name(path.trim_end_matches(".mp3"));
+        Song {
+            path: path.to_string(),
+            name,
+            artist,
+            duration,
+            exists: true,
+        }
+    }
+
+    fn playlist(format: PlaylistFormat) -> Playlist {
+        let mut playlist = Playlist::new(
+            "Mix".to_string(),
+            vec![
+                song("Rock/Queen - Bohemian Rhapsody.mp3", Some(354_000)),
+                song("AC-DC & Co - <Live>.mp3", None),
+            ],
+        );
+        playlist.format = format;
+        playlist
+    }
+
+    fn entry_fields(entries: &[PlaylistEntry]) -> Vec<(&str, u64)> {
+        entries
+            .iter()
+            .map(|x| (x.path.as_str(), x.duration))
+            .collect()
+    }
+
+    #[test]
+    fn m3u_round_trip() {
+        let playlist = playlist(PlaylistFormat::M3u);
+        let (entries, comments) = parse(&write(&playlist), PlaylistFormat::M3u);
+        assert_eq!(
+            entry_fields(&entries),
+            [
+                ("Rock/Queen - Bohemian Rhapsody.mp3", 354_000),
+                ("AC-DC & Co - <Live>.mp3", 0),
+            ]
+        );
+        assert!(comments.is_empty());
+    }
+
+    #[test]
+    fn m3u_round_trip_keeps_comments_and_file_paths() {
+        let mut playlist = playlist(PlaylistFormat::M3u);
+        let path = playlist.songs[1].path.clone();
+        playlist
+            .comments
+            .insert(path.clone(), vec!["# Side B".to_string()]);
+        playlist
+            .comments
+            .insert(String::new(), vec!["# End".to_string()]);
+        playlist
+            .file_paths
+            .insert(path.clone(), format!("./{path}"));
+
+        let content = write(&playlist);
+        let (entries, comments) = parse(&content, PlaylistFormat::M3u);
+        assert!(entries[0].comments.is_empty());
+        assert_eq!(entries[1].comments, ["# Side B"]);
+        assert_eq!(entries[1].path, format!("./{path}"));
+        assert_eq!(comments, ["# End"]);
+    }
+
+    #[test]
+    fn m3u_writes_comments_once_for_repeated_songs() {
+        let mut playlist = playlist(PlaylistFormat::M3u);
+        let repeated = playlist.songs[0].clone();
+        playlist.songs.push(repeated.clone());
+        playlist
+            .comments
+            .insert(repeated.path, vec!["# Favorite".to_string()]);
+        let content = write(&playlist);
+        assert_eq!(content.matches("# Favorite").count(), 1);
+    }
+
+    #[test]
+    fn m3u_parses_byte_order_mark_and_unknown_durations() {
+        let content =
+            "\u{feff}#EXTM3U\r\n#EXTINF:-1,Unknown\r\na.mp3\r\n\r\n#EXTINF:12.5,X\nb.mp3\n";
+        let (entries, _) = parse(content, PlaylistFormat::M3u);
+        assert_eq!(entry_fields(&entries), [("a.mp3", 0), ("b.mp3", 12_500)]);
+    }
+
+    #[test]
+    fn m3u_without_extended_info() {
+        let (entries, _) = parse("a.mp3\n  b.mp3  \n", PlaylistFormat::M3u);
+        assert_eq!(entry_fields(&entries), [("a.mp3", 0), ("b.mp3", 0)]);
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        let playlist = playlist(PlaylistFormat::Pls);
+        let (entries, _) = parse(&write(&playlist), PlaylistFormat::Pls);
+        assert_eq!(
+            entry_fields(&entries),
+            [
+                ("Rock/Queen - Bohemian Rhapsody.mp3", 354_000),
+                ("AC-DC & Co - <Live>.mp3", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let playlist = playlist(PlaylistFormat::Xspf);
+        let (entries, _) = parse(&write(&playlist), PlaylistFormat::Xspf);
+        assert_eq!(
+            entry_fields(&entries),
+            [
+                ("Rock/Queen - Bohemian Rhapsody.mp3", 354_000),
+                ("AC-DC & Co - <Live>.mp3", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_matches_entries_with_the_songs() {
+        let directory = std::env::temp_dir().join("implayer_load_playlist");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("Mix.m3u");
+        fs::write(
+            &path,
+            "#EXTM3U\n# Side A\n#EXTINF:354,Queen - Bohemian Rhapsody\nRock\\Queen - Bohemian Rhapsody.mp3\n#EXTINF:60,Missing\nMissing.mp3\n",
+        )
+        .unwrap();
+        let mut songs = [song("Rock/Queen - Bohemian Rhapsody.mp3", None)];
+
+        let playlist = load(&directory.to_string_lossy(), &path, &mut songs).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(playlist.name, "Mix");
+        assert_eq!(songs[0].duration, Some(354_000));
+        assert_eq!(playlist.songs[0].path, songs[0].path);
+        assert_eq!(
+            playlist.file_paths[&songs[0].path],
+            "Rock\\Queen - Bohemian Rhapsody.mp3"
+        );
+        assert_eq!(playlist.comments[&songs[0].path], ["# Side A"]);
+        assert_eq!(playlist.songs[1].path, "Missing.mp3");
+        assert!(!playlist.songs[1].exists);
+    }
+
+    #[test]
+    fn format_from_path_of_backups() {
+        assert!(PlaylistFormat::from_path(Path::new("Mix.M3U")) == Some(PlaylistFormat::M3u));
+        assert!(PlaylistFormat::from_path(Path::new("Mix.pls.2.bak")) == Some(PlaylistFormat::Pls));
+        assert!(PlaylistFormat::from_path(Path::new("Mix.txt")).is_none());
+    }
+
+    #[test]
+    fn backups_are_recognized() {
+        assert!(is_backup(Path::new("Mix.pls.2.bak")));
+        assert!(is_backup(Path::new("Rock/Mix.m3u.1.bak")));
+        assert!(!is_backup(Path::new("Mix.m3u")));
+        assert!(!is_backup(Path::new("Mix.bak.m3u")));
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::mpsc::Receiver;
+
+/// Scores how well the characters of the pattern appear in the text in the same order, like
+/// "bhrap" in "Bohemian Rhapsody", higher is better. Consecutive characters and characters at
+/// the start of words raise the score, skipped characters lower it. Returns None if not all
+/// characters appear, otherwise the score and the character positions of the matches.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    // Lowercased by character to keep the positions of the original text
+    let to_lowercase = |text: &str| -> Vec<char> {
+        text.chars()
+            .map(|x| x.to_lowercase().next().unwrap_or(x))
+            .collect()
+    };
+    let pattern = to_lowercase(pattern);
+    let text = to_lowercase(text);
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut pattern_index = 0;
+    let mut previous_match: Option<usize> = None;
+    for (i, c) in text.iter().enumerate() {
+        if pattern_index == pattern.len() {
+            break;
+        }
+        if *c != pattern[pattern_index] {
+            continue;
+        }
+        score += match previous_match {
+            Some(previous) if previous + 1 == i => 15,
+            Some(previous) => -((i - previous - 1).min(10) as i32),
+            None => -(i.min(10) as i32),
+        };
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 10;
+        }
+        previous_match = Some(i);
+        positions.push(i);
+        pattern_index += 1;
+    }
+    (pattern_index == pattern.len()).then_some((score, positions))
+}
+
+pub fn receive_all<T>(receiver: &Receiver<T>) -> Vec<T> {
+    let mut result = Vec::new();
+    while let Ok(value) = receiver.try_recv() {
+        result.push(value);
+    }
+    result
+}
+
+/// Percent-encodes all characters of a path except unreserved ones, slashes and colons.
+pub fn percent_encode(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                result.push(byte as char)
+            }
+            _ => result += &format!("%{byte:02X}"),
+        }
+    }
+    result
+}
+
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = if bytes[i] == b'%' {
+            text.get(i + 1..i + 3)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+        } else {
+            None
+        };
+        match decoded {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// Returns the folders of a playlist. `Rock.Live.Name` is the playlist `Name` in the folder `Live`,
+/// which is inside the folder `Rock`.
+pub fn get_playlist_folders(playlist_name: &str) -> Vec<&str> {
+    match playlist_name.rsplit_once('.') {
+        Some((folders, name)) if !folders.is_empty() && !name.is_empty() => {
+            folders.split('.').collect()
+        }
+        _ => Vec::new(),
+    }
+}
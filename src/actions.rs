@@ -1,263 +1,2400 @@
 use std::{
-    collections::hash_map::DefaultHasher,
-    fs::{self, File},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs::{self, OpenOptions},
     hash::{Hash, Hasher},
-    io::Write,
+    io::{self, Write},
     path::{Path, PathBuf},
-    time::Duration,
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime},
 };
 
+use implayer_core::model::{self, SongIndices};
 use souvlaki::{MediaControlEvent, MediaPlayback};
 
 use crate::{
-    app::{self, Playlist, Song, State},
-    player,
+    app::{self, Playlist, Song, State, StatusType},
+    cli::CliCommand,
+    cover, crash, dlna, download,
+    job::{self, JobMessage, JobSender},
+    notes, play_counts, player,
+    playlist_file::{self, PlaylistFormat},
+    ratings,
+    settings::{self, DroppedFilesAction},
+    tags, util,
 };
 
-pub fn handle_media_keys(state: &mut State) {
-    match state.media_controls_rx.try_recv() {
-        Ok(MediaControlEvent::Toggle) => {
+/// Name of playlists created from the context menu, followed by a number if it is taken
+const NEW_PLAYLIST_NAME: &str = "New playlist";
+
+/// Changes of the playback and the playlists that the user interface, the command line, the
+/// tray, the taskbar, the media keys and the remote control request. The user interface emits
+/// them while drawing and they are applied after the frame, so drawing doesn't change the model
+/// it is iterating over.
+#[derive(Debug)]
+pub enum Action {
+    Play {
+        playlist_index: usize,
+        song_index: usize,
+    },
+    Pause,
+    Resume,
+    /// Pauses or resumes depending on whether a song is playing
+    TogglePlayback,
+    Next,
+    Prev,
+    /// Position in milliseconds
+    Seek(u64),
+    /// Position of the volume slider
+    SetVolume(f32),
+    /// Percentage of the volume slider, which also unmutes
+    SetVolumePercent(f32),
+    ToggleMute,
+    /// Plays the playlist with the name or the selected playlist from the start
+    StartPlaylist {
+        name: Option<String>,
+        shuffle: bool,
+    },
+    OpenSongFile(PathBuf),
+    QueueSelectedSongs,
+    /// Adds the song of the playlist to the queue
+    QueueSong {
+        playlist_index: usize,
+        song_index: usize,
+    },
+    ClearQueue,
+    RemoveSelectedSongs,
+    /// Moves the selected songs of the selected playlist one row down or up
+    MoveSelectedSongs {
+        down: bool,
+    },
+    PasteSongs,
+    AddSelectedSongsTo(usize),
+    ShufflePlaylist(usize),
+    UndoShuffle(usize),
+    SavePlaylist(usize),
+    SaveAllPlaylists,
+    SavePlaylistAs {
+        playlist_index: usize,
+        format: PlaylistFormat,
+    },
+    RenamePlaylist {
+        playlist_index: usize,
+        name: String,
+    },
+    DeletePlaylist {
+        playlist_index: usize,
+        delete_file: bool,
+    },
+    /// Inserts the dropped or added songs into the playlist in front of the index
+    InsertSongs {
+        playlist_index: usize,
+        songs: Vec<Song>,
+        index: usize,
+    },
+    /// Moves the songs at the indices of the playlist in front of the target index
+    MoveSongs {
+        playlist_index: usize,
+        indices: Vec<usize>,
+        target_index: usize,
+    },
+    /// Renames the song files of the preview to their new paths
+    RenameSongs(Vec<(String, Option<String>)>),
+    /// Moves the song files to the trash and removes them from the library
+    DeleteSongFiles(Vec<String>),
+    /// Opens another music directory or reloads the current one, asking about unsaved changes
+    OpenMusicDirectory(String),
+    /// Opens another music directory, discarding unsaved changes
+    SwitchMusicDirectory(String),
+}
+
+/// Applies the action to the state.
+pub fn dispatch(state: &mut State, action: Action) {
+    crash::record_action(format!("{action:?}"));
+    match action {
+        Action::Play {
+            playlist_index,
+            song_index,
+        } => {
+            // The playlist may have changed since the action was emitted
+            let is_valid = state
+                .playlists
+                .get(playlist_index)
+                .is_some_and(|x| song_index < x.songs.len());
+            if is_valid {
+                play(state, playlist_index, song_index);
+            }
+        }
+        Action::Pause => pause(state),
+        Action::Resume => resume(state),
+        Action::TogglePlayback => {
             if state.is_playing {
                 pause(state);
             } else {
                 resume(state);
             }
         }
-        Ok(MediaControlEvent::Play) => resume(state),
-        Ok(MediaControlEvent::Pause) => pause(state),
-        Ok(MediaControlEvent::Next) => next(state),
-        Ok(MediaControlEvent::Previous) => prev(state),
+        Action::Next => next(state),
+        Action::Prev => prev(state),
+        Action::Seek(position) => seek(state, position),
+        Action::SetVolume(volume) => set_volume(state, volume),
+        Action::SetVolumePercent(percent) => set_volume_percent(state, percent),
+        Action::ToggleMute => toggle_mute(state),
+        Action::StartPlaylist { name, shuffle } => start_playlist(state, name.as_deref(), shuffle),
+        Action::OpenSongFile(path) => open_song_file(state, path),
+        Action::QueueSelectedSongs => queue_selected_songs(state),
+        Action::QueueSong {
+            playlist_index,
+            song_index,
+        } => {
+            let song = state
+                .playlists
+                .get(playlist_index)
+                .and_then(|x| x.songs.get(song_index));
+            if let Some(song) = song {
+                let path = song.path.clone();
+                state.queued_songs.push_back((playlist_index, path));
+            }
+        }
+        Action::ClearQueue => state.queued_songs.clear(),
+        Action::RemoveSelectedSongs => remove_selected_songs(state),
+        Action::MoveSelectedSongs { down } => move_selected_songs(state, down),
+        Action::PasteSongs => paste_songs(state),
+        Action::AddSelectedSongsTo(playlist_index) => {
+            if playlist_index < state.playlists.len() {
+                add_selected_songs_to(state, playlist_index);
+            }
+        }
+        Action::ShufflePlaylist(playlist_index) => {
+            if playlist_index < state.playlists.len() {
+                shuffle_playlist(state, playlist_index);
+            }
+        }
+        Action::UndoShuffle(playlist_index) => {
+            if playlist_index < state.playlists.len() && can_undo_shuffle(state, playlist_index) {
+                undo_shuffle(state, playlist_index);
+            }
+        }
+        Action::SavePlaylist(playlist_index) => {
+            let can_save = state
+                .playlists
+                .get(playlist_index)
+                .is_some_and(|x| !util::is_default_playlist(&x.name));
+            if can_save {
+                save_playlist(state, playlist_index);
+            }
+        }
+        Action::SaveAllPlaylists => save_all_playlists(state),
+        Action::SavePlaylistAs {
+            playlist_index,
+            format,
+        } => {
+            let can_save = state
+                .playlists
+                .get(playlist_index)
+                .is_some_and(|x| !util::is_default_playlist(&x.name));
+            if can_save {
+                save_playlist_as(state, playlist_index, format);
+            }
+        }
+        Action::RenamePlaylist {
+            playlist_index,
+            name,
+        } => {
+            if playlist_index < state.playlists.len() {
+                rename_playlist(state, playlist_index, &name);
+            }
+        }
+        Action::DeletePlaylist {
+            playlist_index,
+            delete_file,
+        } => {
+            let can_delete = state
+                .playlists
+                .get(playlist_index)
+                .is_some_and(|x| !util::is_default_playlist(&x.name));
+            if can_delete {
+                delete_playlist(state, playlist_index, delete_file);
+            }
+        }
+        Action::InsertSongs {
+            playlist_index,
+            songs,
+            index,
+        } => {
+            if playlist_index < state.playlists.len() {
+                insert_songs(state, playlist_index, songs, index);
+            }
+        }
+        Action::MoveSongs {
+            playlist_index,
+            indices,
+            target_index,
+        } => {
+            let is_valid = state.playlists.get(playlist_index).is_some_and(|x| {
+                target_index <= x.songs.len() && indices.iter().all(|&i| i < x.songs.len())
+            });
+            if is_valid {
+                move_songs(state, playlist_index, indices, target_index);
+            }
+        }
+        Action::RenameSongs(preview) => rename_songs(state, preview),
+        Action::DeleteSongFiles(paths) => delete_song_files(state, &paths),
+        Action::OpenMusicDirectory(path) => open_music_directory(state, &path),
+        Action::SwitchMusicDirectory(path) => switch_music_directory(state, &path),
+    }
+}
+
+/// Applies the actions that were emitted since the last call in their order.
+pub fn dispatch_pending(state: &mut State) {
+    for action in std::mem::take(&mut state.pending_actions) {
+        dispatch(state, action);
+    }
+}
+
+/// Handles the events of the system media controls. Raising the window and opening files are
+/// returned as commands since they are handled like the commands of other instances.
+pub fn handle_media_keys(state: &mut State) -> Option<CliCommand> {
+    match state.media_controls_rx.try_recv() {
+        Ok(MediaControlEvent::Toggle) => dispatch(state, Action::TogglePlayback),
+        Ok(MediaControlEvent::Play) => dispatch(state, Action::Resume),
+        Ok(MediaControlEvent::Pause) => dispatch(state, Action::Pause),
+        Ok(MediaControlEvent::Next) => dispatch(state, Action::Next),
+        Ok(MediaControlEvent::Previous) => dispatch(state, Action::Prev),
         Ok(MediaControlEvent::Stop) => stop(state),
         Ok(MediaControlEvent::Seek(_)) => (),
         Ok(MediaControlEvent::SeekBy(_, _)) => (),
         Ok(MediaControlEvent::SetPosition(_)) => (),
-        Ok(MediaControlEvent::OpenUri(_)) => (),
-        Ok(MediaControlEvent::Raise) => (),
+        Ok(MediaControlEvent::OpenUri(uri)) => return open_uri(state, uri),
+        Ok(MediaControlEvent::Raise) => return Some(CliCommand::Show),
         Ok(MediaControlEvent::Quit) => (),
         Err(_) => (),
     }
+    None
+}
+
+/// Plays a `file://` URI or downloads other URLs into the selected playlist.
+fn open_uri(state: &mut State, uri: String) -> Option<CliCommand> {
+    if uri.starts_with("file://") {
+        return Some(CliCommand::Open(PathBuf::from(util::url_to_path(&uri))));
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        let playlist_index = state.selected_playlist_index;
+        download::download_url(state, playlist_index, uri);
+    } else {
+        state.push_status(StatusType::Error, format!("Cannot open {uri}"));
+    }
+    None
+}
+
+/// Plays the next song once the player reports that the current one ended.
+pub fn handle_song_end(state: &mut State) {
+    if let Ok(()) = state.song_ended_rx.try_recv() {
+        next(state);
+    }
 }
 
+/// Shows the songs that the player could not play. Playback is paused so that the next song can
+/// be chosen.
+pub fn handle_player_errors(state: &mut State) {
+    let errors = util::receive_all(&state.player_error_rx);
+    if errors.is_empty() {
+        return;
+    }
+    for error in errors {
+        state.push_status(StatusType::Error, error);
+    }
+    pause(state);
+}
+
+/// Shows the playing song in the media controls, its cover is added once it is exported.
 pub fn set_current_metadata(state: &mut State) {
+    let playlist = &state.playlists[state.playing_playlist_index.unwrap()];
+    let current_song = &playlist.songs[state.playing_song_index.unwrap()];
+    crash::set_playing_song(&playlist.name, &current_song.path);
+    state.media_cover_rx = Some(cover::start_exporting_media_cover(
+        Path::new(&state.base_path).join(&current_song.path),
+    ));
+    state.media_cover_url = None;
+    send_current_metadata(state);
+}
+
+fn send_current_metadata(state: &mut State) {
     let current_song = &state.playlists[state.playing_playlist_index.unwrap()].songs
         [state.playing_song_index.unwrap()];
-    state
-        .media_controls
-        .set_metadata(souvlaki::MediaMetadata {
+    if let Some(media_controls) = &mut state.media_controls {
+        let _ = media_controls.set_metadata(souvlaki::MediaMetadata {
             title: Some(&current_song.name),
             album: Some(""),
             artist: Some(&current_song.artist),
-            cover_url: None,
+            cover_url: state.media_cover_url.as_deref(),
             duration: current_song.duration.map(Duration::from_millis),
+        });
+    }
+}
+
+/// Shows whether a song is playing in the media controls.
+fn set_media_playback(state: &mut State, playback: MediaPlayback) {
+    if let Some(media_controls) = &mut state.media_controls {
+        let _ = media_controls.set_playback(playback);
+    }
+}
+
+/// Adds the cover to the media controls once it is exported.
+pub fn handle_media_cover(state: &mut State) {
+    let Some(url) = state
+        .media_cover_rx
+        .as_ref()
+        .and_then(|x| x.try_recv().ok())
+    else {
+        return;
+    };
+    state.media_cover_rx = None;
+    // The playing song may have been removed in the meantime
+    let is_playing_song_valid = state
+        .playing_playlist_index
+        .zip(state.playing_song_index)
+        .is_some_and(|(playlist_index, song_index)| {
+            state
+                .playlists
+                .get(playlist_index)
+                .is_some_and(|x| song_index < x.songs.len())
+        });
+    if url.is_some() && is_playing_song_valid {
+        state.media_cover_url = url;
+        send_current_metadata(state);
+    }
+}
+
+/// Fills the Recently Added playlist with the songs of the All playlist whose files were created
+/// within the configured number of days, newest first. The creation times are read while loading
+/// the library, so that the files are not accessed here.
+pub fn update_recently_added(state: &mut State) {
+    let min_time =
+        SystemTime::now() - Duration::from_secs(state.settings.recently_added_days * 24 * 60 * 60);
+    let all_songs = match state
+        .playlists
+        .iter()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+    {
+        Some(playlist) => &playlist.songs,
+        None => return,
+    };
+    let mut recent_songs: Vec<(SystemTime, &Song)> = all_songs
+        .iter()
+        .filter_map(|song| {
+            let time = *state.song_added_times.get(&song.path)?;
+            (time >= min_time).then_some((time, song))
         })
+        .collect();
+    recent_songs.sort_by_key(|x| std::cmp::Reverse(x.0));
+    let songs: Vec<Song> = recent_songs.into_iter().map(|x| x.1.clone()).collect();
+
+    let playlist_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == app::RECENTLY_ADDED_PLAYLIST_NAME);
+    let playlist_index = match playlist_index {
+        Some(i) => i,
+        None => {
+            state.playlists.push(Playlist::new(
+                app::RECENTLY_ADDED_PLAYLIST_NAME.to_string(),
+                songs,
+            ));
+            state.sort_playlists();
+            return;
+        }
+    };
+
+    // Keep playing the current song if it is still contained
+    if state.playing_playlist_index == Some(playlist_index) {
+        let playing_path =
+            &state.playlists[playlist_index].songs[state.playing_song_index.unwrap()].path;
+        match songs.iter().position(|x| &x.path == playing_path) {
+            Some(i) => state.playing_song_index = Some(i),
+            None => stop(state),
+        }
+    }
+    if state.selected_playlist_index == playlist_index {
+        state.selected_song_indices.clear();
+    }
+    state.playlists[playlist_index].songs = songs;
+    state.mark_playlists_changed();
+}
+
+/// Adds the playing song to the front of the History playlist and appends it to the history file.
+fn add_to_history(state: &mut State) {
+    let song = state.playlists[state.playing_playlist_index.unwrap()].songs
+        [state.playing_song_index.unwrap()]
+    .clone();
+    let history_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == app::HISTORY_PLAYLIST_NAME)
         .unwrap();
+
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let line = format!("{timestamp}\t{}\n", song.path);
+    let path = song.path.clone();
+
+    let mut indices = take_song_indices(state, history_index);
+    let history_songs = &mut state.playlists[history_index].songs;
+    history_songs.insert(0, song);
+    history_songs.truncate(app::HISTORY_LIMIT);
+    indices.increment(1);
+    indices.truncate(history_songs.len());
+    // The oldest entry of a full history was playing, which is the entry that was just added
+    if state.playing_playlist_index == Some(history_index) && indices.playing.is_none() {
+        indices.playing = Some(0);
+    }
+    restore_song_indices(state, history_index, indices);
+    state.mark_playlists_changed();
+    play_counts::add_play(state, &path);
+
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let result = fs::create_dir_all(&data_path).and_then(|_| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(data_path.join(app::HISTORY_FILE_NAME))?
+            .write_all(line.as_bytes())
+    });
+    if let Err(e) = result {
+        state.push_status(StatusType::Error, format!("Could not save history:\n{e}"));
+    }
+}
+
+/// Removes the entries of the songs from the history file.
+fn remove_from_history_file(state: &mut State, paths: &HashSet<&str>) {
+    let history_path = Path::new(&state.base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(app::HISTORY_FILE_NAME);
+    let Ok(history) = fs::read_to_string(&history_path) else {
+        return;
+    };
+    let content: String = history
+        .lines()
+        .filter(|x| !x.split_once('\t').is_some_and(|x| paths.contains(x.1)))
+        .map(|x| format!("{x}\n"))
+        .collect();
+    if content.len() == history.len() {
+        return;
+    }
+    if let Err(e) = fs::write(&history_path, content) {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not update the history:\n{e}"),
+        );
+    }
+}
+
+/// Collapses or expands a playlist folder and remembers it in the data directory.
+pub fn toggle_playlist_folder(state: &mut State, folder_path: &str) {
+    let folder_key = folder_path.to_lowercase();
+    if !state.collapsed_folders.remove(&folder_key) {
+        state.collapsed_folders.insert(folder_key);
+    }
+
+    let mut folders: Vec<&String> = state.collapsed_folders.iter().collect();
+    folders.sort();
+    let content: String = folders.iter().map(|x| format!("{x}\n")).collect();
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let result = fs::create_dir_all(&data_path)
+        .and_then(|_| fs::write(data_path.join(app::COLLAPSED_FOLDERS_FILE_NAME), content));
+    if let Err(e) = result {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not save collapsed folders:\n{e}"),
+        );
+    }
 }
 
 pub fn change_file_name(state: &mut State, artist: &str, name: &str) {
-    let exists = Path::new(&state.base_path)
-        .join(&state.original_file_name)
-        .exists();
-    if exists {
-        fs::rename(
-            &Path::new(&state.base_path).join(&state.original_file_name),
-            &Path::new(&state.base_path).join(&state.file_name_text),
-        )
-        .unwrap();
+    let original_file_name = state.original_file_name.clone();
+    let file_name = state.file_name_text.clone();
+    match rename_song_file(state, &original_file_name, &file_name, artist, name) {
+        Ok(()) => {
+            let renamed_paths = HashMap::from([(original_file_name, file_name)]);
+            update_playlists_after_renames(state, &renamed_paths);
+        }
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not rename \"{original_file_name}\":\n{e}"),
+        ),
+    }
+}
+
+/// Renames the song file (and its lyrics file) and updates the song in all playlists. The song
+/// keeps its new path if only its lyrics file could not be renamed, which is reported separately.
+fn rename_song_file(
+    state: &mut State,
+    old_path: &str,
+    new_path: &str,
+    artist: &str,
+    name: &str,
+) -> io::Result<()> {
+    let old_full_path = Path::new(&state.base_path).join(old_path);
+    let new_full_path = Path::new(&state.base_path).join(new_path);
+    if old_full_path.exists() {
+        if let Some(parent) = new_full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&old_full_path, &new_full_path)?;
+
+        let lyrics_path = old_full_path.with_extension("lrc");
+        if lyrics_path.exists() {
+            if let Err(e) = fs::rename(lyrics_path, new_full_path.with_extension("lrc")) {
+                state.push_status(
+                    StatusType::Error,
+                    format!("Could not rename the lyrics of \"{old_path}\":\n{e}"),
+                );
+            }
+        }
+
+        // Remove directories that were emptied by moving the song, fails for non-empty ones
+        for directory in Path::new(old_path).ancestors().skip(1) {
+            if directory.as_os_str().is_empty()
+                || fs::remove_dir(Path::new(&state.base_path).join(directory)).is_err()
+            {
+                break;
+            }
+        }
     }
 
-    let exists = Path::new(&state.base_path)
-        .join(&state.file_name_text)
-        .exists();
+    let exists = new_full_path.exists();
+    if let Some(tags) = state.tags.remove(old_path) {
+        state.tags.insert(new_path.to_string(), tags);
+    }
+    if let Some(time) = state.song_added_times.remove(old_path) {
+        state.song_added_times.insert(new_path.to_string(), time);
+    }
+    if let Some(fingerprint) = state.fingerprints.remove(old_path) {
+        state.fingerprints.insert(new_path.to_string(), fingerprint);
+    }
+    if let Some(count) = state.play_counts.remove(old_path) {
+        state.play_counts.insert(new_path.to_string(), count);
+    }
+    if let Some(stars) = state.ratings.remove(old_path) {
+        state.ratings.insert(new_path.to_string(), stars);
+    }
+    // The notes, play counts and ratings files are saved by the caller once all files are renamed
+    if let Some(note) = state.notes.remove(old_path) {
+        state.notes.insert(new_path.to_string(), note);
+    }
+    state.mark_song_data_changed();
     for playlist in state.playlists.iter_mut() {
         for song in playlist.songs.iter_mut() {
-            if song.path == state.original_file_name {
-                song.path = state.file_name_text.clone();
+            if song.path == old_path {
+                song.path = new_path.to_string();
                 song.artist = artist.to_string();
                 song.name = name.to_string();
                 song.exists = exists;
             }
         }
     }
+    state.mark_playlists_changed();
+    Ok(())
 }
 
-pub fn increment_indices(state: &mut State, playlist_index: usize, amount: usize) {
-    // Update selected song indices
-    if state.selected_playlist_index == playlist_index && !state.selected_song_indices.is_empty() {
-        for i in state.selected_song_indices.iter_mut() {
-            *i += amount;
-        }
-    }
+/// Replaces characters that are not allowed in file names.
+fn to_file_name_part(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
 
-    // Update playing song index
-    if state.playing_playlist_index == Some(playlist_index) && state.playing_song_index.is_some() {
-        state.playing_song_index = Some(state.playing_song_index.unwrap() + amount);
-    }
+/// Replaces the placeholders of a pattern like `%artist% - %title%.%ext%` using the tags of the
+/// song. Song name and artist of the current file name are used for missing tags.
+fn fill_pattern(state: &State, song: &Song, pattern: &str) -> String {
+    let tags = state.tags.get(&song.path).cloned().unwrap_or_default();
+    let path = Path::new(&song.path);
+    let extension = path
+        .extension()
+        .map_or(String::new(), |x| x.to_string_lossy().to_string());
+    let file_name = path
+        .file_stem()
+        .map_or(String::new(), |x| x.to_string_lossy().to_string());
+
+    pattern
+        .replace(
+            "%artist%",
+            &to_file_name_part(&tags.artist.unwrap_or(song.artist.clone())),
+        )
+        .replace(
+            "%title%",
+            &to_file_name_part(&tags.title.unwrap_or(song.name.clone())),
+        )
+        .replace(
+            "%album%",
+            &to_file_name_part(&tags.album.unwrap_or_default()),
+        )
+        .replace("%genre%", &to_file_name_part(&tags.genres.join(", ")))
+        .replace("%filename%", &file_name)
+        .replace("%ext%", &extension)
 }
-fn play(state: &mut State, playlist_index: usize, song_index: usize) {
-    let song = &state.playlists[playlist_index].songs[song_index];
-    if !song.exists {
-        return;
+
+/// Builds a file name from a pattern, the song stays in its directory.
+fn apply_rename_pattern(state: &State, song: &Song, pattern: &str) -> String {
+    let file_name = fill_pattern(state, song, pattern);
+    match Path::new(&song.path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            parent.join(file_name).to_string_lossy().to_string()
+        }
+        _ => file_name,
     }
-    state
-        .action_tx
-        .send(player::PlayerAction::Play(
-            Path::new(&state.base_path).join(&song.path),
-        ))
-        .unwrap();
-    state.is_playing = true;
-    state.playing_playlist_index = Some(playlist_index);
-    state.playing_song_index = Some(song_index);
-    set_current_metadata(state);
-    state
-        .media_controls
-        .set_playback(MediaPlayback::Playing { progress: None })
-        .unwrap();
 }
 
-pub fn pause(state: &mut State) {
-    state.action_tx.send(player::PlayerAction::Pause).unwrap();
-    state.is_playing = false;
-    state
-        .media_controls
-        .set_playback(MediaPlayback::Paused { progress: None })
-        .unwrap();
+/// Builds a path relative to the base path from a template like
+/// `%artist%/%album%/%artist% - %title%.%ext%`. Empty folders, e.g. for songs without album, are
+/// left out.
+fn apply_organize_template(state: &State, song: &Song, template: &str) -> String {
+    fill_pattern(state, song, template)
+        .split(['/', '\\'])
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && *x != "." && *x != "..")
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
 }
 
-fn stop(state: &mut State) {
-    state.action_tx.send(player::PlayerAction::Stop).unwrap();
-    state.is_playing = false;
-    state.playing_playlist_index = None;
-    state.playing_song_index = None;
-    state
-        .media_controls
-        .set_playback(MediaPlayback::Stopped)
-        .unwrap();
+fn get_selected_songs(state: &State) -> Vec<&Song> {
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let mut selected_song_indices = state.selected_song_indices.clone();
+    selected_song_indices.sort_unstable();
+    selected_song_indices.iter().map(|i| &songs[*i]).collect()
 }
 
-pub fn resume(state: &mut State) {
-    if state.playing_song_index.is_none() {
-        return;
+/// Returns the current and the new path of the songs. The new path is `None` if the file can not
+/// be renamed because the name is invalid or already taken.
+fn get_rename_preview(
+    state: &State,
+    songs: Vec<&Song>,
+    get_new_path: impl Fn(&Song) -> String,
+) -> Vec<(String, Option<String>)> {
+    // The same song can be contained multiple times in a playlist
+    let mut seen_paths = HashSet::new();
+    let new_paths: Vec<(String, String)> = songs
+        .iter()
+        .filter(|song| seen_paths.insert(song.path.as_str()))
+        .map(|song| (song.path.clone(), get_new_path(song)))
+        .collect();
+
+    let mut new_path_counts = HashMap::new();
+    for (_, new_path) in new_paths.iter() {
+        *new_path_counts.entry(new_path.as_str()).or_insert(0) += 1;
     }
-    state.action_tx.send(player::PlayerAction::Resume).unwrap();
-    state.is_playing = true;
-    state
-        .media_controls
-        .set_playback(MediaPlayback::Playing { progress: None })
-        .unwrap();
+
+    new_paths
+        .iter()
+        .map(|(path, new_path)| {
+            let is_taken = new_path != path
+                && (new_path_counts[new_path.as_str()] > 1
+                    || Path::new(&state.base_path).join(new_path).exists());
+            let is_valid = Path::new(new_path)
+                .file_stem()
+                .is_some_and(|x| !x.is_empty() && !x.to_string_lossy().starts_with('.'));
+            (
+                path.clone(),
+                if is_taken || !is_valid {
+                    None
+                } else {
+                    Some(new_path.clone())
+                },
+            )
+        })
+        .collect()
 }
 
-pub fn prev(state: &mut State) {
-    if state.playing_playlist_index.is_none() || state.playing_song_index.is_none() {
-        return;
+pub fn get_batch_rename_preview(state: &State, pattern: &str) -> Vec<(String, Option<String>)> {
+    get_rename_preview(state, get_selected_songs(state), |song| {
+        apply_rename_pattern(state, song, pattern)
+    })
+}
+
+/// Returns the current and new paths of all songs of the playlist that would be moved by
+/// organizing them into folders with the template of the textbox. The preview is computed again
+/// only when the template or the songs change.
+pub fn get_organize_preview(
+    state: &mut State,
+    playlist_index: usize,
+) -> Vec<(String, Option<String>)> {
+    let mut hasher = DefaultHasher::new();
+    playlist_index.hash(&mut hasher);
+    state.organize_template_text.hash(&mut hasher);
+    state.playlists_version.hash(&mut hasher);
+    state.song_data_version.hash(&mut hasher);
+    let key = hasher.finish();
+    match &state.organize_preview {
+        Some((preview_key, preview)) if *preview_key == key => preview.clone(),
+        _ => {
+            let preview =
+                compute_organize_preview(state, playlist_index, &state.organize_template_text);
+            state.organize_preview = Some((key, preview.clone()));
+            preview
+        }
     }
-    let mut prev_song_index = None;
-    let mut prev_song = None;
-    let playlist = &state.playlists[state.playing_playlist_index.unwrap()];
-    for (i, song) in playlist
+}
+
+fn compute_organize_preview(
+    state: &State,
+    playlist_index: usize,
+    template: &str,
+) -> Vec<(String, Option<String>)> {
+    let mut songs: Vec<&Song> = state.playlists[playlist_index]
         .songs
         .iter()
-        .rev()
-        .enumerate()
-        .skip(playlist.songs.len() - state.playing_song_index.unwrap())
-    {
-        if song.exists {
-            prev_song_index = Some(playlist.songs.len() - i - 1);
-            prev_song = Some(song);
-            break;
+        .filter(|x| x.exists)
+        .collect();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut preview = get_rename_preview(state, songs, |song| {
+        apply_organize_template(state, song, template)
+    });
+    preview.retain(|(path, new_path)| new_path.as_ref() != Some(path));
+    preview
+}
+
+pub fn get_sanitize_preview(state: &State) -> Vec<(String, Option<String>)> {
+    get_rename_preview(state, get_selected_songs(state), |song| {
+        let path = Path::new(&song.path);
+        let file_stem = path
+            .file_stem()
+            .map_or(String::new(), |x| x.to_string_lossy().to_string());
+        let file_name = match path.extension() {
+            Some(extension) => format!(
+                "{}.{}",
+                util::sanitize_file_name(&file_stem),
+                extension.to_string_lossy()
+            ),
+            None => util::sanitize_file_name(&file_stem),
+        };
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                parent.join(file_name).to_string_lossy().to_string()
+            }
+            _ => file_name,
         }
-    }
+    })
+}
 
-    if prev_song.is_none() {
-        stop(state);
-        return;
+/// Renames all songs of a preview that can be renamed.
+pub fn rename_songs(state: &mut State, preview: Vec<(String, Option<String>)>) {
+    let mut renamed_paths = HashMap::new();
+    for (path, new_path) in preview {
+        let new_path = match new_path {
+            Some(new_path) if new_path != path => new_path,
+            _ => continue,
+        };
+        let file_stem = Path::new(&new_path)
+            .file_stem()
+            .map_or(String::new(), |x| x.to_string_lossy().to_string());
+        let (artist, name) = Song::parse_file_name(&file_stem);
+        match rename_song_file(state, &path, &new_path, &artist, &name) {
+            Ok(()) => {
+                renamed_paths.insert(path, new_path);
+            }
+            Err(e) => state.push_status(
+                StatusType::Error,
+                format!("Could not rename \"{path}\":\n{e}"),
+            ),
+        }
     }
+    update_playlists_after_renames(state, &renamed_paths);
+    state.push_status(
+        StatusType::Info,
+        format!("Renamed {} files", renamed_paths.len()),
+    );
+}
 
-    state
-        .action_tx
-        .send(player::PlayerAction::Play(
-            Path::new(&state.base_path).join(&prev_song.unwrap().path),
-        ))
-        .unwrap();
-    state.is_playing = true;
-    state.playing_song_index = prev_song_index;
-    set_current_metadata(state);
+/// Saves the loaded playlists and rewrites the archived playlists that contain renamed songs, so
+/// that no playlist file refers to the old paths. The notes are saved if they moved with a song.
+fn update_playlists_after_renames(state: &mut State, renamed_paths: &HashMap<String, String>) {
+    if renamed_paths.values().any(|x| state.notes.contains_key(x)) {
+        notes::save(state);
+    }
+    if renamed_paths
+        .values()
+        .any(|x| state.play_counts.contains_key(x))
+    {
+        play_counts::save(state);
+    }
+    if renamed_paths
+        .values()
+        .any(|x| state.ratings.contains_key(x))
+    {
+        ratings::save(state);
+    }
+    let new_paths: HashSet<&String> = renamed_paths.values().collect();
+    let playlist_indices: Vec<usize> = (0..state.playlists.len())
+        .filter(|i| {
+            let playlist = &state.playlists[*i];
+            !util::is_default_playlist(&playlist.name)
+                && playlist.songs.iter().any(|x| new_paths.contains(&x.path))
+        })
+        .collect();
+    for playlist_index in playlist_indices {
+        save_playlist(state, playlist_index);
+    }
+    update_archived_playlists(state, renamed_paths);
 }
 
-pub fn next(state: &mut State) {
-    if state.playing_playlist_index.is_none() || state.playing_song_index.is_none() {
+/// Rewrites renamed song paths in the files of archived playlists, which are not loaded.
+fn update_archived_playlists(state: &mut State, renamed_paths: &HashMap<String, String>) {
+    if renamed_paths.is_empty() {
         return;
     }
-    let mut next_song_index = None;
-    let mut next_song = None;
-    let playlist = &state.playlists[state.playing_playlist_index.unwrap()];
-    for (i, song) in playlist
-        .songs
-        .iter()
-        .enumerate()
-        .skip(state.playing_song_index.unwrap() + 1)
+    let archive_path = Path::new(&state.base_path).join(app::ARCHIVE_DIRECTORY);
+    for name in state.archived_playlists.clone() {
+        let path = match find_playlist_file(&archive_path, &name) {
+            Some(path) => path,
+            None => continue,
+        };
+        let mut playlist = match playlist_file::load(&state.base_path, &path, &mut []) {
+            Ok(playlist) => playlist,
+            Err(e) => {
+                state.push_status(StatusType::Error, e);
+                continue;
+            }
+        };
+        if !playlist
+            .songs
+            .iter()
+            .any(|x| renamed_paths.contains_key(&x.path))
+        {
+            continue;
+        }
+        for song in playlist.songs.iter_mut() {
+            if let Some(new_path) = renamed_paths.get(&song.path) {
+                playlist.file_paths.remove(&song.path);
+                song.path = new_path.clone();
+            }
+        }
+        if let Err(e) = fs::write(&path, playlist_file::write(&playlist)) {
+            state.push_status(
+                StatusType::Error,
+                format!("Could not update archived playlist \"{name}\":\n{e}"),
+            );
+        }
+    }
+}
+
+/// Moves the song files (and their lyrics files) to the trash and removes the songs from all
+/// playlists, the history file, the play counts and the ratings.
+pub fn delete_song_files(state: &mut State, paths: &[String]) {
+    if let (Some(playlist_index), Some(song_index)) =
+        (state.playing_playlist_index, state.playing_song_index)
     {
-        if song.exists {
-            next_song_index = Some(i);
-            next_song = Some(song);
-            break;
+        if paths.contains(&state.playlists[playlist_index].songs[song_index].path) {
+            stop(state);
         }
     }
 
-    if next_song.is_none() {
-        stop(state);
-        return;
+    let mut deleted_paths = Vec::new();
+    for path in paths {
+        let full_path = Path::new(&state.base_path).join(path);
+        if full_path.exists() {
+            if let Err(e) = trash::delete(&full_path) {
+                state.push_status(
+                    StatusType::Error,
+                    format!("Could not move \"{path}\" to trash:\n{e}"),
+                );
+                continue;
+            }
+            let lyrics_path = full_path.with_extension("lrc");
+            if lyrics_path.exists() {
+                let _ = trash::delete(lyrics_path);
+            }
+        }
+        deleted_paths.push(path.clone());
     }
+    let deleted_paths: HashSet<&str> = deleted_paths.iter().map(|x| x.as_str()).collect();
 
-    state
-        .action_tx
-        .send(player::PlayerAction::Play(
-            Path::new(&state.base_path).join(&next_song.unwrap().path),
-        ))
-        .unwrap();
-    state.is_playing = true;
-    state.playing_song_index = next_song_index;
-    set_current_metadata(state);
+    for playlist_index in 0..state.playlists.len() {
+        for song_index in (0..state.playlists[playlist_index].songs.len()).rev() {
+            let path = state.playlists[playlist_index].songs[song_index]
+                .path
+                .as_str();
+            if !deleted_paths.contains(path) {
+                continue;
+            }
+            state.playlists[playlist_index].songs.remove(song_index);
+            if state.playing_playlist_index == Some(playlist_index)
+                && state.playing_song_index > Some(song_index)
+            {
+                state.playing_song_index = Some(state.playing_song_index.unwrap() - 1);
+            }
+        }
+    }
+    state.mark_playlists_changed();
+    let note_count = state.notes.len();
+    let counted_song_count = state.play_counts.len();
+    let rated_song_count = state.ratings.len();
+    for path in deleted_paths.iter().copied() {
+        state.tags.remove(path);
+        state.song_added_times.remove(path);
+        state.fingerprints.remove(path);
+        state.notes.remove(path);
+        state.play_counts.remove(path);
+        state.ratings.remove(path);
+    }
+    if state.notes.len() != note_count {
+        notes::save(state);
+    }
+    if state.play_counts.len() != counted_song_count {
+        play_counts::save(state);
+    }
+    if state.ratings.len() != rated_song_count {
+        ratings::save(state);
+    }
+    remove_from_history_file(state, &deleted_paths);
+    state.mark_song_data_changed();
+    for group in state.duplicate_groups.iter_mut() {
+        group.retain(|x| !deleted_paths.contains(x.path.as_str()));
+    }
+    state.duplicate_groups.retain(|x| x.len() > 1);
+    state.selected_song_indices.clear();
+
+    state.push_status(
+        StatusType::Info,
+        format!("Moved {} files to trash", deleted_paths.len()),
+    );
 }
 
-pub fn save_playlist(base_path: &str, playlist: &mut Playlist) {
-    let mut file =
-        File::create(Path::new(base_path).join(format!("{}.m3u", &playlist.name))).unwrap();
-    write!(file, "#EXTM3U").unwrap();
-    for song in playlist.songs.iter() {
-        write!(
-            file,
-            "\n#EXTINF:{},{} - {}\n{}",
-            song.duration.unwrap_or(0) / 1000,
-            song.artist,
-            song.name,
-            song.path,
-        )
-        .unwrap();
+/// Returns the selected and the playing songs if they are in the playlist.
+fn take_song_indices(state: &mut State, playlist_index: usize) -> SongIndices {
+    SongIndices {
+        selected: if state.selected_playlist_index == playlist_index {
+            std::mem::take(&mut state.selected_song_indices)
+        } else {
+            Vec::new()
+        },
+        playing: state
+            .playing_song_index
+            .filter(|_| state.playing_playlist_index == Some(playlist_index)),
     }
-    file.flush().unwrap();
+}
 
-    let mut hasher = DefaultHasher::new();
-    for song in playlist.songs.iter() {
-        song.hash(&mut hasher);
+fn restore_song_indices(state: &mut State, playlist_index: usize, indices: SongIndices) {
+    if state.selected_playlist_index == playlist_index {
+        state.selected_song_indices = indices.selected;
+    }
+    if state.playing_playlist_index == Some(playlist_index) {
+        state.playing_song_index = indices.playing;
     }
-    playlist.original_hash = hasher.finish();
 }
 
-pub fn add_song(state: &mut State, path: &str, playlist_index: usize) {
-    let path = PathBuf::from(path);
-    let duration = Some(player::get_duration(&path));
-    let song = Song::new(path, &state.base_path, duration);
-
-    state.playlists[playlist_index]
+pub fn increment_indices(state: &mut State, playlist_index: usize, amount: usize) {
+    let mut indices = take_song_indices(state, playlist_index);
+    indices.increment(amount);
+    restore_song_indices(state, playlist_index, indices);
+}
+
+/// Inserts the selected songs at the start of the playlist.
+pub fn add_selected_songs_to(state: &mut State, playlist_index: usize) {
+    let songs: Vec<Song> = get_selected_songs(state).into_iter().cloned().collect();
+    let count = songs.len();
+    state.playlists[playlist_index].songs.splice(0..0, songs);
+    increment_indices(state, playlist_index, count);
+    state.mark_playlists_changed();
+}
+
+/// Removes the selected songs from the selected playlist and clears the selection. The playing
+/// song index is only changed if the selected playlist is the playing one: it keeps pointing to
+/// the playing song, or to the song in front of a removed playing song so that playback continues
+/// after it. A removed first song leaves the index at 0.
+pub fn remove_selected_songs(state: &mut State) {
+    let playlist_index = state.selected_playlist_index;
+    let mut indices = take_song_indices(state, playlist_index);
+    indices.remove_selected(&mut state.playlists[playlist_index].songs);
+    restore_song_indices(state, playlist_index, indices);
+    state.mark_playlists_changed();
+}
+
+/// Swaps each selected song with its neighbor below or above, unless it is blocked by the end of
+/// the playlist or by another selected song that couldn't move.
+pub fn move_selected_songs(state: &mut State, down: bool) {
+    let playlist_index = state.selected_playlist_index;
+    if util::is_default_playlist(&state.playlists[playlist_index].name) {
+        return;
+    }
+    let mut indices = take_song_indices(state, playlist_index);
+    let songs = &mut state.playlists[playlist_index].songs;
+    indices.selected.retain(|x| *x < songs.len());
+    indices.selected.sort_unstable();
+    if down {
+        indices.selected.reverse();
+    }
+    let mut last_index: Option<usize> = None;
+    for selected_song_index in indices.selected.iter_mut() {
+        let target = if down {
+            Some(*selected_song_index + 1).filter(|x| *x < songs.len())
+        } else {
+            selected_song_index.checked_sub(1)
+        };
+        if let Some(target) = target.filter(|x| last_index != Some(*x)) {
+            songs.swap(*selected_song_index, target);
+            if indices.playing == Some(*selected_song_index) {
+                indices.playing = Some(target);
+            } else if indices.playing == Some(target) {
+                indices.playing = Some(*selected_song_index);
+            }
+            *selected_song_index = target;
+        }
+        last_index = Some(*selected_song_index);
+    }
+    indices.selected.sort_unstable();
+    restore_song_indices(state, playlist_index, indices);
+    state.mark_playlists_changed();
+}
+
+/// Remembers the selected songs for pasting.
+pub fn copy_selected_songs(state: &mut State) {
+    if !state.selected_song_indices.is_empty() {
+        state.copied_songs = get_selected_songs(state).into_iter().cloned().collect();
+    }
+}
+
+/// Inserts the copied songs after the last selected song, or at the position where dropped songs
+/// are added if nothing is selected. The pasted songs become the selection.
+pub fn paste_songs(state: &mut State) {
+    let playlist_index = state.selected_playlist_index;
+    if state.copied_songs.is_empty()
+        || util::is_default_playlist(&state.playlists[playlist_index].name)
+    {
+        return;
+    }
+    let index = match state.selected_song_indices.iter().max() {
+        Some(i) => i + 1,
+        None if state.settings.drop_at_end => state.playlists[playlist_index].songs.len(),
+        None => 0,
+    };
+    let songs = state.copied_songs.clone();
+    let count = songs.len();
+    insert_songs(state, playlist_index, songs, index);
+    state.selected_song_indices = (index..index + count).collect();
+}
+
+/// Moves the songs at the given indices in front of the song at the target index, keeping their
+/// order. The moved songs become the selection.
+pub fn move_songs(
+    state: &mut State,
+    playlist_index: usize,
+    mut indices: Vec<usize>,
+    target_index: usize,
+) {
+    indices.sort_unstable();
+    indices.dedup();
+    let songs = &mut state.playlists[playlist_index].songs;
+    indices.retain(|x| *x < songs.len());
+    let target_index = target_index.min(songs.len());
+
+    let mut moved_songs = Vec::new();
+    for i in indices.iter().rev() {
+        moved_songs.insert(0, songs.remove(*i));
+    }
+    let insert_index = target_index - indices.iter().filter(|x| **x < target_index).count();
+    let count = moved_songs.len();
+    songs.splice(insert_index..insert_index, moved_songs);
+
+    // Update playing song index
+    if state.playing_playlist_index == Some(playlist_index) {
+        if let Some(playing_index) = state.playing_song_index {
+            state.playing_song_index =
+                Some(match indices.iter().position(|x| *x == playing_index) {
+                    Some(position) => insert_index + position,
+                    None => {
+                        let index =
+                            playing_index - indices.iter().filter(|x| **x < playing_index).count();
+                        if index >= insert_index {
+                            index + count
+                        } else {
+                            index
+                        }
+                    }
+                });
+        }
+    }
+
+    // Update selected song indices
+    if state.selected_playlist_index == playlist_index {
+        state.selected_song_indices = (insert_index..insert_index + count).collect();
+    }
+    state.mark_playlists_changed();
+}
+
+/// Inserts the songs into the playlist at the given index.
+pub fn insert_songs(state: &mut State, playlist_index: usize, songs: Vec<Song>, index: usize) {
+    let playlist_songs = &mut state.playlists[playlist_index].songs;
+    let index = index.min(playlist_songs.len());
+    let count = songs.len();
+    playlist_songs.splice(index..index, songs);
+
+    // Update selected song indices
+    if state.selected_playlist_index == playlist_index {
+        for i in state
+            .selected_song_indices
+            .iter_mut()
+            .filter(|x| **x >= index)
+        {
+            *i += count;
+        }
+    }
+
+    // Update playing song index
+    if state.playing_playlist_index == Some(playlist_index) {
+        if let Some(playing_index) = state.playing_song_index.filter(|x| *x >= index) {
+            state.playing_song_index = Some(playing_index + count);
+        }
+    }
+    state.mark_playlists_changed();
+}
+
+/// Reorders the songs of the playlist according to the previous index of each song.
+fn reorder_songs(state: &mut State, playlist_index: usize, order: &[usize]) {
+    let songs = &mut state.playlists[playlist_index].songs;
+    *songs = order.iter().map(|i| songs[*i].clone()).collect();
+
+    if state.playing_playlist_index == Some(playlist_index) {
+        state.playing_song_index = state
+            .playing_song_index
+            .and_then(|playing_index| order.iter().position(|x| *x == playing_index));
+    }
+    if state.selected_playlist_index == playlist_index {
+        state.selected_song_indices.clear();
+    }
+    state.mark_playlists_changed();
+}
+
+/// Randomizes the order of the songs in the playlist. The previous order can be restored with
+/// `undo_shuffle` until the playlist is changed.
+pub fn shuffle_playlist(state: &mut State, playlist_index: usize) {
+    let mut order: Vec<usize> = (0..state.playlists[playlist_index].songs.len()).collect();
+    util::shuffle(&mut order);
+    reorder_songs(state, playlist_index, &order);
+
+    let playlist = &state.playlists[playlist_index];
+    state.shuffle_undo = Some((
+        playlist.name.clone(),
+        order,
+        model::hash_songs(&playlist.songs),
+    ));
+}
+
+pub fn can_undo_shuffle(state: &State, playlist_index: usize) -> bool {
+    let playlist = &state.playlists[playlist_index];
+    state.shuffle_undo.as_ref().is_some_and(|(name, _, hash)| {
+        *name == playlist.name && *hash == model::hash_songs(&playlist.songs)
+    })
+}
+
+pub fn undo_shuffle(state: &mut State, playlist_index: usize) {
+    if !can_undo_shuffle(state, playlist_index) {
+        return;
+    }
+    let (_, order, _) = state.shuffle_undo.take().unwrap();
+    // Invert the order to move each song back to its previous index
+    let mut inverse_order = vec![0; order.len()];
+    for (i, previous_index) in order.iter().enumerate() {
+        inverse_order[*previous_index] = i;
+    }
+    reorder_songs(state, playlist_index, &inverse_order);
+}
+
+/// Sends the action to the renderer that songs are played on or otherwise to the local player.
+fn send_player_action(state: &mut State, action: player::PlayerAction) {
+    match &state.renderer {
+        Some(renderer) => renderer.send(action),
+        None => {
+            if state.action_tx.send(action).is_err() {
+                state.push_status(
+                    StatusType::Error,
+                    "The player stopped, restart to play songs again".to_string(),
+                );
+            }
+        }
+    }
+}
+
+/// Plays songs on the renderer or on this computer if it is None. The playing song continues at
+/// the same position.
+pub fn set_renderer(state: &mut State, device: Option<dlna::Device>) {
+    let position = *state.position.lock().unwrap();
+    send_player_action(state, player::PlayerAction::Stop);
+    state.renderer = None;
+    if let Some(device) = device {
+        let name = device.name.clone();
+        match dlna::Renderer::connect(device, state.song_ended_tx.clone(), state.position.clone()) {
+            Ok(renderer) => state.renderer = Some(renderer),
+            Err(e) => {
+                state.push_status(StatusType::Error, format!("Could not play on {name}:\n{e}"))
+            }
+        }
+    }
+    set_volume(state, state.volume);
+
+    let Some((playlist_index, song_index)) =
+        state.playing_playlist_index.zip(state.playing_song_index)
+    else {
+        return;
+    };
+    let path =
+        Path::new(&state.base_path).join(&state.playlists[playlist_index].songs[song_index].path);
+    send_player_action(state, player::PlayerAction::Play(path));
+    if position > 0 {
+        send_player_action(state, player::PlayerAction::Seek(position));
+    }
+    if !state.is_playing {
+        send_player_action(state, player::PlayerAction::Pause);
+    }
+}
+
+/// Ends the background work before the player exits. Threads that write into files are waited for
+/// while threads that only read are left to the end of the process.
+pub fn shutdown(state: &mut State) {
+    if let Some(renderer) = state.renderer.take() {
+        renderer.close();
+    }
+    let _ = state.action_tx.send(player::PlayerAction::Quit);
+    if let Some(player_thread) = state.player_thread.take() {
+        let _ = player_thread.join();
+    }
+    download::stop_all(state);
+    // The threads stop after the current file once they can't send their progress anymore
+    state.sync_rx = None;
+    state.replaygain_job.stop();
+    state.postprocessing_job.stop();
+    state.dropped_files_job.stop();
+    for thread in state.writer_threads.drain(..) {
+        let _ = thread.join();
+    }
+}
+
+/// Shows the errors of the renderer and collects the renderers that are found by a search.
+pub fn handle_renderer(state: &mut State) {
+    if let Some(renderer) = &state.renderer {
+        for error in renderer.receive_errors() {
+            state.push_status(StatusType::Error, error);
+        }
+    }
+    let Some(renderers_rx) = &state.renderers_rx else {
+        return;
+    };
+    loop {
+        match renderers_rx.try_recv() {
+            Ok(device) => {
+                if !state.renderers.contains(&device) {
+                    state.renderers.push(device);
+                }
+            }
+            Err(mpsc::TryRecvError::Empty) => break,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                state.renderers_rx = None;
+                break;
+            }
+        }
+    }
+}
+
+pub fn play(state: &mut State, playlist_index: usize, song_index: usize) {
+    let song = &state.playlists[playlist_index].songs[song_index];
+    if !song.exists {
+        return;
+    }
+    let path = Path::new(&state.base_path).join(&song.path);
+    send_player_action(state, player::PlayerAction::Play(path));
+    state.is_playing = true;
+    state.playing_playlist_index = Some(playlist_index);
+    state.playing_song_index = Some(song_index);
+    set_current_metadata(state);
+    add_to_history(state);
+    set_media_playback(state, MediaPlayback::Playing { progress: None });
+}
+
+/// Moves the volume slider to the position and sets the volume of the player, which grows
+/// steeply with the position.
+pub fn set_volume(state: &mut State, volume: f32) {
+    state.volume = volume;
+    let value = if volume == app::MIN_VOLUME {
+        0.0
+    } else {
+        volume.powi(4)
+    };
+    send_player_action(state, player::PlayerAction::SetVolume(value));
+}
+
+/// Mutes the player or restores the volume from before muting.
+pub fn toggle_mute(state: &mut State) {
+    match state.volume_before_mute.take() {
+        Some(volume) => set_volume(state, volume),
+        // Muted with the slider, so there is no level to restore
+        None if state.volume == app::MIN_VOLUME => set_volume(state, app::DEFAULT_VOLUME),
+        None => {
+            state.volume_before_mute = Some(state.volume);
+            set_volume(state, app::MIN_VOLUME);
+        }
+    }
+}
+
+/// Moves the volume slider to the percentage of its range and unmutes.
+pub fn set_volume_percent(state: &mut State, percent: f32) {
+    let volume =
+        app::MIN_VOLUME + (app::MAX_VOLUME - app::MIN_VOLUME) * percent.clamp(0.0, 100.0) / 100.0;
+    state.volume_before_mute = None;
+    set_volume(state, volume);
+}
+
+pub fn pause(state: &mut State) {
+    send_player_action(state, player::PlayerAction::Pause);
+    state.is_playing = false;
+    set_media_playback(state, MediaPlayback::Paused { progress: None });
+}
+
+fn stop(state: &mut State) {
+    send_player_action(state, player::PlayerAction::Stop);
+    state.is_playing = false;
+    state.playing_playlist_index = None;
+    state.playing_song_index = None;
+    set_media_playback(state, MediaPlayback::Stopped);
+}
+
+pub fn resume(state: &mut State) {
+    if state.playing_song_index.is_none() {
+        return;
+    }
+    send_player_action(state, player::PlayerAction::Resume);
+    state.is_playing = true;
+    set_media_playback(state, MediaPlayback::Playing { progress: None });
+}
+
+pub fn seek(state: &mut State, position: u64) {
+    send_player_action(state, player::PlayerAction::Seek(position));
+    *state.position.lock().unwrap() = position;
+}
+
+pub fn prev(state: &mut State) {
+    if state.playing_playlist_index.is_none() || state.playing_song_index.is_none() {
+        return;
+    }
+    let mut prev_song_index = None;
+    let mut prev_song = None;
+    let playlist = &state.playlists[state.playing_playlist_index.unwrap()];
+    for (i, song) in playlist
         .songs
-        .insert(0, song.clone());
+        .iter()
+        .rev()
+        .enumerate()
+        .skip(playlist.songs.len() - state.playing_song_index.unwrap())
+    {
+        if song.exists {
+            prev_song_index = Some(playlist.songs.len() - i - 1);
+            prev_song = Some(song);
+            break;
+        }
+    }
+
+    if prev_song.is_none() {
+        stop(state);
+        return;
+    }
+
+    let path = Path::new(&state.base_path).join(&prev_song.unwrap().path);
+    send_player_action(state, player::PlayerAction::Play(path));
+    state.is_playing = true;
+    state.playing_song_index = prev_song_index;
+    set_current_metadata(state);
+    add_to_history(state);
+}
+
+/// Adds the selected songs to the queue in the order of the playlist.
+pub fn queue_selected_songs(state: &mut State) {
+    let playlist_index = state.selected_playlist_index;
+    let mut indices = state.selected_song_indices.clone();
+    indices.sort_unstable();
+    let songs = &state.playlists[playlist_index].songs;
+    state.queued_songs.extend(
+        indices
+            .iter()
+            .map(|x| (playlist_index, songs[*x].path.clone())),
+    );
+    state.push_status(StatusType::Info, format!("Queued {} songs", indices.len()));
+}
+
+/// Plays the first queued song that still exists. Returns false if the queue is empty.
+fn play_queued_song(state: &mut State) -> bool {
+    while let Some((playlist_index, path)) = state.queued_songs.pop_front() {
+        let song_index = state.playlists.get(playlist_index).and_then(|playlist| {
+            playlist
+                .songs
+                .iter()
+                .position(|x| x.path == path && x.exists)
+        });
+        if let Some(song_index) = song_index {
+            play(state, playlist_index, song_index);
+            return true;
+        }
+    }
+    false
+}
+
+/// Plays the next queued song or otherwise the next song of the playing playlist.
+pub fn next(state: &mut State) {
+    if play_queued_song(state) {
+        return;
+    }
+    if state.playing_playlist_index.is_none() || state.playing_song_index.is_none() {
+        return;
+    }
+    let mut next_song_index = None;
+    let mut next_song = None;
+    let playlist = &state.playlists[state.playing_playlist_index.unwrap()];
+    for (i, song) in playlist
+        .songs
+        .iter()
+        .enumerate()
+        .skip(state.playing_song_index.unwrap() + 1)
+    {
+        if song.exists {
+            next_song_index = Some(i);
+            next_song = Some(song);
+            break;
+        }
+    }
+
+    if next_song.is_none() {
+        stop(state);
+        return;
+    }
+
+    let path = Path::new(&state.base_path).join(&next_song.unwrap().path);
+    send_player_action(state, player::PlayerAction::Play(path));
+    state.is_playing = true;
+    state.playing_song_index = next_song_index;
+    set_current_metadata(state);
+    add_to_history(state);
+}
+
+/// Returns the file of the playlist in the directory, regardless of its format.
+pub fn find_playlist_file(directory: &Path, playlist_name: &str) -> Option<PathBuf> {
+    PlaylistFormat::ALL
+        .iter()
+        .map(|x| directory.join(format!("{playlist_name}.{}", x.extension())))
+        .find(|x| x.exists())
+}
+
+/// Writes the playlist file, replacing the files of the playlist in other formats. The previous
+/// file is kept as the newest backup if `create_backup` is set.
+fn write_playlist_file(
+    base_path: &str,
+    playlist: &mut Playlist,
+    create_backup: bool,
+) -> io::Result<()> {
+    if create_backup {
+        if let Some(path) = find_playlist_file(Path::new(base_path), &playlist.name) {
+            rotate_playlist_backups(base_path, &playlist.name, &path)?;
+        }
+    }
+
+    // Files in other formats are replaced when the format is changed
+    for format in PlaylistFormat::ALL {
+        let path = Path::new(base_path).join(format!("{}.{}", playlist.name, format.extension()));
+        if format != playlist.format && path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    let path =
+        Path::new(base_path).join(format!("{}.{}", playlist.name, playlist.format.extension()));
+    write_playlist(&path, playlist)
+}
+
+/// Shows why the playlist could not be saved. Returns whether it was saved.
+fn report_save_result(state: &mut State, playlist_index: usize, result: io::Result<()>) -> bool {
+    match result {
+        Ok(()) => {
+            state.mark_playlists_changed();
+            true
+        }
+        Err(e) => {
+            let name = &state.playlists[playlist_index].name;
+            let info = format!("Could not save \"{name}\":\n{e}");
+            state.push_status(StatusType::Error, info);
+            false
+        }
+    }
+}
+
+/// Saves the playlist and keeps the previous version as a backup. Returns whether it was saved.
+pub fn save_playlist(state: &mut State, playlist_index: usize) -> bool {
+    let result = write_playlist_file(&state.base_path, &mut state.playlists[playlist_index], true);
+    state.playlist_backup_preview = None;
+    report_save_result(state, playlist_index, result)
+}
+
+pub fn save_all_playlists(state: &mut State) {
+    let mut count = 0;
+    for i in 0..state.playlists.len() {
+        if state.playlists[i].has_changes() && save_playlist(state, i) {
+            count += 1;
+        }
+    }
+    state.push_status(StatusType::Info, format!("Saved {count} playlists"));
+}
+
+/// Saves the changed playlists once they have not been changed for a few seconds, if enabled.
+/// Automatic saves don't create backups, so the backups keep the versions that were saved by the
+/// user. Playlists that could not be saved are tried again after they were changed.
+pub fn update_auto_save(state: &mut State) {
+    if !state.settings.auto_save {
+        state.auto_save_version = None;
+        return;
+    }
+    // The unsaved changes are only hashed again after the playlists changed
+    if state.auto_save_version != Some(state.playlists_version) {
+        state.auto_save_version = Some(state.playlists_version);
+        let mut hasher = DefaultHasher::new();
+        let mut has_changes = false;
+        for playlist in state.playlists.iter().filter(|x| x.has_changes()) {
+            playlist.name.hash(&mut hasher);
+            model::hash_songs(&playlist.songs).hash(&mut hasher);
+            has_changes = true;
+        }
+        let hash = hasher.finish();
+        state.auto_save_changes = match state.auto_save_changes {
+            _ if !has_changes => None,
+            Some((saved_hash, time)) if saved_hash == hash => Some((saved_hash, time)),
+            _ => Some((hash, Instant::now())),
+        };
+    }
+
+    let time = match state.auto_save_changes {
+        Some((_, time)) => time,
+        None => return,
+    };
+    if time.elapsed().as_millis() < app::AUTO_SAVE_DELAY_MS {
+        return;
+    }
+    state.auto_save_changes = None;
+    for i in 0..state.playlists.len() {
+        if state.playlists[i].has_changes() {
+            let result = write_playlist_file(&state.base_path, &mut state.playlists[i], false);
+            report_save_result(state, i, result);
+        }
+    }
+    state.playlist_backup_preview = None;
+}
+
+/// Returns why the name can not be used for a playlist that is currently named `old_name`.
+fn get_playlist_name_error(state: &State, new_name: &str, old_name: &str) -> Option<String> {
+    // Only the case of the name may be changed on case-insensitive file systems
+    let is_taken =
+        |name: &String| name != old_name && name.to_lowercase() == new_name.to_lowercase();
+    if new_name.is_empty() {
+        Some("The playlist name can not be empty".to_string())
+    } else if new_name.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|']) {
+        Some(format!(
+            "\"{new_name}\" contains characters that are not allowed in file names"
+        ))
+    } else if util::is_default_playlist(new_name)
+        || state.playlists.iter().any(|x| is_taken(&x.name))
+    {
+        Some(format!("A playlist named \"{new_name}\" already exists"))
+    } else if state.archived_playlists.iter().any(is_taken) {
+        Some(format!(
+            "An archived playlist named \"{new_name}\" already exists"
+        ))
+    } else {
+        None
+    }
+}
+
+/// Creates an unsaved playlist with the selected songs of the selected playlist.
+pub fn create_playlist_from_selection(state: &mut State, name: &str) -> bool {
+    let name = name.trim();
+    if let Some(error) = get_playlist_name_error(state, name, "") {
+        state.push_status(StatusType::Error, error);
+        return false;
+    }
+
+    let songs = get_selected_songs(state).into_iter().cloned().collect();
+    let mut playlist = Playlist::new(name.to_string(), songs);
+    playlist.original_hash = 0;
+    state.playlists.push(playlist);
+    state.sort_playlists();
+    true
+}
+
+/// Creates an unsaved empty playlist with a name that is not taken yet and returns its index.
+pub fn create_empty_playlist(state: &mut State) -> usize {
+    let name = (1..)
+        .map(|x| match x {
+            1 => NEW_PLAYLIST_NAME.to_string(),
+            x => format!("{NEW_PLAYLIST_NAME} {x}"),
+        })
+        .find(|x| get_playlist_name_error(state, x, "").is_none())
+        .unwrap();
+    let mut playlist = Playlist::new(name.clone(), Vec::new());
+    playlist.original_hash = 0;
+    state.playlists.push(playlist);
+    state.sort_playlists();
+    state.playlists.iter().position(|x| x.name == name).unwrap()
+}
+
+/// Renames the playlist, its file and its backups.
+pub fn rename_playlist(state: &mut State, playlist_index: usize, new_name: &str) {
+    let new_name = new_name.trim();
+    let old_name = state.playlists[playlist_index].name.clone();
+    if new_name == old_name {
+        return;
+    }
+
+    if let Some(error) = get_playlist_name_error(state, new_name, &old_name) {
+        state.push_status(StatusType::Error, error);
+        return;
+    }
+
+    let base_path = Path::new(&state.base_path);
+    let mut paths = Vec::new();
+    if let Some(path) = find_playlist_file(base_path, &old_name) {
+        paths.push(path);
+    }
+    for number in 1..=app::PLAYLIST_BACKUP_COUNT {
+        if let Some(path) = find_playlist_backup(&state.base_path, &old_name, number) {
+            paths.push(path);
+        }
+    }
+    // The playlist file is renamed first. If a file can't be renamed, the renamed files are renamed
+    // back so that the backups keep matching their playlist.
+    let mut renamed_paths = Vec::new();
+    for path in paths {
+        // The name itself can contain dots, only the part after it is kept
+        let file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let new_path = path.with_file_name(format!("{new_name}{}", &file_name[old_name.len()..]));
+        if let Err(e) = fs::rename(&path, &new_path) {
+            for (old_path, new_path) in renamed_paths.iter().rev() {
+                let _ = fs::rename(new_path, old_path);
+            }
+            state.push_status(
+                StatusType::Error,
+                format!("Could not rename \"{file_name}\":\n{e}"),
+            );
+            return;
+        }
+        renamed_paths.push((path, new_path));
+    }
+
+    for job in state
+        .download_jobs
+        .iter_mut()
+        .filter(|x| x.playlist_name == old_name)
+    {
+        job.playlist_name = new_name.to_string();
+    }
+    if state.duplicates_playlist_name == old_name {
+        state.duplicates_playlist_name = new_name.to_string();
+    }
+    state.playlists[playlist_index].name = new_name.to_string();
+    state.sort_playlists();
+}
+
+pub fn save_playlist_as(state: &mut State, playlist_index: usize, format: PlaylistFormat) {
+    state.playlists[playlist_index].format = format;
+    save_playlist(state, playlist_index);
+}
+
+/// Backups keep the extension of the playlist file they were created from, like `Name.m3u.1.bak`.
+fn find_playlist_backup(base_path: &str, playlist_name: &str, number: usize) -> Option<PathBuf> {
+    PlaylistFormat::ALL
+        .iter()
+        .map(|x| {
+            Path::new(base_path).join(format!("{playlist_name}.{}.{number}.bak", x.extension()))
+        })
+        .find(|x| x.exists())
+}
+
+/// Shifts all backups of the playlist by one, dropping the oldest, and copies the currently saved
+/// playlist file into the first backup.
+fn rotate_playlist_backups(
+    base_path: &str,
+    playlist_name: &str,
+    playlist_path: &Path,
+) -> io::Result<()> {
+    for number in (1..=app::PLAYLIST_BACKUP_COUNT).rev() {
+        let path = match find_playlist_backup(base_path, playlist_name, number) {
+            Some(path) => path,
+            None => continue,
+        };
+        if number == app::PLAYLIST_BACKUP_COUNT {
+            fs::remove_file(path)?;
+            continue;
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let new_file_name =
+            file_name.replace(&format!(".{number}.bak"), &format!(".{}.bak", number + 1));
+        fs::rename(&path, path.with_file_name(new_file_name))?;
+    }
+    let extension = playlist_path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy();
+    fs::copy(
+        playlist_path,
+        Path::new(base_path).join(format!("{playlist_name}.{extension}.1.bak")),
+    )?;
+    Ok(())
+}
+
+/// Returns the numbers of all existing backups of the playlist together with their modification
+/// time, newest first.
+pub fn get_playlist_backups(base_path: &str, playlist_name: &str) -> Vec<(usize, SystemTime)> {
+    (1..=app::PLAYLIST_BACKUP_COUNT)
+        .filter_map(|number| {
+            let path = find_playlist_backup(base_path, playlist_name, number)?;
+            let modified = fs::metadata(path).ok()?.modified().ok()?;
+            Some((number, modified))
+        })
+        .collect()
+}
+
+pub fn load_playlist_backup(state: &mut State, playlist_index: usize, number: usize) -> Playlist {
+    let name = state.playlists[playlist_index].name.clone();
+    let path = match find_playlist_backup(&state.base_path, &name, number) {
+        Some(path) => path,
+        None => return Playlist::new(name, Vec::new()),
+    };
+    let all_songs = &mut state
+        .playlists
+        .iter_mut()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .unwrap()
+        .songs;
+    match playlist_file::load(&state.base_path, &path, all_songs) {
+        Ok(mut playlist) => {
+            playlist.name = name;
+            playlist
+        }
+        Err(e) => {
+            state.push_status(StatusType::Error, e);
+            Playlist::new(name, Vec::new())
+        }
+    }
+}
+
+/// Replaces the songs of the playlist with the ones of the backup. The playlist file itself is
+/// only changed once the playlist is saved again.
+pub fn restore_playlist_backup(state: &mut State, playlist_index: usize, backup: Playlist) {
+    if state.selected_playlist_index == playlist_index {
+        state.selected_song_indices.clear();
+    }
+
+    if state.playing_playlist_index == Some(playlist_index) {
+        let playing_path =
+            &state.playlists[playlist_index].songs[state.playing_song_index.unwrap()].path;
+        match backup.songs.iter().position(|x| &x.path == playing_path) {
+            Some(i) => state.playing_song_index = Some(i),
+            None => stop(state),
+        }
+    }
+
+    let playlist = &mut state.playlists[playlist_index];
+    playlist.songs = backup.songs;
+    playlist.file_paths = backup.file_paths;
+    playlist.comments = backup.comments;
+    state.mark_playlists_changed();
+}
+
+fn get_playlist_file_time(state: &State, playlist_index: usize) -> Option<SystemTime> {
+    let playlist = &state.playlists[playlist_index];
+    let path = find_playlist_file(Path::new(&state.base_path), &playlist.name)?;
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// Checks from time to time whether playlist files were changed by another program. Playlists
+/// without unsaved changes are reloaded, otherwise the user is asked what to do.
+pub fn check_playlist_files(state: &mut State) {
+    if state.last_playlist_check.elapsed().as_millis() < app::PLAYLIST_CHECK_INTERVAL_MS
+        || state.externally_changed_playlist_name.is_some()
+    {
+        return;
+    }
+    state.last_playlist_check = Instant::now();
+
+    for i in 0..state.playlists.len() {
+        let playlist = &state.playlists[i];
+        if playlist.modified_time.is_none() || util::is_default_playlist(&playlist.name) {
+            continue;
+        }
+        let modified_time = get_playlist_file_time(state, i);
+        if modified_time.is_none() || modified_time == state.playlists[i].modified_time {
+            continue;
+        }
+
+        let playlist = &state.playlists[i];
+        if playlist.has_changes() {
+            state.externally_changed_playlist_name = Some(playlist.name.clone());
+            return;
+        }
+        let name = playlist.name.clone();
+        reload_playlist(state, i);
+        state.push_status(
+            StatusType::Info,
+            format!("Reloaded \"{name}\" after it was changed by another program"),
+        );
+    }
+}
+
+/// Replaces the songs of the playlist with the content of its file.
+pub fn reload_playlist(state: &mut State, playlist_index: usize) {
+    let path = match find_playlist_file(
+        Path::new(&state.base_path),
+        &state.playlists[playlist_index].name,
+    ) {
+        Some(path) => path,
+        None => return,
+    };
+    let mut all_songs = state
+        .playlists
+        .iter()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .map_or(Vec::new(), |x| x.songs.clone());
+    let reloaded = match playlist_file::load(&state.base_path, &path, &mut all_songs) {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            state.push_status(StatusType::Error, e);
+            return;
+        }
+    };
+    let original_hash = reloaded.original_hash;
+    let format = reloaded.format;
+    let modified_time = reloaded.modified_time;
+    restore_playlist_backup(state, playlist_index, reloaded);
+
+    let playlist = &mut state.playlists[playlist_index];
+    playlist.original_hash = original_hash;
+    playlist.format = format;
+    playlist.modified_time = modified_time;
+}
+
+/// Keeps the unsaved changes of a playlist whose file was changed by another program. The file is
+/// replaced when the playlist is saved the next time.
+pub fn ignore_playlist_file_change(state: &mut State, playlist_index: usize) {
+    state.playlists[playlist_index].modified_time = get_playlist_file_time(state, playlist_index);
+}
+
+fn write_playlist(path: &Path, playlist: &mut Playlist) -> io::Result<()> {
+    fs::write(path, playlist_file::write(playlist))?;
+    playlist.modified_time = fs::metadata(path).and_then(|x| x.modified()).ok();
+
+    playlist.original_hash = model::hash_songs(&playlist.songs);
+    Ok(())
+}
+
+pub fn add_song(state: &mut State, path: &str, playlist_index: usize) {
+    let path = PathBuf::from(path);
+    let duration = match player::get_duration(&path) {
+        Ok(duration) => Some(duration),
+        Err(e) => {
+            state.push_status(
+                StatusType::Error,
+                format!("Could not read {}:\n{e}", path.display()),
+            );
+            None
+        }
+    };
+    let song = Song::new(path.clone(), &state.base_path, duration);
+    if let Some(tags) = tags::read(&path) {
+        state.tags.insert(song.path.clone(), tags);
+        state.mark_song_data_changed();
+    }
+
+    if state.playlists[playlist_index].name != app::ALL_PLAYLIST_NAME {
+        state.playlists[playlist_index]
+            .songs
+            .insert(0, song.clone());
+        increment_indices(state, playlist_index, 1);
+    }
     state
         .playlists
         .iter_mut()
         .find(|x| x.name == app::ALL_PLAYLIST_NAME)
         .unwrap()
         .songs
-        .push(song);
+        .push(song.clone());
+    add_to_recently_added(state, vec![song]);
+    state.mark_playlists_changed();
+}
+
+/// Adds songs that were just added to the music directory to the top of Recently Added.
+fn add_to_recently_added(state: &mut State, songs: Vec<Song>) {
+    let now = SystemTime::now();
+    for song in songs.iter() {
+        state.song_added_times.insert(song.path.clone(), now);
+    }
+    if let Some(i) = state
+        .playlists
+        .iter()
+        .position(|x| x.name == app::RECENTLY_ADDED_PLAYLIST_NAME)
+    {
+        insert_songs(state, i, songs, 0);
+    }
+}
+
+/// Songs that were dropped from the file manager, with their tags, and the name of the playlist
+/// they are added to
+pub struct DroppedSongs {
+    playlist_name: String,
+    songs: Vec<(Song, Option<tags::Tags>)>,
+    /// Whether the first song is played once it is added
+    play: bool,
+}
+
+/// Adds songs dropped from the file manager to the selected playlist and to the All playlist.
+/// Folders are searched for songs. Depending on the settings, songs outside of the music directory
+/// are copied or moved into it, keeping the dropped folder. The files are imported in a background
+/// job, files dropped while it runs are added afterwards.
+pub fn add_dropped_files(state: &mut State, dropped_paths: Vec<PathBuf>) {
+    if state.dropped_files_job.is_running() {
+        state.dropped_paths.extend(dropped_paths);
+        return;
+    }
+    start_dropped_files_job(state, dropped_paths, false);
+}
+
+fn start_dropped_files_job(state: &mut State, dropped_paths: Vec<PathBuf>, play: bool) {
+    let mut paths = Vec::new();
+    for dropped_path in dropped_paths {
+        // Links dragged out of a browser arrive as internet shortcut files
+        if let Some(url) = download::read_url_shortcut(&dropped_path) {
+            let playlist_index = state.selected_playlist_index;
+            download::download_url(state, playlist_index, url);
+        } else {
+            paths.push(dropped_path);
+        }
+    }
+    if paths.is_empty() {
+        return;
+    }
+
+    let base_path = state.base_path.clone();
+    let action = state.settings.dropped_files_action;
+    let playlist_name = state.playlists[state.selected_playlist_index].name.clone();
+    let sender = state.dropped_files_job.start();
+    state.spawn_writer(move || {
+        let songs = import_dropped_paths(paths, &base_path, action, &sender);
+        let _ = sender.send(JobMessage::Finished(DroppedSongs {
+            playlist_name,
+            songs,
+            play,
+        }));
+    });
+}
+
+/// Finds the songs in the dropped paths, imports them into the music directory and reads their
+/// durations and tags.
+fn import_dropped_paths(
+    dropped_paths: Vec<PathBuf>,
+    base_path: &str,
+    action: DroppedFilesAction,
+    sender: &JobSender<DroppedSongs>,
+) -> Vec<(Song, Option<tags::Tags>)> {
+    let mut dropped_songs = Vec::new();
+    for dropped_path in dropped_paths {
+        let mut paths = Vec::new();
+        if dropped_path.is_dir() {
+            app::find_song_files(&dropped_path, &mut paths);
+            paths.sort();
+        } else if util::is_music_file(&dropped_path) {
+            paths.push(dropped_path.clone());
+        }
+        if paths.is_empty() {
+            let _ = sender.send(JobMessage::Error(format!(
+                "No songs found in {}",
+                dropped_path.display()
+            )));
+            continue;
+        }
+
+        let parent_path = dropped_path.parent().unwrap_or(&dropped_path).to_path_buf();
+        let total = paths.len();
+        for (i, path) in paths.into_iter().enumerate() {
+            let _ = sender.send(JobMessage::Progress {
+                done: i,
+                total,
+                item: path.file_name().map(|x| x.to_string_lossy().to_string()),
+            });
+            let result = import_dropped_file(base_path, action, &path, &parent_path)
+                .map_err(|e| e.to_string())
+                .and_then(|path| Ok((player::get_duration(&path)?, path)));
+            match result {
+                Ok((duration, path)) => {
+                    let song = Song::new(path.clone(), base_path, Some(duration));
+                    dropped_songs.push((song, tags::read(&path)));
+                }
+                Err(e) => {
+                    let _ = sender.send(JobMessage::Error(format!(
+                        "Could not add {}:\n{e}",
+                        path.display()
+                    )));
+                }
+            }
+        }
+    }
+    dropped_songs
+}
+
+/// Adds the songs of the finished dropped files job to the playlists.
+pub fn update_dropped_files(state: &mut State) {
+    let Some(dropped) = job::update(state, |x| &mut x.dropped_files_job, "Adding songs") else {
+        return;
+    };
+    if dropped.songs.is_empty() {
+        return;
+    }
+    let mut songs = Vec::new();
+    for (song, tags) in dropped.songs {
+        if let Some(tags) = tags {
+            state.tags.insert(song.path.clone(), tags);
+            state.mark_song_data_changed();
+        }
+        songs.push(song);
+    }
+    let first_path = songs[0].path.clone();
+
+    // The All playlist only contains songs of the music directory
+    let all_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .unwrap();
+    let all_paths: HashSet<&String> = state.playlists[all_index]
+        .songs
+        .iter()
+        .map(|x| &x.path)
+        .collect();
+    let new_songs: Vec<Song> = songs
+        .iter()
+        .filter(|x| !Path::new(&x.path).is_absolute() && !all_paths.contains(&x.path))
+        .cloned()
+        .collect();
+    let all_song_count = state.playlists[all_index].songs.len();
+    insert_songs(state, all_index, new_songs.clone(), all_song_count);
+    add_to_recently_added(state, new_songs);
+
+    let count = songs.len();
+    let playlist_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == dropped.playlist_name);
+    if let Some(playlist_index) = playlist_index {
+        if !util::is_default_playlist(&state.playlists[playlist_index].name) {
+            let index = if state.settings.drop_at_end {
+                state.playlists[playlist_index].songs.len()
+            } else {
+                0
+            };
+            insert_songs(state, playlist_index, songs, index);
+        }
+    }
+    state.push_status(StatusType::Info, format!("Added {count} songs"));
+
+    if dropped.play {
+        play_song_file(state, &first_path, playlist_index);
+    }
+}
+
+/// Selects the playlist with the name or otherwise the selected playlist and plays it from the
+/// first song, optionally shuffling it first.
+pub fn start_playlist(state: &mut State, name: Option<&str>, shuffle: bool) {
+    let playlist_index = match name {
+        Some(name) => {
+            let index = state
+                .playlists
+                .iter()
+                .position(|x| x.name == name)
+                .or_else(|| {
+                    state
+                        .playlists
+                        .iter()
+                        .position(|x| x.name.eq_ignore_ascii_case(name))
+                });
+            match index {
+                Some(i) => i,
+                None => {
+                    state.push_status(
+                        StatusType::Error,
+                        format!("There is no playlist named {name}"),
+                    );
+                    return;
+                }
+            }
+        }
+        None => state.selected_playlist_index,
+    };
+    state.selected_playlist_index = playlist_index;
+    state.selected_song_indices.clear();
+    state.scroll_to_selected_playlist = true;
+    if shuffle {
+        shuffle_playlist(state, playlist_index);
+    }
+    let song_index = state.playlists[playlist_index]
+        .songs
+        .iter()
+        .position(|x| x.exists);
+    if let Some(song_index) = song_index {
+        play(state, playlist_index, song_index);
+    }
+}
+
+/// Adds the song file like a dropped file and plays it once it is added. Songs of the music
+/// directory are played from the All playlist.
+pub fn open_song_file(state: &mut State, path: PathBuf) {
+    let relative_path = Song::get_relative_path(&path, &state.base_path);
+    let is_in_library = state
+        .playlists
+        .iter()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .is_some_and(|x| x.songs.iter().any(|x| x.path == relative_path));
+    if is_in_library {
+        let playlist_index = state.selected_playlist_index;
+        play_song_file(state, &relative_path, Some(playlist_index));
+    } else if state.dropped_files_job.is_running() {
+        state.push_status(
+            StatusType::Error,
+            format!("Wait until the songs are added to open {}", path.display()),
+        );
+    } else {
+        start_dropped_files_job(state, vec![path], true);
+    }
+}
+
+/// Plays the song from the All playlist or otherwise from the playlist.
+fn play_song_file(state: &mut State, relative_path: &str, playlist_index: Option<usize>) {
+    let all_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .unwrap();
+    let found = [Some(all_index), playlist_index]
+        .into_iter()
+        .flatten()
+        .find_map(|i| {
+            state.playlists[i]
+                .songs
+                .iter()
+                .position(|x| x.path == relative_path)
+                .map(|song_index| (i, song_index))
+        });
+    match found {
+        Some((playlist_index, song_index)) => play(state, playlist_index, song_index),
+        None => state.push_status(
+            StatusType::Error,
+            format!("Select a playlist to add {relative_path} to"),
+        ),
+    }
+}
+
+/// Copies or moves a dropped song into the music directory if enabled and returns its new path.
+/// Existing files are never replaced.
+fn import_dropped_file(
+    base_path: &str,
+    action: DroppedFilesAction,
+    path: &Path,
+    parent_path: &Path,
+) -> io::Result<PathBuf> {
+    let is_outside = Path::new(&Song::get_relative_path(path, base_path)).is_absolute();
+    if !is_outside || action == DroppedFilesAction::Add {
+        return Ok(path.to_path_buf());
+    }
+
+    let new_path = Path::new(base_path).join(path.strip_prefix(parent_path).unwrap_or(path));
+    if new_path.exists() {
+        return Ok(new_path);
+    }
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if action == DroppedFilesAction::Move {
+        // Renaming fails across file systems
+        if fs::rename(path, &new_path).is_err() {
+            fs::copy(path, &new_path)?;
+            fs::remove_file(path)?;
+        }
+    } else {
+        fs::copy(path, &new_path)?;
+    }
+    Ok(new_path)
+}
+
+/// Removes the playlist from the list and keeps all playlist indices pointing to the same
+/// playlists. Playback is stopped if the removed playlist is playing.
+pub fn remove_playlist(state: &mut State, playlist_index: usize) -> Playlist {
+    state.renamed_playlist_index = None;
+    if state.playing_playlist_index == Some(playlist_index) {
+        stop(state);
+    } else if state.playing_playlist_index > Some(playlist_index) {
+        state.playing_playlist_index = Some(state.playing_playlist_index.unwrap() - 1);
+    }
+
+    if state.selected_playlist_index == playlist_index {
+        state.selected_playlist_index = 0;
+        state.selected_song_indices.clear();
+    } else if state.selected_playlist_index > playlist_index {
+        state.selected_playlist_index -= 1;
+    }
+
+    state.mark_playlists_changed();
+    state.playlists.remove(playlist_index)
+}
+
+/// Removes the playlist and moves its file and backups to the trash if `delete_file` is set.
+pub fn delete_playlist(state: &mut State, playlist_index: usize, delete_file: bool) {
+    let playlist = remove_playlist(state, playlist_index);
+    if !delete_file {
+        return;
+    }
+
+    let mut paths = Vec::new();
+    if let Some(path) = find_playlist_file(Path::new(&state.base_path), &playlist.name) {
+        paths.push(path);
+    }
+    for number in 1..=app::PLAYLIST_BACKUP_COUNT {
+        if let Some(path) = find_playlist_backup(&state.base_path, &playlist.name, number) {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return;
+    }
+    if let Err(e) = trash::delete_all(paths) {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not move playlist file to trash:\n{e}"),
+        );
+    }
+}
+
+/// Writes the playlist into the archive directory and removes its file from the music directory.
+fn write_archived_playlist(base_path: &str, playlist: &mut Playlist) -> io::Result<()> {
+    let archive_path = Path::new(base_path).join(app::ARCHIVE_DIRECTORY);
+    fs::create_dir_all(&archive_path)?;
+
+    // An archived playlist with the same name is replaced
+    while let Some(path) = find_playlist_file(&archive_path, &playlist.name) {
+        fs::remove_file(path)?;
+    }
+
+    // The current songs are archived, even if they have not been saved yet
+    let file_name = format!("{}.{}", &playlist.name, playlist.format.extension());
+    write_playlist(&archive_path.join(file_name), playlist)?;
+    while let Some(path) = find_playlist_file(Path::new(base_path), &playlist.name) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Moves the playlist into the archive. The playlist stays loaded if its file can't be archived.
+pub fn archive_playlist(state: &mut State, playlist_index: usize) {
+    let result = write_archived_playlist(&state.base_path, &mut state.playlists[playlist_index]);
+    if let Err(e) = result {
+        let name = &state.playlists[playlist_index].name;
+        let info = format!("Could not archive \"{name}\":\n{e}");
+        state.push_status(StatusType::Error, info);
+        return;
+    }
+
+    let playlist = remove_playlist(state, playlist_index);
+    state.archived_playlists.retain(|x| x != &playlist.name);
+    state.archived_playlists.push(playlist.name);
+    state.archived_playlists.sort_by_key(|x| x.to_lowercase());
+}
+
+pub fn restore_playlist(state: &mut State, archived_index: usize) {
+    let name = state.archived_playlists[archived_index].clone();
+    if state.playlists.iter().any(|x| x.name == name) {
+        state.push_status(
+            StatusType::Error,
+            format!("A playlist named \"{name}\" already exists"),
+        );
+        return;
+    }
+
+    let archived_path = match find_playlist_file(
+        &Path::new(&state.base_path).join(app::ARCHIVE_DIRECTORY),
+        &name,
+    ) {
+        Some(path) => path,
+        None => {
+            state.push_status(
+                StatusType::Error,
+                format!("The archived playlist \"{name}\" does not exist anymore"),
+            );
+            state.archived_playlists.remove(archived_index);
+            return;
+        }
+    };
+    let path = Path::new(&state.base_path).join(archived_path.file_name().unwrap_or_default());
+    if let Err(e) = fs::rename(&archived_path, &path) {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not restore \"{name}\":\n{e}"),
+        );
+        return;
+    }
+    state.archived_playlists.remove(archived_index);
+
+    let all_songs = &mut state
+        .playlists
+        .iter_mut()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+        .unwrap()
+        .songs;
+    match playlist_file::load(&state.base_path, &path, all_songs) {
+        Ok(playlist) => state.playlists.push(playlist),
+        Err(e) => state.push_status(StatusType::Error, e),
+    }
+    state.sort_playlists();
+}
+
+/// Switches to another music directory without restarting. The library of the current directory
+/// is dropped, so the user is asked first if some of its playlists have unsaved changes.
+pub fn open_music_directory(state: &mut State, path: &str) {
+    let path = path.trim();
+    if !Path::new(path).is_dir() {
+        state.push_status(StatusType::Error, format!("\"{path}\" is not a directory"));
+        return;
+    }
+    // Downloads are saved to the directory they were started in
+    if !state.download_jobs.is_empty() {
+        state.push_status(
+            StatusType::Error,
+            "Wait for the downloads to finish before opening another folder".to_string(),
+        );
+        return;
+    }
+    if state.playlists.iter().any(|x| x.has_changes()) {
+        state.pending_music_directory = Some(path.to_string());
+    } else {
+        switch_music_directory(state, path);
+    }
+}
 
-    increment_indices(state, playlist_index, 1);
+/// Replaces the library with the one of the directory, discarding unsaved changes.
+pub fn switch_music_directory(state: &mut State, path: &str) {
+    stop(state);
+    // The window keeps its place, the other settings belong to the directory
+    let settings = settings::Settings {
+        window_position: state.settings.window_position,
+        window_size: state.settings.window_size,
+        window_maximized: state.settings.window_maximized,
+        ..settings::load(path)
+    };
+    app::reopen(state, path.to_string(), settings);
+    state.push_status(StatusType::Info, format!("Opened {path}"));
 }
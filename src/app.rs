@@ -1,131 +1,92 @@
-use __core::time::Duration;
-use souvlaki::{MediaControlEvent, MediaControls, MediaPlayback, PlatformConfig};
+use souvlaki::{MediaControlEvent, MediaControls, PlatformConfig};
 use std::{
-    cmp::Ordering,
-    collections::{hash_map::DefaultHasher, VecDeque},
+    cmp::{Ordering, Reverse},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
     env, ffi,
     fs::{self},
     hash::{Hash, Hasher},
+    mem,
     path::{Path, PathBuf},
-    process::Child,
     sync::{
-        mpsc::{self, Receiver, Sender},
+        mpsc::{self, Receiver, Sender, SyncSender},
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
+use crate::keymap::{self, Command};
 use crate::player;
+use crate::playlist_file::{self, PlaylistFormat};
 use crate::util;
-use crate::{actions, download};
+use crate::widgets::{self, ScrollInfo};
+use crate::{
+    actions::{self, Action},
+    cover, dlna, download, duplicates, export, fonts, ime, import,
+    job::Job,
+    library, lyrics, notes, ratings, remote, replaygain, search, settings, sync, tags, theme,
+};
 use imgui::{internal::DataTypeKind, *};
 
+use implayer_core::model::{self, SongField};
+pub use implayer_core::model::{
+    Playlist, Song, ALL_PLAYLIST_NAME, ALL_UNUSED_PLAYLIST_NAME, HISTORY_PLAYLIST_NAME,
+    RECENTLY_ADDED_PLAYLIST_NAME,
+};
+
 // TODO Context menu padding not working for first level menu, missing bindings to do smth like https://github.com/ocornut/imgui/issues/4129#issuecomment-916195585
 
-pub const TEXT1: [f32; 4] = [0.80, 0.80, 0.80, 1.0];
-pub const TEXT2: [f32; 4] = [0.50, 0.50, 0.50, 1.0];
-pub const DARK1: [f32; 4] = [0.07, 0.07, 0.07, 1.0];
-pub const DARK2: [f32; 4] = [0.11, 0.11, 0.11, 1.0];
-pub const DARK3: [f32; 4] = [0.13, 0.13, 0.13, 1.0];
-pub const DARK4: [f32; 4] = [0.14, 0.14, 0.14, 1.0];
-pub const DARK5: [f32; 4] = [0.15, 0.15, 0.15, 1.0];
-pub const DARK6: [f32; 4] = [0.17, 0.17, 0.17, 1.0];
-pub const DARK7: [f32; 4] = [0.20, 0.20, 0.20, 1.0];
-pub const PRIMARY1: [f32; 4] = [0.00, 0.28, 0.50, 1.0];
-pub const PRIMARY2: [f32; 4] = [0.00, 0.34, 0.61, 1.0];
-pub const PLAYING_COLOR: [f32; 4] = [0.00, 0.70, 0.00, 1.0];
-pub const NOT_EXISTING_COLOR: [f32; 4] = [0.70, 0.00, 0.00, 1.0];
-pub const HOVERED_BG: [f32; 4] = DARK5;
-pub const ACTIVE_BG: [f32; 4] = DARK6;
-pub const DRAG: [f32; 4] = [0.00, 0.28, 0.50, 0.85];
 pub const TRANSPARENT: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
-pub const INFO: [f32; 4] = [0.0, 0.2, 0.4, 1.0];
-pub const ERROR: [f32; 4] = [0.4, 0.0, 0.0, 1.0];
-pub const PROGRESS: [f32; 4] = INFO;
 
-pub const ALL_PLAYLIST_NAME: &str = "All";
-pub const ALL_UNUSED_PLAYLIST_NAME: &str = "All Unused";
+/// Choices for the number of days the Recently Added playlist covers
+pub const RECENTLY_ADDED_DAYS: [u64; 4] = [7, 30, 90, 365];
+/// Choices for the number of downloads that run at the same time
+pub const PARALLEL_DOWNLOAD_COUNTS: [usize; 5] = [1, 2, 3, 4, 6];
+/// Directory inside the music directory for files that are managed by the player itself
+pub const DATA_DIRECTORY: &str = ".implayer";
+pub const HISTORY_FILE_NAME: &str = "history";
+/// Time after the last change of a playlist until it is saved automatically
+pub const AUTO_SAVE_DELAY_MS: u128 = 3000;
+/// Time a playlist needs to be hovered while dragging songs until it is opened
+pub const SPRING_LOADING_DELAY_MS: u128 = 700;
+/// Time after the song list was scrolled by the user until it follows the playing song again
+pub const FOLLOW_PLAYING_SONG_DELAY_MS: u128 = 5000;
+/// Time after the last typed character until typing starts a new search in the song list
+pub const TYPE_AHEAD_RESET_DELAY_MS: u128 = 1000;
+/// Lowest position of the volume slider, which mutes the player
+pub const MIN_VOLUME: f32 = 0.3;
+pub const MAX_VOLUME: f32 = 1.2;
+pub const DEFAULT_VOLUME: f32 = 0.93;
+/// Interval in which playlist files are checked for changes by other programs
+pub const PLAYLIST_CHECK_INTERVAL_MS: u128 = 2000;
+pub const COLLAPSED_FOLDERS_FILE_NAME: &str = "collapsed_folders";
+pub const HISTORY_LIMIT: usize = 1000;
+/// Number of statuses that are kept in the notifications window
+pub const NOTIFICATION_LIMIT: usize = 100;
+pub const ARCHIVE_DIRECTORY: &str = "archive";
+pub const PLAYLIST_BACKUP_COUNT: usize = 5;
 const NEW_PLAYLIST_TEXT: &str = "New playlist name";
 const SONG_SEARCH_TEXT: &str = "Song search";
+const DEFAULT_RENAME_PATTERN: &str = "%artist% - %title%.%ext%";
+const DEFAULT_ORGANIZE_TEMPLATE: &str = "%artist%/%album%/%artist% - %title%.%ext%";
 
 const CONTROLS_HEIGHT: f32 = 100.0;
 const TEXTBOXES_HEIGHT: f32 = 24.0;
 const SONGS_HEADER_HEIGHT: f32 = 30.0;
-
-const DIRECTORY_COLOR: [f32; 4] = TEXT2;
-const PLAYLIST_LIST_BG: [f32; 4] = DARK1;
-const SONGS_HEADER_BG: [f32; 4] = DARK1;
-const SONG_LIST_BG1: [f32; 4] = DARK2;
-const SONG_LIST_BG2: [f32; 4] = DARK1;
-const CONTROLS_BG: [f32; 4] = DARK1;
-
-pub struct Playlist {
-    pub name: String,
-    pub songs: Vec<Song>,
-    pub original_hash: u64,
-}
-
-impl Playlist {
-    pub fn new(name: String, songs: Vec<Song>) -> Playlist {
-        let mut hasher = DefaultHasher::new();
-        for song in songs.iter() {
-            song.hash(&mut hasher);
-        }
-
-        Playlist {
-            name,
-            songs,
-            original_hash: hasher.finish(),
-        }
-    }
-}
-
-#[derive(Clone)]
-pub struct Song {
-    pub path: String,
-    pub name: String,
-    pub artist: String,
-    /// Milliseconds
-    pub duration: Option<u64>,
-    pub exists: bool,
-}
-
-impl Song {
-    pub fn new(path: PathBuf, base_path: &str, duration: Option<u64>) -> Song {
-        let file_name = path.file_stem().unwrap().to_string_lossy();
-        let name_info: Vec<&str> = file_name.splitn(2, " - ").collect();
-
-        Song {
-            path: path
-                .strip_prefix(base_path)
-                .unwrap()
-                .to_string_lossy()
-                .to_string(),
-            name: if name_info.len() > 1 {
-                name_info[1].trim().to_string()
-            } else {
-                String::new()
-            },
-            artist: name_info[0].trim().to_string(),
-            duration,
-            exists: path.exists(),
-        }
-    }
-
-    pub fn is_matching(&self, search_text: &str) -> bool {
-        let search_text = search_text.to_lowercase();
-        self.name.to_lowercase().contains(&search_text)
-            || self.artist.to_lowercase().contains(&search_text)
-    }
-}
-
-impl Hash for Song {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.path.hash(state);
-        self.duration.hash(state);
-    }
-}
+/// Horizontal padding of the text in the columns of the song list
+const SONGS_COLUMN_PADDING: f32 = 6.0;
+/// Minimum width that a column can be resized to
+const COLUMN_MIN_WIDTH: f32 = 40.0;
+const FILTER_BAR_HEIGHT: f32 = 28.0;
+const SELECTION_BAR_HEIGHT: f32 = 28.0;
+const LYRICS_WIDTH: f32 = 400.0;
+const NOW_PLAYING_WIDTH: f32 = 360.0;
+/// Number of songs after the playing one that are listed in the Now Playing panel
+const UPCOMING_SONG_COUNT: usize = 10;
+
+const FOLDER_INDENT: f32 = 16.0;
+/// Size of the lists of the download search results and the download queue
+const DOWNLOAD_LIST_SIZE: [f32; 2] = [600.0, 250.0];
 
 pub enum SortDirection {
     Ascending,
@@ -149,44 +110,93 @@ impl SortDirection {
     }
 }
 
-pub enum SortType {
-    Song(SortDirection),
-    Artist(SortDirection),
-    Duration(SortDirection),
+/// Column of the song list
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    Song,
+    Artist,
+    Album,
+    Duration,
+    Rating,
+    Plays,
+    Path,
 }
-impl SortType {
-    pub fn compare(&self, a: &Song, b: &Song) -> Ordering {
+
+impl Column {
+    pub const ALL: [Column; 7] = [
+        Column::Song,
+        Column::Artist,
+        Column::Album,
+        Column::Duration,
+        Column::Rating,
+        Column::Plays,
+        Column::Path,
+    ];
+
+    pub fn name(&self) -> &'static str {
         match self {
-            SortType::Song(dir) => {
-                dir.apply_direction(a.name.to_lowercase().cmp(&b.name.to_lowercase()))
-            }
-            SortType::Artist(dir) => {
-                dir.apply_direction(a.artist.to_lowercase().cmp(&b.artist.to_lowercase()))
-            }
-            SortType::Duration(dir) => dir.apply_direction(a.duration.cmp(&b.duration)),
+            Column::Song => "Song",
+            Column::Artist => "Artist",
+            Column::Album => "Album",
+            Column::Duration => "Duration",
+            Column::Rating => "Rating",
+            Column::Plays => "Plays",
+            Column::Path => "Path",
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Column::Song => "song",
+            Column::Artist => "artist",
+            Column::Album => "album",
+            Column::Duration => "duration",
+            Column::Rating => "rating",
+            Column::Plays => "plays",
+            Column::Path => "path",
         }
     }
+
+    /// Returns the width of columns with short values. The other columns share the remaining
+    /// width.
+    fn get_fixed_width(&self, ui: &Ui) -> Option<f32> {
+        let content_width = match self {
+            Column::Duration => ui.calc_text_size("Duration")[0],
+            Column::Rating => ui.calc_text_size("★★★★★")[0],
+            Column::Plays => ui.calc_text_size("Plays")[0],
+            _ => return None,
+        };
+        Some(content_width + SortDirection::get_sort_icon_width(ui) + 2.0 * SONGS_COLUMN_PADDING)
+    }
+
+    /// Returns whether the values are aligned to the right side of the column.
+    fn is_right_aligned(&self) -> bool {
+        matches!(self, Column::Duration | Column::Plays)
+    }
 }
 
-pub enum DownloadState {
-    None,
-    Downloading(
-        Child,
-        Receiver<String>,
-        Sender<()>,
-        Receiver<String>,
-        Sender<()>,
-    ),
-    Postprocessing(
-        Child,
-        Receiver<String>,
-        Sender<()>,
-        Receiver<String>,
-        Sender<()>,
-    ),
+pub struct SortType {
+    pub column: Column,
+    pub direction: SortDirection,
 }
 
-#[derive(Debug)]
+/// Songs that the "Not in" filter hides from the song list
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum NotInFilter {
+    /// Songs of the playlist with the name
+    Playlist(String),
+    /// Songs of any playlist file, which leaves the songs of All Unused in the All playlist
+    AnyPlaylist,
+}
+
+/// Pane that receives the keyboard navigation
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FocusedPane {
+    Playlists,
+    Songs,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum StatusType {
     Info,
     //Warning,
@@ -195,304 +205,986 @@ pub enum StatusType {
 }
 
 impl StatusType {
-    fn get_color(&self) -> [f32; 4] {
+    fn get_color(&self, theme: &theme::Theme) -> [f32; 4] {
+        match self {
+            StatusType::Info => theme.info,
+            StatusType::Error => theme.error,
+            StatusType::Progress => theme.info,
+        }
+    }
+
+    fn name(&self) -> &'static str {
         match self {
-            StatusType::Info => INFO,
-            StatusType::Error => ERROR,
-            StatusType::Progress => PROGRESS,
+            StatusType::Info => "Info",
+            StatusType::Error => "Error",
+            StatusType::Progress => "Progress",
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Status {
     pub info: String,
     pub timestamp: Instant,
+    /// Shown in the notifications window
+    pub time: SystemTime,
     pub r#type: StatusType,
 }
 
-pub struct ScrollInfo {
-    pub is_scrolling: bool,
-    pub scroll_start_time: Instant,
-    pub scroll_duration: Duration,
-    pub scroll_target_y: f32,
-}
-
 pub struct State {
     pub base_path: String,
     pub playlists: Vec<Playlist>,
+    pub archived_playlists: Vec<String>,
+    pub show_archived_playlists: bool,
+    /// Lowercase paths of the playlist folders whose playlists are hidden, since playlists are
+    /// grouped into folders ignoring case
+    pub collapsed_folders: HashSet<String>,
+    /// Number of playlists in each folder by lowercase folder path
+    pub playlist_folder_counts: HashMap<String, usize>,
+    /// Playlists version that `playlist_folder_counts` were counted for
+    pub playlist_folder_counts_version: Option<u64>,
+    /// Albums or artists whose songs are hidden in the song list
+    pub collapsed_groups: HashSet<String>,
+    /// Filtered and sorted songs of the last drawn song list
+    pub song_list: Option<SongList>,
+    /// Song whose context menu was opened last, its row is drawn even when it is out of view
+    pub song_context_menu_index: Option<usize>,
+    /// Playlist index, backup number and the parsed backup
+    pub playlist_backup_preview: Option<(usize, usize, Playlist)>,
+    /// Numbers and modification times of the backups of the playlist whose context menu is open,
+    /// read when the menu is opened
+    pub playlist_backups: Vec<(usize, SystemTime)>,
     pub selected_playlist_index: usize,
     pub selected_song_indices: Vec<usize>,
+    pub focused_pane: FocusedPane,
+    /// Beginning of a song name that was typed to jump to the song
+    pub type_ahead_text: String,
+    pub type_ahead_time: Instant,
+    /// Keys of an unfinished vim-style command like the first `g` of `gg`
+    pub vim_pending_keys: String,
+    /// Focus the song search field in the next frame
+    pub focus_song_search: bool,
+    /// Songs that are played before the next song of the playing playlist, by playlist index and
+    /// song path since the indices change when songs are moved
+    pub queued_songs: VecDeque<(usize, String)>,
+    /// Actions emitted while drawing, which are applied after the frame
+    pub pending_actions: Vec<Action>,
+    /// Scroll the playlist list to the selected playlist in the next frame
+    pub scroll_to_selected_playlist: bool,
+    /// Index of the visible column whose right boundary is dragged
+    pub resized_column_index: Option<usize>,
     pub new_playlist_text: String,
+    pub selection_playlist_text: String,
     pub song_search_text: String,
+    /// Parsed song search text, updated when the text changes
+    pub song_search_query: search::Query,
     pub has_textbox_focus: bool,
     pub sort_type: Option<SortType>,
+    pub genre_filter: Option<String>,
+    pub not_in_playlist_filter: Option<NotInFilter>,
+    /// Song paths hidden by `not_in_playlist_filter`
+    pub not_in_playlist_paths: HashSet<String>,
+    /// Filter and playlists version that `not_in_playlist_paths` was collected for
+    pub not_in_playlist_key: Option<(NotInFilter, u64)>,
+    /// Whether only songs whose files don't exist are shown
+    pub missing_filter: bool,
+
+    /// Tags by song path, filled in the background after startup
+    pub tags: HashMap<String, tags::Tags>,
+    /// Number of plays of each song, which are saved separately since the History playlist only
+    /// holds the last plays
+    pub play_counts: HashMap<String, usize>,
+    /// Times that the song files were created, read while loading the library, by their path
+    pub song_added_times: HashMap<String, SystemTime>,
+    pub tags_rx: Option<Receiver<(String, tags::Tags)>>,
+    /// Receives the playlists and songs while the library is loaded in the background
+    pub library_rx: Option<Receiver<library::LibraryMessage>>,
+    pub library_progress: String,
 
     pub dragged_songs: Vec<Song>,
+    /// Songs that were cut or copied and can be pasted into another playlist
+    pub copied_songs: Vec<Song>,
+    /// Indices of the dragged songs in the selected playlist
+    pub dragged_song_indices: Vec<usize>,
+    pub drag_playlist_index: Option<usize>,
+    /// Playlist that is hovered while dragging and since when
+    pub drag_hover: Option<(usize, Instant)>,
+    /// Files and folders dropped onto the window from the file manager
+    pub dropped_paths: Vec<PathBuf>,
+    /// Copies or moves the dropped files into the music directory and reads them
+    pub dropped_files_job: Job<actions::DroppedSongs>,
+    /// Text that is being composed with an input method and not yet entered
+    pub ime_preedit: String,
 
     pub original_file_name: String,
     pub file_name_text: String,
+    pub rename_pattern_text: String,
+    pub organize_template_text: String,
+    /// Preview of organizing a playlist and the hash of what it was computed from, so that the
+    /// files are only checked when the template or the songs change
+    pub organize_preview: Option<(u64, Vec<(String, Option<String>)>)>,
+    /// Notes of songs by their path
+    pub notes: HashMap<String, String>,
+    /// Ratings that were set in the player by song path, they take precedence over rating tags
+    pub ratings: HashMap<String, u8>,
+    pub note_text: String,
+    pub import_path_text: String,
+    /// Playlist URL or CSV file of a streaming service
+    pub streaming_import_text: String,
+    /// yt-dlp listing the videos of a playlist that is imported
+    pub playlist_listing: Option<download::Process>,
+    /// Name of the imported streaming playlist and its songs that are not in the library, which
+    /// are offered for download
+    pub missing_streaming_tracks: Option<(String, Vec<download::StreamingTrack>)>,
+    /// Folder that the library is exported to
+    pub export_path_text: String,
+    pub music_directory_text: String,
+    /// Path of a song or folder that is added to the selected playlist
+    pub add_files_text: String,
+    /// Address of the remote control that is applied with Enter
+    pub remote_address_text: String,
+    /// Music directory to be opened once the user decides about unsaved playlists
+    pub pending_music_directory: Option<String>,
+    /// Path of a font file that is added to the fallback fonts
+    pub fallback_font_text: String,
+    pub renamed_playlist_index: Option<usize>,
+    pub playlist_name_text: String,
+    pub focus_playlist_name_textbox: bool,
+    /// Playlist to be deleted once the user confirms it
+    pub deleted_playlist_name: Option<String>,
+    pub delete_playlist_file: bool,
+    /// Songs to be deleted once the user confirms it
+    pub pending_deletions: Vec<String>,
+    /// Whether closing the window waits for the user to decide about unsaved playlists
+    pub show_exit_confirmation: bool,
+    pub should_exit: bool,
+    pub settings: settings::Settings,
+    pub theme: theme::Theme,
+    /// Incremented whenever the playlists or their songs change, so that values derived from
+    /// them are only computed again then
+    pub playlists_version: u64,
+    /// Incremented whenever the tags, notes, ratings or play counts of songs change
+    pub song_data_version: u64,
+    /// Hash of the unsaved changes and the time they were last changed
+    pub auto_save_changes: Option<(u64, Instant)>,
+    /// Playlists version whose unsaved changes were hashed last
+    pub auto_save_version: Option<u64>,
+    pub last_playlist_check: Instant,
+    /// Playlist whose file was changed by another program while it has unsaved changes
+    pub externally_changed_playlist_name: Option<String>,
 
     pub download_text: String,
-    pub download_playlist_index: Option<usize>,
-    pub download_path: Option<String>,
-    pub download_state: DownloadState,
-    pub last_download_status: Option<Instant>,
+    pub download_jobs: Vec<download::DownloadJob>,
+    pub download_options: download::DownloadOptions,
+    pub programs: download::Programs,
+    pub yt_dlp_updater: Option<download::Process>,
+    pub download_search_text: String,
+    pub download_search: Option<download::Process>,
+    pub download_search_results: Vec<download::SearchResult>,
+    /// Whether the downloads window is shown
+    pub show_downloads: bool,
+    /// Show the window to copy playlists to a device
+    pub show_sync: bool,
+    pub sync_rx: Option<Receiver<sync::SyncMessage>>,
+    /// Number of synced songs, number of all songs and the path of the current song
+    pub sync_progress: Option<(usize, usize, String)>,
+    pub postprocessing_job: Job<usize>,
+    /// Background threads that write into song files, they are waited for on exit
+    pub writer_threads: Vec<JoinHandle<()>>,
 
     pub status_queue: VecDeque<Status>,
+    /// Statuses that are kept after they disappeared, newest last
+    pub notifications: VecDeque<Status>,
+    pub show_notifications: bool,
+    pub show_keymap: bool,
+    pub show_shortcut_help: bool,
+    /// Command whose next pressed key is recorded, with the index of the binding it replaces or
+    /// None to add a binding
+    pub rebound_command: Option<(Command, Option<usize>)>,
+
+    pub duplicates_job: Job<duplicates::Duplicates>,
+    pub duplicates_playlist_name: String,
+    pub duplicate_groups: Vec<Vec<Song>>,
+    pub replaygain_job: Job<usize>,
+    pub show_duplicates: bool,
+    /// Names of the playlists shown in the comparison window
+    pub compared_playlist_names: Option<(String, String)>,
+    /// Name of the last shuffled playlist, the previous index of each song and the hash of the
+    /// shuffled songs to detect later changes
+    pub shuffle_undo: Option<(String, Vec<usize>, u64)>,
+    /// Audio fingerprints by song path
+    pub fingerprints: HashMap<String, Vec<u32>>,
 
     pub playing_playlist_index: Option<usize>,
     pub playing_song_index: Option<usize>,
 
     pub is_playing: bool,
+    /// Position of the volume slider between `MIN_VOLUME` and `MAX_VOLUME`
     pub volume: f32,
-    pub player_thread: JoinHandle<()>,
+    /// Volume that unmuting restores
+    pub volume_before_mute: Option<f32>,
+    /// Taken when the thread is joined on exit
+    pub player_thread: Option<JoinHandle<()>>,
     pub action_tx: Sender<player::PlayerAction>,
     pub song_ended_rx: Receiver<()>,
+    /// Songs that the player could not play
+    pub player_error_rx: Receiver<String>,
+    /// Lets a renderer report the end of songs like the player does
+    pub song_ended_tx: Sender<()>,
     pub last_progress: Option<f64>,
     pub position: Arc<Mutex<u64>>,
-    pub media_controls: MediaControls,
+    /// None if the media controls of the system are not available
+    pub media_controls: Option<MediaControls>,
     pub media_controls_rx: Receiver<MediaControlEvent>,
+    /// File URL of the playing song's cover that is shown by the media controls
+    pub media_cover_url: Option<String>,
+    pub media_cover_rx: Option<Receiver<Option<String>>>,
+    /// Renderer in the network that songs are played on instead of this computer
+    pub renderer: Option<dlna::Renderer>,
+    /// Renderers that were found by the last searches
+    pub renderers: Vec<dlna::Device>,
+    pub renderers_rx: Option<Receiver<dlna::Device>>,
+
+    pub show_lyrics: bool,
+    pub lyrics: Option<Vec<lyrics::LyricsLine>>,
+    /// Path of the song the lyrics were loaded for
+    pub lyrics_song_path: Option<String>,
+    pub lyrics_current_line: Option<usize>,
+
+    pub show_now_playing: bool,
+    /// Path of the song the cover was loaded for
+    pub cover_song_path: Option<String>,
+    pub cover_rx: Option<Receiver<Option<cover::Cover>>>,
+    /// Cover that replaces the current texture once the frame is rendered, `Some(None)` removes it
+    pub loaded_cover: Option<Option<cover::Cover>>,
+    /// Texture of the cover and the size of the image
+    pub cover_texture: Option<(TextureId, [f32; 2])>,
+
+    /// Show the albums of the selected playlist as a grid of covers instead of the song list
+    pub show_album_grid: bool,
+    /// Atlas cell and image size of the thumbnail of each album, None if it has no cover
+    pub album_thumbnails: HashMap<String, Option<(usize, [f32; 2])>>,
+    /// Albums whose thumbnails are being loaded
+    pub requested_thumbnails: HashSet<String>,
+    pub thumbnails_rx: Option<Receiver<(String, Option<cover::Cover>)>>,
+    /// Thumbnails that are copied into their atlas cells once the frame is rendered
+    pub loaded_thumbnails: Vec<(usize, cover::Cover)>,
+    /// Texture that holds the thumbnails of the album grid
+    pub thumbnail_atlas: Option<TextureId>,
 
     pub playlists_scroll_info: ScrollInfo,
     pub songs_scroll_info: ScrollInfo,
+    /// Playing playlist and song that the song list last followed
+    pub followed_song: Option<(usize, usize)>,
+    /// Hovered song whose cut off texts are scrolling and the time it was first hovered
+    pub scrolling_song: Option<(usize, Instant)>,
     pub add_to_menu_scroll_info: ScrollInfo,
+    pub lyrics_scroll_info: ScrollInfo,
+    pub now_playing_scroll_info: ScrollInfo,
+    pub download_search_scroll_info: ScrollInfo,
+    pub download_queue_scroll_info: ScrollInfo,
 }
 
 impl State {
     pub fn sort_playlists(&mut self) {
-        self.playlists.sort_by(|a, b| {
-            if a.name == ALL_PLAYLIST_NAME {
-                return Ordering::Less;
-            }
+        // Remember playlists by name since their indices change when sorting
+        let get_name = |i: usize| self.playlists.get(i).map(|x| x.name.clone());
+        let selected_name = get_name(self.selected_playlist_index);
+        let playing_name = self.playing_playlist_index.and_then(get_name);
+        let renamed_name = self.renamed_playlist_index.and_then(get_name);
+        let dragged_name = self.drag_playlist_index.and_then(get_name);
+        let hovered_name = self.drag_hover.and_then(|x| get_name(x.0));
+        let preview_name = self
+            .playlist_backup_preview
+            .as_ref()
+            .and_then(|x| get_name(x.0));
+
+        self.playlists
+            .sort_by(|a, b| model::compare_playlist_names(&a.name, &b.name));
+        self.mark_playlists_changed();
+
+        let find_index = |name: Option<String>| {
+            name.and_then(|name| self.playlists.iter().position(|x| x.name == name))
+        };
+        self.selected_playlist_index = find_index(selected_name).unwrap_or(0);
+        self.playing_playlist_index = find_index(playing_name);
+        self.renamed_playlist_index = find_index(renamed_name);
+        self.drag_playlist_index = find_index(dragged_name);
+        let preview_index = find_index(preview_name);
+        self.playlist_backup_preview = self.playlist_backup_preview.take().and_then(|mut x| {
+            x.0 = preview_index?;
+            Some(x)
+        });
+        let hovered_index = find_index(hovered_name);
+        self.drag_hover = self.drag_hover.and_then(|x| Some((hovered_index?, x.1)));
+    }
 
-            // Sort unused playlist below all playlist
-            if a.name == ALL_UNUSED_PLAYLIST_NAME && b.name != ALL_PLAYLIST_NAME {
-                return Ordering::Less;
+    /// Whether the song passes the search text and the filters of the song list.
+    pub fn is_song_shown(&self, song: &Song) -> bool {
+        if !self.song_search_query.is_empty() && !self.song_search_query.is_matching(self, song) {
+            return false;
+        }
+        if let Some(genre) = &self.genre_filter {
+            if !self
+                .tags
+                .get(&song.path)
+                .is_some_and(|x| x.genres.contains(genre))
+            {
+                return false;
             }
-            if a.name != ALL_PLAYLIST_NAME && b.name == ALL_UNUSED_PLAYLIST_NAME {
-                return Ordering::Greater;
+        }
+        if self.not_in_playlist_filter.is_some() && self.not_in_playlist_paths.contains(&song.path)
+        {
+            return false;
+        }
+        if self.missing_filter && song.exists {
+            return false;
+        }
+        true
+    }
+
+    /// Returns the indices of the shown songs of the selected playlist in the order they are shown.
+    pub fn get_shown_song_indices(&self) -> Vec<usize> {
+        let songs = &self.playlists[self.selected_playlist_index].songs;
+        let mut indices: Vec<usize> = (0..songs.len())
+            .filter(|x| self.is_song_shown(&songs[*x]) && !self.is_song_collapsed(&songs[*x]))
+            .collect();
+        if self.sort_type.is_some() {
+            indices.sort_by(|a, b| self.compare_songs(&songs[*a], &songs[*b]));
+        } else if !self.song_search_query.text.is_empty() {
+            indices.sort_by_cached_key(|x| Reverse(self.get_search_score(&songs[*x])));
+        }
+        indices
+    }
+
+    /// Returns the album or artist that the song is grouped by in the song list.
+    pub fn get_song_group(&self, song: &Song) -> Option<String> {
+        let tags = self.tags.get(&song.path);
+        match self.settings.song_grouping {
+            settings::SongGrouping::None => None,
+            settings::SongGrouping::Album => Some(
+                tags.and_then(|x| x.album.clone())
+                    .unwrap_or_else(|| "Unknown album".to_string()),
+            ),
+            settings::SongGrouping::Artist => Some(
+                tags.and_then(|x| x.artist.clone())
+                    .unwrap_or_else(|| song.artist.clone()),
+            ),
+        }
+    }
+
+    /// Whether the song is hidden because the header of its group is collapsed.
+    pub fn is_song_collapsed(&self, song: &Song) -> bool {
+        !self.collapsed_groups.is_empty()
+            && self
+                .get_song_group(song)
+                .is_some_and(|x| self.collapsed_groups.contains(&x))
+    }
+
+    /// Returns how well the song matches the search text, songs that only match by their note
+    /// are ranked last.
+    pub fn get_search_score(&self, song: &Song) -> i32 {
+        song.get_match_score(&self.song_search_query.text)
+            .unwrap_or(i32::MIN)
+    }
+
+    /// Compares the songs by the sorted column.
+    pub fn compare_songs(&self, a: &Song, b: &Song) -> Ordering {
+        let sort_type = match &self.sort_type {
+            Some(sort_type) => sort_type,
+            None => return Ordering::Equal,
+        };
+        let tags_a = self.tags.get(&a.path);
+        let tags_b = self.tags.get(&b.path);
+        let ordering = match sort_type.column {
+            Column::Song => a.compare_by(b, SongField::Name),
+            Column::Artist => a.compare_by(b, SongField::Artist),
+            Column::Album => {
+                let get_album = |x: Option<&tags::Tags>| {
+                    x.and_then(|x| x.album.as_ref()).map(|x| x.to_lowercase())
+                };
+                get_album(tags_a).cmp(&get_album(tags_b))
             }
+            Column::Duration => a.compare_by(b, SongField::Duration),
+            Column::Rating => self.get_rating(&a.path).cmp(&self.get_rating(&b.path)),
+            Column::Plays => self
+                .play_counts
+                .get(&a.path)
+                .cmp(&self.play_counts.get(&b.path)),
+            Column::Path => a.compare_by(b, SongField::Path),
+        };
+        sort_type.direction.apply_direction(ordering)
+    }
 
-            // Sort directory playlists below everything else
-            if a.name.contains('.') && !b.name.contains('.') {
-                return Ordering::Greater;
+    /// Returns the rating set in the player or otherwise the rating from the tags.
+    pub fn get_rating(&self, path: &str) -> Option<u8> {
+        self.ratings
+            .get(path)
+            .copied()
+            .or_else(|| self.tags.get(path).and_then(|x| x.rating))
+    }
+
+    /// Returns the text of the song shown in the column.
+    pub fn get_column_text(&self, column: Column, song: &Song) -> String {
+        let tags = self.tags.get(&song.path);
+        match column {
+            Column::Song => song.name.clone(),
+            Column::Artist => song.artist.clone(),
+            Column::Album => tags.and_then(|x| x.album.clone()).unwrap_or_default(),
+            Column::Duration => util::ms_to_string(song.duration.unwrap_or(0)),
+            Column::Rating => match self.get_rating(&song.path) {
+                Some(rating) => "★".repeat(rating as usize) + &"☆".repeat(5 - rating as usize),
+                None => String::new(),
+            },
+            Column::Plays => self
+                .play_counts
+                .get(&song.path)
+                .map(|x| x.to_string())
+                .unwrap_or_default(),
+            Column::Path => song.path.clone(),
+        }
+    }
+
+    /// Returns whether the playlist is hidden inside a collapsed folder.
+    pub fn is_playlist_hidden(&self, playlist_index: usize) -> bool {
+        let folders = util::get_playlist_folders(&self.playlists[playlist_index].name);
+        (1..=folders.len()).any(|n| {
+            self.collapsed_folders
+                .contains(&folders[..n].join(".").to_lowercase())
+        })
+    }
+
+    /// Counts the playlists of each folder, including the playlists of its subfolders, once the
+    /// playlists change.
+    pub fn update_playlist_folder_counts(&mut self) {
+        if self.playlist_folder_counts_version == Some(self.playlists_version) {
+            return;
+        }
+        self.playlist_folder_counts_version = Some(self.playlists_version);
+        self.playlist_folder_counts.clear();
+        for playlist in self.playlists.iter() {
+            let folders = util::get_playlist_folders(&playlist.name);
+            for n in 1..=folders.len() {
+                *self
+                    .playlist_folder_counts
+                    .entry(folders[..n].join(".").to_lowercase())
+                    .or_insert(0) += 1;
             }
-            if !a.name.contains('.') && b.name.contains('.') {
-                return Ordering::Less;
+        }
+    }
+
+    /// Collects the song paths hidden by the "Not in" filter once the filter or the playlists
+    /// change. The filter is removed if its playlist no longer exists.
+    pub fn update_not_in_playlist_paths(&mut self) {
+        let key = self
+            .not_in_playlist_filter
+            .clone()
+            .map(|x| (x, self.playlists_version));
+        if key == self.not_in_playlist_key {
+            return;
+        }
+        self.not_in_playlist_paths = match &self.not_in_playlist_filter {
+            Some(NotInFilter::Playlist(name)) => {
+                match self.playlists.iter().find(|x| &x.name == name) {
+                    Some(playlist) => model::get_song_paths([playlist]),
+                    None => {
+                        self.not_in_playlist_filter = None;
+                        HashSet::new()
+                    }
+                }
             }
-            a.name.to_lowercase().cmp(&b.name.to_lowercase())
-        });
+            Some(NotInFilter::AnyPlaylist) => model::get_song_paths(
+                self.playlists
+                    .iter()
+                    .filter(|x| !util::is_default_playlist(&x.name)),
+            ),
+            None => HashSet::new(),
+        };
+        self.not_in_playlist_key = key.filter(|_| self.not_in_playlist_filter.is_some());
+    }
+
+    /// Runs work that writes into files in a background thread, which is waited for on exit so that
+    /// no file is left half written.
+    pub fn spawn_writer(&mut self, f: impl FnOnce() + Send + 'static) {
+        self.writer_threads.retain(|x| !x.is_finished());
+        self.writer_threads.push(thread::spawn(f));
+    }
+
+    /// Requests a change of the model that is applied once the frame is drawn.
+    pub fn emit(&mut self, action: Action) {
+        self.pending_actions.push(action);
+    }
+
+    /// Records that playlists were added, removed or renamed or that their songs changed.
+    pub fn mark_playlists_changed(&mut self) {
+        self.playlists_version += 1;
     }
-}
 
-pub fn initialize(hwnd: Option<*mut ffi::c_void>) -> State {
-    let args: Vec<String> = env::args().collect();
+    /// Records that the tags, notes, ratings or play counts of songs changed.
+    pub fn mark_song_data_changed(&mut self) {
+        self.song_data_version += 1;
+    }
 
-    let base_path = if args.len() >= 2 {
-        if fs::metadata(&args[1]).is_err() || !fs::metadata(&args[1]).unwrap().is_dir() {
-            println!("Please pass a directory");
-            std::process::exit(1);
+    pub fn push_status(&mut self, r#type: StatusType, info: String) {
+        let status = Status {
+            info,
+            timestamp: Instant::now(),
+            time: SystemTime::now(),
+            r#type,
+        };
+        // Progress is replaced continuously and only interesting while it is shown
+        if !matches!(r#type, StatusType::Progress) {
+            if self.notifications.len() >= NOTIFICATION_LIMIT {
+                self.notifications.pop_front();
+            }
+            self.notifications.push_back(status.clone());
         }
-        args[1].clone()
-    } else {
+        self.status_queue.push_back(status);
+    }
+}
+
+/// Returns the music directory passed as argument or the directory of the executable.
+pub fn get_base_path(directory: Option<String>) -> String {
+    directory.unwrap_or_else(|| {
         let mut exe = env::current_exe().expect("Could not get current directory");
         exe.pop();
         exe.to_string_lossy().to_string()
-    };
+    })
+}
 
-    let music_extensions = vec!["flac", "mp3", "m4a", "ogg", "wav"];
+/// The part of the state that outlives a music directory: the player, the media controls,
+/// the renderer, the textures and the notifications.
+struct Session {
+    player_thread: Option<JoinHandle<()>>,
+    action_tx: Sender<player::PlayerAction>,
+    song_ended_rx: Receiver<()>,
+    player_error_rx: Receiver<String>,
+    song_ended_tx: Sender<()>,
+    position: Arc<Mutex<u64>>,
+    volume: f32,
+    volume_before_mute: Option<f32>,
+    media_controls: Option<MediaControls>,
+    media_controls_rx: Receiver<MediaControlEvent>,
+    renderer: Option<dlna::Renderer>,
+    renderers: Vec<dlna::Device>,
+    renderers_rx: Option<Receiver<dlna::Device>>,
+    writer_threads: Vec<JoinHandle<()>>,
+    status_queue: VecDeque<Status>,
+    notifications: VecDeque<Status>,
+    cover_texture: Option<(TextureId, [f32; 2])>,
+    thumbnail_atlas: Option<TextureId>,
+    yt_dlp_updater: Option<download::Process>,
+}
+
+impl Session {
+    /// Takes the session out of the state, which must be rebuilt afterwards.
+    fn take(state: &mut State) -> Session {
+        Session {
+            player_thread: state.player_thread.take(),
+            action_tx: state.action_tx.clone(),
+            song_ended_rx: mem::replace(&mut state.song_ended_rx, mpsc::channel().1),
+            player_error_rx: mem::replace(&mut state.player_error_rx, mpsc::channel().1),
+            song_ended_tx: state.song_ended_tx.clone(),
+            position: state.position.clone(),
+            volume: state.volume,
+            volume_before_mute: state.volume_before_mute,
+            media_controls: state.media_controls.take(),
+            media_controls_rx: mem::replace(&mut state.media_controls_rx, mpsc::sync_channel(0).1),
+            renderer: state.renderer.take(),
+            renderers: mem::take(&mut state.renderers),
+            renderers_rx: state.renderers_rx.take(),
+            writer_threads: mem::take(&mut state.writer_threads),
+            status_queue: mem::take(&mut state.status_queue),
+            notifications: mem::take(&mut state.notifications),
+            cover_texture: state.cover_texture.take(),
+            thumbnail_atlas: state.thumbnail_atlas.take(),
+            yt_dlp_updater: state.yt_dlp_updater.take(),
+        }
+    }
+}
 
+/// Creates the state with the settings that were already loaded to create the window.
+pub fn initialize(
+    hwnd: Option<*mut ffi::c_void>,
+    base_path: String,
+    settings: settings::Settings,
+) -> State {
     let (action_tx, action_rx) = mpsc::channel();
     let (song_ended_tx, song_ended_rx) = mpsc::channel();
     let position = Arc::new(Mutex::new(0));
     let thread_position = position.clone();
+    let thread_song_ended_tx = song_ended_tx.clone();
+    let (player_error_tx, player_error_rx) = mpsc::channel();
+
+    let player_thread = thread::spawn(|| {
+        player::run(
+            action_rx,
+            thread_song_ended_tx,
+            player_error_tx,
+            thread_position,
+        )
+    });
 
-    let player_thread = thread::spawn(|| player::run(action_rx, song_ended_tx, thread_position));
+    let (media_controls_tx, media_controls_rx) = mpsc::sync_channel(32);
+    let media_controls = create_media_controls(hwnd, media_controls_tx);
 
-    let config = PlatformConfig {
-        dbus_name: "ImPlayer",
-        display_name: "ImPlayer",
-        hwnd,
+    let session = Session {
+        player_thread: Some(player_thread),
+        action_tx,
+        song_ended_rx,
+        player_error_rx,
+        song_ended_tx,
+        position,
+        volume: DEFAULT_VOLUME,
+        volume_before_mute: None,
+        media_controls: None,
+        media_controls_rx,
+        renderer: None,
+        renderers: Vec::new(),
+        renderers_rx: None,
+        writer_threads: Vec::new(),
+        status_queue: VecDeque::new(),
+        notifications: VecDeque::new(),
+        cover_texture: None,
+        thumbnail_atlas: None,
+        yt_dlp_updater: None,
     };
-    let mut media_controls = MediaControls::new(config).unwrap();
-    let (media_controls_tx, media_controls_rx) = mpsc::sync_channel(32);
-    media_controls
-        .attach(move |e| media_controls_tx.send(e).unwrap())
-        .unwrap();
+    let mut state = create_state(base_path, settings, session);
+    match media_controls {
+        Ok(media_controls) => state.media_controls = Some(media_controls),
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not connect to the media controls:\n{e}"),
+        ),
+    }
+    load_library(&mut state);
 
-    let mut state = State {
+    state
+}
+
+/// Replaces everything that belongs to the previous music directory with the library of
+/// `base_path`, keeping only the session.
+pub fn reopen(state: &mut State, base_path: String, settings: settings::Settings) {
+    let session = Session::take(state);
+    *state = create_state(base_path, settings, session);
+    load_library(state);
+}
+
+/// Creates the state of a music directory before its library is loaded.
+fn create_state(base_path: String, settings: settings::Settings, session: Session) -> State {
+    let Session {
+        player_thread,
+        action_tx,
+        song_ended_rx,
+        player_error_rx,
+        song_ended_tx,
+        position,
+        volume,
+        volume_before_mute,
+        media_controls,
+        media_controls_rx,
+        renderer,
+        renderers,
+        renderers_rx,
+        writer_threads,
+        status_queue,
+        notifications,
+        cover_texture,
+        thumbnail_atlas,
+        yt_dlp_updater,
+    } = session;
+    State {
         base_path,
         playlists: Vec::new(),
+        archived_playlists: Vec::new(),
+        show_archived_playlists: false,
+        collapsed_folders: HashSet::new(),
+        playlist_folder_counts: HashMap::new(),
+        playlist_folder_counts_version: None,
+        collapsed_groups: HashSet::new(),
+        song_list: None,
+        song_context_menu_index: None,
+        focused_pane: FocusedPane::Songs,
+        type_ahead_text: String::new(),
+        type_ahead_time: Instant::now(),
+        vim_pending_keys: String::new(),
+        focus_song_search: false,
+        queued_songs: VecDeque::new(),
+        pending_actions: Vec::new(),
+        scroll_to_selected_playlist: false,
+        resized_column_index: None,
+        playlist_backup_preview: None,
+        playlist_backups: Vec::new(),
         selected_playlist_index: 0,
         selected_song_indices: Vec::new(),
         new_playlist_text: String::new(),
+        selection_playlist_text: String::new(),
         song_search_text: String::new(),
+        song_search_query: search::Query::default(),
         has_textbox_focus: false,
         sort_type: None,
+        genre_filter: None,
+        not_in_playlist_filter: None,
+        not_in_playlist_paths: HashSet::new(),
+        not_in_playlist_key: None,
+        missing_filter: false,
+
+        tags: HashMap::new(),
+        play_counts: HashMap::new(),
+        song_added_times: HashMap::new(),
+        tags_rx: None,
+        library_rx: None,
+        library_progress: String::new(),
 
         dragged_songs: Vec::new(),
+        copied_songs: Vec::new(),
+        dragged_song_indices: Vec::new(),
+        drag_playlist_index: None,
+        drag_hover: None,
+        dropped_paths: Vec::new(),
+        dropped_files_job: Job::default(),
+        ime_preedit: String::new(),
 
         original_file_name: String::new(),
         file_name_text: String::new(),
+        rename_pattern_text: DEFAULT_RENAME_PATTERN.to_string(),
+        organize_template_text: DEFAULT_ORGANIZE_TEMPLATE.to_string(),
+        organize_preview: None,
+        import_path_text: import::find_default_library().unwrap_or_default(),
+        streaming_import_text: String::new(),
+        playlist_listing: None,
+        missing_streaming_tracks: None,
+        export_path_text: String::new(),
+        music_directory_text: String::new(),
+        add_files_text: String::new(),
+        remote_address_text: settings.remote_control_address.clone(),
+        pending_music_directory: None,
+        fallback_font_text: String::new(),
+        renamed_playlist_index: None,
+        playlist_name_text: String::new(),
+        focus_playlist_name_textbox: false,
+        deleted_playlist_name: None,
+        delete_playlist_file: false,
+        pending_deletions: Vec::new(),
+        show_exit_confirmation: false,
+        should_exit: false,
+        settings,
+        theme: theme::Theme::default(),
+        playlists_version: 0,
+        song_data_version: 0,
+        auto_save_changes: None,
+        auto_save_version: None,
+        last_playlist_check: Instant::now(),
+        externally_changed_playlist_name: None,
+        notes: HashMap::new(),
+        ratings: HashMap::new(),
+        note_text: String::new(),
 
         download_text: String::new(),
-        download_playlist_index: None,
-        download_path: None,
-        download_state: DownloadState::None,
-        last_download_status: None,
-
-        status_queue: VecDeque::new(),
+        download_jobs: Vec::new(),
+        download_options: download::DownloadOptions::default(),
+        programs: download::Programs::default(),
+        yt_dlp_updater,
+        download_search_text: String::new(),
+        download_search: None,
+        download_search_results: Vec::new(),
+        show_downloads: false,
+        show_sync: false,
+        sync_rx: None,
+        sync_progress: None,
+        postprocessing_job: Job::default(),
+        writer_threads,
+
+        status_queue,
+        notifications,
+        show_notifications: false,
+        show_keymap: false,
+        show_shortcut_help: false,
+        rebound_command: None,
+
+        duplicates_job: Job::default(),
+        duplicates_playlist_name: String::new(),
+        duplicate_groups: Vec::new(),
+        replaygain_job: Job::default(),
+        show_duplicates: false,
+        compared_playlist_names: None,
+        shuffle_undo: None,
+        fingerprints: HashMap::new(),
 
         playing_playlist_index: None,
         playing_song_index: None,
 
         is_playing: false,
-        volume: 0.93,
+        volume,
+        volume_before_mute,
         player_thread,
         action_tx,
         song_ended_rx,
+        player_error_rx,
+        song_ended_tx,
         last_progress: None,
         position,
         media_controls,
         media_controls_rx,
+        media_cover_url: None,
+        media_cover_rx: None,
+        renderer,
+        renderers,
+        renderers_rx,
+
+        show_lyrics: false,
+        lyrics: None,
+        lyrics_song_path: None,
+        lyrics_current_line: None,
+
+        show_now_playing: false,
+        cover_song_path: None,
+        cover_rx: None,
+        loaded_cover: None,
+        cover_texture,
+        show_album_grid: false,
+        album_thumbnails: HashMap::new(),
+        requested_thumbnails: HashSet::new(),
+        thumbnails_rx: None,
+        loaded_thumbnails: Vec::new(),
+        thumbnail_atlas,
+
+        playlists_scroll_info: ScrollInfo::default(),
+        songs_scroll_info: ScrollInfo::default(),
+        followed_song: None,
+        scrolling_song: None,
+        add_to_menu_scroll_info: ScrollInfo::default(),
+        lyrics_scroll_info: ScrollInfo::default(),
+        now_playing_scroll_info: ScrollInfo::default(),
+        download_search_scroll_info: ScrollInfo::default(),
+        download_queue_scroll_info: ScrollInfo::default(),
+    }
+}
 
-        playlists_scroll_info: ScrollInfo {
-            is_scrolling: false,
-            scroll_start_time: Instant::now(),
-            scroll_duration: Duration::from_millis(200),
-            scroll_target_y: 0.0,
-        },
-        songs_scroll_info: ScrollInfo {
-            is_scrolling: false,
-            scroll_start_time: Instant::now(),
-            scroll_duration: Duration::from_millis(200),
-            scroll_target_y: 0.0,
-        },
-        add_to_menu_scroll_info: ScrollInfo {
-            is_scrolling: false,
-            scroll_start_time: Instant::now(),
-            scroll_duration: Duration::from_millis(200),
-            scroll_target_y: 0.0,
-        },
+/// Registers the player with the media controls of the system, which send their events to
+/// `sender`.
+fn create_media_controls(
+    hwnd: Option<*mut ffi::c_void>,
+    sender: SyncSender<MediaControlEvent>,
+) -> Result<MediaControls, String> {
+    let config = PlatformConfig {
+        dbus_name: "ImPlayer",
+        display_name: "ImPlayer",
+        hwnd,
     };
+    let mut media_controls = MediaControls::new(config).map_err(|e| format!("{e:?}"))?;
+    media_controls
+        .attach(move |e| {
+            let _ = sender.send(e);
+        })
+        .map_err(|e| format!("{e:?}"))?;
+    Ok(media_controls)
+}
 
-    // Parse songs
-    let mut songs = Vec::new();
-    for file in fs::read_dir(&state.base_path).unwrap().filter(|x| {
-        x.as_ref().unwrap().file_type().unwrap().is_file()
-            && music_extensions.contains(
-                &x.as_ref()
-                    .unwrap()
-                    .path()
-                    .extension()
-                    .map_or("", |e| e.to_str().unwrap_or("")),
-            )
-    }) {
-        let path = file.as_ref().unwrap().path();
-        songs.push(Song::new(path, &state.base_path, None));
-    }
-
-    // Parse playlists
-    for file in fs::read_dir(&state.base_path).unwrap().filter(|x| {
-        x.as_ref().unwrap().file_type().unwrap().is_file()
-            && x.as_ref().unwrap().path().extension() == Some(ffi::OsStr::new("m3u"))
-    }) {
-        let playlist_name = file
-            .as_ref()
-            .unwrap()
-            .path()
-            .file_stem()
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        let content = fs::read_to_string(file.as_ref().unwrap().path()).unwrap();
-        let lines: Vec<&str> = content.lines().collect();
-        let mut playlist_songs = Vec::<Song>::new();
-        for line in (1..lines.len()).step_by(2) {
-            let info = lines[line]
-                .split_once(":")
-                .unwrap()
-                .1
-                .split_once(",")
-                .unwrap();
-            let duration = info.0.parse::<u64>().unwrap() * 1000;
-            let path = lines[line + 1].to_string();
-
-            let s = songs.iter_mut().find(|x| x.path == path);
-            if s.is_none() {
-                // Song will be added with exists = false
-                playlist_songs.push(Song::new(
-                    PathBuf::from(&state.base_path).join(&path),
-                    &state.base_path,
-                    Some(duration),
-                ));
-                continue;
+/// Loads the data of the music directory into the state and starts loading its songs and playlists
+/// in the background.
+pub fn load_library(state: &mut State) {
+    library::start_loading(state);
+
+    // Collect archived playlists, they are only parsed when being restored
+    if let Ok(entries) = fs::read_dir(Path::new(&state.base_path).join(ARCHIVE_DIRECTORY)) {
+        for file in entries.filter_map(|x| x.ok()) {
+            let path = file.path();
+            if let (true, Some(_), Some(name)) = (
+                path.is_file() && !playlist_file::is_backup(&path),
+                PlaylistFormat::from_path(&path),
+                path.file_stem(),
+            ) {
+                state
+                    .archived_playlists
+                    .push(name.to_string_lossy().to_string());
             }
-            let s = s.unwrap();
-            if s.duration.is_none() {
-                if duration == 0 {
-                    s.duration = Some(player::get_duration(
-                        &Path::new(&state.base_path).join(&s.path),
-                    ));
-                } else {
-                    s.duration = Some(duration);
-                }
-            }
-
-            playlist_songs.push(s.clone());
-        }
-        state
-            .playlists
-            .push(Playlist::new(playlist_name, playlist_songs));
-    }
-
-    // Add All and All Unused playlists
-    let mut unused_songs = Vec::new();
-    for song in songs.iter_mut() {
-        let mut is_song_used = false;
-        // Check if song is used in any playlist
-        for playlist in state.playlists.iter() {
-            let mut hasher = DefaultHasher::new();
-            song.hash(&mut hasher);
-            let hash = hasher.finish();
-            if playlist.songs.iter().any(|s| {
-                let mut hasher = DefaultHasher::new();
-                s.hash(&mut hasher);
-                hasher.finish() == hash
-            }) {
-                is_song_used = true;
-                break;
-            }
-        }
-        if is_song_used {
-            continue;
         }
+        state.archived_playlists.sort_by_key(|x| x.to_lowercase());
+        state.archived_playlists.dedup();
+    }
 
-        song.duration = Some(player::get_duration(
-            &Path::new(&state.base_path).join(&song.path),
-        ));
-        unused_songs.push(song.clone());
+    state.notes = notes::load(&state.base_path);
+    state.ratings = ratings::load(&state.base_path);
+    state.mark_song_data_changed();
+    match theme::load(&state.base_path) {
+        Ok(theme) => state.theme = theme,
+        Err(e) => state.push_status(StatusType::Error, format!("Could not load theme:\n{e}")),
     }
-    state.playlists.push(Playlist::new(
-        ALL_UNUSED_PLAYLIST_NAME.to_string(),
-        unused_songs,
-    ));
-    state
-        .playlists
-        .push(Playlist::new(ALL_PLAYLIST_NAME.to_string(), songs.clone()));
+    // The accent chosen in the player takes precedence over the theme file
+    if let Some(hue) = state.settings.accent_hue {
+        state.theme.set_accent_hue(hue);
+    }
+    download::detect_programs(state);
+    state.collapsed_folders = fs::read_to_string(
+        Path::new(&state.base_path)
+            .join(DATA_DIRECTORY)
+            .join(COLLAPSED_FOLDERS_FILE_NAME),
+    )
+    .unwrap_or_default()
+    .lines()
+    .map(|x| x.to_lowercase())
+    .collect();
 
     state.sort_playlists();
+}
 
-    state
+/// Collects music files in the directory and its subdirectories. Hidden directories, like the data
+/// directory, are skipped.
+pub fn find_song_files(directory: &Path, song_paths: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|x| x.ok()) {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                find_song_files(&path, song_paths);
+            }
+        } else if file_type.is_file() && model::is_music_file(&path) {
+            song_paths.push(path);
+        }
+    }
 }
 
-pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f32) -> bool {
-    //println!("Draw");
-    if let Ok(()) = state.song_ended_rx.try_recv() {
-        actions::next(state);
+/// Receives the results of the background work and saves changes. Called on every iteration of
+/// the event loop since a hidden window is not redrawn.
+pub fn update(state: &mut State) {
+    if let Some(tags_rx) = &state.tags_rx {
+        let received_tags = util::receive_all(tags_rx);
+        if !received_tags.is_empty() {
+            state.tags.extend(received_tags);
+            state.mark_song_data_changed();
+        }
+    }
+    state
+        .status_queue
+        .retain(|x| (Instant::now() - x.timestamp).as_secs() < 3);
+    library::update(state);
+    download::update(state);
+    download::update_yt_dlp_updater(state);
+    download::update_search(state);
+    download::update_playlist_listing(state);
+    download::update_postprocessing(state);
+    actions::update_dropped_files(state);
+    duplicates::update(state);
+    replaygain::update(state);
+    sync::update(state);
+    actions::update_auto_save(state);
+    actions::check_playlist_files(state);
+    if !state.dropped_paths.is_empty() && !state.dropped_files_job.is_running() {
+        let paths = std::mem::take(&mut state.dropped_paths);
+        actions::add_dropped_files(state, paths);
     }
+}
 
+pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f32) -> bool {
+    let theme = state.theme;
     let playlists_width;
     {
         let longest_playlist_name = &state
@@ -506,7 +1198,9 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
     }
     let style = ui.clone_style();
 
-    let song_scroll_index = handle_keyboard_shortcuts(ui, state);
+    state.update_not_in_playlist_paths();
+    let song_scroll_index =
+        handle_keyboard_shortcuts(ui, state).or_else(|| get_followed_song_position(state));
 
     state.has_textbox_focus = false;
     ui.window("main_window")
@@ -517,6 +1211,7 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
         .movable(false)
         .collapsible(false)
         .draw_background(true)
+        .bring_to_front_on_focus(false)
         .build(|| {
             ui.child_window("playlists")
                 .size([playlists_width, height - TEXTBOXES_HEIGHT - CONTROLS_HEIGHT])
@@ -526,12 +1221,16 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
                         .add_rect(
                             [0.0, 0.0],
                             [playlists_width, height - CONTROLS_HEIGHT],
-                            PLAYLIST_LIST_BG,
+                            theme.dark1,
                         )
                         .filled(true)
                         .build();
-                    apply_smooth_scrolling(ui, scroll_delta, &mut state.playlists_scroll_info);
-                    draw_playlists(ui, state);
+                    widgets::apply_smooth_scrolling(
+                        ui,
+                        scroll_delta,
+                        &mut state.playlists_scroll_info,
+                    );
+                    draw_playlists(ui, state, scroll_delta);
                 });
 
             ui.set_cursor_pos([0.0, height - TEXTBOXES_HEIGHT - CONTROLS_HEIGHT]);
@@ -543,40 +1242,147 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
                         .add_rect(
                             [0.0, 0.0],
                             [playlists_width, height - CONTROLS_HEIGHT],
-                            PLAYLIST_LIST_BG,
+                            theme.dark1,
                         )
                         .filled(true)
                         .build();
                     draw_textboxes(ui, &style, state);
                 });
 
+            let lyrics_width = if state.show_lyrics { LYRICS_WIDTH } else { 0.0 };
+            let now_playing_width = if state.show_now_playing {
+                NOW_PLAYING_WIDTH
+            } else {
+                0.0
+            };
+            // The Now Playing panel is left of the lyrics panel
+            let side_panels_width = lyrics_width + now_playing_width;
             let mut scrollbar_width = 0.0;
-            ui.set_cursor_pos([playlists_width, SONGS_HEADER_HEIGHT]);
+            // The selection bar is shown below the songs while several songs are selected
+            let selection_bar_height = if state.selected_song_indices.len() > 1 {
+                SELECTION_BAR_HEIGHT
+            } else {
+                0.0
+            };
+            ui.set_cursor_pos([playlists_width, FILTER_BAR_HEIGHT + SONGS_HEADER_HEIGHT]);
             ui.child_window("songs")
                 .size([
-                    width - playlists_width,
-                    height - CONTROLS_HEIGHT - SONGS_HEADER_HEIGHT,
+                    width - playlists_width - side_panels_width,
+                    height
+                        - CONTROLS_HEIGHT
+                        - FILTER_BAR_HEIGHT
+                        - SONGS_HEADER_HEIGHT
+                        - selection_bar_height,
                 ])
                 .movable(false)
                 .build(|| {
-                    apply_smooth_scrolling(ui, scroll_delta, &mut state.songs_scroll_info);
-                    if draw_songs(ui, state, song_scroll_index, scroll_delta) {
+                    widgets::apply_smooth_scrolling(ui, scroll_delta, &mut state.songs_scroll_info);
+                    let has_scrollbar = if state.show_album_grid {
+                        draw_album_grid(ui, state)
+                    } else {
+                        draw_songs(ui, state, song_scroll_index, scroll_delta)
+                    };
+                    if has_scrollbar {
                         scrollbar_width = style.scrollbar_size
                     }
                 });
 
+            if selection_bar_height > 0.0 {
+                ui.set_cursor_pos([
+                    playlists_width,
+                    height - CONTROLS_HEIGHT - selection_bar_height,
+                ]);
+                ui.child_window("selection_bar")
+                    .size([
+                        width - playlists_width - side_panels_width,
+                        selection_bar_height,
+                    ])
+                    .movable(false)
+                    .build(|| {
+                        ui.get_window_draw_list()
+                            .add_rect([0.0, 0.0], [width, height], theme.dark1)
+                            .filled(true)
+                            .build();
+                        draw_selection_bar(ui, state, scroll_delta);
+                    });
+            }
+
             ui.set_cursor_pos([playlists_width, 0.0]);
+            ui.child_window("filter_bar")
+                .size([
+                    width - playlists_width - side_panels_width,
+                    FILTER_BAR_HEIGHT,
+                ])
+                .movable(false)
+                .build(|| {
+                    ui.get_window_draw_list()
+                        .add_rect([0.0, 0.0], [width, FILTER_BAR_HEIGHT], theme.dark1)
+                        .filled(true)
+                        .build();
+                    draw_filter_bar(ui, &style, state);
+                });
+
+            ui.set_cursor_pos([playlists_width, FILTER_BAR_HEIGHT]);
             ui.child_window("songs_header")
-                .size([width - playlists_width, SONGS_HEADER_HEIGHT])
+                .size([
+                    width - playlists_width - side_panels_width,
+                    SONGS_HEADER_HEIGHT,
+                ])
                 .movable(false)
                 .build(|| {
                     ui.get_window_draw_list()
-                        .add_rect([0.0, 0.0], [width, SONGS_HEADER_HEIGHT], SONGS_HEADER_BG)
+                        .add_rect([0.0, 0.0], [width, SONGS_HEADER_HEIGHT], theme.dark1)
                         .filled(true)
                         .build();
                     draw_songs_header(ui, state, scrollbar_width);
                 });
 
+            if state.show_now_playing {
+                ui.set_cursor_pos([width - side_panels_width, 0.0]);
+                ui.child_window("now_playing")
+                    .size([now_playing_width, height - CONTROLS_HEIGHT])
+                    .movable(false)
+                    .build(|| {
+                        ui.get_window_draw_list()
+                            .add_rect(
+                                [width - side_panels_width, 0.0],
+                                [width - lyrics_width, height - CONTROLS_HEIGHT],
+                                theme.dark1,
+                            )
+                            .filled(true)
+                            .build();
+                        widgets::apply_smooth_scrolling(
+                            ui,
+                            scroll_delta,
+                            &mut state.now_playing_scroll_info,
+                        );
+                        draw_now_playing(ui, state);
+                    });
+            }
+
+            if state.show_lyrics {
+                ui.set_cursor_pos([width - lyrics_width, 0.0]);
+                ui.child_window("lyrics")
+                    .size([lyrics_width, height - CONTROLS_HEIGHT])
+                    .movable(false)
+                    .build(|| {
+                        ui.get_window_draw_list()
+                            .add_rect(
+                                [width - lyrics_width, 0.0],
+                                [width, height - CONTROLS_HEIGHT],
+                                theme.dark1,
+                            )
+                            .filled(true)
+                            .build();
+                        widgets::apply_smooth_scrolling(
+                            ui,
+                            scroll_delta,
+                            &mut state.lyrics_scroll_info,
+                        );
+                        draw_lyrics(ui, state);
+                    });
+            }
+
             ui.set_cursor_pos([0.0, height - CONTROLS_HEIGHT]);
             ui.child_window("controls")
                 .size([width, CONTROLS_HEIGHT])
@@ -586,7 +1392,7 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
                         .add_rect(
                             [0.0, height - CONTROLS_HEIGHT],
                             [width, height],
-                            CONTROLS_BG,
+                            theme.dark1,
                         )
                         .filled(true)
                         .build();
@@ -598,228 +1404,710 @@ pub fn draw(ui: &Ui, width: f32, height: f32, state: &mut State, scroll_delta: f
             {
                 ui.reset_mouse_drag_delta(MouseButton::Left);
                 state.dragged_songs.clear();
+                state.dragged_song_indices.clear();
+                state.drag_playlist_index = None;
+                state.drag_hover = None;
             }
 
             // Drag
             if ui.is_mouse_dragging(MouseButton::Left) && !state.dragged_songs.is_empty() {
-                ui.get_foreground_draw_list()
-                    .add_circle(ui.io().mouse_pos, 10.0, DRAG)
-                    .filled(true)
-                    .build();
-                ui.get_foreground_draw_list().add_text(
-                    util::add_pos(ui.io().mouse_pos, [5.0, -20.0]),
-                    TEXT1,
-                    state.dragged_songs.len().to_string(),
-                );
+                widgets::draw_drag_indicator(ui, &theme, state.dragged_songs.len());
             }
 
-            state
-                .status_queue
-                .retain(|x| (Instant::now() - x.timestamp).as_secs() < 3);
-            download::update(state);
+            draw_delete_confirmation(ui, state);
+            draw_playlist_delete_confirmation(ui, state);
+            draw_exit_confirmation(ui, state);
+            draw_external_change_confirmation(ui, state);
+            draw_missing_tracks_confirmation(ui, state);
             draw_statuses(ui, state);
         });
 
+    if state.show_duplicates {
+        draw_duplicates(ui, state);
+    }
+    if state.compared_playlist_names.is_some() {
+        draw_playlist_comparison(ui, state);
+    }
+    if state.show_downloads {
+        draw_downloads(ui, state, scroll_delta);
+    }
+    if state.show_sync {
+        draw_sync(ui, state);
+    }
+    if state.show_notifications {
+        draw_notifications(ui, state);
+    }
+    if state.show_shortcut_help {
+        draw_shortcut_help(ui, state, width, height);
+    }
+    if state.show_keymap {
+        draw_keymap(ui, state);
+    } else {
+        state.rebound_command = None;
+    }
+    draw_ime_preedit(ui, state);
+
     state.is_playing
+        || state.drag_hover.is_some()
         || state.playlists_scroll_info.is_scrolling
         || state.songs_scroll_info.is_scrolling
         || state.add_to_menu_scroll_info.is_scrolling
+        || state.scrolling_song.is_some()
+        || state.lyrics_scroll_info.is_scrolling
+        || state.now_playing_scroll_info.is_scrolling
+        || state.download_search_scroll_info.is_scrolling
+        || state.download_queue_scroll_info.is_scrolling
+        || state.cover_rx.is_some()
+        || state.thumbnails_rx.is_some()
+        || state.renderers_rx.is_some()
+        || state.library_rx.is_some()
 }
 
 pub fn handle_keyboard_shortcuts(ui: &Ui, state: &mut State) -> Option<usize> {
     let mut song_scroll_index = None;
-    if !state.has_textbox_focus {
-        if ui.is_key_pressed_no_repeat(Key::Space) {
-            if state.is_playing {
-                actions::pause(state);
-            } else {
-                actions::resume(state);
-            }
+    if !state.has_textbox_focus && state.rebound_command.is_none() {
+        // Typed letters are commands in the vim mode
+        song_scroll_index = if state.settings.vim_mode {
+            handle_vim_keys(ui, state)
+        } else {
+            handle_type_ahead(ui, state)
+        };
+        // Typed characters continue the search instead of triggering their shortcuts
+        let is_typing_ahead = !state.type_ahead_text.is_empty();
+        let pressed_commands: Vec<Command> = Command::ALL
+            .into_iter()
+            .filter(|x| state.settings.keymap.is_pressed(ui, *x, is_typing_ahead))
+            .collect();
+        let is_pressed = |command| pressed_commands.contains(&command);
+
+        if is_pressed(Command::RenamePlaylist)
+            && !util::is_default_playlist(&state.playlists[state.selected_playlist_index].name)
+        {
+            start_playlist_rename(state, state.selected_playlist_index);
+        }
+        if is_pressed(Command::SaveAllPlaylists) {
+            state.emit(Action::SaveAllPlaylists);
+        }
+        if is_pressed(Command::SavePlaylist) {
+            state.emit(Action::SavePlaylist(state.selected_playlist_index));
+        }
+        if is_pressed(Command::PlayPause) {
+            state.emit(Action::TogglePlayback);
         }
-        if ui.io().key_ctrl && ui.is_key_pressed_no_repeat(Key::RightArrow) {
-            actions::next(state);
+        if is_pressed(Command::ToggleMute) {
+            state.emit(Action::ToggleMute);
         }
-        if ui.io().key_ctrl && ui.is_key_pressed_no_repeat(Key::LeftArrow) {
-            actions::prev(state);
+        if is_pressed(Command::Next) {
+            state.emit(Action::Next);
         }
-        if ui.io().key_ctrl && ui.is_key_pressed_no_repeat(Key::A) {
+        if is_pressed(Command::Prev) {
+            state.emit(Action::Prev);
+        }
+        if is_pressed(Command::ToggleLyrics) {
+            state.show_lyrics = !state.show_lyrics;
+        }
+        if is_pressed(Command::ToggleNowPlaying) {
+            state.show_now_playing = !state.show_now_playing;
+        }
+        if is_pressed(Command::ShowShortcutHelp) {
+            state.show_shortcut_help = !state.show_shortcut_help;
+        }
+        if is_pressed(Command::ToggleAlwaysOnTop) {
+            state.settings.always_on_top = !state.settings.always_on_top;
+            settings::save(state);
+        }
+        if is_pressed(Command::SelectAll) {
             state.selected_song_indices.clear();
             for (i, song) in state.playlists[state.selected_playlist_index]
                 .songs
                 .iter()
                 .enumerate()
             {
-                if !state.song_search_text.is_empty() && !song.is_matching(&state.song_search_text)
-                {
+                if !state.is_song_shown(song) {
                     continue;
                 }
                 state.selected_song_indices.push(i);
             }
         }
-        if ui.is_key_pressed_no_repeat(Key::Delete) && !state.selected_song_indices.is_empty() {
-            state.selected_song_indices.sort_unstable();
-            for i in state.selected_song_indices.iter().rev() {
-                // Update playing song index
-                if state.playing_song_index == Some(*i) {
-                    state.playing_song_index = Some(0.max(state.playing_song_index.unwrap() - 1));
-                } else if state.playing_song_index > Some(*i) {
-                    state.playing_song_index = Some(state.playing_song_index.unwrap() - 1);
-                }
-
-                state.playlists[state.selected_playlist_index]
-                    .songs
-                    .remove(*i);
-            }
-            state.selected_song_indices.clear();
+        if is_pressed(Command::RemoveSelection) && !state.selected_song_indices.is_empty() {
+            state.emit(Action::RemoveSelectedSongs);
         }
-
-        if ui.is_key_pressed(Key::J)
-            && !state.selected_song_indices.is_empty()
-            && state.song_search_text.is_empty()
-            && state.sort_type.is_none()
+        if is_pressed(Command::Copy) {
+            copy_selected_songs(ui, state);
+        }
+        if is_pressed(Command::Cut)
             && !util::is_default_playlist(&state.playlists[state.selected_playlist_index].name)
         {
-            // Move selection down
-            state.selected_song_indices.sort_unstable();
-            let playlist = &mut state.playlists[state.selected_playlist_index];
-            let mut last_index = None;
-            for selected_song_index in state.selected_song_indices.iter_mut().rev() {
-                if *selected_song_index < playlist.songs.len() - 1
-                    && (last_index.is_none() || last_index.unwrap() > *selected_song_index + 1)
-                {
-                    playlist
-                        .songs
-                        .swap(*selected_song_index, *selected_song_index + 1);
-
-                    // Update playing song index
-                    if state.playing_song_index == Some(*selected_song_index) {
-                        state.playing_song_index = Some(*selected_song_index + 1);
-                    } else if state.playing_song_index == Some(*selected_song_index + 1) {
-                        state.playing_song_index = Some(*selected_song_index);
-                    }
+            copy_selected_songs(ui, state);
+            state.emit(Action::RemoveSelectedSongs);
+        }
+        if is_pressed(Command::Paste) {
+            state.emit(Action::PasteSongs);
+        }
+        if is_pressed(Command::Queue) && !state.selected_song_indices.is_empty() {
+            state.emit(Action::QueueSelectedSongs);
+        }
 
-                    *selected_song_index += 1;
-                }
-                last_index = Some(*selected_song_index);
+        if is_pressed(Command::SwitchPane) {
+            state.focused_pane = match state.focused_pane {
+                FocusedPane::Playlists => FocusedPane::Songs,
+                FocusedPane::Songs => FocusedPane::Playlists,
+            };
+        }
+        let direction = if is_pressed(Command::SelectPrevious) {
+            Some(-1)
+        } else if is_pressed(Command::SelectNext) {
+            Some(1)
+        } else {
+            None
+        };
+        if let Some(direction) = direction {
+            match state.focused_pane {
+                FocusedPane::Playlists => select_next_playlist(state, direction),
+                FocusedPane::Songs => song_scroll_index = select_next_song(state, direction),
+            }
+        }
+        let extend_direction = if is_pressed(Command::ExtendSelectionUp) {
+            Some(-1)
+        } else if is_pressed(Command::ExtendSelectionDown) {
+            Some(1)
+        } else {
+            None
+        };
+        if let Some(direction) = extend_direction {
+            match state.focused_pane {
+                FocusedPane::Playlists => select_next_playlist(state, direction),
+                FocusedPane::Songs => song_scroll_index = extend_song_selection(state, direction),
             }
-            song_scroll_index = Some(*state.selected_song_indices.last().unwrap());
         }
-        if ui.is_key_pressed(Key::K)
+        if is_pressed(Command::PlaySelection) {
+            play_selection(state);
+        }
+
+        if is_pressed(Command::MoveSelectionDown)
             && !state.selected_song_indices.is_empty()
             && state.song_search_text.is_empty()
             && state.sort_type.is_none()
             && !util::is_default_playlist(&state.playlists[state.selected_playlist_index].name)
         {
-            // Move selection up
-            state.selected_song_indices.sort_unstable();
-            let playlist = &mut state.playlists[state.selected_playlist_index];
-            let mut last_index = None;
-            for selected_song_index in state.selected_song_indices.iter_mut() {
-                if *selected_song_index > 0
-                    && (last_index.is_none() || last_index.unwrap() < *selected_song_index - 1)
-                {
-                    playlist
-                        .songs
-                        .swap(*selected_song_index, *selected_song_index - 1);
+            state.emit(Action::MoveSelectedSongs { down: true });
+            // The last selected song ends up one row further down unless it is the last song
+            let last_index = state.playlists[state.selected_playlist_index].songs.len() - 1;
+            let max = *state.selected_song_indices.iter().max().unwrap();
+            song_scroll_index = Some((max + 1).min(last_index));
+        }
+        if is_pressed(Command::MoveSelectionUp)
+            && !state.selected_song_indices.is_empty()
+            && state.song_search_text.is_empty()
+            && state.sort_type.is_none()
+            && !util::is_default_playlist(&state.playlists[state.selected_playlist_index].name)
+        {
+            state.emit(Action::MoveSelectedSongs { down: false });
+            let min = *state.selected_song_indices.iter().min().unwrap();
+            song_scroll_index = Some(min.saturating_sub(1));
+        }
+    }
+    song_scroll_index
+}
 
-                    // Update playing song index
-                    if state.playing_song_index == Some(*selected_song_index) {
-                        state.playing_song_index = Some(*selected_song_index - 1);
-                    } else if state.playing_song_index == Some(*selected_song_index - 1) {
-                        state.playing_song_index = Some(*selected_song_index);
-                    }
+/// Selects the next shown playlist in the direction (-1 or 1), skipping collapsed folders.
+fn select_next_playlist(state: &mut State, direction: isize) {
+    let mut i = state.selected_playlist_index as isize;
+    loop {
+        i += direction;
+        if i < 0 || i as usize >= state.playlists.len() {
+            return;
+        }
+        if !state.is_playlist_hidden(i as usize) {
+            break;
+        }
+    }
+    state.selected_playlist_index = i as usize;
+    state.selected_song_indices.clear();
+    state.scroll_to_selected_playlist = true;
+}
 
-                    *selected_song_index -= 1;
-                }
-                last_index = Some(*selected_song_index);
+/// Selects the shown song after or before (-1) the last selected song and returns its position in
+/// the list to scroll to.
+fn select_next_song(state: &mut State, direction: isize) -> Option<usize> {
+    let shown_indices = state.get_shown_song_indices();
+    if shown_indices.is_empty() {
+        return None;
+    }
+    let position = match state
+        .selected_song_indices
+        .last()
+        .and_then(|x| shown_indices.iter().position(|y| y == x))
+    {
+        Some(position) => {
+            (position as isize + direction).clamp(0, shown_indices.len() as isize - 1) as usize
+        }
+        None if direction > 0 => 0,
+        None => shown_indices.len() - 1,
+    };
+    state.selected_song_indices = vec![shown_indices[position]];
+    Some(position)
+}
+
+/// Selects the next shown song whose name starts with the characters typed shortly after each
+/// other, like in file managers. Returns the position of the selected song.
+fn handle_type_ahead(ui: &Ui, state: &mut State) -> Option<usize> {
+    if state.type_ahead_time.elapsed().as_millis() >= TYPE_AHEAD_RESET_DELAY_MS {
+        state.type_ahead_text.clear();
+    }
+    if ui.io().key_ctrl || ui.io().key_alt || state.focused_pane != FocusedPane::Songs {
+        return None;
+    }
+    let mut typed_text = String::new();
+    for c in ui.io().input_queue_characters() {
+        let c = c.to_lowercase().next().unwrap_or(c);
+        let is_shortcut = state.type_ahead_text.is_empty()
+            && typed_text.is_empty()
+            && state.settings.keymap.is_character_bound(c);
+        if !c.is_control() && !is_shortcut {
+            typed_text.push(c);
+        }
+    }
+    if typed_text.is_empty() {
+        return None;
+    }
+    // A new search starts after the selected song to go through the songs with the same first
+    // letter, a continued search may still match the selected song
+    let is_new_search = state.type_ahead_text.is_empty();
+    state.type_ahead_text.push_str(&typed_text);
+    state.type_ahead_time = Instant::now();
+
+    let shown_indices = state.get_shown_song_indices();
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let start_position = match state
+        .selected_song_indices
+        .last()
+        .and_then(|x| shown_indices.iter().position(|y| y == x))
+    {
+        Some(position) if is_new_search => position + 1,
+        Some(position) => position,
+        None => 0,
+    };
+    let position = (0..shown_indices.len())
+        .map(|x| (start_position + x) % shown_indices.len())
+        .find(|x| {
+            songs[shown_indices[*x]]
+                .name
+                .to_lowercase()
+                .starts_with(&state.type_ahead_text)
+        })?;
+    state.selected_song_indices = vec![shown_indices[position]];
+    Some(position)
+}
+
+/// Selects the first or last shown song and returns its position.
+fn select_song_at_end(state: &mut State, is_last: bool) -> Option<usize> {
+    let shown_indices = state.get_shown_song_indices();
+    let position = if is_last {
+        shown_indices.len().checked_sub(1)?
+    } else if shown_indices.is_empty() {
+        return None;
+    } else {
+        0
+    };
+    state.selected_song_indices = vec![shown_indices[position]];
+    Some(position)
+}
+
+/// Handles the keys of the vim mode on top of the keymap: `gg`/`G` jump to the first/last song,
+/// `/` focuses the search, `dd` removes, `yy` copies and `p` pastes the selected songs and `x`
+/// queues them. Typed characters are used so that the keys work with any keyboard layout,
+/// characters that the keymap binds keep their shortcut. Returns the position of the selected
+/// song.
+fn handle_vim_keys(ui: &Ui, state: &mut State) -> Option<usize> {
+    if ui.io().key_ctrl || ui.io().key_alt {
+        state.vim_pending_keys.clear();
+        return None;
+    }
+    let mut song_scroll_index = None;
+    for c in ui.io().input_queue_characters() {
+        if c.is_lowercase() && state.settings.keymap.is_character_bound(c) {
+            state.vim_pending_keys.clear();
+            continue;
+        }
+        state.vim_pending_keys.push(c);
+        match state.vim_pending_keys.as_str() {
+            // Wait for the second key
+            "g" | "d" | "y" => continue,
+            "gg" => song_scroll_index = select_song_at_end(state, false),
+            "G" => song_scroll_index = select_song_at_end(state, true),
+            "/" => state.focus_song_search = true,
+            "dd" if !state.selected_song_indices.is_empty() => {
+                state.emit(Action::RemoveSelectedSongs)
+            }
+            "yy" => copy_selected_songs(ui, state),
+            "p" => state.emit(Action::PasteSongs),
+            "x" if !state.selected_song_indices.is_empty() => {
+                state.emit(Action::QueueSelectedSongs)
             }
-            song_scroll_index = Some(*state.selected_song_indices.first().unwrap());
+            _ => (),
         }
+        state.vim_pending_keys.clear();
     }
     song_scroll_index
 }
 
-fn draw_playlists(ui: &Ui, state: &mut State) {
+/// Grows or shrinks the selected range like Shift+click. The first selected song is the anchor of
+/// the range, its end moves by the direction. Returns the position of the new end.
+fn extend_song_selection(state: &mut State, direction: isize) -> Option<usize> {
+    let shown_indices = state.get_shown_song_indices();
+    let anchor_position = state
+        .selected_song_indices
+        .first()
+        .and_then(|x| shown_indices.iter().position(|y| y == x));
+    let Some(anchor_position) = anchor_position else {
+        return select_next_song(state, direction);
+    };
+    // The end of the range is the selected song farthest away from the anchor
+    let end_position = state
+        .selected_song_indices
+        .iter()
+        .filter_map(|x| shown_indices.iter().position(|y| y == x))
+        .max_by_key(|x| x.abs_diff(anchor_position))
+        .unwrap_or(anchor_position);
+    let new_end_position =
+        (end_position as isize + direction).clamp(0, shown_indices.len() as isize - 1) as usize;
+
+    let range: Vec<usize> = if new_end_position >= anchor_position {
+        (anchor_position..=new_end_position).collect()
+    } else {
+        (new_end_position..=anchor_position).rev().collect()
+    };
+    state.selected_song_indices = range.into_iter().map(|x| shown_indices[x]).collect();
+    Some(new_end_position)
+}
+
+/// Returns the position of the playing song in the song list if the song changed and the list
+/// should scroll to it.
+fn get_followed_song_position(state: &mut State) -> Option<usize> {
+    let playing_indices = state.playing_playlist_index.zip(state.playing_song_index);
+    if playing_indices == state.followed_song {
+        return None;
+    }
+    state.followed_song = playing_indices;
+    let (playlist_index, song_index) = playing_indices?;
+    // Don't take away the position the user has just scrolled to
+    let is_scrolled_recently = state
+        .songs_scroll_info
+        .scroll_start_time
+        .elapsed()
+        .as_millis()
+        < FOLLOW_PLAYING_SONG_DELAY_MS;
+    if !state.settings.follow_playing_song
+        || playlist_index != state.selected_playlist_index
+        || is_scrolled_recently
+    {
+        return None;
+    }
+    state
+        .get_shown_song_indices()
+        .into_iter()
+        .position(|x| x == song_index)
+}
+
+/// Plays the selected song or, if the playlist list is focused, the selected playlist.
+fn play_selection(state: &mut State) {
+    let playlist_index = state.selected_playlist_index;
+    let song_index = match state.focused_pane {
+        FocusedPane::Songs => state.selected_song_indices.first().copied(),
+        FocusedPane::Playlists => {
+            let songs = &state.playlists[playlist_index].songs;
+            state
+                .get_shown_song_indices()
+                .into_iter()
+                .find(|x| songs[*x].exists)
+        }
+    };
+    if let Some(song_index) = song_index {
+        state.emit(Action::Play {
+            playlist_index,
+            song_index,
+        });
+    }
+}
+
+fn draw_playlists(ui: &Ui, state: &mut State, scroll_delta: f32) {
+    let theme = state.theme;
     let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
     ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 2.0]);
     let padding_left = 6.0;
     let padding_right = 3.0;
+    let mut archived_playlist_index = None;
+    let mut previous_folders: Vec<String> = Vec::new();
+    let mut is_drop_hovered = false;
+    state.update_playlist_folder_counts();
     for i in 0..state.playlists.len() {
+        // Draw the headers of the folders that were not drawn for the previous playlist
+        let folders: Vec<String> = util::get_playlist_folders(&state.playlists[i].name)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let shared_count = folders
+            .iter()
+            .zip(previous_folders.iter())
+            .take_while(|(a, b)| a.to_lowercase() == b.to_lowercase())
+            .count();
+        for depth in shared_count..folders.len() {
+            let folder_path = folders[..=depth].join(".");
+            let is_parent_collapsed = (1..=depth).any(|n| {
+                state
+                    .collapsed_folders
+                    .contains(&folders[..n].join(".").to_lowercase())
+            });
+            if !is_parent_collapsed {
+                draw_playlist_folder(ui, state, &folder_path, depth, width, padding_left);
+            }
+        }
+        let is_hidden = state.is_playlist_hidden(i);
+        let depth = folders.len();
+        previous_folders = folders;
+        if is_hidden {
+            continue;
+        }
+
         let token = ui.push_id_usize(i);
         // Draw selectable
-        if ui
-            .selectable_config("")
-            .selected(i == state.selected_playlist_index)
-            .allow_double_click(true)
-            .build()
-        {
+        let row = widgets::draw_list_row(
+            ui,
+            &theme,
+            &widgets::ListRow {
+                selected: i == state.selected_playlist_index,
+                background: None,
+                // Default playlists are not saved, so songs can't be dropped onto them
+                drop_kind: (!state.dragged_songs.is_empty()
+                    && !util::is_default_playlist(&state.playlists[i].name))
+                .then_some(widgets::DropKind::Into {
+                    at_end: state.settings.drop_at_end,
+                }),
+            },
+        );
+        if state.scroll_to_selected_playlist && i == state.selected_playlist_index {
+            widgets::scroll_to_item(ui);
+            state.scroll_to_selected_playlist = false;
+        }
+        if row.clicked {
             let playlist = &state.playlists[i];
             state.selected_playlist_index = i;
             state.selected_song_indices.clear();
-
-            if ui.is_mouse_double_clicked(MouseButton::Left) && !playlist.songs.is_empty() {
-                let result = playlist.songs.iter().enumerate().find(|x| x.1.exists);
-                if let Some(result) = result {
-                    state
-                        .action_tx
-                        .send(player::PlayerAction::Play(
-                            Path::new(&state.base_path).join(&result.1.path),
-                        ))
-                        .unwrap();
-                    state.is_playing = true;
-                    state.playing_playlist_index = Some(i);
-                    state.playing_song_index = Some(result.0);
-                    actions::set_current_metadata(state);
-                    state
-                        .media_controls
-                        .set_playback(MediaPlayback::Playing { progress: None })
-                        .unwrap();
+            state.focused_pane = FocusedPane::Playlists;
+
+            if row.double_clicked && !playlist.songs.is_empty() {
+                let result = playlist.songs.iter().position(|x| x.exists);
+                if let Some(song_index) = result {
+                    state.emit(Action::Play {
+                        playlist_index: i,
+                        song_index,
+                    });
                 }
             }
         };
+        if ui.is_item_hovered() && state.dragged_songs.is_empty() {
+            draw_playlist_tooltip(ui, &theme, &state.playlists[i]);
+        }
 
         // Drop
-        if !state.dragged_songs.is_empty()
-            && ui.is_item_visible()
-            && util::is_point_in_rect(
-                ui.io().mouse_pos,
-                util::add_pos(ui.item_rect_min(), [0.0, 1.0]),
-                ui.item_rect_max(),
-            )
-        {
-            if ui.is_mouse_released(MouseButton::Left) {
-                let song_count = state.dragged_songs.len();
-                for dragged_index in (0..song_count).rev() {
-                    state.playlists[i]
-                        .songs
-                        .insert(0, state.dragged_songs.remove(dragged_index));
-                }
-                actions::increment_indices(state, i, song_count);
+        if let Some(at_end) = row.dropped_below {
+            let songs = std::mem::take(&mut state.dragged_songs);
+            let index = if at_end {
+                state.playlists[i].songs.len()
             } else {
-                ui.get_window_draw_list()
-                    .add_rect(
-                        util::add_pos(ui.item_rect_min(), [4.0, 1.0]),
-                        util::sub_pos(ui.item_rect_max(), [4.0, 0.0]),
-                        DRAG,
-                    )
-                    .build();
+                0
+            };
+            state.emit(Action::InsertSongs {
+                playlist_index: i,
+                songs,
+                index,
+            });
+        } else if row.is_drop_hovered {
+            is_drop_hovered = true;
+            // Open the playlist after hovering it for a while to drop at a specific position
+            match state.drag_hover {
+                Some((hovered_index, time)) if hovered_index == i => {
+                    if time.elapsed().as_millis() >= SPRING_LOADING_DELAY_MS
+                        && state.selected_playlist_index != i
+                    {
+                        state.selected_playlist_index = i;
+                        state.selected_song_indices.clear();
+                    }
+                }
+                _ => state.drag_hover = Some((i, Instant::now())),
             }
         }
 
-        if ui.is_item_clicked_with_button(MouseButton::Right) {
+        if row.right_clicked {
+            state.playlist_backups = if util::is_default_playlist(&state.playlists[i].name) {
+                Vec::new()
+            } else {
+                actions::get_playlist_backups(&state.base_path, &state.playlists[i].name)
+            };
             ui.open_popup("playlist_context_menu");
         }
         ui.popup("playlist_context_menu", || {
-            let _style_token = ui.push_style_var(StyleVar::WindowPadding([4.0, 10.0]));
-            let playlist = &mut state.playlists[i];
+            let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+            if ui
+                .menu_item_config("Rename")
+                .enabled(!util::is_default_playlist(&state.playlists[i].name))
+                .build()
+            {
+                start_playlist_rename(state, i);
+            }
             if ui
                 .menu_item_config("Save")
+                .enabled(!util::is_default_playlist(&state.playlists[i].name))
+                .build()
+            {
+                state.emit(Action::SavePlaylist(i));
+            }
+            if ui
+                .menu_item_config("Save all")
+                .shortcut("Ctrl+Shift+S")
+                .enabled(state.playlists.iter().any(|x| x.has_changes()))
+                .build()
+            {
+                state.emit(Action::SaveAllPlaylists);
+            }
+            let playlist = &state.playlists[i];
+            let current_format = playlist.format;
+            ui.menu_with_enabled(
+                "Save as",
+                !util::is_default_playlist(&playlist.name),
+                || {
+                    for format in PlaylistFormat::ALL {
+                        if ui
+                            .menu_item_config(format!(
+                                "{} (.{})",
+                                format.name(),
+                                format.extension()
+                            ))
+                            .selected(current_format == format)
+                            .build()
+                        {
+                            state.emit(Action::SavePlaylistAs {
+                                playlist_index: i,
+                                format,
+                            });
+                        }
+                    }
+                },
+            );
+            let playlist = &state.playlists[i];
+            if ui
+                .menu_item_config("Shuffle")
+                .enabled(!util::is_default_playlist(&playlist.name))
+                .build()
+            {
+                state.emit(Action::ShufflePlaylist(i));
+            }
+            if ui
+                .menu_item_config("Undo shuffle")
+                .enabled(actions::can_undo_shuffle(state, i))
+                .build()
+            {
+                state.emit(Action::UndoShuffle(i));
+            }
+            let playlist = &state.playlists[i];
+            if ui
+                .menu_item_config("Archive")
+                .enabled(!util::is_default_playlist(&playlist.name))
+                .build()
+            {
+                archived_playlist_index = Some(i);
+            }
+            if ui
+                .menu_item_config("Delete")
                 .enabled(!util::is_default_playlist(&playlist.name))
                 .build()
             {
-                actions::save_playlist(&state.base_path, playlist);
+                state.deleted_playlist_name = Some(playlist.name.clone());
+                state.delete_playlist_file = false;
+            }
+            let backups = state.playlist_backups.clone();
+            ui.menu_with_enabled("Restore previous version", !backups.is_empty(), || {
+                for (number, modified) in backups {
+                    ui.menu(
+                        format!("Version {number} ({})", util::time_ago_to_string(modified)),
+                        || draw_playlist_backup_preview(ui, state, i, number),
+                    );
+                }
+            });
+            ui.menu("Compare with", || {
+                let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+                for other_index in (0..state.playlists.len()).filter(|x| *x != i) {
+                    if ui.menu_item(&state.playlists[other_index].name) {
+                        state.compared_playlist_names = Some((
+                            state.playlists[i].name.clone(),
+                            state.playlists[other_index].name.clone(),
+                        ));
+                    }
+                }
+            });
+            if ui
+                .menu_item_config("Find duplicates")
+                .enabled(!state.duplicates_job.is_running())
+                .build()
+            {
+                duplicates::find_duplicates(state, i);
+            }
+            if ui
+                .menu_item_config("Scan ReplayGain")
+                .enabled(!state.replaygain_job.is_running())
+                .build()
+            {
+                let paths = state.playlists[i]
+                    .songs
+                    .iter()
+                    .map(|x| x.path.clone())
+                    .collect();
+                replaygain::scan(state, paths);
+            }
+            if ui
+                .menu_item_config("Adjust volume level")
+                .enabled(!state.postprocessing_job.is_running() && state.programs.aacgain.is_some())
+                .build()
+            {
+                let paths = state.playlists[i]
+                    .songs
+                    .iter()
+                    .map(|x| x.path.clone())
+                    .collect();
+                download::postprocess_songs(state, paths);
             }
-            ui.menu("Download", || {
+            ui.menu("Organize files", || {
+                let token = ui.push_id("organize_template_textbox");
+                ui.set_next_item_width(500.0);
+                let is_confirmed = ui
+                    .input_text("", &mut state.organize_template_text)
+                    .enter_returns_true(true)
+                    .build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                token.pop();
+                ui.text_colored(
+                    theme.text2,
+                    "Placeholders: %artist% %title% %album% %genre% %filename% %ext%",
+                );
+
+                let preview = actions::get_organize_preview(state, i);
+                if preview.is_empty() {
+                    ui.text_colored(theme.text2, "All songs are already organized");
+                } else {
+                    draw_rename_preview(ui, &theme, &preview);
+                }
+
+                if ui.button("Apply") || is_confirmed {
+                    state.emit(Action::RenameSongs(preview));
+                    ui.close_current_popup();
+                }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    ui.close_current_popup();
+                }
+            });
+            ui.menu_with_enabled("Download", state.programs.yt_dlp.is_some(), || {
                 let token = ui.push_id("download_textbox");
                 ui.set_next_item_width(500.0);
                 if ui
@@ -828,59 +2116,53 @@ fn draw_playlists(ui: &Ui, state: &mut State) {
                     .hint("URL")
                     .build()
                 {
-                    state.download_playlist_index = Some(i);
-                    download::download(state);
+                    download::download(state, i);
                     ui.close_current_popup();
                 }
                 state.has_textbox_focus |= ui.is_item_focused();
                 token.pop();
+                draw_download_options(ui, &mut state.download_options);
 
                 if ui.button("Run") {
-                    state.download_playlist_index = Some(i);
-                    download::download(state);
+                    download::download(state, i);
                     ui.close_current_popup();
                 }
                 ui.same_line();
                 if ui.button("Cancel") {
                     ui.close_current_popup();
                 }
+                draw_download_queue(ui, state, scroll_delta);
             });
+            ui.menu_with_enabled(
+                "Search and download",
+                state.programs.yt_dlp.is_some(),
+                || draw_download_search(ui, state, i, scroll_delta),
+            );
         });
 
         let playlist = &state.playlists[i];
-        let has_changes = if util::is_default_playlist(&playlist.name) {
-            false
-        } else {
-            let mut hasher = DefaultHasher::new();
-            for song in playlist.songs.iter() {
-                song.hash(&mut hasher);
-            }
-            playlist.original_hash != hasher.finish()
-        };
+        let has_changes = playlist.has_changes();
 
         // Draw playlist name
-        ui.same_line_with_pos(ui.cursor_pos()[0] + padding_left);
-        let playlist_name_parts = playlist
-            .name
-            .split_once('.')
-            .unwrap_or(("", &playlist.name));
-        let playlist_prefix_text = format!(
-            "{}{}",
-            if has_changes { "● " } else { "" },
-            playlist_name_parts.0
-        );
+        ui.same_line_with_pos(ui.cursor_pos()[0] + padding_left + depth as f32 * FOLDER_INDENT);
+        if state.renamed_playlist_index == Some(i) {
+            draw_playlist_name_textbox(ui, state, i, width - ui.cursor_pos()[0] - padding_right);
+            token.pop();
+            continue;
+        }
+        let playlist_name = util::get_playlist_short_name(&playlist.name);
         if state.playing_playlist_index == Some(i) {
-            if !playlist_prefix_text.is_empty() {
-                ui.text_colored(PLAYING_COLOR, playlist_prefix_text);
+            if has_changes {
+                ui.text_colored(theme.playing, "● ");
                 ui.same_line();
             }
-            ui.text_colored(PLAYING_COLOR, playlist_name_parts.1);
+            ui.text_colored(theme.playing, playlist_name);
         } else {
-            if !playlist_prefix_text.is_empty() {
-                ui.text_colored(DIRECTORY_COLOR, playlist_prefix_text);
+            if has_changes {
+                ui.text_colored(theme.text2, "● ");
                 ui.same_line();
             }
-            ui.text(playlist_name_parts.1);
+            ui.text(playlist_name);
         }
 
         // Draw playlist info
@@ -892,177 +2174,1767 @@ fn draw_playlists(ui: &Ui, state: &mut State) {
         );
 
         ui.same_line_with_pos(width - padding_right - ui.calc_text_size(&playlist_info)[0]);
-        let color_token = ui.push_style_color(StyleColor::Text, TEXT2);
+        let color_token = ui.push_style_color(StyleColor::Text, theme.text2);
         ui.text(&playlist_info);
         color_token.pop();
 
         token.pop();
     }
-    ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 2.0]);
-}
-
-fn draw_textboxes(ui: &Ui, style: &Style, state: &mut State) {
-    let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
-    let style_token = ui.push_style_var(StyleVar::ItemSpacing([1.0, 0.0]));
 
-    let token = ui.push_id("new_playlist_textbox");
-    ui.set_next_item_width(width / 2.0);
-    if ui
-        .input_text("", &mut state.new_playlist_text)
-        .enter_returns_true(true)
-        .hint(NEW_PLAYLIST_TEXT)
-        .build()
-    {
-        if state
-            .playlists
-            .iter()
-            .any(|x| x.name == state.new_playlist_text)
-        {
-            return;
-        }
-        let mut new_playlist = Playlist::new(state.new_playlist_text.clone(), Vec::new());
-        new_playlist.original_hash = 0;
-        state.playlists.push(new_playlist);
-        state.sort_playlists();
-        state.new_playlist_text.clear();
+    if !is_drop_hovered {
+        state.drag_hover = None;
     }
-    state.has_textbox_focus |= ui.is_item_focused();
-    token.pop();
 
-    let token = ui.push_id("song_search_textbox");
-    let border_color_token;
-    let border_size_token;
-    if state.song_search_text.is_empty() {
-        border_color_token =
-            ui.push_style_color(StyleColor::Border, ui.style_color(StyleColor::Border));
-        border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(style.frame_border_size));
-    } else {
-        border_color_token = ui.push_style_color(StyleColor::Border, PRIMARY1);
-        border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(2.0));
-    }
-    ui.same_line();
-    ui.set_next_item_width(width / 2.0);
-    if ui
-        .input_text("", &mut state.song_search_text)
-        .hint(SONG_SEARCH_TEXT)
-        .build()
-    {
-        state.selected_song_indices.clear();
+    if !state.archived_playlists.is_empty() {
+        draw_archived_playlists(ui, state, width, padding_left);
     }
-    if !ui.is_item_focused() && ui.io().key_ctrl && ui.is_key_pressed_no_repeat(Key::F) {
-        ui.set_keyboard_focus_here_with_offset(FocusedWidget::Previous);
+    if state.library_rx.is_some() {
+        ui.set_cursor_pos([ui.cursor_pos()[0] + padding_left, ui.cursor_pos()[1] + 2.0]);
+        ui.text_colored(theme.text2, &state.library_progress);
     }
-    state.has_textbox_focus |= ui.is_item_focused();
-    border_size_token.pop();
-    border_color_token.pop();
-    token.pop();
-
-    style_token.pop();
-}
+    ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 2.0]);
 
-fn draw_songs_header(ui: &Ui, state: &mut State, scrollbar_offset: f32) {
-    let width =
-        ui.window_content_region_max()[0] - ui.window_content_region_min()[0] - scrollbar_offset;
-    let horizontal_padding = 6.0;
-    ui.set_cursor_pos([
-        ui.cursor_pos()[0] + horizontal_padding,
-        ui.cursor_pos()[1] + 6.0,
-    ]);
+    if let Some(i) = archived_playlist_index {
+        actions::archive_playlist(state, i);
+    }
 
-    // Draw header
-    let rect_min = ui.window_pos();
-    let rect_max = util::add_pos(ui.window_pos(), [width / 2.0, SONGS_HEADER_HEIGHT]);
-    if ui.is_mouse_hovering_rect(rect_min, rect_max) {
-        ui.get_window_draw_list()
-            .add_rect(rect_min, rect_max, HOVERED_BG)
-            .filled(true)
+    // Context menu of the empty area below the playlists
+    if ui.is_window_hovered()
+        && !ui.is_any_item_hovered()
+        && ui.is_mouse_clicked(MouseButton::Right)
+    {
+        ui.open_popup("playlists_context_menu");
+    }
+    ui.popup("playlists_context_menu", || {
+        let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+        draw_library_menu_items(ui, state);
+        ui.separator();
+        if ui
+            .menu_item_config("Save all")
+            .shortcut("Ctrl+Shift+S")
+            .enabled(state.playlists.iter().any(|x| x.has_changes()))
+            .build()
+        {
+            state.emit(Action::SaveAllPlaylists);
+        }
+        if ui
+            .menu_item_config("Auto-save playlists")
+            .selected(state.settings.auto_save)
+            .build()
+        {
+            state.settings.auto_save = !state.settings.auto_save;
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Add dropped songs to the end of playlists")
+            .selected(state.settings.drop_at_end)
+            .build()
+        {
+            state.settings.drop_at_end = !state.settings.drop_at_end;
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Always on top")
+            .shortcut("Ctrl+T")
+            .selected(state.settings.always_on_top)
+            .build()
+        {
+            state.settings.always_on_top = !state.settings.always_on_top;
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Follow playing song")
+            .selected(state.settings.follow_playing_song)
+            .build()
+        {
+            state.settings.follow_playing_song = !state.settings.follow_playing_song;
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Scroll long titles")
+            .selected(state.settings.scroll_long_titles)
+            .build()
+        {
+            state.settings.scroll_long_titles = !state.settings.scroll_long_titles;
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Vim keybindings")
+            .selected(state.settings.vim_mode)
+            .build()
+        {
+            state.settings.vim_mode = !state.settings.vim_mode;
+            state.vim_pending_keys.clear();
+            settings::save(state);
+        }
+        if ui
+            .menu_item_config("Close to tray")
+            .selected(state.settings.close_to_tray)
+            .build()
+        {
+            state.settings.close_to_tray = !state.settings.close_to_tray;
+            settings::save(state);
+        }
+        ui.menu("Recently Added", || {
+            for days in RECENTLY_ADDED_DAYS {
+                if ui
+                    .menu_item_config(format!("Last {days} days"))
+                    .selected(state.settings.recently_added_days == days)
+                    .build()
+                {
+                    state.settings.recently_added_days = days;
+                    settings::save(state);
+                    actions::update_recently_added(state);
+                }
+            }
+        });
+        ui.menu("Files dropped from outside", || {
+            for action in settings::DroppedFilesAction::ALL {
+                if ui
+                    .menu_item_config(action.name())
+                    .selected(state.settings.dropped_files_action == action)
+                    .build()
+                {
+                    state.settings.dropped_files_action = action;
+                    settings::save(state);
+                }
+            }
+        });
+        ui.menu("Accent color", || {
+            let mut hue = state.theme.accent_hue();
+            ui.set_next_item_width(300.0);
+            // The theme is applied every frame, so the new color is previewed while dragging
+            if ui
+                .slider_config("Hue", 0.0, 360.0)
+                .display_format("%.0f")
+                .build(&mut hue)
+            {
+                state.theme.set_accent_hue(hue);
+            }
+            if ui.is_item_deactivated_after_edit() {
+                state.settings.accent_hue = Some(hue);
+                settings::save(state);
+            }
+            ui.color_button("Selection", state.theme.primary1);
+            ui.same_line();
+            ui.color_button("Hovered selection", state.theme.primary2);
+            ui.same_line();
+            ui.color_button("Dragged items", state.theme.drag);
+            if ui.menu_item("Reset to default") {
+                // Back to the accent colors of the theme file
+                let file_theme = theme::load(&state.base_path).unwrap_or_default();
+                state.theme.primary1 = file_theme.primary1;
+                state.theme.primary2 = file_theme.primary2;
+                state.theme.drag = file_theme.drag;
+                state.settings.accent_hue = None;
+                settings::save(state);
+            }
+        });
+        ui.menu("Parallel downloads", || {
+            for count in PARALLEL_DOWNLOAD_COUNTS {
+                if ui
+                    .menu_item_config(count.to_string())
+                    .selected(state.settings.parallel_downloads == count)
+                    .build()
+                {
+                    state.settings.parallel_downloads = count;
+                    settings::save(state);
+                }
+            }
+        });
+        ui.menu("Download network", || {
+            let fields = [
+                (
+                    "Proxy",
+                    "socks5://127.0.0.1:1080",
+                    &mut state.settings.download_proxy,
+                ),
+                (
+                    "Rate limit",
+                    "e.g. 500K or 2M",
+                    &mut state.settings.download_rate_limit,
+                ),
+                (
+                    "Cookies file",
+                    "cookies.txt path",
+                    &mut state.settings.cookies_file,
+                ),
+            ];
+            let mut is_changed = false;
+            for (name, hint, value) in fields {
+                ui.set_next_item_width(400.0);
+                ui.input_text(name, value).hint(hint).build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                is_changed |= ui.is_item_deactivated_after_edit();
+            }
+            if is_changed {
+                settings::save(state);
+            }
+        });
+        if ui.menu_item("Show downloads") {
+            state.show_downloads = true;
+        }
+        if ui.menu_item("Sync to device") {
+            state.show_sync = true;
+        }
+        if ui.menu_item("Show notifications") {
+            state.show_notifications = true;
+        }
+        if ui.menu_item("Keyboard shortcuts") {
+            state.show_keymap = true;
+        }
+        ui.menu("Postprocessing of downloads", || {
+            for postprocessor in download::Postprocessor::ALL {
+                let position = state
+                    .settings
+                    .postprocessors
+                    .iter()
+                    .position(|x| *x == postprocessor);
+                // Enabled steps are appended to the end of the chain
+                let label = match position {
+                    Some(i) => format!("{}. {}", i + 1, postprocessor.name()),
+                    None => postprocessor.name().to_string(),
+                };
+                if ui
+                    .menu_item_config(label)
+                    .selected(position.is_some())
+                    .build()
+                {
+                    match position {
+                        Some(i) => {
+                            state.settings.postprocessors.remove(i);
+                        }
+                        None => state.settings.postprocessors.push(postprocessor),
+                    }
+                    settings::save(state);
+                }
+            }
+        });
+        ui.menu("External programs", || {
+            let programs = [
+                ("yt-dlp", &mut state.settings.yt_dlp_path),
+                ("aacgain", &mut state.settings.aacgain_path),
+                ("mp3gain", &mut state.settings.mp3gain_path),
+                ("ffmpeg", &mut state.settings.ffmpeg_path),
+            ];
+            let mut is_changed = false;
+            for (name, path) in programs {
+                ui.set_next_item_width(400.0);
+                ui.input_text(name, path).build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                is_changed |= ui.is_item_deactivated_after_edit();
+            }
+            for (name, command) in [
+                ("yt-dlp", &state.programs.yt_dlp),
+                ("aacgain", &state.programs.aacgain),
+                ("mp3gain", &state.programs.mp3gain),
+                ("ffmpeg", &state.programs.ffmpeg),
+            ] {
+                match command {
+                    Some(command) => ui.text_colored(theme.text2, format!("{name}: {command}")),
+                    None => ui.text_colored(theme.text2, format!("{name}: not found")),
+                }
+            }
+            if is_changed {
+                settings::save(state);
+                download::detect_programs(state);
+            }
+            ui.separator();
+            // yt-dlp can't replace itself while downloads are running
+            if ui
+                .menu_item_config("Update yt-dlp")
+                .enabled(
+                    state.programs.yt_dlp.is_some()
+                        && state.yt_dlp_updater.is_none()
+                        && state.download_jobs.is_empty(),
+                )
+                .build()
+            {
+                download::update_yt_dlp(state);
+            }
+        });
+        ui.menu("Fallback fonts", || {
+            ui.text_colored(
+                theme.text2,
+                "Used for characters like CJK or emoji that the main font doesn't contain",
+            );
+            let mut removed_index = None;
+            for (i, path) in state.settings.fallback_fonts.iter().enumerate() {
+                let token = ui.push_id_usize(i);
+                if ui.small_button("Remove") {
+                    removed_index = Some(i);
+                }
+                ui.same_line();
+                if Path::new(path).is_file() {
+                    ui.text(path);
+                } else {
+                    ui.text_colored(theme.not_existing, format!("{path} (not found)"));
+                }
+                token.pop();
+            }
+            if let Some(i) = removed_index {
+                state.settings.fallback_fonts.remove(i);
+                settings::save(state);
+            }
+
+            let token = ui.push_id("fallback_font_textbox");
+            ui.set_next_item_width(500.0);
+            let is_confirmed = ui
+                .input_text("", &mut state.fallback_font_text)
+                .enter_returns_true(true)
+                .hint("Path of a .ttf, .otf or .ttc file")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+            ui.same_line();
+            if (ui.button("Add") || is_confirmed) && !state.fallback_font_text.trim().is_empty() {
+                let path = std::mem::take(&mut state.fallback_font_text);
+                state.settings.fallback_fonts.push(path.trim().to_string());
+                settings::save(state);
+            }
+            if ui.button("Reset to defaults") {
+                state.settings.fallback_fonts = fonts::get_default_fallback_fonts();
+                settings::save(state);
+            }
+        });
+        ui.menu("Remote control", || {
+            if ui
+                .menu_item_config("Enabled")
+                .selected(state.settings.remote_control)
+                .build()
+            {
+                state.settings.remote_control = !state.settings.remote_control;
+                settings::save(state);
+            }
+            let token = ui.push_id("remote_address_textbox");
+            ui.set_next_item_width(300.0);
+            let is_confirmed = ui
+                .input_text("", &mut state.remote_address_text)
+                .enter_returns_true(true)
+                .hint(remote::DEFAULT_ADDRESS)
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+            ui.same_line();
+            if (ui.button("Apply") || is_confirmed) && !state.remote_address_text.trim().is_empty()
+            {
+                state.settings.remote_control_address =
+                    state.remote_address_text.trim().to_string();
+                settings::save(state);
+            }
+            ui.text_colored(
+                state.theme.text2,
+                "Use 0.0.0.0 instead of 127.0.0.1 to allow other devices",
+            );
+        });
+        ui.menu("Import library", || {
+            let token = ui.push_id("import_path_textbox");
+            ui.set_next_item_width(500.0);
+            let is_confirmed = ui
+                .input_text("", &mut state.import_path_text)
+                .enter_returns_true(true)
+                .hint("Path of iTunes Library.xml or Rhythmbox playlists.xml")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+
+            if ui.button("Import") || is_confirmed {
+                let path = state.import_path_text.clone();
+                import::import_library(state, &path);
+                ui.close_current_popup();
+            }
+        });
+        ui.menu("Import streaming playlist", || {
+            let token = ui.push_id("streaming_import_textbox");
+            ui.set_next_item_width(500.0);
+            let is_confirmed = ui
+                .input_text("", &mut state.streaming_import_text)
+                .enter_returns_true(true)
+                .hint("YouTube (Music) playlist URL or CSV file exported from Spotify")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+
+            if state.playlist_listing.is_some() {
+                ui.text_disabled("Reading the playlist...");
+            } else if ui.button("Import") || is_confirmed {
+                let input = state.streaming_import_text.clone();
+                import::import_streaming_playlist(state, &input);
+                ui.close_current_popup();
+            }
+            ui.text_colored(
+                state.theme.text2,
+                "Songs that are not in the library can be downloaded afterwards",
+            );
+        });
+        ui.menu("Export library", || {
+            let token = ui.push_id("export_path_textbox");
+            ui.set_next_item_width(500.0);
+            ui.input_text("", &mut state.export_path_text)
+                .hint("Folder, \"export\" in the music directory if empty")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+
+            let mut format = None;
+            if ui.button("Export as CSV") {
+                format = Some(export::ExportFormat::Csv);
+            }
+            ui.same_line();
+            if ui.button("Export as JSON") {
+                format = Some(export::ExportFormat::Json);
+            }
+            if let Some(format) = format {
+                let path = state.export_path_text.clone();
+                export::export_library(state, &path, format);
+                ui.close_current_popup();
+            }
+            ui.text_colored(
+                state.theme.text2,
+                "Writes the playlists, the songs with their plays and ratings and the history",
+            );
+        });
+        ui.menu("Open folder...", || {
+            let token = ui.push_id("music_directory_textbox");
+            ui.set_next_item_width(500.0);
+            let is_confirmed = ui
+                .input_text("", &mut state.music_directory_text)
+                .enter_returns_true(true)
+                .hint("Path of the music directory")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            token.pop();
+
+            if ui.button("Open") || is_confirmed {
+                let path = state.music_directory_text.clone();
+                state.emit(Action::OpenMusicDirectory(path));
+                ui.close_current_popup();
+            }
+        });
+    });
+}
+
+fn draw_playlist_tooltip(ui: &Ui, theme: &theme::Theme, playlist: &Playlist) {
+    ui.tooltip(|| {
+        let duration_sum: u64 = playlist.songs.iter().map(|x| x.duration.unwrap_or(0)).sum();
+        ui.text(format!(
+            "{} songs, {}",
+            playlist.songs.len(),
+            util::ms_to_string(duration_sum)
+        ));
+        let missing_count = playlist.songs.iter().filter(|x| !x.exists).count();
+        if missing_count > 0 {
+            ui.text_colored(theme.not_existing, format!("{missing_count} missing songs"));
+        }
+        if util::is_default_playlist(&playlist.name) {
+            return;
+        }
+        if playlist.has_changes() {
+            ui.text("Unsaved changes");
+        }
+        match playlist.modified_time {
+            Some(time) => ui.text_colored(
+                theme.text2,
+                format!("Saved {}", util::time_ago_to_string(time)),
+            ),
+            None => ui.text_colored(theme.text2, "Not saved yet"),
+        }
+    });
+}
+
+/// Draws the header of a playlist folder, clicking it collapses or expands the folder.
+fn draw_playlist_folder(
+    ui: &Ui,
+    state: &mut State,
+    folder_path: &str,
+    depth: usize,
+    width: f32,
+    padding_left: f32,
+) {
+    let theme = state.theme;
+    let padding_right = 3.0;
+    let token = ui.push_id(folder_path);
+    if widgets::draw_row(ui, false, None).clicked {
+        actions::toggle_playlist_folder(state, folder_path);
+    }
+
+    let folder_key = folder_path.to_lowercase();
+    let folder_prefix = format!("{folder_key}.");
+    let is_playing = state.playing_playlist_index.is_some_and(|i| {
+        state.playlists[i]
+            .name
+            .to_lowercase()
+            .starts_with(&folder_prefix)
+    });
+    let folder_text = format!(
+        "{} {}",
+        if state.collapsed_folders.contains(&folder_key) {
+            "▶"
+        } else {
+            "▼"
+        },
+        folder_path.rsplit('.').next().unwrap_or(folder_path)
+    );
+    ui.same_line_with_pos(ui.cursor_pos()[0] + padding_left + depth as f32 * FOLDER_INDENT);
+    ui.text_colored(
+        if is_playing {
+            theme.playing
+        } else {
+            theme.text2
+        },
+        folder_text,
+    );
+
+    let count = state
+        .playlist_folder_counts
+        .get(&folder_key)
+        .copied()
+        .unwrap_or(0)
+        .to_string();
+    ui.same_line_with_pos(width - padding_right - ui.calc_text_size(&count)[0]);
+    ui.text_colored(theme.text2, &count);
+    token.pop();
+}
+
+/// Copies the selected songs for pasting them into another playlist. Their paths are also put
+/// into the system clipboard.
+fn copy_selected_songs(ui: &Ui, state: &mut State) {
+    actions::copy_selected_songs(state);
+    let paths: Vec<&str> = state.copied_songs.iter().map(|x| x.path.as_str()).collect();
+    ui.set_clipboard_text(paths.join("\n"));
+}
+
+fn draw_download_options(ui: &Ui, options: &mut download::DownloadOptions) {
+    ui.checkbox("Download the whole playlist", &mut options.whole_playlist);
+
+    ui.set_next_item_width(200.0);
+    if let Some(_combo_token) = ui.begin_combo("Format", options.format.name()) {
+        for format in download::DownloadFormat::ALL {
+            if ui
+                .selectable_config(format.name())
+                .selected(options.format == format)
+                .build()
+            {
+                options.format = format;
+            }
+        }
+    }
+
+    // The bitrate only applies to converted formats
+    if options.format != download::DownloadFormat::Original {
+        let bitrate_text = |bitrate: Option<u32>| match bitrate {
+            Some(bitrate) => format!("{bitrate} kbit/s"),
+            None => "Best".to_string(),
+        };
+        ui.set_next_item_width(200.0);
+        if let Some(_combo_token) = ui.begin_combo("Bitrate", bitrate_text(options.bitrate)) {
+            let bitrates = std::iter::once(None).chain(download::DOWNLOAD_BITRATES.map(Some));
+            for bitrate in bitrates {
+                if ui
+                    .selectable_config(bitrate_text(bitrate))
+                    .selected(options.bitrate == bitrate)
+                    .build()
+                {
+                    options.bitrate = bitrate;
+                }
+            }
+        }
+    }
+
+    ui.checkbox("Embed cover art", &mut options.embed_thumbnail);
+    ui.checkbox("Embed tags", &mut options.embed_metadata);
+    if options.embed_metadata {
+        ui.indent();
+        ui.checkbox(
+            "Take the artist from \"Artist - Title\"",
+            &mut options.parse_artist_title,
+        );
+        ui.unindent();
+    }
+    ui.checkbox(
+        "Split chapters into separate songs",
+        &mut options.split_chapters,
+    );
+}
+
+fn draw_download_search(ui: &Ui, state: &mut State, playlist_index: usize, scroll_delta: f32) {
+    let theme = state.theme;
+    let token = ui.push_id("download_search_textbox");
+    ui.set_next_item_width(500.0);
+    let is_confirmed = ui
+        .input_text("", &mut state.download_search_text)
+        .enter_returns_true(true)
+        .hint("Search YouTube")
+        .build();
+    state.has_textbox_focus |= ui.is_item_focused();
+    token.pop();
+    ui.same_line();
+    let is_searching = state.download_search.is_some();
+    ui.disabled(is_searching, || {
+        if ui.button("Search") || is_confirmed {
+            download::search(state);
+        }
+    });
+    draw_download_options(ui, &mut state.download_options);
+
+    if is_searching {
+        ui.text_colored(theme.text2, "Searching...");
+    }
+    let mut downloaded_index = None;
+    let results = &state.download_search_results;
+    let row_height = ui.text_line_height_with_spacing();
+    widgets::draw_virtual_list(
+        ui,
+        "download_search_results",
+        [
+            DOWNLOAD_LIST_SIZE[0],
+            DOWNLOAD_LIST_SIZE[1].min(results.len() as f32 * row_height),
+        ],
+        scroll_delta,
+        &mut state.download_search_scroll_info,
+        results.len(),
+        |i| {
+            let result = &results[i];
+            let row = widgets::draw_list_row(
+                ui,
+                &theme,
+                &widgets::ListRow {
+                    selected: false,
+                    background: Some(widgets::stripe_color(i, theme.dark1, theme.dark2)),
+                    drop_kind: None,
+                },
+            );
+            ui.set_item_allow_overlap();
+            if row.double_clicked {
+                downloaded_index = Some(i);
+            }
+            let details = [
+                result.channel.clone(),
+                result.duration.map(util::ms_to_string).unwrap_or_default(),
+            ]
+            .into_iter()
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<String>>()
+            .join(", ");
+            let button_width = ui.calc_text_size("Download")[0] + 10.0;
+            let details_width = ui.calc_text_size(&details)[0];
+            let title_width = DOWNLOAD_LIST_SIZE[0] - button_width - details_width - 30.0;
+            ui.same_line_with_pos(4.0);
+            widgets::draw_truncated_text(ui, &result.title, title_width);
+            if !details.is_empty() {
+                ui.same_line_with_pos(title_width + 10.0);
+                ui.text_colored(theme.text2, &details);
+            }
+            ui.same_line_with_pos(DOWNLOAD_LIST_SIZE[0] - button_width - 10.0);
+            if ui.small_button("Download") {
+                downloaded_index = Some(i);
+            }
+        },
+    );
+    if let Some(i) = downloaded_index {
+        download::download_search_result(state, playlist_index, i);
+    }
+    draw_download_queue(ui, state, scroll_delta);
+}
+
+/// Lists the queued and running downloads, each of them can be canceled.
+fn draw_download_queue(ui: &Ui, state: &mut State, scroll_delta: f32) {
+    if state.download_jobs.is_empty() {
+        return;
+    }
+    let theme = state.theme;
+    ui.separator();
+    let mut canceled_index = None;
+    let jobs = &state.download_jobs;
+    let row_height = ui.text_line_height_with_spacing();
+    widgets::draw_virtual_list(
+        ui,
+        "download_queue",
+        [
+            DOWNLOAD_LIST_SIZE[0],
+            DOWNLOAD_LIST_SIZE[1].min(jobs.len() as f32 * row_height),
+        ],
+        scroll_delta,
+        &mut state.download_queue_scroll_info,
+        jobs.len(),
+        |i| {
+            let job = &jobs[i];
+            widgets::draw_list_row(
+                ui,
+                &theme,
+                &widgets::ListRow {
+                    selected: false,
+                    background: Some(widgets::stripe_color(i, theme.dark1, theme.dark2)),
+                    drop_kind: None,
+                },
+            );
+            ui.set_item_allow_overlap();
+            let button_width = ui.calc_text_size("Cancel")[0] + 10.0;
+            let progress_width = 200.0;
+            let title_width = DOWNLOAD_LIST_SIZE[0] - button_width - progress_width - 30.0;
+            ui.same_line_with_pos(4.0);
+            widgets::draw_truncated_text(ui, &job.get_title(), title_width);
+            ui.same_line_with_pos(title_width + 10.0);
+            ProgressBar::new(job.get_fraction())
+                .size([progress_width, ui.text_line_height()])
+                .overlay_text(job.get_status())
+                .build(ui);
+            ui.same_line_with_pos(DOWNLOAD_LIST_SIZE[0] - button_width - 10.0);
+            if ui.small_button("Cancel") {
+                canceled_index = Some(i);
+            }
+        },
+    );
+    if let Some(i) = canceled_index {
+        download::cancel(state, i);
+    }
+}
+
+/// Draws the window listing the downloads with their progress. It is shown when a download is
+/// added.
+fn draw_downloads(ui: &Ui, state: &mut State, scroll_delta: f32) {
+    let theme = state.theme;
+    let mut opened = true;
+    ui.window("Downloads")
+        .size([450.0, 300.0], Condition::FirstUseEver)
+        .position([300.0, 150.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            if state.download_jobs.is_empty() {
+                ui.text_colored(theme.text2, "No downloads");
+            }
+            draw_download_queue(ui, state, scroll_delta);
+        });
+    state.show_downloads = opened;
+}
+
+/// Draws the window to copy the checked playlists and their songs to a mounted phone or music
+/// player.
+fn draw_sync(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let mut opened = true;
+    ui.window("Sync to device")
+        .size([450.0, 400.0], Condition::FirstUseEver)
+        .position([300.0, 150.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            ui.set_next_item_width(-1.0);
+            ui.input_text("##sync_path", &mut state.settings.sync_path)
+                .hint("Folder on the device, like /run/user/1000/gvfs/mtp:host=.../Music")
+                .build();
+            state.has_textbox_focus |= ui.is_item_focused();
+            if ui.is_item_deactivated_after_edit() {
+                settings::save(state);
+            }
+
+            ui.text_colored(theme.text2, "Playlists to sync");
+            let footer_height = ui.frame_height_with_spacing() * 2.0;
+            ui.child_window("sync_playlists")
+                .size([0.0, -footer_height])
+                .build(|| {
+                    for i in 0..state.playlists.len() {
+                        let name = &state.playlists[i].name;
+                        if util::is_default_playlist(name) {
+                            continue;
+                        }
+                        let mut is_checked = state.settings.sync_playlists.contains(name);
+                        if ui.checkbox(format!("{name}###sync_playlist{i}"), &mut is_checked) {
+                            let name = name.clone();
+                            if is_checked {
+                                state.settings.sync_playlists.push(name);
+                            } else {
+                                state.settings.sync_playlists.retain(|x| *x != name);
+                            }
+                            settings::save(state);
+                        }
+                    }
+                });
+
+            if state.sync_rx.is_some() {
+                if ui.button("Cancel") {
+                    sync::cancel(state);
+                }
+                let (done, total, path) = state.sync_progress.clone().unwrap_or_default();
+                ProgressBar::new(if total == 0 {
+                    0.0
+                } else {
+                    done as f32 / total as f32
+                })
+                .size([-1.0, 0.0])
+                .overlay_text(format!("{done}/{total} {path}"))
+                .build(ui);
+            } else if ui.button("Sync") {
+                sync::start(state);
+            }
+        });
+    state.show_sync = opened;
+}
+
+/// Menu items of the empty areas of the playlist and song lists.
+fn draw_library_menu_items(ui: &Ui, state: &mut State) {
+    if ui.menu_item("New playlist") {
+        let playlist_index = actions::create_empty_playlist(state);
+        state.selected_playlist_index = playlist_index;
+        state.selected_song_indices.clear();
+        state.scroll_to_selected_playlist = true;
+        start_playlist_rename(state, playlist_index);
+    }
+    if ui
+        .menu_item_config("Paste URL to download")
+        .enabled(state.programs.yt_dlp.is_some())
+        .build()
+    {
+        let url = ui.clipboard_text().unwrap_or_default().trim().to_string();
+        if url.starts_with("http://") || url.starts_with("https://") {
+            download::download_url(state, state.selected_playlist_index, url);
+        } else {
+            state.push_status(
+                StatusType::Error,
+                "The clipboard does not contain a URL".to_string(),
+            );
+        }
+    }
+    ui.menu("Add files...", || {
+        let token = ui.push_id("add_files_textbox");
+        ui.set_next_item_width(500.0);
+        let is_confirmed = ui
+            .input_text("", &mut state.add_files_text)
+            .enter_returns_true(true)
+            .hint("Path of a song or folder")
             .build();
-        if ui.is_mouse_clicked(MouseButton::Left) {
-            state.sort_type = match state.sort_type {
-                Some(SortType::Song(SortDirection::Ascending)) => {
-                    Some(SortType::Song(SortDirection::Descending))
+        state.has_textbox_focus |= ui.is_item_focused();
+        token.pop();
+
+        if (ui.button("Add") || is_confirmed) && !state.add_files_text.trim().is_empty() {
+            let path = PathBuf::from(std::mem::take(&mut state.add_files_text).trim());
+            actions::add_dropped_files(state, vec![path]);
+            ui.close_current_popup();
+        }
+    });
+    if ui.menu_item("Refresh library") {
+        state.emit(Action::OpenMusicDirectory(state.base_path.clone()));
+    }
+}
+
+fn start_playlist_rename(state: &mut State, playlist_index: usize) {
+    state.renamed_playlist_index = Some(playlist_index);
+    state.playlist_name_text = state.playlists[playlist_index].name.clone();
+    state.focus_playlist_name_textbox = true;
+}
+
+/// Draws the textbox replacing the name of a playlist that is being renamed. The new name is
+/// applied with Enter, clicking elsewhere or Escape cancels renaming.
+fn draw_playlist_name_textbox(ui: &Ui, state: &mut State, playlist_index: usize, width: f32) {
+    let token = ui.push_id("playlist_name_textbox");
+    if state.focus_playlist_name_textbox {
+        ui.set_keyboard_focus_here();
+        state.focus_playlist_name_textbox = false;
+    }
+    ui.set_next_item_width(width);
+    let is_confirmed = ui
+        .input_text("", &mut state.playlist_name_text)
+        .enter_returns_true(true)
+        .auto_select_all(true)
+        .build();
+    state.has_textbox_focus |= ui.is_item_focused();
+    let is_deactivated = ui.is_item_deactivated();
+    token.pop();
+
+    if is_confirmed {
+        let name = state.playlist_name_text.clone();
+        state.renamed_playlist_index = None;
+        state.emit(Action::RenamePlaylist {
+            playlist_index,
+            name,
+        });
+    } else if is_deactivated {
+        state.renamed_playlist_index = None;
+    }
+}
+
+fn draw_playlist_backup_preview(ui: &Ui, state: &mut State, playlist_index: usize, number: usize) {
+    let theme = state.theme;
+    let max_lines = 20;
+    if !matches!(state.playlist_backup_preview, Some((p, n, _)) if p == playlist_index && n == number)
+    {
+        let backup = actions::load_playlist_backup(state, playlist_index, number);
+        state.playlist_backup_preview = Some((playlist_index, number, backup));
+    }
+    let backup = &state.playlist_backup_preview.as_ref().unwrap().2;
+    let songs = &state.playlists[playlist_index].songs;
+
+    let duration_sum: u64 = backup.songs.iter().map(|x| x.duration.unwrap_or(0)).sum();
+    ui.text(format!(
+        "{} songs ({})",
+        backup.songs.len(),
+        util::ms_to_string(duration_sum)
+    ));
+    ui.separator();
+
+    // Songs that would be added back and songs that would be removed when restoring
+    let mut changes: Vec<(bool, &Song)> = backup
+        .songs
+        .iter()
+        .filter(|x| !songs.iter().any(|s| s.path == x.path))
+        .map(|x| (true, x))
+        .collect();
+    changes.extend(
+        songs
+            .iter()
+            .filter(|x| !backup.songs.iter().any(|s| s.path == x.path))
+            .map(|x| (false, x)),
+    );
+
+    if changes.is_empty() {
+        if songs
+            .iter()
+            .map(|x| &x.path)
+            .eq(backup.songs.iter().map(|x| &x.path))
+        {
+            ui.text_colored(theme.text2, "No differences");
+        } else {
+            ui.text_colored(theme.text2, "Same songs in a different order");
+        }
+    }
+    for (is_added, song) in changes.iter().take(max_lines) {
+        if *is_added {
+            ui.text_colored(theme.playing, format!("+ {} - {}", song.artist, song.name));
+        } else {
+            ui.text_colored(
+                theme.not_existing,
+                format!("- {} - {}", song.artist, song.name),
+            );
+        }
+    }
+    if changes.len() > max_lines {
+        ui.text_colored(
+            theme.text2,
+            format!("... and {} more", changes.len() - max_lines),
+        );
+    }
+
+    ui.separator();
+    if ui.button("Restore") {
+        let (_, _, backup) = state.playlist_backup_preview.take().unwrap();
+        actions::restore_playlist_backup(state, playlist_index, backup);
+        ui.close_current_popup();
+    }
+}
+
+fn draw_archived_playlists(ui: &Ui, state: &mut State, width: f32, padding_left: f32) {
+    let theme = state.theme;
+    let padding_right = 3.0;
+    ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 8.0]);
+    let token = ui.push_id("archived_playlists");
+    if ui.selectable("") {
+        state.show_archived_playlists = !state.show_archived_playlists;
+    }
+    ui.same_line_with_pos(ui.cursor_pos()[0] + padding_left);
+    ui.text_colored(
+        theme.text2,
+        format!(
+            "{} Archived",
+            if state.show_archived_playlists {
+                "▼"
+            } else {
+                "▶"
+            }
+        ),
+    );
+    let count = state.archived_playlists.len().to_string();
+    ui.same_line_with_pos(width - padding_right - ui.calc_text_size(&count)[0]);
+    ui.text_colored(theme.text2, &count);
+
+    let mut restored_playlist_index = None;
+    if state.show_archived_playlists {
+        for (i, name) in state.archived_playlists.iter().enumerate() {
+            let token = ui.push_id_usize(i);
+            let row = widgets::draw_row(ui, false, None);
+            if row.double_clicked {
+                restored_playlist_index = Some(i);
+            }
+            if row.right_clicked {
+                ui.open_popup("archived_playlist_context_menu");
+            }
+            ui.popup("archived_playlist_context_menu", || {
+                let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+                if ui.menu_item("Restore") {
+                    restored_playlist_index = Some(i);
+                }
+            });
+            ui.same_line_with_pos(ui.cursor_pos()[0] + 2.0 * padding_left);
+            ui.text_colored(theme.text2, name);
+            token.pop();
+        }
+    }
+    token.pop();
+
+    if let Some(i) = restored_playlist_index {
+        actions::restore_playlist(state, i);
+    }
+}
+
+fn draw_textboxes(ui: &Ui, style: &Style, state: &mut State) {
+    let theme = state.theme;
+    let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
+    let style_token = ui.push_style_var(StyleVar::ItemSpacing([1.0, 0.0]));
+
+    let token = ui.push_id("new_playlist_textbox");
+    ui.set_next_item_width(width / 2.0);
+    if ui
+        .input_text("", &mut state.new_playlist_text)
+        .enter_returns_true(true)
+        .hint(NEW_PLAYLIST_TEXT)
+        .build()
+    {
+        if state
+            .playlists
+            .iter()
+            .any(|x| x.name == state.new_playlist_text)
+        {
+            return;
+        }
+        let mut new_playlist = Playlist::new(state.new_playlist_text.clone(), Vec::new());
+        new_playlist.original_hash = 0;
+        state.playlists.push(new_playlist);
+        state.sort_playlists();
+        state.new_playlist_text.clear();
+    }
+    state.has_textbox_focus |= ui.is_item_focused();
+    token.pop();
+
+    let token = ui.push_id("song_search_textbox");
+    let border_color_token;
+    let border_size_token;
+    if state.song_search_text.is_empty() {
+        border_color_token =
+            ui.push_style_color(StyleColor::Border, ui.style_color(StyleColor::Border));
+        border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(style.frame_border_size));
+    } else {
+        border_color_token = ui.push_style_color(StyleColor::Border, theme.primary1);
+        border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(2.0));
+    }
+    ui.same_line();
+    ui.set_next_item_width(width / 2.0);
+    if ui
+        .input_text("", &mut state.song_search_text)
+        .hint(SONG_SEARCH_TEXT)
+        .build()
+    {
+        state.song_search_query = search::parse(&state.song_search_text);
+        state.selected_song_indices.clear();
+    }
+    if !ui.is_item_focused()
+        && state.rebound_command.is_none()
+        && (state.focus_song_search
+            || state
+                .settings
+                .keymap
+                .is_pressed(ui, Command::FocusSearch, false))
+    {
+        ui.set_keyboard_focus_here_with_offset(FocusedWidget::Previous);
+    }
+    state.focus_song_search = false;
+    state.has_textbox_focus |= ui.is_item_focused();
+    border_size_token.pop();
+    border_color_token.pop();
+    token.pop();
+
+    style_token.pop();
+}
+
+fn draw_filter_bar(ui: &Ui, style: &Style, state: &mut State) {
+    let theme = state.theme;
+    let horizontal_padding = 6.0;
+    let all_genres_text = "All genres";
+    ui.set_cursor_pos([horizontal_padding, 2.0]);
+
+    let token = ui.push_id("genre_filter");
+    let (border_color, border_size) = if state.genre_filter.is_some() {
+        (theme.primary1, 2.0)
+    } else {
+        (ui.style_color(StyleColor::Border), style.frame_border_size)
+    };
+    let border_color_token = ui.push_style_color(StyleColor::Border, border_color);
+    let border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(border_size));
+    ui.set_next_item_width(200.0);
+    if let Some(_combo_token) =
+        ui.begin_combo("", state.genre_filter.as_deref().unwrap_or(all_genres_text))
+    {
+        // Only offer genres of the selected playlist
+        let mut genres: Vec<&String> = state.playlists[state.selected_playlist_index]
+            .songs
+            .iter()
+            .filter_map(|x| state.tags.get(&x.path))
+            .flat_map(|x| x.genres.iter())
+            .collect();
+        genres.sort_by_key(|x| x.to_lowercase());
+        genres.dedup();
+
+        let mut new_filter = None;
+        if ui
+            .selectable_config(all_genres_text)
+            .selected(state.genre_filter.is_none())
+            .build()
+        {
+            new_filter = Some(None);
+        }
+        for genre in genres {
+            if ui
+                .selectable_config(genre)
+                .selected(state.genre_filter.as_ref() == Some(genre))
+                .build()
+            {
+                new_filter = Some(Some(genre.clone()));
+            }
+        }
+        if let Some(genre_filter) = new_filter {
+            state.genre_filter = genre_filter;
+            state.selected_song_indices.clear();
+        }
+    }
+    border_size_token.pop();
+    border_color_token.pop();
+    token.pop();
+
+    // Hide songs that are contained in another playlist
+    let all_playlists_text = "In any playlist";
+    let no_playlist_text = "Not in any playlist";
+    let token = ui.push_id("not_in_playlist_filter");
+    let (border_color, border_size) = if state.not_in_playlist_filter.is_some() {
+        (theme.primary1, 2.0)
+    } else {
+        (ui.style_color(StyleColor::Border), style.frame_border_size)
+    };
+    let border_color_token = ui.push_style_color(StyleColor::Border, border_color);
+    let border_size_token = ui.push_style_var(StyleVar::FrameBorderSize(border_size));
+    ui.same_line();
+    ui.set_next_item_width(200.0);
+    let preview = match &state.not_in_playlist_filter {
+        Some(NotInFilter::Playlist(name)) => format!("Not in {name}"),
+        Some(NotInFilter::AnyPlaylist) => no_playlist_text.to_string(),
+        None => all_playlists_text.to_string(),
+    };
+    if let Some(_combo_token) = ui.begin_combo("", preview) {
+        let mut new_filter = None;
+        if ui
+            .selectable_config(all_playlists_text)
+            .selected(state.not_in_playlist_filter.is_none())
+            .build()
+        {
+            new_filter = Some(None);
+        }
+        if ui
+            .selectable_config(no_playlist_text)
+            .selected(state.not_in_playlist_filter == Some(NotInFilter::AnyPlaylist))
+            .build()
+        {
+            new_filter = Some(Some(NotInFilter::AnyPlaylist));
+        }
+        for (i, playlist) in state.playlists.iter().enumerate() {
+            if i == state.selected_playlist_index {
+                continue;
+            }
+            if ui
+                .selectable_config(format!("Not in {}", playlist.name))
+                .selected(
+                    state.not_in_playlist_filter
+                        == Some(NotInFilter::Playlist(playlist.name.clone())),
+                )
+                .build()
+            {
+                new_filter = Some(Some(NotInFilter::Playlist(playlist.name.clone())));
+            }
+        }
+        if let Some(not_in_playlist_filter) = new_filter {
+            state.not_in_playlist_filter = not_in_playlist_filter;
+            state.selected_song_indices.clear();
+            state.update_not_in_playlist_paths();
+        }
+    }
+    border_size_token.pop();
+    border_color_token.pop();
+    token.pop();
+
+    // Only show broken entries so they can be removed at once
+    ui.same_line();
+    let missing_count = state.playlists[state.selected_playlist_index]
+        .songs
+        .iter()
+        .filter(|x| !x.exists)
+        .count();
+    let color_token = ui.push_style_color(
+        StyleColor::Text,
+        if state.missing_filter {
+            theme.primary2
+        } else {
+            theme.text1
+        },
+    );
+    if ui.button(format!("Missing ({missing_count})###missing_filter")) {
+        state.missing_filter = !state.missing_filter;
+        state.selected_song_indices.clear();
+    }
+    color_token.pop();
+    if ui.is_item_hovered() {
+        ui.tooltip_text("Only show songs whose files don't exist");
+    }
+
+    ui.same_line();
+    let color_token = ui.push_style_color(
+        StyleColor::Text,
+        if state.show_album_grid {
+            theme.primary2
+        } else {
+            theme.text1
+        },
+    );
+    if ui.button("Album grid") {
+        state.show_album_grid = !state.show_album_grid;
+        state.scrolling_song = None;
+    }
+    color_token.pop();
+}
+
+/// Shown songs of the selected playlist with the same album tag
+struct Album {
+    name: String,
+    /// Artist of the songs or "Various artists" if they differ
+    artist: String,
+    song_indices: Vec<usize>,
+}
+
+/// Returns the albums of the shown songs sorted by their names. Songs without an album tag are
+/// left out.
+fn get_albums(state: &State) -> Vec<Album> {
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let mut albums: Vec<Album> = Vec::new();
+    let mut album_indices: HashMap<String, usize> = HashMap::new();
+    for i in state.get_shown_song_indices() {
+        let song = &songs[i];
+        let tags = state.tags.get(&song.path);
+        let Some(name) = tags.and_then(|x| x.album.as_ref()) else {
+            continue;
+        };
+        let artist = tags.and_then(|x| x.artist.as_ref()).unwrap_or(&song.artist);
+        match album_indices.get(&name.to_lowercase()) {
+            Some(index) => {
+                let album = &mut albums[*index];
+                if album.artist != *artist {
+                    album.artist = "Various artists".to_string();
                 }
-                Some(SortType::Song(SortDirection::Descending)) => None,
-                _ => Some(SortType::Song(SortDirection::Ascending)),
-            };
+                album.song_indices.push(i);
+            }
+            None => {
+                album_indices.insert(name.to_lowercase(), albums.len());
+                albums.push(Album {
+                    name: name.clone(),
+                    artist: artist.clone(),
+                    song_indices: vec![i],
+                });
+            }
         }
     }
-    ui.text("Song");
-    match &state.sort_type {
-        Some(SortType::Song(sort_direction)) => {
-            let icon = sort_direction.get_sort_icon();
-            ui.same_line_with_pos(
-                rect_max[0] - ui.window_pos()[0] - horizontal_padding - ui.calc_text_size(icon)[0],
-            );
-            ui.text(icon);
+    albums.sort_by_key(|x| x.name.to_lowercase());
+    albums
+}
+
+/// Stores the thumbnails that were loaded in the background in free cells of the atlas.
+fn receive_thumbnails(state: &mut State) {
+    let Some(receiver) = &state.thumbnails_rx else {
+        return;
+    };
+    let mut thumbnails = Vec::new();
+    let is_finished = loop {
+        match receiver.try_recv() {
+            Ok(thumbnail) => thumbnails.push(thumbnail),
+            Err(mpsc::TryRecvError::Empty) => break false,
+            Err(mpsc::TryRecvError::Disconnected) => break true,
         }
-        _ => (),
     };
+    if is_finished {
+        state.thumbnails_rx = None;
+    }
+
+    let cell_count = (cover::ATLAS_CELLS * cover::ATLAS_CELLS) as usize;
+    for (key, thumbnail) in thumbnails {
+        state.requested_thumbnails.remove(&key);
+        let Some(thumbnail) = thumbnail else {
+            state.album_thumbnails.insert(key, None);
+            continue;
+        };
+        let mut cell = state.album_thumbnails.values().flatten().count();
+        if cell >= cell_count {
+            // The atlas is full, the thumbnails that are still visible are loaded again
+            state.album_thumbnails.clear();
+            cell = 0;
+        }
+        let size = [thumbnail.width as f32, thumbnail.height as f32];
+        state.album_thumbnails.insert(key, Some((cell, size)));
+        state.loaded_thumbnails.push((cell, thumbnail));
+    }
+}
 
-    let duration_text_x = width
-        - 2.0 * horizontal_padding
-        - ui.calc_text_size("Duration")[0]
-        - SortDirection::get_sort_icon_width(ui);
+/// Draws the albums of the selected playlist as a grid of covers. Clicking an album shows its
+/// songs, the play button on the cover plays them. Returns whether a scrollbar is shown.
+fn draw_album_grid(ui: &Ui, state: &mut State) -> bool {
+    let theme = state.theme;
+    let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
+    let padding = 16.0;
+    let cover_size = cover::THUMBNAIL_SIZE as f32;
+    let cell_size = [
+        cover_size,
+        cover_size + 2.0 * ui.text_line_height_with_spacing(),
+    ];
+    let columns = ((width - padding) / (cell_size[0] + padding)).max(1.0) as usize;
+
+    receive_thumbnails(state);
+    let albums = get_albums(state);
+    if albums.is_empty() {
+        ui.set_cursor_pos([padding, padding]);
+        ui.text_colored(theme.text2, "No songs with album tags");
+        return false;
+    }
 
-    let rect_min = util::add_pos(ui.window_pos(), [width / 2.0, 0.0]);
-    let rect_max = util::add_pos(ui.window_pos(), [duration_text_x, SONGS_HEADER_HEIGHT]);
-    if ui.is_mouse_hovering_rect(rect_min, rect_max) {
-        ui.get_window_draw_list()
-            .add_rect(rect_min, rect_max, HOVERED_BG)
-            .filled(true)
-            .build();
-        if ui.is_mouse_clicked(MouseButton::Left) {
-            state.sort_type = match state.sort_type {
-                Some(SortType::Artist(SortDirection::Ascending)) => {
-                    Some(SortType::Artist(SortDirection::Descending))
+    let visible_min = ui.scroll_y();
+    let visible_max = visible_min + ui.window_size()[1];
+    let mut requests = Vec::new();
+    let mut shown_album = None;
+    let mut played_album = None;
+    for (n, album) in albums.iter().enumerate() {
+        let pos = [
+            padding + (n % columns) as f32 * (cell_size[0] + padding),
+            padding + (n / columns) as f32 * (cell_size[1] + padding),
+        ];
+        if pos[1] + cell_size[1] < visible_min || pos[1] > visible_max {
+            continue;
+        }
+        ui.set_cursor_pos(pos);
+        let min = ui.cursor_screen_pos();
+        let cover_max = util::add_pos(min, [cover_size, cover_size]);
+        let token = ui.push_id_usize(n);
+
+        // The play button is drawn first to take the clicks from the cell below it
+        if ui.is_mouse_hovering_rect(min, util::add_pos(min, cell_size)) {
+            let button_size = 32.0;
+            ui.set_cursor_screen_pos(util::sub_pos(cover_max, [button_size + 4.0; 2]));
+            if ui.button_with_size("▶", [button_size, button_size]) {
+                played_album = Some(n);
+            }
+            ui.set_cursor_pos(pos);
+        }
+        if ui.selectable_config("").size(cell_size).build() {
+            shown_album = Some(n);
+        }
+
+        let key = album.name.to_lowercase();
+        let draw_list = ui.get_window_draw_list();
+        match (state.album_thumbnails.get(&key), state.thumbnail_atlas) {
+            (Some(Some((cell, size))), Some(atlas)) => {
+                let cells = cover::ATLAS_CELLS as f32;
+                let uv_min = [
+                    (*cell as f32 % cells) / cells,
+                    (*cell as f32 / cells).floor() / cells,
+                ];
+                let uv_max = util::add_pos(
+                    uv_min,
+                    [size[0] / cover_size / cells, size[1] / cover_size / cells],
+                );
+                // Centered in the square of the cover
+                let offset = [(cover_size - size[0]) / 2.0, (cover_size - size[1]) / 2.0];
+                let image_min = util::add_pos(min, offset);
+                draw_list
+                    .add_image(atlas, image_min, util::add_pos(image_min, *size))
+                    .uv_min(uv_min)
+                    .uv_max(uv_max)
+                    .build();
+            }
+            (album_thumbnail, _) => {
+                draw_list
+                    .add_rect(min, cover_max, theme.dark4)
+                    .filled(true)
+                    .build();
+                if album_thumbnail.is_none() && !state.requested_thumbnails.contains(&key) {
+                    let songs = &state.playlists[state.selected_playlist_index].songs;
+                    requests.push((key, songs[album.song_indices[0]].path.clone()));
                 }
-                Some(SortType::Artist(SortDirection::Descending)) => None,
-                _ => Some(SortType::Artist(SortDirection::Ascending)),
-            };
+            }
         }
+
+        ui.set_cursor_pos([pos[0], pos[1] + cover_size + 4.0]);
+        widgets::draw_truncated_text(ui, &album.name, cover_size);
+        ui.set_cursor_pos([pos[0], ui.cursor_pos()[1]]);
+        let color_token = ui.push_style_color(StyleColor::Text, theme.text2);
+        widgets::draw_truncated_text(ui, &album.artist, cover_size);
+        color_token.pop();
+        token.pop();
     }
-    ui.same_line_with_pos(width / 2.0 + horizontal_padding);
-    ui.text("Artist");
-    match &state.sort_type {
-        Some(SortType::Artist(sort_direction)) => {
-            let icon = sort_direction.get_sort_icon();
-            ui.same_line_with_pos(
-                rect_max[0] - ui.window_pos()[0] - horizontal_padding - ui.calc_text_size(icon)[0],
-            );
+    // Extend the content to the last row to scroll through all albums
+    let rows = albums.len().div_ceil(columns);
+    ui.set_cursor_pos([0.0, padding + rows as f32 * (cell_size[1] + padding)]);
+    ui.dummy([0.0, 0.0]);
+
+    // Thumbnails are loaded in batches of the visible albums
+    if state.thumbnails_rx.is_none() && !requests.is_empty() {
+        state
+            .requested_thumbnails
+            .extend(requests.iter().map(|(key, _)| key.clone()));
+        state.thumbnails_rx = Some(cover::start_loading_thumbnails(
+            state.base_path.clone(),
+            requests,
+        ));
+    }
+
+    if let Some(n) = played_album {
+        let songs = &state.playlists[state.selected_playlist_index].songs;
+        if let Some(i) = albums[n].song_indices.iter().find(|x| songs[**x].exists) {
+            state.emit(Action::Play {
+                playlist_index: state.selected_playlist_index,
+                song_index: *i,
+            });
+        }
+    } else if let Some(n) = shown_album {
+        state.song_search_text = format!("album:\"{}\"", albums[n].name);
+        state.song_search_query = search::parse(&state.song_search_text);
+        state.selected_song_indices.clear();
+        state.show_album_grid = false;
+    }
+    ui.scroll_max_y() > 0.0
+}
+
+/// Returns the visible columns of the song list with their x position and width. Columns without
+/// a fixed width share the remaining width by their weights.
+fn get_column_layout(
+    ui: &Ui,
+    settings: &settings::Settings,
+    width: f32,
+) -> Vec<(Column, f32, f32)> {
+    let columns = &settings.song_columns;
+    let fixed_width: f32 = columns.iter().filter_map(|x| x.get_fixed_width(ui)).sum();
+    let total_weight: f32 = columns
+        .iter()
+        .filter(|x| x.get_fixed_width(ui).is_none())
+        .map(|x| settings.get_column_weight(*x))
+        .sum();
+    let flexible_width = (width - fixed_width).max(0.0);
+    let mut x = 0.0;
+    let mut layout = Vec::new();
+    for column in columns {
+        let column_width = column.get_fixed_width(ui).unwrap_or_else(|| {
+            flexible_width * settings.get_column_weight(*column) / total_weight.max(f32::EPSILON)
+        });
+        layout.push((*column, x, column_width));
+        x += column_width;
+    }
+    layout
+}
+
+/// Handles dragging the boundaries between columns without a fixed width. Returns whether a
+/// boundary is hovered or dragged.
+fn handle_column_resizing(ui: &Ui, state: &mut State, layout: &[(Column, f32, f32)]) -> bool {
+    if let Some(i) = state.resized_column_index {
+        if !ui.is_mouse_down(MouseButton::Left) || i + 1 >= layout.len() {
+            state.resized_column_index = None;
+            settings::save(state);
+        } else {
+            // Move weight between the two columns next to the boundary
+            let (left, _, left_width) = layout[i];
+            let (right, _, right_width) = layout[i + 1];
+            let left_weight = state.settings.get_column_weight(left);
+            let right_weight = state.settings.get_column_weight(right);
+            let pair_width = left_width + right_width;
+            let min_width = COLUMN_MIN_WIDTH.min(pair_width / 2.0);
+            let new_left_width = (ui.io().mouse_pos[0] - ui.window_pos()[0] - layout[i].1)
+                .clamp(min_width, pair_width - min_width);
+            let pair_weight = left_weight + right_weight;
+            let new_left_weight = pair_weight * new_left_width / pair_width.max(f32::EPSILON);
+            let weights = &mut state.settings.column_weights;
+            weights.insert(left, new_left_weight);
+            weights.insert(right, pair_weight - new_left_weight);
+            ui.set_mouse_cursor(Some(MouseCursor::ResizeEW));
+            return true;
+        }
+    }
+
+    for i in 0..layout.len().saturating_sub(1) {
+        let (left, x, column_width) = layout[i];
+        let (right, _, _) = layout[i + 1];
+        if left.get_fixed_width(ui).is_some() || right.get_fixed_width(ui).is_some() {
+            continue;
+        }
+        let boundary_x = x + column_width;
+        let rect_min = util::add_pos(ui.window_pos(), [boundary_x - 4.0, 0.0]);
+        let rect_max = util::add_pos(ui.window_pos(), [boundary_x + 4.0, SONGS_HEADER_HEIGHT]);
+        if ui.is_mouse_hovering_rect(rect_min, rect_max) {
+            ui.set_mouse_cursor(Some(MouseCursor::ResizeEW));
+            if ui.is_mouse_clicked(MouseButton::Left) {
+                state.resized_column_index = Some(i);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+fn draw_songs_header(ui: &Ui, state: &mut State, scrollbar_offset: f32) {
+    let theme = state.theme;
+    let width =
+        ui.window_content_region_max()[0] - ui.window_content_region_min()[0] - scrollbar_offset;
+    let text_y = ui.cursor_pos()[1] + 6.0;
+    let layout = get_column_layout(ui, &state.settings, width);
+    let is_resizing = handle_column_resizing(ui, state, &layout);
+
+    // Draw header
+    for (i, (column, x, column_width)) in layout.into_iter().enumerate() {
+        let rect_min = util::add_pos(ui.window_pos(), [x, 0.0]);
+        let rect_max = util::add_pos(ui.window_pos(), [x + column_width, SONGS_HEADER_HEIGHT]);
+        if i > 0 {
+            ui.get_window_draw_list()
+                .add_line(
+                    util::add_pos(rect_min, [0.0, 6.0]),
+                    util::add_pos(rect_min, [0.0, SONGS_HEADER_HEIGHT - 6.0]),
+                    theme.hovered_bg(),
+                )
+                .build();
+        }
+        if !is_resizing && ui.is_mouse_hovering_rect(rect_min, rect_max) {
+            ui.get_window_draw_list()
+                .add_rect(rect_min, rect_max, theme.hovered_bg())
+                .filled(true)
+                .build();
+            if ui.is_mouse_clicked(MouseButton::Left) {
+                state.sort_type = match &state.sort_type {
+                    Some(sort_type) if sort_type.column == column => match sort_type.direction {
+                        SortDirection::Ascending => Some(SortType {
+                            column,
+                            direction: SortDirection::Descending,
+                        }),
+                        SortDirection::Descending => None,
+                    },
+                    _ => Some(SortType {
+                        column,
+                        direction: SortDirection::Ascending,
+                    }),
+                };
+            }
+        }
+        ui.set_cursor_pos([x + SONGS_COLUMN_PADDING, text_y]);
+        ui.text(column.name());
+        if let Some(sort_type) = state.sort_type.as_ref().filter(|x| x.column == column) {
+            let icon = sort_type.direction.get_sort_icon();
+            ui.set_cursor_pos([
+                x + column_width - SONGS_COLUMN_PADDING - ui.calc_text_size(icon)[0],
+                text_y,
+            ]);
             ui.text(icon);
         }
-        _ => (),
-    };
+    }
 
-    let rect_min = util::add_pos(ui.window_pos(), [duration_text_x, 0.0]);
+    // Choose the visible columns
     let rect_max = util::add_pos(ui.window_pos(), [width, SONGS_HEADER_HEIGHT]);
-    if ui.is_mouse_hovering_rect(rect_min, rect_max) {
-        ui.get_window_draw_list()
-            .add_rect(rect_min, rect_max, HOVERED_BG)
-            .filled(true)
-            .build();
-        if ui.is_mouse_clicked(MouseButton::Left) {
-            state.sort_type = match state.sort_type {
-                Some(SortType::Duration(SortDirection::Ascending)) => {
-                    Some(SortType::Duration(SortDirection::Descending))
+    if ui.is_mouse_hovering_rect(ui.window_pos(), rect_max)
+        && ui.is_mouse_clicked(MouseButton::Right)
+    {
+        ui.open_popup("songs_header_context_menu");
+    }
+    ui.popup("songs_header_context_menu", || {
+        let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+        for column in Column::ALL {
+            let columns = &state.settings.song_columns;
+            let is_visible = columns.contains(&column);
+            if ui
+                .menu_item_config(column.name())
+                .selected(is_visible)
+                // At least one column stays visible
+                .enabled(!is_visible || columns.len() > 1)
+                .build()
+            {
+                state.settings.song_columns = Column::ALL
+                    .into_iter()
+                    .filter(|x| (*x == column) != columns.contains(x))
+                    .collect();
+                if state.sort_type.as_ref().is_some_and(|x| x.column == column) {
+                    state.sort_type = None;
                 }
-                Some(SortType::Duration(SortDirection::Descending)) => None,
-                _ => Some(SortType::Duration(SortDirection::Ascending)),
-            };
+                settings::save(state);
+            }
+        }
+        ui.separator();
+        for grouping in settings::SongGrouping::ALL {
+            if ui
+                .menu_item_config(grouping.name())
+                .selected(state.settings.song_grouping == grouping)
+                .build()
+            {
+                state.settings.song_grouping = grouping;
+                state.collapsed_groups.clear();
+                settings::save(state);
+            }
         }
+    });
+}
+
+/// Consecutive shown songs with the same album or artist
+struct SongGroup {
+    name: String,
+    song_indices: Vec<usize>,
+}
+
+/// Shown songs of the song list, kept between frames until the songs or the filters change
+pub struct SongList {
+    /// Hash of everything the list depends on
+    key: u64,
+    /// Indices of the shown songs in the order they are shown, including collapsed songs
+    indices: Vec<usize>,
+    groups: HashMap<usize, SongGroup>,
+}
+
+/// Returns a hash of the versions of the playlists and the song data and of the sorting, search
+/// and filters. The versions change with every change of the songs, so they aren't hashed
+/// themselves.
+fn get_song_list_key(state: &State) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.selected_playlist_index.hash(&mut hasher);
+    state.playlists_version.hash(&mut hasher);
+    state.song_data_version.hash(&mut hasher);
+    if let Some(sort_type) = &state.sort_type {
+        sort_type.column.hash(&mut hasher);
+        matches!(sort_type.direction, SortDirection::Ascending).hash(&mut hasher);
     }
-    ui.same_line_with_pos(duration_text_x);
-    ui.text("Duration");
-    match &state.sort_type {
-        Some(SortType::Duration(sort_direction)) => {
-            let icon = sort_direction.get_sort_icon();
-            ui.same_line_with_pos(
-                rect_max[0] - ui.window_pos()[0] - horizontal_padding - ui.calc_text_size(icon)[0],
-            );
-            ui.text(icon);
+    state.song_search_text.hash(&mut hasher);
+    state.genre_filter.hash(&mut hasher);
+    state.not_in_playlist_filter.hash(&mut hasher);
+    state.missing_filter.hash(&mut hasher);
+    state.settings.song_grouping.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Filters and sorts the songs of the selected playlist.
+fn get_song_list(state: &State, key: u64) -> SongList {
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let mut indices: Vec<usize> = (0..songs.len())
+        .filter(|x| state.is_song_shown(&songs[*x]))
+        .collect();
+    if state.sort_type.is_some() {
+        indices.sort_by(|a, b| state.compare_songs(&songs[*a], &songs[*b]));
+    } else if !state.song_search_query.text.is_empty() {
+        // Without a sorted column the best matches of the search are shown first
+        indices.sort_by_cached_key(|x| Reverse(state.get_search_score(&songs[*x])));
+    }
+    let groups = get_song_groups(state, &indices);
+    SongList {
+        key,
+        indices,
+        groups,
+    }
+}
+
+/// Splits the shown songs into groups, which are returned by the index of their first song.
+fn get_song_groups(state: &State, indices: &[usize]) -> HashMap<usize, SongGroup> {
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let mut groups: HashMap<usize, SongGroup> = HashMap::new();
+    let mut current_group: Option<(usize, String)> = None;
+    for i in indices {
+        let Some(name) = state.get_song_group(&songs[*i]) else {
+            return groups;
+        };
+        match &current_group {
+            Some((first_index, current_name)) if *current_name == name => {
+                groups.get_mut(first_index).unwrap().song_indices.push(*i);
+            }
+            _ => {
+                groups.insert(
+                    *i,
+                    SongGroup {
+                        name: name.clone(),
+                        song_indices: vec![*i],
+                    },
+                );
+                current_group = Some((*i, name));
+            }
+        }
+    }
+    groups
+}
+
+/// Returns the header text of the group and its song count and duration.
+fn get_song_group_texts(state: &State, group: &SongGroup) -> (String, String) {
+    let icon = if state.collapsed_groups.contains(&group.name) {
+        "▶"
+    } else {
+        "▼"
+    };
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let duration: u64 = group
+        .song_indices
+        .iter()
+        .filter_map(|x| songs[*x].duration)
+        .sum();
+    let count = group.song_indices.len();
+    (
+        format!("{icon} {}", group.name),
+        format!(
+            "{count} {}, {}",
+            if count == 1 { "song" } else { "songs" },
+            util::ms_to_string(duration)
+        ),
+    )
+}
+
+/// Draws the header row of a group, clicking it collapses the group and double-clicking it plays
+/// the group.
+fn draw_song_group_header(ui: &Ui, state: &mut State, group: &SongGroup) {
+    let theme = state.theme;
+    let token = ui.push_id(&group.name);
+    let row = widgets::draw_row(ui, false, Some(theme.dark4));
+    let (title, details) = get_song_group_texts(state, group);
+    ui.same_line_with_pos(SONGS_COLUMN_PADDING);
+    ui.text(&title);
+    ui.same_line();
+    ui.text_colored(theme.text2, &details);
+
+    let play_group = |state: &mut State| {
+        let songs = &state.playlists[state.selected_playlist_index].songs;
+        if let Some(i) = group.song_indices.iter().find(|x| songs[**x].exists) {
+            state.emit(Action::Play {
+                playlist_index: state.selected_playlist_index,
+                song_index: *i,
+            });
         }
-        _ => (),
     };
+    if row.double_clicked {
+        play_group(state);
+    }
+    // A double click toggles the group twice and keeps it as it was
+    if row.clicked && !state.collapsed_groups.remove(&group.name) {
+        state.collapsed_groups.insert(group.name.clone());
+    }
+    if row.right_clicked {
+        ui.open_popup("song_group_context_menu");
+    }
+    ui.popup("song_group_context_menu", || {
+        let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+        if ui.menu_item("Play") {
+            play_group(state);
+        }
+        if ui.menu_item("Select songs") {
+            state.selected_song_indices = group.song_indices.clone();
+            state.focused_pane = FocusedPane::Songs;
+        }
+        if ui.menu_item("Collapse all") {
+            let songs = &state.playlists[state.selected_playlist_index].songs;
+            state.collapsed_groups = songs
+                .iter()
+                .filter_map(|x| state.get_song_group(x))
+                .collect();
+        }
+        if ui.menu_item("Expand all") {
+            state.collapsed_groups.clear();
+        }
+    });
+    token.pop();
 }
 
 fn draw_songs(
@@ -1071,248 +3943,387 @@ fn draw_songs(
     song_scroll_index: Option<usize>,
     scroll_delta: f32,
 ) -> bool {
+    let theme = state.theme;
     let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
-    let horizontal_padding = 6.0;
 
     ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 2.0]);
-    let draw_list = ui.get_window_draw_list();
-    let songs = state.playlists[state.selected_playlist_index].songs.clone();
-    let mut counter = 0;
-
-    // TODO Copy a list of all songs here (Vec<&Song>) to handle selection issues etc.
-    let song_iter = if state.sort_type.is_none() {
-        songs.iter().enumerate().collect()
-    } else {
-        let mut a: Vec<(usize, &Song)> = songs.iter().enumerate().collect();
-        a.sort_by(|a, b| state.sort_type.as_ref().unwrap().compare(a.1, b.1));
-        a
+    let playlist_index = state.selected_playlist_index;
+    let key = get_song_list_key(state);
+    // Taken out of the state while drawing, so that the rows can change the state
+    let song_list = match state.song_list.take() {
+        Some(song_list) if song_list.key == key => song_list,
+        _ => get_song_list(state, key),
     };
+    let mut counter = 0;
 
-    for (sorted_i, (i, song)) in song_iter.iter().enumerate() {
-        if !state.song_search_text.is_empty() && !song.is_matching(&state.song_search_text) {
+    // Songs can only be reordered if all songs are shown in their actual order
+    let column_layout = get_column_layout(ui, &state.settings, width);
+
+    let can_reorder = state.sort_type.is_none()
+        && state.song_search_text.is_empty()
+        && !util::is_default_playlist(&state.playlists[state.selected_playlist_index].name);
+    let mut reorder_target_index = None;
+    // Rect and index of the last shown row to drop songs below it
+    let mut last_row = None;
+    // Positions of the drawn group headers to keep the header of the top group visible
+    let mut header_positions: Vec<(f32, usize)> = Vec::new();
+    // Hovered song with a text that doesn't fit into its column
+    let mut hovered_scrolling_song = None;
+
+    for (sorted_i, i) in song_list.indices.iter().enumerate() {
+        // The songs may have been changed from a context menu, the list follows in the next frame
+        if state.selected_playlist_index != playlist_index
+            || *i >= state.playlists[playlist_index].songs.len()
+        {
+            break;
+        }
+        if let Some(group) = song_list.groups.get(i) {
+            header_positions.push((ui.cursor_pos()[1], *i));
+            draw_song_group_header(ui, state, group);
+        }
+        if state.is_song_collapsed(&state.playlists[playlist_index].songs[*i]) {
             continue;
         }
         counter += 1;
 
         let token = ui.push_id_usize(*i);
         // Draw selectable
-        draw_list.channels_split(2, |channel| {
-            channel.set_current(1);
-            if ui
-                .selectable_config("")
-                .selected(state.selected_song_indices.contains(&i))
-                .allow_double_click(true)
-                .build()
-            {
-                if ui.io().key_shift {
-                    if !state.selected_song_indices.is_empty() {
-                        let first = *state.selected_song_indices.first().unwrap();
-                        let sorted_first = song_iter.iter().position(|x| x.0 == first).unwrap();
-                        let range = if sorted_first <= sorted_i {
-                            (sorted_first + 1)..(sorted_i + 1)
-                        } else {
-                            sorted_i..sorted_first
-                        };
-
-                        state.selected_song_indices.truncate(1);
-                        for sorted_idx in range {
-                            let idx = song_iter[sorted_idx].0;
-                            if !state.song_search_text.is_empty()
-                                && !songs[idx].is_matching(&state.song_search_text)
-                            {
-                                continue;
-                            }
-                            state.selected_song_indices.push(idx);
-                        }
-                    }
-                } else if ui.io().key_ctrl {
-                    if state.selected_song_indices.contains(&i) {
-                        let index = state
-                            .selected_song_indices
-                            .iter()
-                            .position(|x| *x == *i)
-                            .unwrap();
-                        state.selected_song_indices.remove(index);
+        let row = widgets::draw_list_row(
+            ui,
+            &theme,
+            &widgets::ListRow {
+                selected: state.selected_song_indices.contains(i),
+                background: Some(widgets::stripe_color(counter, theme.dark1, theme.dark2)),
+                drop_kind: (can_reorder && !state.dragged_songs.is_empty())
+                    .then_some(widgets::DropKind::Between),
+            },
+        );
+        last_row = Some((ui.item_rect_min(), ui.item_rect_max(), *i));
+        // Rows outside of the view only take up their space, except for the row whose context
+        // menu may be open
+        if !row.is_visible && state.song_context_menu_index != Some(*i) {
+            token.pop();
+            if song_scroll_index == Some(counter - 1) {
+                widgets::scroll_to_item(ui);
+            }
+            continue;
+        }
+        let song = state.playlists[playlist_index].songs[*i].clone();
+        let is_row_hovered = ui.is_item_hovered() && state.dragged_songs.is_empty();
+        if is_row_hovered {
+            if let Some(note) = state.notes.get(&song.path) {
+                ui.tooltip_text(note);
+            }
+        }
+
+        // Drop
+        if let Some(is_below) = row.dropped_below {
+            reorder_target_index = Some(if is_below { *i + 1 } else { *i });
+        }
+        if row.clicked {
+            if ui.io().key_shift {
+                let first = state.selected_song_indices.first();
+                let sorted_first =
+                    first.and_then(|x| song_list.indices.iter().position(|y| y == x));
+                if let Some(sorted_first) = sorted_first {
+                    let range = if sorted_first <= sorted_i {
+                        (sorted_first + 1)..(sorted_i + 1)
                     } else {
-                        state.selected_song_indices.push(*i);
+                        sorted_i..sorted_first
+                    };
+
+                    state.selected_song_indices.truncate(1);
+                    for sorted_idx in range {
+                        let idx = song_list.indices[sorted_idx];
+                        if state.is_song_collapsed(&state.playlists[playlist_index].songs[idx]) {
+                            continue;
+                        }
+                        state.selected_song_indices.push(idx);
                     }
+                }
+            } else if ui.io().key_ctrl {
+                if state.selected_song_indices.contains(&i) {
+                    let index = state
+                        .selected_song_indices
+                        .iter()
+                        .position(|x| *x == *i)
+                        .unwrap();
+                    state.selected_song_indices.remove(index);
                 } else {
-                    state.selected_song_indices.clear();
                     state.selected_song_indices.push(*i);
-                    if ui.is_mouse_double_clicked(MouseButton::Left) && song.exists {
-                        state
-                            .action_tx
-                            .send(player::PlayerAction::Play(
-                                Path::new(&state.base_path).join(&song.path),
-                            ))
-                            .unwrap();
-                        state.is_playing = true;
-                        state.playing_playlist_index = Some(state.selected_playlist_index);
-                        state.playing_song_index = Some(*i);
-                        actions::set_current_metadata(state);
-                        state
-                            .media_controls
-                            .set_playback(MediaPlayback::Playing { progress: None })
-                            .unwrap();
-                    }
                 }
-            };
+            } else {
+                state.selected_song_indices.clear();
+                state.selected_song_indices.push(*i);
+                state.focused_pane = FocusedPane::Songs;
+                if row.double_clicked && song.exists {
+                    state.emit(Action::Play {
+                        playlist_index: state.selected_playlist_index,
+                        song_index: *i,
+                    });
+                }
+            }
+        };
+
+        if row.right_clicked {
+            state.song_context_menu_index = Some(*i);
+            if !state.selected_song_indices.contains(&i) {
+                state.selected_song_indices.clear();
+            }
+            if state.selected_song_indices.is_empty() {
+                state.selected_song_indices.push(*i);
+            }
+            state.original_file_name = state.playlists[state.selected_playlist_index].songs
+                [state.selected_song_indices[0]]
+                .path
+                .clone();
+            state.file_name_text = state.original_file_name.clone();
+            state.note_text = state
+                .notes
+                .get(&state.original_file_name)
+                .cloned()
+                .unwrap_or_default();
+            ui.open_popup("song_context_menu");
+        }
+        ui.popup("song_context_menu", || {
+            let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+            ui.menu("Add to", || draw_add_to_menu_items(ui, state, scroll_delta));
+            if ui.menu_item("Add to queue") {
+                state.emit(Action::QueueSelectedSongs);
+            }
+            ui.menu("New playlist from selection", || {
+                let token = ui.push_id("selection_playlist_textbox");
+                ui.set_next_item_width(300.0);
+                let is_confirmed = ui
+                    .input_text("", &mut state.selection_playlist_text)
+                    .enter_returns_true(true)
+                    .hint(NEW_PLAYLIST_TEXT)
+                    .build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                token.pop();
 
-            if ui.is_item_clicked_with_button(MouseButton::Right) {
-                if !state.selected_song_indices.contains(&i) {
-                    state.selected_song_indices.clear();
+                if ui.button("Create") || is_confirmed {
+                    let name = state.selection_playlist_text.clone();
+                    if actions::create_playlist_from_selection(state, &name) {
+                        state.selection_playlist_text.clear();
+                        ui.close_current_popup();
+                    }
                 }
-                if state.selected_song_indices.is_empty() {
-                    state.selected_song_indices.push(*i);
+            });
+            let is_default_playlist =
+                util::is_default_playlist(&state.playlists[state.selected_playlist_index].name);
+            if ui
+                .menu_item_config("Cut")
+                .shortcut("Ctrl+X")
+                .enabled(!is_default_playlist)
+                .build()
+            {
+                copy_selected_songs(ui, state);
+                state.emit(Action::RemoveSelectedSongs);
+            }
+            if ui.menu_item_config("Copy").shortcut("Ctrl+C").build() {
+                copy_selected_songs(ui, state);
+            }
+            if ui
+                .menu_item_config("Paste")
+                .shortcut("Ctrl+V")
+                .enabled(!is_default_playlist && !state.copied_songs.is_empty())
+                .build()
+            {
+                state.emit(Action::PasteSongs);
+            }
+            if ui.menu_item("Remove") {
+                state.emit(Action::RemoveSelectedSongs);
+            }
+            if ui.menu_item("Delete file") {
+                let songs = &state.playlists[playlist_index].songs;
+                let mut paths: Vec<String> = state
+                    .selected_song_indices
+                    .iter()
+                    .map(|i| songs[*i].path.clone())
+                    .collect();
+                paths.sort();
+                paths.dedup();
+                state.pending_deletions = paths;
+            }
+            if ui
+                .menu_item_config("Scan ReplayGain")
+                .enabled(!state.replaygain_job.is_running())
+                .build()
+            {
+                let songs = &state.playlists[playlist_index].songs;
+                let paths = state
+                    .selected_song_indices
+                    .iter()
+                    .map(|i| songs[*i].path.clone())
+                    .collect();
+                replaygain::scan(state, paths);
+            }
+            if ui
+                .menu_item_config("Adjust volume level")
+                .enabled(!state.postprocessing_job.is_running() && state.programs.aacgain.is_some())
+                .build()
+            {
+                let songs = &state.playlists[playlist_index].songs;
+                let paths = state
+                    .selected_song_indices
+                    .iter()
+                    .map(|i| songs[*i].path.clone())
+                    .collect();
+                download::postprocess_songs(state, paths);
+            }
+            if ui.menu_item("Show in file manager") {
+                let path = Path::new(&state.base_path).join(&state.original_file_name);
+                if let Err(e) = util::reveal_in_file_manager(&path) {
+                    state.push_status(
+                        StatusType::Error,
+                        format!("Could not open file manager:\n{e}"),
+                    );
                 }
-                state.original_file_name = state.playlists[state.selected_playlist_index].songs
+            }
+            if ui.menu_item("Reload file") {
+                let path = state.playlists[state.selected_playlist_index].songs
                     [state.selected_song_indices[0]]
                     .path
                     .clone();
-                state.file_name_text = state.original_file_name.clone();
-                ui.open_popup("song_context_menu");
-            }
-            ui.popup("song_context_menu", || {
-                let _style_token = ui.push_style_var(StyleVar::WindowPadding([4.0, 10.0]));
-                ui.menu("Add to", || {
-                    apply_smooth_scrolling(ui, scroll_delta, &mut state.add_to_menu_scroll_info);
-                    for playlist_index in 0..state.playlists.len() {
-                        let playlist_name = &state.playlists[playlist_index].name;
-                        if playlist_name == ALL_PLAYLIST_NAME
-                            || playlist_name == ALL_UNUSED_PLAYLIST_NAME
-                        {
-                            continue;
-                        }
-                        if ui.menu_item(playlist_name) {
-                            state.selected_song_indices.sort_unstable();
-                            for i in state.selected_song_indices.iter().rev() {
-                                state.playlists[playlist_index]
-                                    .songs
-                                    .insert(0, songs[*i].clone());
+                match player::get_duration(&Path::new(&state.base_path).join(&path)) {
+                    Ok(duration) => {
+                        for playlist in state.playlists.iter_mut() {
+                            for song in playlist.songs.iter_mut() {
+                                if song.path == *path {
+                                    song.duration = Some(duration / 1000 * 1000);
+                                }
                             }
-
-                            actions::increment_indices(
-                                state,
-                                playlist_index,
-                                state.selected_song_indices.len(),
-                            );
                         }
+                        state.mark_playlists_changed();
                     }
-                });
-                if ui.menu_item("Remove") {
-                    state.selected_song_indices.sort_unstable();
-                    for i in state.selected_song_indices.iter().rev() {
-                        // Update playing song index
-                        if state.playing_song_index == Some(*i) {
-                            state.playing_song_index =
-                                Some(0.max(state.playing_song_index.unwrap() - 1));
-                        } else if state.playing_song_index > Some(*i) {
-                            state.playing_song_index = Some(state.playing_song_index.unwrap() - 1);
-                        }
+                    Err(e) => {
+                        state.push_status(StatusType::Error, format!("Could not read {path}:\n{e}"))
+                    }
+                }
+            }
+            ui.menu("Note", || {
+                let token = ui.push_id("note_textbox");
+                ui.input_text_multiline("", &mut state.note_text, [400.0, 100.0])
+                    .build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                token.pop();
+
+                if ui.button("Save") {
+                    let songs = &state.playlists[playlist_index].songs;
+                    let paths: Vec<String> = state
+                        .selected_song_indices
+                        .iter()
+                        .map(|i| songs[*i].path.clone())
+                        .collect();
+                    let note = state.note_text.clone();
+                    notes::set_note(state, &paths, &note);
+                    ui.close_current_popup();
+                }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    ui.close_current_popup();
+                }
+            });
+            ui.menu("Batch rename", || {
+                let token = ui.push_id("rename_pattern_textbox");
+                ui.set_next_item_width(500.0);
+                let is_confirmed = ui
+                    .input_text("", &mut state.rename_pattern_text)
+                    .enter_returns_true(true)
+                    .build();
+                state.has_textbox_focus |= ui.is_item_focused();
+                token.pop();
+                ui.text_colored(
+                    theme.text2,
+                    "Placeholders: %artist% %title% %album% %genre% %filename% %ext%",
+                );
 
-                        state.playlists[state.selected_playlist_index]
-                            .songs
-                            .remove(*i);
-                    }
-                    state.selected_song_indices.clear();
+                let preview = actions::get_batch_rename_preview(state, &state.rename_pattern_text);
+                draw_rename_preview(ui, &theme, &preview);
+
+                if ui.button("Apply") || is_confirmed {
+                    state.emit(Action::RenameSongs(preview));
+                    ui.close_current_popup();
                 }
-                if ui.menu_item("Reload file") {
-                    let path = state.playlists[state.selected_playlist_index].songs
-                        [state.selected_song_indices[0]]
-                        .path
-                        .clone();
-                    let duration = Some(
-                        player::get_duration(&Path::new(&state.base_path).join(&path)) / 1000
-                            * 1000,
-                    );
-                    for playlist in state.playlists.iter_mut() {
-                        for song in playlist.songs.iter_mut() {
-                            if song.path == *path {
-                                song.duration = duration;
-                            }
-                        }
-                    }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    ui.close_current_popup();
                 }
-                let _disabled_token = ui.begin_disabled(state.selected_song_indices.len() != 1);
-                ui.menu("Properties", || {
-                    let name_info = &state.file_name_text[..state
-                        .file_name_text
-                        .rfind('.')
-                        .unwrap_or_else(|| state.file_name_text.len())];
-                    let name_info: Vec<&str> = name_info.splitn(2, " - ").collect();
-                    let artist = name_info[0].trim().to_string();
-                    let name = if name_info.len() > 1 {
-                        name_info[1].trim().to_string()
-                    } else {
-                        String::new()
-                    };
+            });
+            ui.menu("Sanitize file names", || {
+                let preview = actions::get_sanitize_preview(state);
+                draw_rename_preview(ui, &theme, &preview);
 
-                    let token = ui.push_id("file_name_textbox");
-                    ui.set_next_item_width(500.0);
-                    if ui
-                        .input_text("", &mut state.file_name_text)
-                        .enter_returns_true(true)
-                        .build()
-                    {
-                        actions::change_file_name(state, &artist, &name);
-                        ui.close_current_popup();
-                    }
-                    state.has_textbox_focus |= ui.is_item_focused();
-                    token.pop();
+                if ui.button("Apply") {
+                    state.emit(Action::RenameSongs(preview));
+                    ui.close_current_popup();
+                }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    ui.close_current_popup();
+                }
+            });
+            let _disabled_token = ui.begin_disabled(state.selected_song_indices.len() != 1);
+            ui.menu("Properties", || {
+                let name_info = &state.file_name_text[..state
+                    .file_name_text
+                    .rfind('.')
+                    .unwrap_or_else(|| state.file_name_text.len())];
+                let name_info: Vec<&str> = name_info.splitn(2, " - ").collect();
+                let artist = name_info[0].trim().to_string();
+                let name = if name_info.len() > 1 {
+                    name_info[1].trim().to_string()
+                } else {
+                    String::new()
+                };
 
-                    ui.text("Artist: ");
-                    ui.same_line();
-                    ui.text(&artist);
+                let token = ui.push_id("file_name_textbox");
+                ui.set_next_item_width(500.0);
+                if ui
+                    .input_text("", &mut state.file_name_text)
+                    .enter_returns_true(true)
+                    .build()
+                {
+                    actions::change_file_name(state, &artist, &name);
+                    ui.close_current_popup();
+                }
+                state.has_textbox_focus |= ui.is_item_focused();
+                token.pop();
 
-                    ui.text("Song: ");
-                    ui.same_line();
-                    ui.text(&name);
+                ui.text("Artist: ");
+                ui.same_line();
+                ui.text(&artist);
 
-                    if ui.button("Apply") {
-                        actions::change_file_name(state, &artist, &name);
-                        ui.close_current_popup();
-                    }
-                    ui.same_line();
-                    if ui.button("Cancel") {
-                        ui.close_current_popup();
-                    }
-                });
+                ui.text("Song: ");
+                ui.same_line();
+                ui.text(&name);
+
+                if ui.button("Apply") {
+                    actions::change_file_name(state, &artist, &name);
+                    ui.close_current_popup();
+                }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    ui.close_current_popup();
+                }
             });
-            channel.set_current(0);
-            draw_list
-                .add_rect(
-                    ui.item_rect_min(),
-                    ui.item_rect_max(),
-                    if counter % 2 == 1 {
-                        SONG_LIST_BG1
-                    } else {
-                        SONG_LIST_BG2
-                    },
-                )
-                .filled(true)
-                .build();
         });
 
         let color_token = if !song.exists {
-            Some(ui.push_style_color(StyleColor::Text, NOT_EXISTING_COLOR))
+            Some(ui.push_style_color(StyleColor::Text, theme.not_existing))
         } else if state.playing_playlist_index == Some(state.selected_playlist_index)
             && state.playing_song_index == Some(*i)
         {
-            Some(ui.push_style_color(StyleColor::Text, PLAYING_COLOR))
+            Some(ui.push_style_color(StyleColor::Text, theme.playing))
         } else {
             None
         };
 
         // Start dragging (if selection is empty or if selected song is dragged)
-        if ui.is_mouse_dragging(MouseButton::Left)
-            && ui.is_item_visible()
-            && state.dragged_songs.is_empty()
-            && util::is_point_in_rect(
-                util::sub_pos(ui.io().mouse_pos, ui.mouse_drag_delta()),
-                util::add_pos(ui.item_rect_min(), [0.0, 1.0]),
-                ui.item_rect_max(),
-            )
-            && (state.selected_song_indices.is_empty() || state.selected_song_indices.contains(&i))
+        if state.dragged_songs.is_empty()
+            && row.is_drag_started
+            && (state.selected_song_indices.is_empty() || state.selected_song_indices.contains(i))
         {
             if state.selected_song_indices.is_empty() {
                 state.selected_song_indices.push(*i);
@@ -1323,44 +4334,234 @@ fn draw_songs(
                         .clone(),
                 );
             }
+            state.dragged_song_indices = state.selected_song_indices.clone();
+            state.drag_playlist_index = Some(state.selected_playlist_index);
         }
 
-        // Draw song name
-        ui.same_line_with_pos(ui.cursor_pos()[0] + horizontal_padding);
-        draw_truncated_text(ui, &song.name, width / 2.0 - 2.0 * horizontal_padding);
-
-        // Get duration time width
-        let song_duration = util::ms_to_string(song.duration.unwrap_or(0));
-        let song_duration_width = ui.calc_text_size(&song_duration)[0];
-
-        // Draw song artist
-        ui.same_line_with_pos(width / 2.0 + horizontal_padding);
-        draw_truncated_text(
-            ui,
-            &song.artist,
-            width / 2.0 - 3.0 * horizontal_padding - song_duration_width,
-        );
+        // Seconds that cut off texts of the hovered or playing song have been scrolling for
+        let scroll_time = if !state.settings.scroll_long_titles {
+            None
+        } else if is_row_hovered {
+            Some(
+                state
+                    .scrolling_song
+                    .filter(|x| x.0 == *i)
+                    .map_or(0.0, |x| x.1.elapsed().as_secs_f32()),
+            )
+        } else if state.playing_playlist_index == Some(state.selected_playlist_index)
+            && state.playing_song_index == Some(*i)
+        {
+            Some(*state.position.lock().unwrap() as f32 / 1000.0)
+        } else {
+            None
+        };
 
-        // Draw song duration
-        ui.same_line_with_pos(width - horizontal_padding - song_duration_width);
-        ui.text(&song_duration);
+        // Draw the columns
+        for (column, x, column_width) in column_layout.iter() {
+            let text = state.get_column_text(*column, &song);
+            if column.is_right_aligned() {
+                let text_width = ui.calc_text_size(&text)[0];
+                ui.same_line_with_pos(x + column_width - SONGS_COLUMN_PADDING - text_width);
+                ui.text(&text);
+            } else {
+                ui.same_line_with_pos(x + SONGS_COLUMN_PADDING);
+                let text_width = column_width - 2.0 * SONGS_COLUMN_PADDING;
+                // Show why the song matches the search
+                let match_positions = match column {
+                    Column::Song | Column::Artist if !state.song_search_query.text.is_empty() => {
+                        util::fuzzy_match(&state.song_search_query.text, &text)
+                            .map(|(_, positions)| positions)
+                    }
+                    _ => None,
+                };
+                match match_positions {
+                    Some(positions) => widgets::draw_highlighted_text(
+                        ui,
+                        &text,
+                        text_width,
+                        &positions,
+                        theme.primary2,
+                    ),
+                    None => match scroll_time {
+                        Some(time) => {
+                            if widgets::draw_scrolling_text(ui, &text, text_width, time)
+                                && is_row_hovered
+                            {
+                                hovered_scrolling_song = Some(*i);
+                            }
+                        }
+                        None => widgets::draw_truncated_text(ui, &text, text_width),
+                    },
+                }
+            }
+        }
         if let Some(t) = color_token {
             t.pop();
         }
         token.pop();
 
-        if song_scroll_index.is_some()
-            && counter == song_scroll_index.unwrap()
-            && !ui.is_item_visible()
-        {
-            ui.set_scroll_here_y();
+        if song_scroll_index == Some(counter - 1) {
+            widgets::scroll_to_item(ui);
         }
     }
     ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 2.0]);
+    // The texts start scrolling from the beginning when another song is hovered
+    state.scrolling_song = match (hovered_scrolling_song, state.scrolling_song) {
+        (Some(i), Some((j, start))) if i == j => Some((j, start)),
+        (Some(i), _) => Some((i, Instant::now())),
+        (None, _) => None,
+    };
+
+    // Sticky header of the group at the top whose header was scrolled out of view
+    let scroll_y = ui.scroll_y();
+    let top_header = header_positions.iter().rposition(|(y, _)| *y < scroll_y);
+    if let Some(header_index) = top_header {
+        let row_height = ui.text_line_height_with_spacing();
+        // The next header pushes the sticky header out of view
+        let offset = header_positions
+            .get(header_index + 1)
+            .map_or(0.0, |(y, _)| (*y - scroll_y - row_height).min(0.0));
+        let group = &song_list.groups[&header_positions[header_index].1];
+        let (title, details) = get_song_group_texts(state, group);
+        let min = util::add_pos(ui.window_pos(), [0.0, offset]);
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_rect(min, util::add_pos(min, [width, row_height]), theme.dark4)
+            .filled(true)
+            .build();
+        let spacing = ui.clone_style().item_spacing;
+        let text_pos = util::add_pos(min, [SONGS_COLUMN_PADDING, spacing[1] / 2.0]);
+        draw_list.add_text(text_pos, theme.text1, &title);
+        let details_x = ui.calc_text_size(&title)[0] + spacing[0];
+        draw_list.add_text(
+            util::add_pos(text_pos, [details_x, 0.0]),
+            theme.text2,
+            &details,
+        );
+    }
+
+    // Dropping into the empty space below the rows adds the songs to the end
+    if let Some((rect_min, rect_max, i)) = last_row {
+        let window_max = util::add_pos(ui.window_pos(), ui.window_size());
+        let mouse_pos = ui.io().mouse_pos;
+        if can_reorder
+            && !state.dragged_songs.is_empty()
+            && reorder_target_index.is_none()
+            && util::is_point_in_rect(mouse_pos, [rect_min[0], rect_max[1]], window_max)
+        {
+            if ui.is_mouse_released(MouseButton::Left) {
+                reorder_target_index = Some(i + 1);
+            } else {
+                widgets::draw_insertion_line_at_rect(ui, &theme, rect_min, rect_max, true);
+            }
+        }
+    }
+
+    if let Some(target_index) = reorder_target_index {
+        if state.drag_playlist_index == Some(state.selected_playlist_index) {
+            let indices = std::mem::take(&mut state.dragged_song_indices);
+            state.emit(Action::MoveSongs {
+                playlist_index: state.selected_playlist_index,
+                indices,
+                target_index,
+            });
+        } else {
+            let songs = std::mem::take(&mut state.dragged_songs);
+            state.emit(Action::InsertSongs {
+                playlist_index: state.selected_playlist_index,
+                songs,
+                index: target_index,
+            });
+        }
+    }
+
+    // Context menu of the empty area below the songs
+    if ui.is_window_hovered()
+        && !ui.is_any_item_hovered()
+        && ui.is_mouse_clicked(MouseButton::Right)
+    {
+        ui.open_popup("songs_context_menu");
+    }
+    ui.popup("songs_context_menu", || {
+        let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+        draw_library_menu_items(ui, state);
+    });
+    state.song_list = Some(song_list);
     ui.scroll_max_y() > 0.0
 }
 
+/// Lists the playlists that the selected songs can be added to.
+fn draw_add_to_menu_items(ui: &Ui, state: &mut State, scroll_delta: f32) {
+    widgets::apply_smooth_scrolling(ui, scroll_delta, &mut state.add_to_menu_scroll_info);
+    for playlist_index in 0..state.playlists.len() {
+        let playlist_name = &state.playlists[playlist_index].name;
+        if util::is_default_playlist(playlist_name) {
+            continue;
+        }
+        if ui.menu_item(playlist_name) {
+            state.emit(Action::AddSelectedSongsTo(playlist_index));
+        }
+    }
+}
+
+/// Shows the number and total duration of the selected songs with actions for all of them.
+fn draw_selection_bar(ui: &Ui, state: &mut State, scroll_delta: f32) {
+    let theme = state.theme;
+    let songs = &state.playlists[state.selected_playlist_index].songs;
+    let mut indices = state.selected_song_indices.clone();
+    indices.sort_unstable();
+    indices.dedup();
+    let duration_sum: u64 = indices
+        .iter()
+        .filter_map(|i| songs.get(*i))
+        .map(|x| x.duration.unwrap_or(0))
+        .sum();
+
+    ui.set_cursor_pos([SONGS_COLUMN_PADDING, 5.0]);
+    ui.text(format!(
+        "{} songs selected — total {}",
+        indices.len(),
+        util::ms_to_string(duration_sum)
+    ));
+    ui.same_line();
+    if ui.small_button("Add to...") {
+        ui.open_popup("selection_add_to_menu");
+    }
+    ui.popup("selection_add_to_menu", || {
+        let _style_token = ui.push_style_var(StyleVar::WindowPadding(theme.popup_padding));
+        draw_add_to_menu_items(ui, state, scroll_delta);
+    });
+    ui.same_line();
+    if ui.small_button("Remove") {
+        state.emit(Action::RemoveSelectedSongs);
+    }
+    ui.same_line();
+    if ui.small_button("Queue") {
+        state.emit(Action::QueueSelectedSongs);
+    }
+}
+
+/// Lists the current and new paths of songs that are about to be renamed.
+fn draw_rename_preview(ui: &Ui, theme: &theme::Theme, preview: &[(String, Option<String>)]) {
+    let preview_height = (preview.len() as f32 * ui.text_line_height_with_spacing()).min(300.0);
+    ui.child_window("rename_preview")
+        .size([800.0, preview_height])
+        .build(|| {
+            for (path, new_path) in preview.iter() {
+                match new_path {
+                    Some(new_path) if new_path == path => ui.text_colored(theme.text2, path),
+                    Some(new_path) => ui.text(format!("{path}  →  {new_path}")),
+                    None => ui.text_colored(
+                        theme.not_existing,
+                        format!("{path}  (invalid or already taken)"),
+                    ),
+                }
+            }
+        });
+}
+
 fn draw_controls(ui: &Ui, style: &Style, state: &mut State) {
+    let theme = state.theme;
     let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
     let height_middle = CONTROLS_HEIGHT / 2.0;
     ui.columns(5, "control_columns", false);
@@ -1374,7 +4575,7 @@ fn draw_controls(ui: &Ui, style: &Style, state: &mut State) {
     let style_token2 = ui.push_style_var(StyleVar::FrameRounding(f32::MAX));
     let text_offset_token = ui.push_style_var(StyleVar::ButtonTextAlign([0.55, 0.9]));
     if ui.button_with_size("⏮", [50.0, 50.0]) {
-        actions::prev(state);
+        state.emit(Action::Prev);
     }
     text_offset_token.pop();
     ui.same_line();
@@ -1383,17 +4584,13 @@ fn draw_controls(ui: &Ui, style: &Style, state: &mut State) {
         if state.is_playing { "⏸" } else { "▶" }, // ⏮ ▶ ⏸ ⏭
         [50.0, 50.0],
     ) {
-        if state.is_playing {
-            actions::pause(state);
-        } else {
-            actions::resume(state);
-        }
+        state.emit(Action::TogglePlayback);
     }
     text_offset_token.pop();
     ui.same_line();
     let text_offset_token = ui.push_style_var(StyleVar::ButtonTextAlign([0.8, 0.9]));
     if ui.button_with_size("⏭︎", [50.0, 50.0]) {
-        actions::next(state);
+        state.emit(Action::Next);
     }
     text_offset_token.pop();
     font_token.pop();
@@ -1473,11 +4670,7 @@ fn draw_controls(ui: &Ui, style: &Style, state: &mut State) {
     }
     if ui.is_item_deactivated_after_edit() && state.last_progress.is_some() {
         let new_position = (state.last_progress.unwrap() * total_time as f64) as u64;
-        state
-            .action_tx
-            .send(player::PlayerAction::Seek(new_position))
-            .unwrap();
-        *state.position.lock().unwrap() = new_position;
+        state.emit(Action::Seek(new_position));
         state.last_progress = None;
     }
 
@@ -1494,75 +4687,450 @@ fn draw_controls(ui: &Ui, style: &Style, state: &mut State) {
                 rect_pos,
                 [(song_slider_width - 22.0) * progress as f32 + 17.0, 17.0],
             ),
-            PRIMARY2,
+            theme.primary2,
         )
         .filled(true)
         .thickness(0.0)
         .rounding(f32::MAX)
         .build();
 
-    // Total time
-    let total_time_string = util::ms_to_string(total_time);
-    ui.next_column();
-    ui.set_current_column_width(time_width);
-    ui.set_cursor_pos([
-        ui.cursor_pos()[0] - style.item_spacing[0],
-        ui.cursor_pos()[1] + height_middle - ui.calc_text_size(&total_time_string)[1] / 2.0 + 1.0,
-    ]);
-    ui.text(&total_time_string);
+    // Total or remaining time, toggled by clicking it
+    let total_time_string = if state.settings.show_remaining_time {
+        format!(
+            "-{}",
+            util::ms_to_string(total_time.saturating_sub(current_time))
+        )
+    } else {
+        util::ms_to_string(total_time)
+    };
+    ui.next_column();
+    ui.set_current_column_width(time_width);
+    ui.set_cursor_pos([
+        ui.cursor_pos()[0] - style.item_spacing[0],
+        ui.cursor_pos()[1] + height_middle - ui.calc_text_size(&total_time_string)[1] / 2.0 + 1.0,
+    ]);
+    ui.text(&total_time_string);
+    if ui.is_item_clicked() {
+        state.settings.show_remaining_time = !state.settings.show_remaining_time;
+        settings::save(state);
+    }
+
+    // Volume slider
+    ui.next_column();
+    ui.set_current_column_width(width / 4.0);
+    ui.set_cursor_pos([
+        ui.cursor_pos()[0] + width / 16.0,
+        ui.cursor_pos()[1] + height_middle - ui.current_font().font_size / 2.0,
+    ]);
+    let volume_slider_pos = ui.cursor_pos();
+    if draw_slider(
+        ui,
+        "volume_slider",
+        MIN_VOLUME,
+        MAX_VOLUME,
+        &mut state.volume,
+        width / 8.0,
+        20.0,
+    ) {
+        state.volume_before_mute = None;
+        state.emit(Action::SetVolume(state.volume));
+    }
+
+    // Another rectangle drawn over a slider to make it look filled
+    let rect_pos = util::add_pos(
+        util::add_pos(
+            ui.window_pos(),
+            util::add_pos(volume_slider_pos, [2.0, 2.0]),
+        ),
+        style.window_padding,
+    );
+    ui.get_window_draw_list()
+        .add_rect(
+            rect_pos,
+            util::add_pos(
+                rect_pos,
+                [
+                    (width / 8.0 - 22.0) * (state.volume - MIN_VOLUME) / (MAX_VOLUME - MIN_VOLUME)
+                        + 17.0,
+                    17.0,
+                ],
+            ),
+            theme.primary2,
+        )
+        .filled(true)
+        .thickness(0.0)
+        .rounding(f32::MAX)
+        .build();
+
+    // Mute toggle left of the volume slider
+    let icon_size = 24.0;
+    ui.set_cursor_pos([
+        volume_slider_pos[0] - icon_size - 6.0,
+        volume_slider_pos[1] - 2.0,
+    ]);
+    if ui.invisible_button("mute", [icon_size, icon_size]) {
+        state.emit(Action::ToggleMute);
+    }
+    widgets::draw_speaker_icon(
+        ui,
+        ui.item_rect_min(),
+        icon_size,
+        if ui.is_item_hovered() {
+            theme.text1
+        } else {
+            theme.text2
+        },
+        state.volume == MIN_VOLUME,
+    );
+
+    // Lyrics toggle
+    ui.set_cursor_pos([
+        volume_slider_pos[0] + width / 8.0 + 20.0,
+        volume_slider_pos[1] - 1.0,
+    ]);
+    let style_token = ui.push_style_color(StyleColor::Button, TRANSPARENT);
+    let color_token = ui.push_style_color(
+        StyleColor::Text,
+        if state.show_lyrics {
+            theme.primary2
+        } else {
+            theme.text2
+        },
+    );
+    if ui.button("Lyrics") {
+        state.show_lyrics = !state.show_lyrics;
+    }
+    color_token.pop();
+
+    // Now Playing toggle
+    ui.same_line();
+    let color_token = ui.push_style_color(
+        StyleColor::Text,
+        if state.show_now_playing {
+            theme.primary2
+        } else {
+            theme.text2
+        },
+    );
+    if ui.button("Now Playing") {
+        state.show_now_playing = !state.show_now_playing;
+    }
+    color_token.pop();
+
+    // Renderer choice
+    ui.same_line();
+    let color_token = ui.push_style_color(
+        StyleColor::Text,
+        if state.renderer.is_some() {
+            theme.primary2
+        } else {
+            theme.text2
+        },
+    );
+    if ui.button("Cast") {
+        ui.open_popup("renderers_popup");
+        if state.renderers_rx.is_none() {
+            state.renderers_rx = Some(dlna::start_discovery());
+        }
+    }
+    color_token.pop();
+    if ui.is_item_hovered() {
+        match &state.renderer {
+            Some(renderer) => ui.tooltip_text(format!("Playing on {}", renderer.device.name)),
+            None => ui.tooltip_text("Play on a TV or speaker in the network"),
+        }
+    }
+    style_token.pop();
+
+    ui.popup("renderers_popup", || {
+        if ui
+            .menu_item_config("This computer")
+            .selected(state.renderer.is_none())
+            .build()
+            && state.renderer.is_some()
+        {
+            actions::set_renderer(state, None);
+        }
+        for i in 0..state.renderers.len() {
+            let device = &state.renderers[i];
+            let is_selected = state.renderer.as_ref().is_some_and(|x| x.device == *device);
+            if ui
+                .menu_item_config(format!("{}###renderer{i}", device.name))
+                .selected(is_selected)
+                .build()
+                && !is_selected
+            {
+                let device = state.renderers[i].clone();
+                actions::set_renderer(state, Some(device));
+            }
+        }
+        ui.separator();
+        if state.renderers_rx.is_some() {
+            ui.text_disabled("Searching...");
+        } else if ui.menu_item("Search again") {
+            state.renderers.clear();
+            state.renderers_rx = Some(dlna::start_discovery());
+        }
+    });
+}
+
+fn draw_now_playing(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let padding = 12.0;
+    let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
+
+    let playing_indices = state.playing_playlist_index.zip(state.playing_song_index);
+    let playing_path = playing_indices.map(|(playlist_index, song_index)| {
+        state.playlists[playlist_index].songs[song_index]
+            .path
+            .clone()
+    });
+    // Load the cover again when the playing song changed
+    if state.cover_song_path != playing_path {
+        state.cover_rx = playing_path
+            .as_ref()
+            .map(|x| cover::start_loading(Path::new(&state.base_path).join(x)));
+        state.cover_song_path = playing_path;
+        state.loaded_cover = Some(None);
+        state.now_playing_scroll_info = ScrollInfo::default();
+        ui.set_scroll_y(0.0);
+    }
+    if let Some(cover) = state.cover_rx.as_ref().and_then(|x| x.try_recv().ok()) {
+        state.loaded_cover = Some(cover);
+        state.cover_rx = None;
+    }
+
+    ui.set_cursor_pos([padding, padding]);
+    let (playlist_index, song_index) = match playing_indices {
+        Some(indices) => indices,
+        None => {
+            ui.text_colored(theme.text2, "Nothing is playing");
+            return;
+        }
+    };
+    let song = state.playlists[playlist_index].songs[song_index].clone();
+    let tags = state.tags.get(&song.path).cloned().unwrap_or_default();
+
+    // Cover, scaled to fit into a square with the width of the panel
+    let cover_size = width - 2.0 * padding;
+    match state.cover_texture {
+        Some((texture_id, [image_width, image_height])) => {
+            let scale = cover_size / image_width.max(image_height);
+            let size = [image_width * scale, image_height * scale];
+            ui.set_cursor_pos([
+                padding + (cover_size - size[0]) / 2.0,
+                padding + (cover_size - size[1]) / 2.0,
+            ]);
+            Image::new(texture_id, size).build(ui);
+        }
+        None => {
+            let min = ui.cursor_screen_pos();
+            ui.get_window_draw_list()
+                .add_rect(
+                    min,
+                    util::add_pos(min, [cover_size, cover_size]),
+                    theme.dark4,
+                )
+                .filled(true)
+                .build();
+            let text = if state.cover_rx.is_some() {
+                "Loading cover..."
+            } else {
+                "No cover"
+            };
+            let text_size = ui.calc_text_size(text);
+            ui.set_cursor_pos([
+                padding + (cover_size - text_size[0]) / 2.0,
+                padding + (cover_size - text_size[1]) / 2.0,
+            ]);
+            ui.text_colored(theme.text2, text);
+        }
+    }
+    ui.set_cursor_pos([padding, 2.0 * padding + cover_size]);
+
+    let wrap_token = ui.push_text_wrap_pos_with_pos(width - padding);
+    ui.text_wrapped(tags.title.as_ref().unwrap_or(&song.name));
+    ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+    ui.text_colored(theme.text2, tags.artist.as_ref().unwrap_or(&song.artist));
+
+    // Rating, clicking the current rating removes all stars
+    ui.set_cursor_pos([padding, ui.cursor_pos()[1] + padding / 2.0]);
+    let rating = state.get_rating(&song.path).unwrap_or(0);
+    let button_token = ui.push_style_color(StyleColor::Button, TRANSPARENT);
+    for stars in 1..=5 {
+        let color_token = ui.push_style_color(
+            StyleColor::Text,
+            if stars <= rating {
+                theme.primary2
+            } else {
+                theme.text2
+            },
+        );
+        let label = if stars <= rating { "★" } else { "☆" };
+        if ui.small_button(format!("{label}##rating{stars}")) {
+            let new_rating = if stars == rating { 0 } else { stars };
+            ratings::set_rating(state, &song.path, new_rating);
+        }
+        color_token.pop();
+        if stars < 5 {
+            ui.same_line_with_spacing(0.0, 0.0);
+        }
+    }
+    button_token.pop();
+
+    // Metadata
+    ui.dummy([0.0, padding / 2.0]);
+    let label_width = 80.0;
+    let fields = [
+        ("Album", tags.album.clone()),
+        ("Track", tags.track_number.clone()),
+        ("Date", tags.date.clone()),
+        (
+            "Genre",
+            (!tags.genres.is_empty()).then(|| tags.genres.join(", ")),
+        ),
+        ("Duration", song.duration.map(util::ms_to_string)),
+        (
+            "Plays",
+            state.play_counts.get(&song.path).map(|x| x.to_string()),
+        ),
+        (
+            "Playlist",
+            Some(state.playlists[playlist_index].name.clone()),
+        ),
+        ("Path", Some(song.path.clone())),
+        ("Note", state.notes.get(&song.path).cloned()),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+            ui.text_colored(theme.text2, name);
+            ui.same_line_with_pos(padding + label_width);
+            ui.text_wrapped(value);
+        }
+    }
+
+    // Upcoming songs of the playing playlist
+    ui.dummy([0.0, padding]);
+    ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+    ui.text_colored(theme.text2, "Up next");
+    let upcoming_songs: Vec<(usize, String)> = state.playlists[playlist_index]
+        .songs
+        .iter()
+        .enumerate()
+        .skip(song_index + 1)
+        .filter(|(_, x)| x.exists)
+        .take(UPCOMING_SONG_COUNT)
+        .map(|(i, x)| (i, format!("{} - {}", x.artist, x.name)))
+        .collect();
+    if upcoming_songs.is_empty() {
+        ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+        ui.text("End of the playlist");
+    }
+    wrap_token.pop();
+    for (i, text) in upcoming_songs {
+        ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+        let size = [width - 2.0 * padding, 0.0];
+        if ui
+            .selectable_config(format!("{text}##upcoming{i}"))
+            .size(size)
+            .build()
+        {
+            state.emit(Action::Play {
+                playlist_index,
+                song_index: i,
+            });
+        }
+    }
+    ui.dummy([0.0, padding]);
+}
+
+fn draw_lyrics(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let padding = 12.0;
+    let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
 
-    // Volume slider
-    ui.next_column();
-    ui.set_current_column_width(width / 4.0);
-    ui.set_cursor_pos([
-        ui.cursor_pos()[0] + width / 16.0,
-        ui.cursor_pos()[1] + height_middle - ui.current_font().font_size / 2.0,
-    ]);
-    let volume_slider_pos = ui.cursor_pos();
-    if draw_slider(
-        ui,
-        "volume_slider",
-        0.3,
-        1.2,
-        &mut state.volume,
-        width / 8.0,
-        20.0,
-    ) {
-        let value = if state.volume == 0.3 {
-            0.0
-        } else {
-            state.volume.powi(4)
-        };
-        state
-            .action_tx
-            .send(player::PlayerAction::SetVolume(value))
-            .unwrap();
+    // Reload lyrics when the playing song changed
+    let playing_song = match (state.playing_playlist_index, state.playing_song_index) {
+        (Some(playlist_index), Some(song_index)) => {
+            Some(&state.playlists[playlist_index].songs[song_index])
+        }
+        _ => None,
+    };
+    let playing_path = playing_song.map(|x| x.path.clone());
+    if state.lyrics_song_path != playing_path {
+        state.lyrics = playing_path
+            .as_ref()
+            .and_then(|x| lyrics::load(&Path::new(&state.base_path).join(x)));
+        state.lyrics_song_path = playing_path;
+        state.lyrics_current_line = None;
+        ui.set_scroll_y(0.0);
     }
 
-    // Another rectangle drawn over a slider to make it look filled
-    let rect_pos = util::add_pos(
-        util::add_pos(
-            ui.window_pos(),
-            util::add_pos(volume_slider_pos, [2.0, 2.0]),
-        ),
-        style.window_padding,
-    );
-    ui.get_window_draw_list()
-        .add_rect(
-            rect_pos,
-            util::add_pos(
-                rect_pos,
-                [
-                    (width / 8.0 - 22.0) * (state.volume - 0.3) * (1.0 / 0.9) + 17.0,
-                    17.0,
-                ],
-            ),
-            PRIMARY2,
-        )
-        .filled(true)
-        .thickness(0.0)
-        .rounding(f32::MAX)
-        .build();
+    let current_line = state
+        .lyrics
+        .as_ref()
+        .and_then(|x| lyrics::get_current_line(x, *state.position.lock().unwrap()));
+    let is_current_line_changed = current_line != state.lyrics_current_line;
+    state.lyrics_current_line = current_line;
+
+    ui.set_cursor_pos([padding, padding]);
+    let wrap_token = ui.push_text_wrap_pos_with_pos(width - padding);
+    match playing_song {
+        Some(song) => ui.text_wrapped(format!("{} - {}", song.artist, song.name)),
+        None => ui.text_colored(theme.text2, "Nothing is playing"),
+    }
+    ui.dummy([0.0, padding]);
+
+    let mut seek_position = None;
+    match &state.lyrics {
+        Some(lines) => {
+            let is_synced = lines.iter().any(|x| x.time.is_some());
+            for (i, line) in lines.iter().enumerate() {
+                ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+
+                // Scroll smoothly so that the current line stays in the middle
+                if is_current_line_changed && current_line == Some(i) {
+                    state.lyrics_scroll_info.scroll_target_y =
+                        ui.cursor_pos()[1] - ui.window_size()[1] / 2.0;
+                    state.lyrics_scroll_info.scroll_start_time = Instant::now();
+                    state.lyrics_scroll_info.is_scrolling = true;
+                }
+
+                if line.text.is_empty() {
+                    ui.dummy([0.0, ui.text_line_height()]);
+                    continue;
+                }
+                let color = if !is_synced || current_line == Some(i) {
+                    theme.text1
+                } else {
+                    theme.text2
+                };
+                let color_token = ui.push_style_color(StyleColor::Text, color);
+                ui.text_wrapped(&line.text);
+                color_token.pop();
+
+                // Seek to the line when clicking it
+                if line.time.is_some() && ui.is_item_hovered() {
+                    ui.set_mouse_cursor(Some(MouseCursor::Hand));
+                    if ui.is_item_clicked() {
+                        seek_position = line.time;
+                    }
+                }
+            }
+        }
+        None if state.lyrics_song_path.is_some() => {
+            ui.set_cursor_pos([padding, ui.cursor_pos()[1]]);
+            ui.text_colored(theme.text2, "No lyrics found");
+        }
+        None => (),
+    }
+    wrap_token.end();
+    ui.dummy([0.0, padding]);
+
+    if let Some(position) = seek_position {
+        state.emit(Action::Seek(position));
+    }
 }
 
 fn draw_slider<Data: DataTypeKind>(
@@ -1596,7 +5164,371 @@ fn draw_slider<Data: DataTypeKind>(
     result
 }
 
+fn draw_duplicates(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let mut opened = true;
+    ui.window(format!("Duplicates in {}", state.duplicates_playlist_name))
+        .size([700.0, 500.0], Condition::FirstUseEver)
+        .position([200.0, 100.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            if state.duplicate_groups.is_empty() {
+                ui.text_colored(theme.text2, "No duplicates found");
+            }
+            let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
+            let mut clicked_song = None;
+            for (group_index, group) in state.duplicate_groups.iter().enumerate() {
+                if group_index > 0 {
+                    ui.separator();
+                }
+                let group_token = ui.push_id_usize(group_index);
+                for (i, song) in group.iter().enumerate() {
+                    let token = ui.push_id_usize(i);
+                    let row = widgets::draw_row(ui, false, None);
+                    if row.clicked {
+                        clicked_song = Some((song.path.clone(), row.double_clicked));
+                    }
+                    ui.same_line();
+                    let color_token = if song.exists {
+                        None
+                    } else {
+                        Some(ui.push_style_color(StyleColor::Text, theme.not_existing))
+                    };
+                    let duration = util::ms_to_string(song.duration.unwrap_or(0));
+                    widgets::draw_truncated_text(
+                        ui,
+                        &format!("{} - {}", song.artist, song.name),
+                        width / 2.0,
+                    );
+                    ui.same_line_with_pos(width / 2.0 + 10.0);
+                    widgets::draw_truncated_text(
+                        ui,
+                        &song.path,
+                        width / 2.0 - 20.0 - ui.calc_text_size(&duration)[0],
+                    );
+                    ui.same_line_with_pos(width - ui.calc_text_size(&duration)[0]);
+                    ui.text(&duration);
+                    if let Some(t) = color_token {
+                        t.pop();
+                    }
+                    token.pop();
+                }
+                group_token.pop();
+            }
+
+            // Select the clicked song in the searched playlist, play it on double click
+            if let Some((path, double_clicked)) = clicked_song {
+                let playlist_index = state
+                    .playlists
+                    .iter()
+                    .position(|x| x.name == state.duplicates_playlist_name);
+                if let Some(playlist_index) = playlist_index {
+                    let song_index = state.playlists[playlist_index]
+                        .songs
+                        .iter()
+                        .position(|x| x.path == path);
+                    if let Some(song_index) = song_index {
+                        state.selected_playlist_index = playlist_index;
+                        state.selected_song_indices = vec![song_index];
+                        if double_clicked {
+                            state.emit(Action::Play {
+                                playlist_index,
+                                song_index,
+                            });
+                        }
+                    }
+                }
+            }
+        });
+    state.show_duplicates = opened;
+}
+
+/// Shows the songs that are only in one of the two compared playlists.
+fn draw_playlist_comparison(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let (name_a, name_b) = state.compared_playlist_names.clone().unwrap();
+    let find_index = |name: &str| state.playlists.iter().position(|x| x.name == name);
+    let (index_a, index_b) = match (find_index(&name_a), find_index(&name_b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            state.compared_playlist_names = None;
+            return;
+        }
+    };
+    let get_missing_songs = |from: usize, to: usize| -> Vec<Song> {
+        let paths: HashSet<&String> = state.playlists[to].songs.iter().map(|x| &x.path).collect();
+        let mut added_paths = HashSet::new();
+        state.playlists[from]
+            .songs
+            .iter()
+            .filter(|x| !paths.contains(&x.path) && added_paths.insert(&x.path))
+            .cloned()
+            .collect()
+    };
+    let only_in_a = get_missing_songs(index_a, index_b);
+    let only_in_b = get_missing_songs(index_b, index_a);
+
+    let mut opened = true;
+    let mut added_songs = None;
+    ui.window(format!("Compare {name_a} with {name_b}"))
+        .size([900.0, 500.0], Condition::FirstUseEver)
+        .position([200.0, 100.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            let width = ui.window_content_region_max()[0] - ui.window_content_region_min()[0];
+            let column_width = width / 2.0 - 5.0;
+            let columns = [
+                (&name_a, &only_in_a, &name_b, index_b),
+                (&name_b, &only_in_b, &name_a, index_a),
+            ];
+            for (column, (name, songs, other_name, other_index)) in columns.iter().enumerate() {
+                if column > 0 {
+                    ui.same_line_with_pos(width / 2.0 + 5.0);
+                }
+                ui.child_window(format!("comparison_column_{column}"))
+                    .size([column_width, 0.0])
+                    .build(|| {
+                        ui.text(format!("Only in {name} ({})", songs.len()));
+                        if !util::is_default_playlist(other_name) {
+                            ui.same_line();
+                            if ui.small_button(format!("Add all to {other_name}")) {
+                                added_songs = Some((*other_index, (*songs).clone()));
+                            }
+                        }
+                        ui.separator();
+                        for song in songs.iter() {
+                            let color = if song.exists {
+                                theme.text1
+                            } else {
+                                theme.not_existing
+                            };
+                            let color_token = ui.push_style_color(StyleColor::Text, color);
+                            widgets::draw_truncated_text(
+                                ui,
+                                &format!("{} - {}", song.artist, song.name),
+                                column_width - 10.0,
+                            );
+                            color_token.pop();
+                        }
+                    });
+            }
+        });
+
+    if let Some((playlist_index, songs)) = added_songs {
+        let index = state.playlists[playlist_index].songs.len();
+        state.emit(Action::InsertSongs {
+            playlist_index,
+            songs,
+            index,
+        });
+    }
+    if !opened {
+        state.compared_playlist_names = None;
+    }
+}
+
+fn draw_delete_confirmation(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    if state.pending_deletions.is_empty() {
+        return;
+    }
+    ui.open_popup("Delete files");
+    ui.modal_popup_config("Delete files")
+        .always_auto_resize(true)
+        .build(|| {
+            ui.text(format!(
+                "Move {} files to the trash and remove them from all playlists?",
+                state.pending_deletions.len()
+            ));
+            ui.child_window("delete_preview")
+                .size([
+                    600.0,
+                    (state.pending_deletions.len() as f32 * ui.text_line_height_with_spacing())
+                        .min(300.0),
+                ])
+                .build(|| {
+                    for path in state.pending_deletions.iter() {
+                        ui.text_colored(theme.text2, path);
+                    }
+                });
+
+            if ui.button("Delete") {
+                let paths = std::mem::take(&mut state.pending_deletions);
+                state.emit(Action::DeleteSongFiles(paths));
+                ui.close_current_popup();
+            }
+            ui.same_line();
+            if ui.button("Cancel") || ui.is_key_pressed(Key::Escape) {
+                state.pending_deletions.clear();
+                ui.close_current_popup();
+            }
+        });
+}
+
+fn draw_playlist_delete_confirmation(ui: &Ui, state: &mut State) {
+    let name = match &state.deleted_playlist_name {
+        Some(name) => name.clone(),
+        None => return,
+    };
+    let playlist_index = match state.playlists.iter().position(|x| x.name == name) {
+        Some(i) => i,
+        None => {
+            state.deleted_playlist_name = None;
+            return;
+        }
+    };
+    ui.open_popup("Delete playlist");
+    ui.modal_popup_config("Delete playlist")
+        .always_auto_resize(true)
+        .build(|| {
+            ui.text(format!("Delete the playlist \"{name}\"?"));
+            ui.checkbox(
+                "Move the playlist file to the trash",
+                &mut state.delete_playlist_file,
+            );
+
+            if ui.button("Delete") {
+                state.deleted_playlist_name = None;
+                state.emit(Action::DeletePlaylist {
+                    playlist_index,
+                    delete_file: state.delete_playlist_file,
+                });
+                ui.close_current_popup();
+            }
+            ui.same_line();
+            if ui.button("Cancel") || ui.is_key_pressed(Key::Escape) {
+                state.deleted_playlist_name = None;
+                ui.close_current_popup();
+            }
+        });
+}
+
+/// Lists the playlists with unsaved changes before the window is closed or another music
+/// directory is opened.
+fn draw_exit_confirmation(ui: &Ui, state: &mut State) {
+    if !state.show_exit_confirmation && state.pending_music_directory.is_none() {
+        return;
+    }
+    ui.open_popup("Unsaved changes");
+    ui.modal_popup_config("Unsaved changes")
+        .always_auto_resize(true)
+        .build(|| {
+            ui.text("The following playlists have unsaved changes:");
+            for playlist in state.playlists.iter().filter(|x| x.has_changes()) {
+                ui.bullet_text(&playlist.name);
+            }
+
+            let mut is_confirmed = false;
+            if ui.button("Save all") {
+                state.emit(Action::SaveAllPlaylists);
+                is_confirmed = true;
+            }
+            ui.same_line();
+            if ui.button("Discard") {
+                is_confirmed = true;
+            }
+            if is_confirmed {
+                match state.pending_music_directory.take() {
+                    Some(path) => {
+                        state.emit(Action::SwitchMusicDirectory(path));
+                        ui.close_current_popup();
+                    }
+                    None => state.should_exit = true,
+                }
+            }
+            ui.same_line();
+            if ui.button("Cancel") || ui.is_key_pressed(Key::Escape) {
+                state.show_exit_confirmation = false;
+                state.pending_music_directory = None;
+                ui.close_current_popup();
+            }
+        });
+}
+
+/// Number of missing songs that are listed by name
+const MAX_LISTED_MISSING_TRACKS: usize = 15;
+
+fn draw_missing_tracks_confirmation(ui: &Ui, state: &mut State) {
+    if state.missing_streaming_tracks.is_none() {
+        return;
+    }
+    ui.open_popup("Missing songs");
+    ui.modal_popup_config("Missing songs")
+        .always_auto_resize(true)
+        .build(|| {
+            let (name, tracks) = state.missing_streaming_tracks.as_ref().unwrap();
+            ui.text(format!(
+                "{} songs of \"{name}\" are not in the library:",
+                tracks.len()
+            ));
+            for track in tracks.iter().take(MAX_LISTED_MISSING_TRACKS) {
+                if track.artist.is_empty() {
+                    ui.bullet_text(&track.title);
+                } else {
+                    ui.bullet_text(format!("{} - {}", track.artist, track.title));
+                }
+            }
+            if tracks.len() > MAX_LISTED_MISSING_TRACKS {
+                ui.text(format!(
+                    "and {} more",
+                    tracks.len() - MAX_LISTED_MISSING_TRACKS
+                ));
+            }
+
+            if ui.button("Download") {
+                let (name, tracks) = state.missing_streaming_tracks.take().unwrap();
+                if let Some(playlist_index) = state.playlists.iter().position(|x| x.name == name) {
+                    download::download_tracks(state, playlist_index, &tracks);
+                }
+                ui.close_current_popup();
+                return;
+            }
+            ui.same_line();
+            if ui.button("Skip") || ui.is_key_pressed(Key::Escape) {
+                state.missing_streaming_tracks = None;
+                ui.close_current_popup();
+            }
+        });
+}
+
+fn draw_external_change_confirmation(ui: &Ui, state: &mut State) {
+    let name = match &state.externally_changed_playlist_name {
+        Some(name) => name.clone(),
+        None => return,
+    };
+    let playlist_index = match state.playlists.iter().position(|x| x.name == name) {
+        Some(i) => i,
+        None => {
+            state.externally_changed_playlist_name = None;
+            return;
+        }
+    };
+    ui.open_popup("Playlist changed");
+    ui.modal_popup_config("Playlist changed")
+        .always_auto_resize(true)
+        .build(|| {
+            ui.text(format!(
+                "The playlist \"{name}\" was changed by another program,\nbut it also has unsaved changes."
+            ));
+
+            if ui.button("Reload") {
+                state.externally_changed_playlist_name = None;
+                actions::reload_playlist(state, playlist_index);
+                ui.close_current_popup();
+            }
+            ui.same_line();
+            if ui.button("Keep my changes") || ui.is_key_pressed(Key::Escape) {
+                state.externally_changed_playlist_name = None;
+                actions::ignore_playlist_file_change(state, playlist_index);
+                ui.close_current_popup();
+            }
+        });
+}
+
 fn draw_statuses(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
     let x_offset = 20.0;
     let padding = 10.0;
     let spacing = 20.0;
@@ -1610,69 +5542,241 @@ fn draw_statuses(ui: &Ui, state: &mut State) {
             .add_rect(
                 [x_offset, y_offset],
                 util::add_pos([x_offset, y_offset], rect_size),
-                status.r#type.get_color(),
+                status.r#type.get_color(&theme),
             )
             .filled(true)
             .build();
         ui.get_foreground_draw_list().add_text(
             [x_offset + padding, y_offset + padding],
-            TEXT1,
+            theme.text1,
             &status.info,
         );
+        // Clicking a status opens the notifications to read it after it disappeared
+        let rect_max = util::add_pos([x_offset, y_offset], rect_size);
+        if ui.is_mouse_clicked(MouseButton::Left)
+            && util::is_point_in_rect(ui.io().mouse_pos, [x_offset, y_offset], rect_max)
+        {
+            state.show_notifications = true;
+        }
         y_offset += rect_size[1] + spacing;
     }
 }
 
-fn apply_smooth_scrolling(ui: &Ui, scroll_delta: f32, scroll_info: &mut ScrollInfo) {
-    let current_time = Instant::now();
-    let elapsed_time = current_time.duration_since(scroll_info.scroll_start_time);
+/// Draws the text that is being composed with an input method over the focused text field since
+/// imgui only receives the text once it is entered.
+fn draw_ime_preedit(ui: &Ui, state: &State) {
+    if state.ime_preedit.is_empty() {
+        return;
+    }
+    let Some((pos, line_height)) = ime::get_input_position() else {
+        return;
+    };
+    let theme = state.theme;
+    let size = [ui.calc_text_size(&state.ime_preedit)[0], line_height];
+    let max = util::add_pos(pos, size);
+    let draw_list = ui.get_foreground_draw_list();
+    draw_list
+        .add_rect(pos, max, theme.dark3)
+        .filled(true)
+        .build();
+    draw_list.add_text(pos, theme.text1, &state.ime_preedit);
+    // Underlined like in other programs to show that the text is not entered yet
+    draw_list
+        .add_line([pos[0], max[1]], max, theme.text1)
+        .build();
+}
 
-    if scroll_info.is_scrolling {
-        if elapsed_time < scroll_info.scroll_duration {
-            let progress = elapsed_time.as_secs_f32() / scroll_info.scroll_duration.as_secs_f32();
-            let mut scroll_y = util::lerp(ui.scroll_y(), scroll_info.scroll_target_y, progress);
+/// Draws an overlay in the middle of the window that lists the shortcuts of the keymap grouped by
+/// area. Clicking anywhere or pressing Escape closes it.
+fn draw_shortcut_help(ui: &Ui, state: &mut State, width: f32, height: f32) {
+    let theme = state.theme;
+    let name_width = Command::ALL
+        .iter()
+        .map(|x| ui.calc_text_size(x.name())[0])
+        .fold(0.0, f32::max)
+        + 30.0;
+    ui.window("shortcut_help")
+        .position([width / 2.0, height / 2.0], Condition::Always)
+        .position_pivot([0.5, 0.5])
+        .title_bar(false)
+        .resizable(false)
+        .movable(false)
+        .collapsible(false)
+        .always_auto_resize(true)
+        .focus_on_appearing(true)
+        .build(|| {
+            ui.text("Keyboard shortcuts");
+            for group in keymap::CommandGroup::ALL {
+                ui.spacing();
+                ui.text_colored(theme.primary2, group.name());
+                for command in Command::ALL.into_iter().filter(|x| x.group() == group) {
+                    let bindings = state.settings.keymap.get(command);
+                    // Commands without a binding aren't active
+                    if bindings.is_empty() {
+                        continue;
+                    }
+                    ui.text(command.name());
+                    ui.same_line_with_pos(name_width);
+                    let names: Vec<String> = bindings.iter().map(|x| x.name()).collect();
+                    ui.text_colored(theme.text2, names.join(" or "));
+                }
+            }
+            if state.settings.vim_mode {
+                ui.spacing();
+                ui.text_colored(theme.primary2, "Vim mode");
+                for (keys, description) in keymap::VIM_KEYS {
+                    ui.text(description);
+                    ui.same_line_with_pos(name_width);
+                    ui.text_colored(theme.text2, keys);
+                }
+            }
+        });
+    if ui.is_key_pressed_no_repeat(Key::Escape)
+        || ui.is_mouse_clicked(MouseButton::Left)
+        || ui.is_mouse_clicked(MouseButton::Right)
+    {
+        state.show_shortcut_help = false;
+    }
+}
 
-            // Clamp values here and to ensure smooth movements at the top/bottom. Clamping the
-            // target value when it is set results in an unsmooth transition.
-            if scroll_y < 0.0 || scroll_y > ui.scroll_max_y() {
-                scroll_y = scroll_y.clamp(0.0, ui.scroll_max_y());
-                scroll_info.scroll_target_y =
-                    scroll_info.scroll_target_y.clamp(0.0, ui.scroll_max_y());
+/// Draws the window listing the commands with their key bindings. Clicking a binding records the
+/// next pressed key as its replacement, bindings used by several commands are highlighted.
+fn draw_keymap(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let mut opened = true;
+    ui.window("Keyboard shortcuts")
+        .size([650.0, 600.0], Condition::FirstUseEver)
+        .position([300.0, 100.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            if let Some((command, index)) = state.rebound_command {
+                if ui.is_key_pressed_no_repeat(Key::Escape) {
+                    state.rebound_command = None;
+                } else if let Some(binding) = keymap::get_pressed_binding(ui) {
+                    let mut bindings = state.settings.keymap.get(command).to_vec();
+                    match index {
+                        Some(i) if i < bindings.len() => bindings[i] = binding,
+                        _ if !bindings.contains(&binding) => bindings.push(binding),
+                        _ => (),
+                    }
+                    state.settings.keymap.set(command, bindings);
+                    state.rebound_command = None;
+                    settings::save(state);
+                }
             }
 
-            ui.set_scroll_y(scroll_y);
-        } else {
-            scroll_info.is_scrolling = false;
-        }
-    }
+            ui.text_colored(
+                theme.text2,
+                "Click a shortcut and press the new keys, Escape cancels",
+            );
+            if ui.small_button("Reset to defaults") {
+                state.settings.keymap = keymap::Keymap::default();
+                state.rebound_command = None;
+                settings::save(state);
+            }
+            ui.separator();
+
+            let mut removed_binding = None;
+            for (n, command) in Command::ALL.into_iter().enumerate() {
+                let token = ui.push_id_usize(n);
+                ui.text(command.name());
+                ui.same_line_with_pos(300.0);
+                for (i, binding) in state.settings.keymap.get(command).iter().enumerate() {
+                    let token = ui.push_id_usize(i);
+                    let label = if state.rebound_command == Some((command, Some(i))) {
+                        "Press keys...".to_string()
+                    } else {
+                        binding.name()
+                    };
+                    let conflicts = state.settings.keymap.get_conflicts(command, *binding);
+                    let color_token = (!conflicts.is_empty())
+                        .then(|| ui.push_style_color(StyleColor::Text, theme.not_existing));
+                    if ui.small_button(label) {
+                        state.rebound_command = Some((command, Some(i)));
+                    }
+                    if let Some(color_token) = color_token {
+                        color_token.pop();
+                    }
+                    if !conflicts.is_empty() && ui.is_item_hovered() {
+                        ui.tooltip(|| {
+                            ui.text("Also used for:");
+                            for conflict in &conflicts {
+                                ui.text(conflict.name());
+                            }
+                        });
+                    }
+                    ui.same_line();
+                    if ui.small_button("x") {
+                        removed_binding = Some((command, i));
+                    }
+                    ui.same_line();
+                    token.pop();
+                }
+                let add_label = if state.rebound_command == Some((command, None)) {
+                    "Press keys..."
+                } else {
+                    "+"
+                };
+                if ui.small_button(add_label) {
+                    state.rebound_command = Some((command, None));
+                }
+                token.pop();
+            }
 
-    // Update scroll target when scrolling with the mouse wheel
-    if ui.is_window_hovered() {
-        if scroll_delta != 0.0 {
-            scroll_info.scroll_target_y = if scroll_info.is_scrolling {
-                scroll_info.scroll_target_y - scroll_delta * 66.0
-            } else {
-                ui.scroll_y() - scroll_delta * 66.0
-            };
-            scroll_info.scroll_start_time = Instant::now();
-            scroll_info.is_scrolling = true;
-        }
+            if let Some((command, i)) = removed_binding {
+                let mut bindings = state.settings.keymap.get(command).to_vec();
+                bindings.remove(i);
+                state.settings.keymap.set(command, bindings);
+                settings::save(state);
+            }
+        });
+    if !opened {
+        state.show_keymap = false;
     }
 }
 
-fn draw_truncated_text(ui: &Ui, text: &str, width: f32) {
-    if ui.calc_text_size(text)[0] <= width {
-        ui.text(text);
-        return;
-    }
-
-    let ellipsis = "...";
-    let ellipsis_width = ui.calc_text_size(ellipsis)[0];
-    for i in (1..text.len()).rev() {
-        let part = text.chars().take(i).collect::<String>();
-        if ellipsis_width + ui.calc_text_size(&part)[0] <= width {
-            ui.text(format!("{part}{ellipsis}"));
-            return;
-        }
-    }
+/// Draws the window listing the recent statuses, newest first, with their full text.
+fn draw_notifications(ui: &Ui, state: &mut State) {
+    let theme = state.theme;
+    let mut opened = true;
+    ui.window("Notifications")
+        .size([500.0, 400.0], Condition::FirstUseEver)
+        .position([300.0, 150.0], Condition::FirstUseEver)
+        .opened(&mut opened)
+        .collapsible(false)
+        .build(|| {
+            if state.notifications.is_empty() {
+                ui.text_colored(theme.text2, "No notifications");
+                return;
+            }
+            if ui.small_button("Clear") {
+                state.notifications.clear();
+                return;
+            }
+            for (i, status) in state.notifications.iter().enumerate().rev() {
+                ui.separator();
+                let token = ui.push_id_usize(i);
+                if ui.small_button("Copy") {
+                    ui.set_clipboard_text(&status.info);
+                }
+                token.pop();
+                ui.same_line();
+                // The status colors are backgrounds and too dark for text
+                let color = match status.r#type {
+                    StatusType::Error => theme.not_existing,
+                    _ => theme.text2,
+                };
+                ui.text_colored(
+                    color,
+                    format!(
+                        "{}, {}",
+                        status.r#type.name(),
+                        util::time_ago_to_string(status.time)
+                    ),
+                );
+                ui.text_wrapped(&status.info);
+            }
+        });
+    state.show_notifications = opened;
 }
@@ -0,0 +1,146 @@
+use std::{env, path::PathBuf};
+
+const USAGE: &str = "Usage: implayer [MUSIC_DIRECTORY] [--show] [--play] [--pause] [--toggle] [--next] [--prev] [--playlist NAME] [--shuffle] [--volume 0-100] [SONG_FILE...]";
+
+/// Commands that are passed as arguments. A running instance receives them from later instances
+/// one per line.
+pub enum CliCommand {
+    /// Show and focus the window
+    Show,
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Prev,
+    /// Add the song file like a dropped file and play it
+    Open(PathBuf),
+    /// Play the playlist with the name or the selected playlist from the start, shuffling it first
+    /// if the flag is set
+    PlayPlaylist {
+        name: Option<String>,
+        shuffle: bool,
+    },
+    /// Percentage of the volume slider
+    Volume(f32),
+}
+
+impl CliCommand {
+    pub fn to_line(&self) -> String {
+        match self {
+            CliCommand::Show => "show".to_string(),
+            CliCommand::Play => "play".to_string(),
+            CliCommand::Pause => "pause".to_string(),
+            CliCommand::Toggle => "toggle".to_string(),
+            CliCommand::Next => "next".to_string(),
+            CliCommand::Prev => "prev".to_string(),
+            CliCommand::Open(path) => format!("open {}", path.to_string_lossy()),
+            CliCommand::PlayPlaylist { name, shuffle } => format!(
+                "{} {}",
+                if *shuffle {
+                    "shuffle-playlist"
+                } else {
+                    "play-playlist"
+                },
+                name.as_deref().unwrap_or_default()
+            ),
+            CliCommand::Volume(percent) => format!("volume {percent}"),
+        }
+    }
+
+    pub fn parse_line(line: &str) -> Option<CliCommand> {
+        let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match name {
+            "show" => Some(CliCommand::Show),
+            "play" => Some(CliCommand::Play),
+            "pause" => Some(CliCommand::Pause),
+            "toggle" => Some(CliCommand::Toggle),
+            "next" => Some(CliCommand::Next),
+            "prev" => Some(CliCommand::Prev),
+            "open" if !argument.is_empty() => Some(CliCommand::Open(PathBuf::from(argument))),
+            "play-playlist" | "shuffle-playlist" => Some(CliCommand::PlayPlaylist {
+                name: (!argument.is_empty()).then(|| argument.to_string()),
+                shuffle: name == "shuffle-playlist",
+            }),
+            "volume" => argument.parse().ok().map(CliCommand::Volume),
+            _ => None,
+        }
+    }
+}
+
+pub struct Arguments {
+    /// Music directory, otherwise the directory of the executable is used
+    pub directory: Option<String>,
+    pub commands: Vec<CliCommand>,
+}
+
+/// Parses the arguments of the process. Song files are made absolute since a running instance
+/// may have another working directory.
+pub fn parse() -> Result<Arguments, String> {
+    let mut arguments = Arguments {
+        directory: None,
+        commands: Vec::new(),
+    };
+    // The playlist is played where the first of its options is given, so that later song files
+    // are played instead
+    let mut playlist_command_index = None;
+    let mut playlist_name = None;
+    let mut shuffle = false;
+    let mut args = env::args().skip(1);
+    while let Some(argument) = args.next() {
+        let command = match argument.as_str() {
+            "--playlist" => {
+                let name = args
+                    .next()
+                    .ok_or(format!("Missing playlist name\n{USAGE}"))?;
+                playlist_name = Some(name);
+                playlist_command_index.get_or_insert(arguments.commands.len());
+                continue;
+            }
+            "--shuffle" => {
+                shuffle = true;
+                playlist_command_index.get_or_insert(arguments.commands.len());
+                continue;
+            }
+            "--volume" => {
+                let percent = args
+                    .next()
+                    .and_then(|x| x.parse::<f32>().ok())
+                    .filter(|x| (0.0..=100.0).contains(x))
+                    .ok_or(format!("The volume must be between 0 and 100\n{USAGE}"))?;
+                CliCommand::Volume(percent)
+            }
+            "--show" => CliCommand::Show,
+            "--play" => CliCommand::Play,
+            "--pause" => CliCommand::Pause,
+            "--toggle" => CliCommand::Toggle,
+            "--next" => CliCommand::Next,
+            "--prev" => CliCommand::Prev,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            x if x.starts_with("--") => return Err(format!("Unknown option {x}\n{USAGE}")),
+            path => {
+                let path = env::current_dir()
+                    .map(|x| x.join(path))
+                    .unwrap_or_else(|_| PathBuf::from(path));
+                if path.is_dir() && arguments.directory.is_none() {
+                    arguments.directory = Some(argument);
+                    continue;
+                }
+                if !path.is_file() {
+                    return Err(format!("{argument} is neither a directory nor a file"));
+                }
+                CliCommand::Open(path)
+            }
+        };
+        arguments.commands.push(command);
+    }
+    if let Some(i) = playlist_command_index {
+        arguments.commands.insert(
+            i,
+            CliCommand::PlayPlaylist {
+                name: playlist_name,
+                shuffle,
+            },
+        );
+    }
+    Ok(arguments)
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use image::{DynamicImage, ImageFormat};
+use symphonia::core::meta::{MetadataRevision, StandardVisualKey};
+
+use crate::app;
+
+/// Image files in the folder of a song that are used if it has no embedded cover
+const COVER_FILE_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// Covers are scaled down to fit into this size to keep the texture small
+const MAX_COVER_SIZE: u32 = 512;
+/// Maximum size of the covers in the album grid, which is also the size of a cell in the atlas
+pub const THUMBNAIL_SIZE: u32 = 128;
+/// Number of cells in each row and column of the atlas texture that holds the thumbnails
+pub const ATLAS_CELLS: u32 = 16;
+/// Folder in the data directory that caches the extracted thumbnails
+const THUMBNAIL_DIRECTORY: &str = "covers";
+/// Directory inside the temporary directory with the cover of the playing song for the system
+/// media controls
+const MEDIA_COVER_DIRECTORY: &str = "implayer-covers";
+
+/// Decoded cover art of a song
+pub struct Cover {
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns the embedded front cover or otherwise the first embedded image.
+fn find_visual(revision: &MetadataRevision) -> Option<Vec<u8>> {
+    let visuals = revision.visuals();
+    visuals
+        .iter()
+        .find(|x| x.usage == Some(StandardVisualKey::FrontCover))
+        .or(visuals.first())
+        .map(|x| x.data.to_vec())
+}
+
+fn read_embedded(path: &Path) -> Option<Vec<u8>> {
+    let mss = symphonia::core::io::MediaSourceStream::new(
+        Box::new(File::open(path).ok()?),
+        Default::default(),
+    );
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .ok()?;
+
+    if let Some(data) = probed
+        .metadata
+        .get()
+        .as_ref()
+        .and_then(|x| x.current())
+        .and_then(find_visual)
+    {
+        return Some(data);
+    }
+    probed.format.metadata().current().and_then(find_visual)
+}
+
+fn read_folder_image(path: &Path) -> Option<Vec<u8>> {
+    let folder = path.parent()?;
+    COVER_FILE_NAMES
+        .iter()
+        .flat_map(|name| {
+            COVER_EXTENSIONS
+                .iter()
+                .map(move |extension| folder.join(format!("{name}.{extension}")))
+        })
+        .find_map(|x| fs::read(x).ok())
+}
+
+fn load_image(path: &Path, max_size: u32) -> Option<DynamicImage> {
+    let data = read_embedded(path).or_else(|| read_folder_image(path))?;
+    let image = image::load_from_memory(&data).ok()?;
+    if image.width() > max_size || image.height() > max_size {
+        Some(image.thumbnail(max_size, max_size))
+    } else {
+        Some(image)
+    }
+}
+
+fn to_cover(image: DynamicImage) -> Cover {
+    let image = image.into_rgba8();
+    Cover {
+        width: image.width(),
+        height: image.height(),
+        rgba: image.into_raw(),
+    }
+}
+
+pub fn load(path: &Path) -> Option<Cover> {
+    load_image(path, MAX_COVER_SIZE).map(to_cover)
+}
+
+/// Loads the thumbnail of the song from the cache or extracts it and adds it to the cache. Songs
+/// without a cover are cached as an empty file.
+fn load_thumbnail(base_path: &str, song_path: &str) -> Option<Cover> {
+    let directory = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(THUMBNAIL_DIRECTORY);
+    let mut hasher = DefaultHasher::new();
+    song_path.hash(&mut hasher);
+    let cache_path = directory.join(format!("{:016x}.png", hasher.finish()));
+
+    if let Ok(data) = fs::read(&cache_path) {
+        if data.is_empty() {
+            return None;
+        }
+        if let Ok(image) = image::load_from_memory(&data) {
+            return Some(to_cover(image));
+        }
+    }
+    let image = load_image(&Path::new(base_path).join(song_path), THUMBNAIL_SIZE);
+    let _ = fs::create_dir_all(&directory);
+    match image {
+        Some(image) => {
+            let _ = image.save(&cache_path);
+            Some(to_cover(image))
+        }
+        None => {
+            let _ = fs::write(&cache_path, []);
+            None
+        }
+    }
+}
+
+/// Loads the thumbnails one after another in the background. Each request consists of a key
+/// that is sent back with the thumbnail and the relative path of a song.
+pub fn start_loading_thumbnails(
+    base_path: String,
+    requests: Vec<(String, String)>,
+) -> Receiver<(String, Option<Cover>)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (key, song_path) in requests {
+            if sender
+                .send((key, load_thumbnail(&base_path, &song_path)))
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// Loads the cover in the background since decoding large images takes a moment.
+pub fn start_loading(path: PathBuf) -> Receiver<Option<Cover>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(load(&path));
+    });
+    receiver
+}
+
+/// Writes the unchanged cover of the song to a temporary file since the system media controls only
+/// accept URLs. Returns the file URL.
+fn export_media_cover(path: &Path) -> Option<String> {
+    let data = read_embedded(path).or_else(|| read_folder_image(path))?;
+    let extension = match image::guess_format(&data).ok()? {
+        ImageFormat::Jpeg => "jpg",
+        ImageFormat::Png => "png",
+        _ => return None,
+    };
+    let directory = std::env::temp_dir().join(MEDIA_COVER_DIRECTORY);
+    // Only the cover of the playing song is kept
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).ok()?;
+    // Media widgets cache images by their URL, so each song gets its own file name
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let cover_path = directory.join(format!("{:016x}.{extension}", hasher.finish()));
+    fs::write(&cover_path, data).ok()?;
+    Some(format!("file://{}", cover_path.to_string_lossy()))
+}
+
+/// Exports the cover for the system media controls in the background.
+pub fn start_exporting_media_cover(path: PathBuf) -> Receiver<Option<String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(export_media_cover(&path));
+    });
+    receiver
+}
@@ -0,0 +1,162 @@
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::SystemTime,
+};
+
+use crate::app;
+
+/// Number of the last actions that are listed in a crash report
+const RECENT_ACTION_COUNT: usize = 20;
+
+/// What the player was doing, kept outside of the state so that the panic hook can read it
+struct CrashContext {
+    recent_actions: VecDeque<String>,
+    /// Playlist name and song path
+    playing_song: Option<(String, String)>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    recent_actions: VecDeque::new(),
+    playing_song: None,
+});
+
+/// Remembers the action for the crash report.
+pub fn record_action(action: String) {
+    let mut context = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    if context.recent_actions.len() == RECENT_ACTION_COUNT {
+        context.recent_actions.pop_front();
+    }
+    context.recent_actions.push_back(action);
+}
+
+/// Remembers the playing song for the crash report.
+pub fn set_playing_song(playlist_name: &str, song_path: &str) {
+    let mut context = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    context.playing_song = Some((playlist_name.to_string(), song_path.to_string()));
+}
+
+fn format_report(info: &PanicHookInfo, backtrace: &Backtrace) -> String {
+    let thread = thread::current();
+    let mut report = format!(
+        "{} {} crashed\n\nThread '{}' {info}\n",
+        crate::TITLE,
+        env!("CARGO_PKG_VERSION"),
+        thread.name().unwrap_or("unnamed"),
+    );
+    // The panic may have happened while the context was locked by this thread
+    match CONTEXT.try_lock() {
+        Ok(context) => {
+            if let Some((playlist_name, song_path)) = &context.playing_song {
+                report += &format!("\nPlaying: {song_path}\nPlaylist: {playlist_name}\n");
+            }
+            report += "\nLast actions, oldest first:\n";
+            for action in context.recent_actions.iter() {
+                report += &format!("  {action}\n");
+            }
+        }
+        Err(_) => report += "\nThe last actions are not available\n",
+    }
+    report += &format!("\nBacktrace:\n{backtrace}\n");
+    report
+}
+
+/// Writes the report into the data directory, or next to the executable if that fails.
+fn write_report(base_path: &str, report: &str) -> Option<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let file_name = format!("crash-{timestamp}.txt");
+    let data_path = Path::new(base_path).join(app::DATA_DIRECTORY);
+    let fallback_path = std::env::current_exe()
+        .ok()
+        .and_then(|x| x.parent().map(|x| x.to_path_buf()));
+    [Some(data_path), fallback_path]
+        .into_iter()
+        .flatten()
+        .map(|x| x.join(&file_name))
+        .find(|x| fs::write(x, report).is_ok())
+}
+
+#[cfg(target_os = "windows")]
+fn show_message(message: &str) {
+    use windows::{
+        core::HSTRING,
+        Win32::{
+            Foundation::HWND,
+            UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK},
+        },
+    };
+
+    unsafe {
+        MessageBoxW(
+            HWND::default(),
+            &HSTRING::from(message),
+            &HSTRING::from(crate::TITLE),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn show_message(message: &str) {
+    use std::process::Command;
+
+    let script = format!(
+        "display alert \"{}\" message \"{}\" as critical",
+        crate::TITLE,
+        message.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    let _ = Command::new("osascript").arg("-e").arg(script).status();
+}
+
+/// Shows the message with the first dialog program that is installed.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn show_message(message: &str) {
+    use std::process::Command;
+
+    let is_shown = Command::new("zenity")
+        .arg("--error")
+        .arg("--title")
+        .arg(crate::TITLE)
+        .arg("--text")
+        .arg(message)
+        .status()
+        .is_ok();
+    if !is_shown {
+        let _ = Command::new("kdialog")
+            .arg("--title")
+            .arg(crate::TITLE)
+            .arg("--error")
+            .arg(message)
+            .status();
+    }
+}
+
+/// Writes a crash report with the backtrace, the last actions and the playing song when a thread
+/// panics. Panics of the main thread end the player, so they are also shown in a message box.
+pub fn install_panic_hook(base_path: &str) {
+    let base_path = base_path.to_string();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let report = format_report(info, &Backtrace::force_capture());
+        let report_path = write_report(&base_path, &report);
+        if thread::current().name() != Some("main") {
+            return;
+        }
+        let message = match report_path {
+            Some(path) => format!(
+                "The player crashed. A crash report was written to\n{}",
+                path.display()
+            ),
+            None => format!("The player crashed:\n{info}"),
+        };
+        show_message(&message);
+    }));
+}
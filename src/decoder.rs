@@ -0,0 +1,77 @@
+use std::{fs::File, path::Path};
+
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    conv::ConvertibleSample,
+    errors::Error,
+    formats::FormatReader,
+};
+
+/// Decodes the first track of a song file into interleaved samples, used for analyzing songs
+/// outside of playback.
+pub struct SampleDecoder {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+impl SampleDecoder {
+    pub fn open(path: &Path) -> Option<SampleDecoder> {
+        let mss = symphonia::core::io::MediaSourceStream::new(
+            Box::new(File::open(path).ok()?),
+            Default::default(),
+        );
+        let reader = symphonia::default::get_probe()
+            .format(
+                &Default::default(),
+                mss,
+                &Default::default(),
+                &Default::default(),
+            )
+            .ok()?
+            .format;
+
+        let track = reader.tracks().first()?;
+        let track_id = track.id;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions { verify: false })
+            .ok()?;
+        let sample_rate = track.codec_params.sample_rate?;
+        let channels = track.codec_params.channels?.count() as u32;
+
+        Some(SampleDecoder {
+            reader,
+            decoder,
+            track_id,
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Decodes the next packet into the sample buffer, which is created on first use. Returns
+    /// `None` at the end of the track or on errors.
+    pub fn next_samples<'a, S: ConvertibleSample>(
+        &mut self,
+        sample_buf: &'a mut Option<SampleBuffer<S>>,
+    ) -> Option<&'a [S]> {
+        loop {
+            let packet = self.reader.next_packet().ok()?;
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return None,
+            };
+            let buf = sample_buf.get_or_insert_with(|| {
+                SampleBuffer::<S>::new(decoded.capacity() as u64, *decoded.spec())
+            });
+            buf.copy_interleaved_ref(decoded);
+            return Some(buf.samples());
+        }
+    }
+}
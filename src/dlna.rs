@@ -0,0 +1,565 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app,
+    player::PlayerAction,
+    playlist_file::{escape_xml, get_element_text, unescape_xml},
+    util,
+};
+
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const MEDIA_RENDERER: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:";
+const RENDERING_CONTROL: &str = "urn:schemas-upnp-org:service:RenderingControl:";
+/// Time that renderers have to answer the search, they wait up to the `MX` seconds of the request
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+/// Time that a renderer has to answer a control request
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Time between checks of the position and whether the song ended
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Time between checks whether the file server was stopped while no connection arrives
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, PartialEq)]
+struct Service {
+    /// Service type with its version like `urn:schemas-upnp-org:service:AVTransport:1`
+    service_type: String,
+    control_path: String,
+}
+
+/// Media renderer in the local network like a smart TV or a network amplifier
+#[derive(Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    /// Host and port of the device's HTTP server
+    address: String,
+    av_transport: Service,
+    /// Not all renderers let the volume be controlled
+    rendering_control: Option<Service>,
+}
+
+/// Searches the local network for renderers in the background. Each renderer is sent as soon as
+/// its description was read, the channel is closed when the search is over.
+pub fn start_discovery() -> Receiver<Device> {
+    let (device_tx, device_rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(e) = discover(&device_tx) {
+            eprintln!("Failed to search for renderers: {e}");
+        }
+    });
+    device_rx
+}
+
+fn discover(device_tx: &Sender<Device>) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {SSDP_ADDRESS}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {MEDIA_RENDERER}\r\n\r\n"
+    );
+    socket.send_to(request.as_bytes(), SSDP_ADDRESS)?;
+
+    let start = Instant::now();
+    let mut locations = HashSet::new();
+    let mut buffer = [0; 2048];
+    while start.elapsed() < DISCOVERY_TIME {
+        let Ok((length, _)) = socket.recv_from(&mut buffer) else {
+            continue;
+        };
+        let response = String::from_utf8_lossy(&buffer[..length]);
+        let location = response
+            .lines()
+            .filter_map(|x| x.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("location"))
+            .map(|(_, value)| value.trim().to_string());
+        // Devices answer more than once
+        let Some(location) = location.filter(|x| locations.insert(x.clone())) else {
+            continue;
+        };
+        if let Some(device) = read_device(&location) {
+            if device_tx.send(device).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the name and the control URLs of a renderer from its description.
+fn read_device(location: &str) -> Option<Device> {
+    let (address, path) = split_url(location)?;
+    let (status, description) =
+        http_request(&address, &format!("GET {path} HTTP/1.0\r\n"), "").ok()?;
+    if status != 200 {
+        return None;
+    }
+    let mut services = Vec::new();
+    let mut rest = description.as_str();
+    while let Some(start) = rest.find("<service>") {
+        let end = start + rest[start..].find("</service>")?;
+        let service = &rest[start..end];
+        if let (Some(service_type), Some(control_url)) = (
+            get_element_text(service, "serviceType"),
+            get_element_text(service, "controlURL"),
+        ) {
+            services.push(Service {
+                service_type: service_type.to_string(),
+                control_path: to_path(&unescape_xml(control_url)),
+            });
+        }
+        rest = &rest[end..];
+    }
+    let find_service = |prefix: &str| {
+        services
+            .iter()
+            .find(|x| x.service_type.starts_with(prefix))
+            .cloned()
+    };
+    Some(Device {
+        name: get_element_text(&description, "friendlyName")
+            .map(unescape_xml)
+            .unwrap_or_else(|| address.clone()),
+        av_transport: find_service(AV_TRANSPORT)?,
+        rendering_control: find_service(RENDERING_CONTROL),
+        address,
+    })
+}
+
+/// Splits an `http://` URL into the host with the port and the path.
+fn split_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("http://")?;
+    let (address, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{address}:80")
+    };
+    Some((address, to_path(path)))
+}
+
+/// Control URLs are given as absolute URLs or as paths relative to the device.
+fn to_path(url: &str) -> String {
+    if let Some((_, path)) = split_url(url) {
+        path
+    } else if url.starts_with('/') {
+        url.to_string()
+    } else {
+        format!("/{url}")
+    }
+}
+
+/// Sends an HTTP/1.0 request, which keeps the response from being chunked, and returns the status
+/// code and the body of the response. The request head consists of the request line and optional
+/// headers.
+fn http_request(address: &str, request_head: &str, body: &str) -> io::Result<(u16, String)> {
+    let socket_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Unknown address"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "{request_head}Host: {address}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0);
+    Ok((status, body.to_string()))
+}
+
+/// Calls an action of a service on the renderer and returns the response.
+fn call(
+    device: &Device,
+    service: &Service,
+    action: &str,
+    arguments: &[(&str, &str)],
+) -> Result<String, String> {
+    let service_type = &service.service_type;
+    let arguments: String = arguments
+        .iter()
+        .map(|(name, value)| format!("<{name}>{}</{name}>", escape_xml(value)))
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{action} xmlns:u=\"{service_type}\">{arguments}</u:{action}></s:Body></s:Envelope>"
+    );
+    let request_head = format!(
+        "POST {} HTTP/1.0\r\nContent-Type: text/xml; charset=\"utf-8\"\r\nSOAPAction: \"{service_type}#{action}\"\r\n",
+        service.control_path
+    );
+    let (status, response) =
+        http_request(&device.address, &request_head, &body).map_err(|e| e.to_string())?;
+    if status != 200 {
+        return Err(get_element_text(&response, "errorDescription")
+            .map(unescape_xml)
+            .unwrap_or_else(|| format!("{action} failed with status {status}")));
+    }
+    Ok(response)
+}
+
+fn call_av_transport(
+    device: &Device,
+    action: &str,
+    arguments: &[(&str, &str)],
+) -> Result<String, String> {
+    let arguments: Vec<(&str, &str)> = [("InstanceID", "0")]
+        .into_iter()
+        .chain(arguments.iter().copied())
+        .collect();
+    call(device, &device.av_transport, action, &arguments)
+}
+
+/// Converts milliseconds into the `H:MM:SS` format of positions.
+fn to_time(milli_seconds: u64) -> String {
+    let seconds = milli_seconds / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Converts a position in the `H:MM:SS` format, optionally with fractions of a second, into
+/// milliseconds.
+fn parse_time(text: &str) -> Option<u64> {
+    let mut parts = text.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some((hours * 3600 + minutes * 60) * 1000 + (seconds * 1000.0) as u64)
+}
+
+fn get_mime_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("flac") => "audio/flac",
+        Some("mp3") => "audio/mpeg",
+        Some("m4a") => "audio/mp4",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Description of the song that many renderers require to play it.
+fn get_metadata(path: &Path, url: &str) -> String {
+    let title = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!(
+        "<DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\"><item id=\"0\" parentID=\"-1\" restricted=\"1\"><dc:title>{}</dc:title><upnp:class>object.item.audioItem.musicTrack</upnp:class><res protocolInfo=\"http-get:*:{}:*\">{}</res></item></DIDL-Lite>",
+        escape_xml(&title),
+        get_mime_type(path),
+        escape_xml(url)
+    )
+}
+
+/// Plays songs on a renderer instead of the local player. It accepts the same actions and reports
+/// the position and the end of songs the same way.
+pub struct Renderer {
+    pub device: Device,
+    action_tx: Sender<PlayerAction>,
+    error_rx: Receiver<String>,
+    control_thread: JoinHandle<()>,
+}
+
+impl Renderer {
+    /// Starts the server that the renderer downloads the playing song from and the thread that
+    /// controls the renderer. Both end when the renderer is dropped.
+    pub fn connect(
+        device: Device,
+        song_ended_tx: Sender<()>,
+        position: Arc<Mutex<u64>>,
+    ) -> io::Result<Renderer> {
+        let local_ip = get_local_ip(&device.address)?;
+        let file = Arc::new(Mutex::new(None));
+        let is_stopped = Arc::new(AtomicBool::new(false));
+        let port = start_file_server(file.clone(), is_stopped.clone())?;
+
+        let (action_tx, action_rx) = mpsc::channel();
+        let (error_tx, error_rx) = mpsc::channel();
+        let control = Control {
+            device: device.clone(),
+            server_url: format!("http://{local_ip}:{port}"),
+            file,
+            song_number: 0,
+            is_playing: false,
+            has_started: false,
+            song_ended_tx,
+            position,
+        };
+        let control_thread = thread::spawn(move || {
+            control.run(action_rx, error_tx);
+            is_stopped.store(true, Ordering::Relaxed);
+        });
+        Ok(Renderer {
+            device,
+            action_tx,
+            error_rx,
+            control_thread,
+        })
+    }
+
+    /// Stops playback on the renderer and waits for the thread that controls it.
+    pub fn close(self) {
+        self.send(PlayerAction::Quit);
+        let _ = self.control_thread.join();
+    }
+
+    pub fn send(&self, action: PlayerAction) {
+        let _ = self.action_tx.send(action);
+    }
+
+    /// Returns the errors of the actions that failed since the last call.
+    pub fn receive_errors(&self) -> Vec<String> {
+        util::receive_all(&self.error_rx)
+    }
+}
+
+/// Returns the address of this computer in the network of the device.
+fn get_local_ip(device_address: &str) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    // Connecting a UDP socket sends nothing but chooses the network interface
+    socket.connect(device_address)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// State of the thread that sends the actions to the renderer
+struct Control {
+    device: Device,
+    /// URL of the file server without a path
+    server_url: String,
+    /// Song that the file server serves
+    file: Arc<Mutex<Option<PathBuf>>>,
+    /// Part of the song URL so that renderers do not play a cached earlier song
+    song_number: u64,
+    is_playing: bool,
+    /// Whether the renderer started playing the current song, it reports that it is stopped
+    /// before
+    has_started: bool,
+    song_ended_tx: Sender<()>,
+    position: Arc<Mutex<u64>>,
+}
+
+impl Control {
+    fn run(mut self, action_rx: Receiver<PlayerAction>, error_tx: Sender<String>) {
+        loop {
+            let result = match action_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(PlayerAction::Quit) | Err(RecvTimeoutError::Disconnected) => {
+                    let _ = call_av_transport(&self.device, "Stop", &[]);
+                    return;
+                }
+                Ok(action) => self.handle_action(action),
+                Err(RecvTimeoutError::Timeout) if self.is_playing => self.poll(),
+                Err(RecvTimeoutError::Timeout) => Ok(()),
+            };
+            if let Err(e) = result {
+                if error_tx.send(format!("{}: {e}", self.device.name)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_action(&mut self, action: PlayerAction) -> Result<(), String> {
+        match action {
+            PlayerAction::Play(path) => {
+                self.song_number += 1;
+                let extension = path
+                    .extension()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let url = format!("{}/{}.{extension}", self.server_url, self.song_number);
+                let metadata = get_metadata(&path, &url);
+                *self.file.lock().unwrap() = Some(path);
+                // Some renderers refuse a new song while playing
+                let _ = call_av_transport(&self.device, "Stop", &[]);
+                call_av_transport(
+                    &self.device,
+                    "SetAVTransportURI",
+                    &[("CurrentURI", &url), ("CurrentURIMetaData", &metadata)],
+                )?;
+                call_av_transport(&self.device, "Play", &[("Speed", "1")])?;
+                *self.position.lock().unwrap() = 0;
+                self.is_playing = true;
+                self.has_started = false;
+            }
+            PlayerAction::Pause => {
+                call_av_transport(&self.device, "Pause", &[])?;
+                self.is_playing = false;
+            }
+            PlayerAction::Resume => {
+                call_av_transport(&self.device, "Play", &[("Speed", "1")])?;
+                self.is_playing = true;
+            }
+            PlayerAction::Stop => {
+                self.is_playing = false;
+                call_av_transport(&self.device, "Stop", &[])?;
+                *self.file.lock().unwrap() = None;
+            }
+            PlayerAction::Seek(position) => {
+                call_av_transport(
+                    &self.device,
+                    "Seek",
+                    &[("Unit", "REL_TIME"), ("Target", &to_time(position))],
+                )?;
+                *self.position.lock().unwrap() = position;
+            }
+            PlayerAction::SetVolume(value) => {
+                if let Some(service) = &self.device.rendering_control {
+                    // Renderers scale their volume themselves, so the position of the volume
+                    // slider is sent instead of the amplitude
+                    let volume = if value == 0.0 {
+                        app::MIN_VOLUME
+                    } else {
+                        value.powf(0.25)
+                    };
+                    let percent =
+                        ((volume - app::MIN_VOLUME) / (app::MAX_VOLUME - app::MIN_VOLUME) * 100.0)
+                            .round()
+                            .clamp(0.0, 100.0);
+                    call(
+                        &self.device,
+                        service,
+                        "SetVolume",
+                        &[
+                            ("InstanceID", "0"),
+                            ("Channel", "Master"),
+                            ("DesiredVolume", &percent.to_string()),
+                        ],
+                    )?;
+                }
+            }
+            // Ends the thread in `run`
+            PlayerAction::Quit => (),
+        }
+        Ok(())
+    }
+
+    /// Updates the position and reports the end of the song once the renderer stopped by itself.
+    fn poll(&mut self) -> Result<(), String> {
+        let transport_info = call_av_transport(&self.device, "GetTransportInfo", &[])?;
+        match get_element_text(&transport_info, "CurrentTransportState") {
+            Some("PLAYING") => self.has_started = true,
+            Some("STOPPED" | "NO_MEDIA_PRESENT") if self.has_started => {
+                self.is_playing = false;
+                let _ = self.song_ended_tx.send(());
+                return Ok(());
+            }
+            _ => (),
+        }
+        let position_info = call_av_transport(&self.device, "GetPositionInfo", &[])?;
+        if let Some(position) = get_element_text(&position_info, "RelTime").and_then(parse_time) {
+            *self.position.lock().unwrap() = position;
+        }
+        Ok(())
+    }
+}
+
+/// Serves the playing song to the renderer on a random port. Any path returns the current song,
+/// other files are not accessible.
+fn start_file_server(
+    file: Arc<Mutex<Option<PathBuf>>>,
+    is_stopped: Arc<AtomicBool>,
+) -> io::Result<u16> {
+    let listener = TcpListener::bind("0.0.0.0:0")?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    thread::spawn(move || {
+        while !is_stopped.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let file = file.lock().unwrap().clone();
+                    // Renderers often open another connection before the first one is closed
+                    thread::spawn(move || {
+                        let _ = serve_file(stream, file);
+                    });
+                }
+                Err(_) => thread::sleep(ACCEPT_INTERVAL),
+            }
+        }
+    });
+    Ok(port)
+}
+
+/// Answers a request for the song, supporting ranges since renderers use them to seek.
+fn serve_file(mut stream: TcpStream, path: Option<PathBuf>) -> io::Result<()> {
+    // The stream inherits the non-blocking mode of the listener on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut range_start = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                // Only ranges to the end of the file like `bytes=1000-` are supported, the rest
+                // is sent for others
+                range_start = value
+                    .trim()
+                    .strip_prefix("bytes=")
+                    .and_then(|x| x.split('-').next())
+                    .and_then(|x| x.parse::<u64>().ok());
+            }
+        }
+    }
+
+    let Some((path, mut file)) = path.and_then(|x| File::open(&x).ok().map(|file| (x, file)))
+    else {
+        return write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    };
+    let length = file.metadata()?.len();
+    let start = range_start.filter(|x| *x < length).unwrap_or(0);
+    let status = if range_start.is_some() && length > 0 {
+        format!(
+            "206 Partial Content\r\nContent-Range: bytes {start}-{}/{length}",
+            length - 1
+        )
+    } else {
+        "200 OK".to_string()
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\ntransferMode.dlna.org: Streaming\r\nConnection: close\r\n\r\n",
+        get_mime_type(&path),
+        length - start
+    )?;
+    if request_line.starts_with("HEAD") {
+        return Ok(());
+    }
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(&mut file, &mut stream)?;
+    Ok(())
+}
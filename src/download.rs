@@ -1,224 +1,522 @@
-use std::{
-    io::{BufRead, BufReader, Read},
-    process::{Child, Command, Stdio},
-    sync::mpsc::{self, Receiver, Sender},
-    thread::{self},
-    time::Instant,
-};
+use std::{path::Path, process::Command};
+
+pub use implayer_core::download::*;
 
 use crate::{
     actions,
-    app::{DownloadState, State, Status, StatusType},
-    util,
+    app::{State, StatusType, ALL_PLAYLIST_NAME},
+    import, job, util,
 };
 
-fn start_download(base_path: &str, url: &str) -> Child {
-    Command::new("yt-dlp")
-        .arg("-o")
-        .arg(format!("{}/%(title)s.%(ext)s", base_path))
+/// Number of videos that are listed by the search
+const SEARCH_RESULT_COUNT: usize = 10;
+
+/// Finds the external programs using the paths from the settings. Features that need a missing
+/// program are disabled.
+pub fn detect_programs(state: &mut State) {
+    state.programs = Programs {
+        yt_dlp: util::find_executable(&state.settings.yt_dlp_path),
+        aacgain: util::find_executable(&state.settings.aacgain_path),
+        mp3gain: util::find_executable(&state.settings.mp3gain_path),
+        ffmpeg: util::find_executable(&state.settings.ffmpeg_path),
+    };
+    if state.programs.yt_dlp.is_none() {
+        state.push_status(
+            StatusType::Error,
+            format!(
+                "yt-dlp was not found at \"{}\", downloading is disabled.\nSet its path under External programs in the playlist list's context menu.",
+                state.settings.yt_dlp_path
+            ),
+        );
+    }
+    if state.programs.aacgain.is_none() {
+        state.push_status(
+            StatusType::Error,
+            format!(
+                "aacgain was not found at \"{}\", volume adjustment is disabled.\nSet its path under External programs in the playlist list's context menu.",
+                state.settings.aacgain_path
+            ),
+        );
+    }
+    let missing_steps: Vec<&str> = state
+        .settings
+        .postprocessors
+        .iter()
+        .filter(|x| x.program(&state.programs).is_none())
+        .map(|x| x.name())
+        .collect();
+    if !missing_steps.is_empty() {
+        state.push_status(
+            StatusType::Error,
+            format!(
+                "The programs of these postprocessing steps were not found, they are skipped:\n{}",
+                missing_steps.join("\n")
+            ),
+        );
+    }
+}
+
+/// Adds a download of the URL in the download textbox to the queue. The song is added to the
+/// playlist once it is downloaded.
+pub fn download(state: &mut State, playlist_index: usize) {
+    let url = state.download_text.trim().to_string();
+    if url.is_empty() {
+        return;
+    }
+    let options = state.download_options.clone();
+    add_job(state, playlist_index, url, options);
+    state.download_text.clear();
+}
+
+/// Adds a download of a URL that was dropped onto the window to the queue.
+pub fn download_url(state: &mut State, playlist_index: usize, url: String) {
+    if state.programs.yt_dlp.is_none() {
+        state.push_status(
+            StatusType::Error,
+            "Can't download the dropped link, yt-dlp was not found".to_string(),
+        );
+        return;
+    }
+    let options = state.download_options.clone();
+    add_job(state, playlist_index, url, options);
+}
+
+/// Adds a download of a search result to the queue.
+pub fn download_search_result(state: &mut State, playlist_index: usize, result_index: usize) {
+    let url = state.download_search_results[result_index].url.clone();
+    let options = DownloadOptions {
+        whole_playlist: false,
+        ..state.download_options.clone()
+    };
+    add_job(state, playlist_index, url, options);
+}
+
+fn add_job(state: &mut State, playlist_index: usize, url: String, options: DownloadOptions) {
+    state.download_jobs.push(DownloadJob {
+        url,
+        playlist_name: state.playlists[playlist_index].name.clone(),
+        path: None,
+        options,
+        postprocessors: state.settings.postprocessors.clone(),
+        stage: DownloadStage::Queued,
+        progress: String::new(),
+    });
+    state.show_downloads = true;
+
+    let queued_count = state
+        .download_jobs
+        .iter()
+        .filter(|x| matches!(x.stage, DownloadStage::Queued))
+        .count();
+    if state.download_jobs.len() > queued_count {
+        state.push_status(
+            StatusType::Info,
+            format!("Added download to the queue, {queued_count} waiting"),
+        );
+    } else {
+        state.push_status(StatusType::Info, "Starting download...".to_string());
+    }
+}
+
+/// Stops the download and removes it from the queue.
+pub fn cancel(state: &mut State, job_index: usize) {
+    state.download_jobs.remove(job_index).stop();
+}
+
+/// Stops the downloads and the other processes before the player exits. An update of yt-dlp is
+/// finished since killing it could leave a broken program behind.
+pub fn stop_all(state: &mut State) {
+    while !state.download_jobs.is_empty() {
+        cancel(state, 0);
+    }
+    for mut process in [state.playlist_listing.take(), state.download_search.take()]
+        .into_iter()
+        .flatten()
+    {
+        process.kill();
+    }
+    if let Some(mut process) = state.yt_dlp_updater.take() {
+        process.wait();
+    }
+}
+
+/// Adds the song to the playlist of the download. The playlist might have been removed in the
+/// meantime, then the song is only added to the All playlist.
+fn add_downloaded_song(state: &mut State, path: &str, playlist_name: &str) {
+    let playlist_index = state
+        .playlists
+        .iter()
+        .position(|x| x.name == playlist_name)
+        .or_else(|| {
+            state
+                .playlists
+                .iter()
+                .position(|x| x.name == ALL_PLAYLIST_NAME)
+        });
+    if let Some(playlist_index) = playlist_index {
+        actions::add_song(state, path, playlist_index);
+    }
+}
+
+/// Starts a YouTube search for the text in the search textbox in the background.
+pub fn search(state: &mut State) {
+    let query = state.download_search_text.trim();
+    let Some(yt_dlp) = &state.programs.yt_dlp else {
+        return;
+    };
+    if query.is_empty() || state.download_search.is_some() {
+        return;
+    }
+    let mut command = Command::new(yt_dlp);
+    command
+        .arg(format!("ytsearch{SEARCH_RESULT_COUNT}:{query}"))
+        .arg("--flat-playlist")
         .arg("--print")
-        .arg("%(filename)s")
-        .arg("-q")
-        .arg("--no-simulate")
-        .arg("--no-playlist")
-        .arg("-f")
-        .arg("ba[ext=m4a] / ba[ext=mp3]")
-        .arg("--progress")
-        .arg("--newline")
-        .arg("--progress-template")
-        .arg("#status#%(progress._percent_str)s")
+        .arg("%(url)s\t%(duration)s\t%(channel)s\t%(title)s");
+    match Process::start(command) {
+        Ok(process) => {
+            state.download_search = Some(process);
+            state.download_search_results.clear();
+        }
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not start the search:\n{e}"),
+        ),
+    }
+}
+
+/// Adds the search results that yt-dlp printed so far.
+pub fn update_search(state: &mut State) {
+    let Some(process) = &mut state.download_search else {
+        return;
+    };
+    let status = process.try_wait();
+    for line in process.receive_output(status.is_some()) {
+        let mut fields = line.trim_end_matches(['\r', '\n']).splitn(4, '\t');
+        let (Some(url), Some(duration), Some(channel), Some(title)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        state.download_search_results.push(SearchResult {
+            url: url.to_string(),
+            title: title.to_string(),
+            channel: if channel == "NA" {
+                String::new()
+            } else {
+                channel.to_string()
+            },
+            duration: duration.parse::<f64>().ok().map(|x| (x * 1000.0) as u64),
+        });
+    }
+
+    if let Some(status) = status {
+        let error = process.get_error_output();
+        state.download_search = None;
+        if !status.success() {
+            state.push_status(StatusType::Error, format!("Search failed:\n{error}"));
+        }
+    }
+}
+
+/// Starts listing the videos of a YouTube or YouTube Music playlist in the background, the
+/// playlist is imported once the list is complete.
+pub fn start_listing_playlist(state: &mut State, url: &str) {
+    let Some(yt_dlp) = &state.programs.yt_dlp else {
+        state.push_status(
+            StatusType::Error,
+            "Can't read the playlist, yt-dlp was not found".to_string(),
+        );
+        return;
+    };
+    if state.playlist_listing.is_some() {
+        return;
+    }
+    let mut command = Command::new(yt_dlp);
+    command
         .arg(url)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .unwrap()
-}
-
-fn start_postprocessing(path: &str) -> Child {
-    Command::new("aacgain")
-        .arg("-r")
-        .arg(path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .unwrap()
-}
-
-fn start_listener<R: Read + std::marker::Send + 'static>(
-    stdout: R,
-    sender: Sender<String>,
-    receiver: Receiver<()>,
-) {
-    thread::spawn(move || {
-        let mut f = BufReader::new(stdout);
-        loop {
-            if let Ok(()) = receiver.try_recv() {
-                return;
-            }
-            let mut buf = String::new();
-            f.read_line(&mut buf).unwrap();
-            if buf.trim().len() > 0 {
-                if let Err(_) = sender.send(buf) {
-                    return;
-                }
-            }
+        .arg("--flat-playlist")
+        .arg("--yes-playlist")
+        .arg("--print")
+        .arg("%(playlist_title)s\t%(url)s\t%(channel)s\t%(title)s");
+    match Process::start(command) {
+        Ok(process) => {
+            state.playlist_listing = Some(process);
+            state.push_status(StatusType::Info, "Reading the playlist...".to_string());
         }
-    });
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not read the playlist:\n{e}"),
+        ),
+    }
 }
 
-pub fn download(state: &mut State) {
-    if !matches!(state.download_state, DownloadState::None) {
+/// Imports the listed playlist once yt-dlp has finished.
+pub fn update_playlist_listing(state: &mut State) {
+    let Some(process) = &mut state.playlist_listing else {
         return;
+    };
+    let Some(status) = process.try_wait() else {
+        return;
+    };
+    let mut playlist_name = None;
+    let mut tracks = Vec::new();
+    for line in process.receive_output(true) {
+        let mut fields = line.trim_end_matches(['\r', '\n']).splitn(4, '\t');
+        let (Some(name), Some(url), Some(channel), Some(title)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        playlist_name.get_or_insert_with(|| name.to_string());
+        let channel = if channel == "NA" { "" } else { channel };
+        tracks.push(StreamingTrack::from_video(url, channel, title));
+    }
+    let error = process.get_error_output();
+    state.playlist_listing = None;
+    if !status.success() || tracks.is_empty() {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not read the playlist:\n{error}"),
+        );
+        return;
+    }
+    let playlist_name = playlist_name
+        .filter(|x| x != "NA")
+        .unwrap_or("Imported playlist".to_string());
+    import::import_streaming_tracks(state, &playlist_name, tracks);
+}
+
+/// Adds downloads of the tracks to the queue that add them to the playlist.
+pub fn download_tracks(state: &mut State, playlist_index: usize, tracks: &[StreamingTrack]) {
+    if state.programs.yt_dlp.is_none() {
+        state.push_status(
+            StatusType::Error,
+            "Can't download the songs, yt-dlp was not found".to_string(),
+        );
+        return;
+    }
+    let options = DownloadOptions {
+        whole_playlist: false,
+        ..state.download_options.clone()
+    };
+    for track in tracks {
+        let url = match &track.url {
+            Some(url) => url.clone(),
+            None => format!("ytsearch1:{} - {}", track.artist, track.title),
+        };
+        add_job(state, playlist_index, url, options.clone());
+    }
+}
+
+/// Starts the self-update of yt-dlp in the background.
+pub fn update_yt_dlp(state: &mut State) {
+    let Some(yt_dlp) = &state.programs.yt_dlp else {
+        return;
+    };
+    let mut command = Command::new(yt_dlp);
+    command.arg("-U");
+    match Process::start(command) {
+        Ok(process) => state.yt_dlp_updater = Some(process),
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not start the yt-dlp update:\n{e}"),
+        ),
+    }
+}
+
+/// Reports the output of the yt-dlp update.
+pub fn update_yt_dlp_updater(state: &mut State) {
+    let Some(process) = &mut state.yt_dlp_updater else {
+        return;
+    };
+    let status = process.try_wait();
+    let lines = process.receive_output(status.is_some());
+    let Some(status) = status else {
+        let has_status = state
+            .status_queue
+            .iter()
+            .any(|x| matches!(x.r#type, StatusType::Progress));
+        if has_status && lines.is_empty() {
+            return;
+        }
+        let info = match lines.last() {
+            Some(line) => format!("Updating yt-dlp...\n{}", line.trim()),
+            None => "Updating yt-dlp...".to_string(),
+        };
+        state
+            .status_queue
+            .retain(|x| !matches!(x.r#type, StatusType::Progress));
+        state.push_status(StatusType::Progress, info);
+        return;
+    };
+
+    let error_output = process.get_error_output();
+    state.yt_dlp_updater = None;
+    state
+        .status_queue
+        .retain(|x| !matches!(x.r#type, StatusType::Progress));
+    let output = lines.join("").trim().to_string();
+    if status.success() {
+        state.push_status(StatusType::Info, output);
+    } else {
+        let error = [output, error_output]
+            .into_iter()
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n");
+        state.push_status(
+            StatusType::Error,
+            format!("Could not update yt-dlp:\n{error}"),
+        );
     }
-    let now = Instant::now();
-    let mut child = start_download(&state.base_path, &state.download_text);
-    state.status_queue.push_back(Status {
-        info: "Starting download...".to_string(),
-        timestamp: now,
-        r#type: StatusType::Info,
-    });
-    state.last_download_status = None;
-    let (stdout_tx, stdout_rx) = mpsc::channel();
-    let (stdout_kill_tx, stdout_kill_rx) = mpsc::channel();
-    start_listener(child.stdout.take().unwrap(), stdout_tx, stdout_kill_rx);
-    let (stderr_tx, stderr_rx) = mpsc::channel();
-    let (stderr_kill_tx, stderr_kill_rx) = mpsc::channel();
-    start_listener(child.stderr.take().unwrap(), stderr_tx, stderr_kill_rx);
-    state.download_state =
-        DownloadState::Downloading(child, stdout_rx, stdout_kill_tx, stderr_rx, stderr_kill_tx);
 }
 
+/// Starts the next queued download once the previous one is finished and reports the progress.
 pub fn update(state: &mut State) {
-    let now = Instant::now();
-    match state.download_state {
-        DownloadState::None => (),
-        DownloadState::Downloading(
-            ref mut child,
-            ref stdout_rx,
-            ref stdout_kill_tx,
-            ref stderr_rx,
-            ref stderr_kill_tx,
-        ) => {
-            // Report progress once every few seconds
-            for line in util::receive_all(stdout_rx).iter().rev() {
-                if line.trim().len() == 0 {
-                    continue;
-                }
-                if line.starts_with("#status#") {
-                    if state.last_download_status.is_none()
-                        || (now - state.last_download_status.unwrap()).as_millis() >= 100
-                    {
-                        state
-                            .status_queue
-                            .retain(|x| !matches!(x.r#type, StatusType::Progress));
-                        state.status_queue.push_back(Status {
-                            info: format!("Download progress: {}", line[8..].trim()),
-                            timestamp: now,
-                            r#type: StatusType::Progress,
-                        });
-                        state.last_download_status = Some(now);
-                    }
-                } else {
-                    state.download_path = Some(line.trim().to_string());
-                }
-            }
+    if state.download_jobs.is_empty() {
+        return;
+    }
 
-            // Check if child exited
-            if let Ok(Some(status)) = child.try_wait() {
-                stdout_kill_tx.send(()).unwrap();
-                stderr_kill_tx.send(()).unwrap();
-                if status.success() {
-                    state.status_queue.push_back(Status {
-                        info: "Download finished, starting postprocessing...".to_string(),
-                        timestamp: now,
-                        r#type: StatusType::Info,
-                    });
-
-                    // Start postprocessing
-                    let mut new_child =
-                        start_postprocessing(&state.download_path.as_ref().unwrap());
-                    state.last_download_status = None;
-                    let (stdout_tx, stdout_rx) = mpsc::channel();
-                    let (stdout_kill_tx, stdout_kill_rx) = mpsc::channel();
-                    start_listener(new_child.stdout.take().unwrap(), stdout_tx, stdout_kill_rx);
-                    let (stderr_tx, stderr_rx) = mpsc::channel();
-                    let (stderr_kill_tx, stderr_kill_rx) = mpsc::channel();
-                    start_listener(new_child.stderr.take().unwrap(), stderr_tx, stderr_kill_rx);
-                    state.download_state = DownloadState::Postprocessing(
-                        new_child,
-                        stdout_rx,
-                        stdout_kill_tx,
-                        stderr_rx,
-                        stderr_kill_tx,
-                    );
-                } else {
-                    let error = util::receive_all(stderr_rx).join("\n");
-                    state.status_queue.push_back(Status {
-                        info: format!("Error while downloading:\n{error}"),
-                        timestamp: now,
-                        r#type: StatusType::Error,
-                    });
-                    state.download_state = DownloadState::None;
-                }
+    // Start queued downloads until the configured number of downloads is running
+    let mut running_count = state
+        .download_jobs
+        .iter()
+        .filter(|x| matches!(x.stage, DownloadStage::Downloading(_)))
+        .count();
+    let network_options = NetworkOptions {
+        proxy: state.settings.download_proxy.clone(),
+        rate_limit: state.settings.download_rate_limit.clone(),
+        cookies_file: state.settings.cookies_file.clone(),
+    };
+    let mut i = 0;
+    while running_count < state.settings.parallel_downloads && i < state.download_jobs.len() {
+        if !matches!(state.download_jobs[i].stage, DownloadStage::Queued) {
+            i += 1;
+            continue;
+        }
+        let Some(yt_dlp) = &state.programs.yt_dlp else {
+            state.download_jobs.clear();
+            return;
+        };
+        let job = &mut state.download_jobs[i];
+        match Process::start(download_command(
+            yt_dlp,
+            &state.base_path,
+            &job.url,
+            &job.options,
+            &network_options,
+        )) {
+            Ok(process) => {
+                job.stage = DownloadStage::Downloading(process);
+                running_count += 1;
+                i += 1;
+            }
+            Err(e) => {
+                state.download_jobs.remove(i);
+                state.push_status(StatusType::Error, format!("Could not start yt-dlp:\n{e}"));
             }
         }
-        DownloadState::Postprocessing(
-            ref mut child,
-            ref stdout_rx,
-            ref stdout_kill_tx,
-            ref stderr_rx,
-            ref stderr_kill_tx,
-        ) => {
-            // Check if child exited
-            if let Ok(Some(status)) = child.try_wait() {
-                stdout_kill_tx.send(()).unwrap();
-                stderr_kill_tx.send(()).unwrap();
-                if status.success() {
-                    state.status_queue.push_back(Status {
-                        info: "Postprocessing finished".to_string(),
-                        timestamp: now,
-                        r#type: StatusType::Info,
-                    });
-
-                    actions::add_song(
-                        state,
-                        &state.download_path.clone().unwrap(),
-                        state.download_playlist_index.unwrap(),
-                    );
-                    state.download_text = String::new();
-                } else {
-                    let error = util::receive_all(stdout_rx).join("\n");
-                    state.status_queue.push_back(Status {
-                        info: format!("Error during postprocessing:\n{error}"),
-                        timestamp: now,
-                        r#type: StatusType::Error,
-                    });
+    }
+
+    let mut i = 0;
+    while i < state.download_jobs.len() {
+        let mut is_removed = false;
+        let programs = state.programs.clone();
+        for event in update_job(&mut state.download_jobs[i], &programs) {
+            match event {
+                DownloadEvent::Error(e) => {
+                    is_removed = true;
+                    state.push_status(StatusType::Error, e);
                 }
-                state.download_state = DownloadState::None;
-            } else {
-                // Report progress once every few seconds
-                for line in util::receive_all(stdout_rx).iter().rev() {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    let percent_char = line.chars().nth(2);
-                    if percent_char.is_some() && percent_char.unwrap() == '%' {
-                        if state.last_download_status.is_none()
-                            || (now - state.last_download_status.unwrap()).as_millis() >= 100
-                        {
-                            state
-                                .status_queue
-                                .retain(|x| !matches!(x.r#type, StatusType::Progress));
-                            state.status_queue.push_back(Status {
-                                info: format!("Postprocessing progress: {}", line[..3].trim()),
-                                timestamp: now,
-                                r#type: StatusType::Progress,
-                            });
-                            state.last_download_status = Some(now);
+                DownloadEvent::Finished(path) => {
+                    is_removed = true;
+                    let title = state.download_jobs[i].get_title();
+                    state.push_status(StatusType::Info, format!("Download of {title} finished"));
+                    let playlist_name = state.download_jobs[i].playlist_name.clone();
+                    add_downloaded_song(state, &path, &playlist_name);
+                }
+                DownloadEvent::SongDownloaded(path) => {
+                    // Postprocess the songs as separate jobs while the playlist download continues
+                    let paths = if state.download_jobs[i].options.split_chapters {
+                        find_chapter_files(&path)
+                    } else {
+                        vec![path]
+                    };
+                    let mut inserted_count = 0;
+                    for path in paths {
+                        let job = &state.download_jobs[i];
+                        let mut song_job = DownloadJob {
+                            url: job.url.clone(),
+                            playlist_name: job.playlist_name.clone(),
+                            path: Some(path.clone()),
+                            options: DownloadOptions {
+                                whole_playlist: false,
+                                ..job.options.clone()
+                            },
+                            postprocessors: job.postprocessors.clone(),
+                            stage: DownloadStage::Queued,
+                            progress: String::new(),
+                        };
+                        match start_postprocessing(&mut song_job, &programs, 0) {
+                            Ok(true) => {
+                                inserted_count += 1;
+                                state.download_jobs.insert(i + inserted_count, song_job);
+                            }
+                            Ok(false) => {
+                                add_downloaded_song(state, &path, &song_job.playlist_name);
+                            }
+                            Err(e) => state.push_status(StatusType::Error, e),
                         }
                     }
                 }
+                DownloadEvent::PlaylistFinished => {
+                    is_removed = true;
+                    let job = &state.download_jobs[i];
+                    let message = if job.options.whole_playlist {
+                        format!("Download of playlist {} finished", job.url)
+                    } else {
+                        format!("Download of {} finished", job.get_title())
+                    };
+                    state.push_status(StatusType::Info, message);
+                }
             }
         }
+        if is_removed {
+            state.download_jobs.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Runs the postprocessing of downloads for existing songs in the background.
+pub fn postprocess_songs(state: &mut State, paths: Vec<String>) {
+    if state.postprocessing_job.is_running() {
+        return;
+    }
+    let Some(aacgain) = state.programs.aacgain.clone() else {
+        return;
     };
+    let mut full_paths: Vec<String> = Vec::new();
+    for path in paths {
+        let full_path = Path::new(&state.base_path)
+            .join(path)
+            .to_string_lossy()
+            .to_string();
+        if !full_paths.contains(&full_path) {
+            full_paths.push(full_path);
+        }
+    }
+    let sender = state.postprocessing_job.start();
+    state.spawn_writer(move || run_postprocessing(aacgain, full_paths, sender));
+}
+
+pub fn update_postprocessing(state: &mut State) {
+    if let Some(count) = job::update(state, |x| &mut x.postprocessing_job, "Postprocessing") {
+        state.push_status(
+            StatusType::Info,
+            format!("Postprocessing finished for {count} songs"),
+        );
+    }
 }
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use rusty_chromaprint::{Configuration, Fingerprinter};
+
+use crate::{
+    app::{Song, State, StatusType},
+    decoder::SampleDecoder,
+    job::{self, JobMessage, JobSender},
+};
+
+/// Only the beginning of a song is used for its fingerprint
+const FINGERPRINT_SECONDS: u64 = 60;
+/// Songs whose durations differ more than this are never considered duplicates
+const MAX_DURATION_DIFFERENCE_MS: u64 = 5000;
+/// Maximum shift in fingerprint items when aligning two fingerprints (one item is ~0.12s)
+const MAX_ALIGNMENT_OFFSET: isize = 8;
+/// Minimum share of equal fingerprint bits for two songs to be considered duplicates
+const SIMILARITY_THRESHOLD: f32 = 0.85;
+
+pub struct Duplicates {
+    pub groups: Vec<Vec<Song>>,
+    /// Fingerprints calculated by the search, to be reused by later searches
+    pub fingerprints: HashMap<String, Vec<u32>>,
+}
+
+fn calculate_fingerprint(path: &Path) -> Option<Vec<u32>> {
+    let mut decoder = SampleDecoder::open(path)?;
+    let config = Configuration::default();
+    let mut fingerprinter = Fingerprinter::new(&config);
+    fingerprinter
+        .start(decoder.sample_rate, decoder.channels)
+        .ok()?;
+
+    let max_samples = FINGERPRINT_SECONDS * decoder.sample_rate as u64 * decoder.channels as u64;
+    let mut sample_count = 0;
+    let mut sample_buf = None;
+    while sample_count < max_samples {
+        let samples: &[i16] = match decoder.next_samples(&mut sample_buf) {
+            Some(samples) => samples,
+            None => break,
+        };
+        fingerprinter.consume(samples);
+        sample_count += samples.len() as u64;
+    }
+    fingerprinter.finish();
+
+    Some(fingerprinter.fingerprint().to_vec())
+}
+
+/// Returns the highest share of equal bits over all tested alignments of the two fingerprints.
+fn get_similarity(first: &[u32], second: &[u32]) -> f32 {
+    let mut best = 0.0_f32;
+    for offset in -MAX_ALIGNMENT_OFFSET..=MAX_ALIGNMENT_OFFSET {
+        let (a, b) = if offset >= 0 {
+            (first.get(offset as usize..), Some(second))
+        } else {
+            (Some(first), second.get((-offset) as usize..))
+        };
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+        let length = a.len().min(b.len());
+        if length == 0 {
+            continue;
+        }
+        let different_bits: u32 = a
+            .iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x ^ y).count_ones())
+            .sum();
+        best = best.max(1.0 - different_bits as f32 / (length as f32 * 32.0));
+    }
+    best
+}
+
+fn is_same_tag(first: &Song, second: &Song) -> bool {
+    !first.name.is_empty()
+        && first.name.to_lowercase() == second.name.to_lowercase()
+        && first.artist.to_lowercase() == second.artist.to_lowercase()
+}
+
+fn run(
+    songs: Vec<Song>,
+    base_path: String,
+    mut fingerprints: HashMap<String, Vec<u32>>,
+    sender: JobSender<Duplicates>,
+) {
+    for (i, song) in songs.iter().enumerate() {
+        if song.exists && !fingerprints.contains_key(&song.path) {
+            if let Some(fingerprint) =
+                calculate_fingerprint(&PathBuf::from(&base_path).join(&song.path))
+            {
+                fingerprints.insert(song.path.clone(), fingerprint);
+            }
+        }
+        let progress = JobMessage::Progress {
+            done: i + 1,
+            total: songs.len(),
+            item: None,
+        };
+        if sender.send(progress).is_err() {
+            return;
+        }
+    }
+
+    // Assign every song to a group, songs that are similar to any song of a group join it
+    let mut group_ids: Vec<usize> = (0..songs.len()).collect();
+    for i in 0..songs.len() {
+        for j in (i + 1)..songs.len() {
+            if group_ids[j] != j || songs[i].path == songs[j].path {
+                continue;
+            }
+            let is_duplicate = is_same_tag(&songs[i], &songs[j])
+                || match (
+                    fingerprints.get(&songs[i].path),
+                    fingerprints.get(&songs[j].path),
+                ) {
+                    (Some(a), Some(b)) => {
+                        songs[i]
+                            .duration
+                            .unwrap_or(0)
+                            .abs_diff(songs[j].duration.unwrap_or(0))
+                            <= MAX_DURATION_DIFFERENCE_MS
+                            && get_similarity(a, b) >= SIMILARITY_THRESHOLD
+                    }
+                    _ => false,
+                };
+            if is_duplicate {
+                group_ids[j] = group_ids[i];
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, group_id) in group_ids.iter().enumerate() {
+        match groups.iter_mut().find(|x| group_ids[x[0]] == *group_id) {
+            Some(group) => group.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+    let groups = groups
+        .iter()
+        .filter(|x| x.len() > 1)
+        .map(|x| x.iter().map(|i| songs[*i].clone()).collect())
+        .collect();
+
+    let _ = sender.send(JobMessage::Finished(Duplicates {
+        groups,
+        fingerprints,
+    }));
+}
+
+/// Starts searching for duplicates in the playlist in the background. Songs are compared by their
+/// name and artist and by their audio fingerprints.
+pub fn find_duplicates(state: &mut State, playlist_index: usize) {
+    if state.duplicates_job.is_running() {
+        return;
+    }
+    let songs = state.playlists[playlist_index].songs.clone();
+    let base_path = state.base_path.clone();
+    let fingerprints = std::mem::take(&mut state.fingerprints);
+    let sender = state.duplicates_job.start();
+    thread::spawn(move || run(songs, base_path, fingerprints, sender));
+
+    state.duplicates_playlist_name = state.playlists[playlist_index].name.clone();
+    state.push_status(StatusType::Info, "Searching for duplicates...".to_string());
+}
+
+pub fn update(state: &mut State) {
+    let result = job::update(state, |x| &mut x.duplicates_job, "Fingerprinting songs");
+    if let Some(duplicates) = result {
+        state.fingerprints = duplicates.fingerprints;
+        state.duplicate_groups = duplicates.groups;
+        state.push_status(
+            StatusType::Info,
+            format!(
+                "Found {} groups of duplicates",
+                state.duplicate_groups.len()
+            ),
+        );
+        state.show_duplicates = true;
+    }
+}
@@ -0,0 +1,205 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    app::{self, Song, State, StatusType},
+    util,
+};
+
+/// Folder inside the music directory that is used if no folder is entered
+const DEFAULT_DIRECTORY: &str = "export";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// `songs.csv`, `playlists.csv` and `history.csv`
+    Csv,
+    /// `library.json` with all data
+    Json,
+}
+
+/// Song with the data that is exported for it
+struct ExportedSong<'a> {
+    song: &'a Song,
+    album: &'a str,
+    plays: usize,
+    rating: Option<u8>,
+    note: &'a str,
+}
+
+/// Reads the whole history with the Unix time of each play, oldest first. The History playlist
+/// only contains the last entries.
+fn read_history(base_path: &str) -> Vec<(u64, String)> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(app::HISTORY_FILE_NAME);
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| x.split_once('\t'))
+        .map(|(time, path)| (time.parse().unwrap_or(0), path.to_string()))
+        .collect()
+}
+
+fn get_songs(state: &State) -> Vec<ExportedSong<'_>> {
+    let Some(all_playlist) = state
+        .playlists
+        .iter()
+        .find(|x| x.name == app::ALL_PLAYLIST_NAME)
+    else {
+        return Vec::new();
+    };
+    all_playlist
+        .songs
+        .iter()
+        .map(|song| ExportedSong {
+            song,
+            album: state
+                .tags
+                .get(&song.path)
+                .and_then(|x| x.album.as_deref())
+                .unwrap_or_default(),
+            plays: state.play_counts.get(&song.path).copied().unwrap_or(0),
+            rating: state.get_rating(&song.path),
+            note: state.notes.get(&song.path).map_or("", |x| x.as_str()),
+        })
+        .collect()
+}
+
+/// Writes the playlists, the songs with their play counts and the history into the folder for
+/// analysis in other programs.
+pub fn export_library(state: &mut State, directory: &str, format: ExportFormat) {
+    let directory = match directory.trim() {
+        "" => Path::new(&state.base_path).join(DEFAULT_DIRECTORY),
+        directory => Path::new(directory).to_path_buf(),
+    };
+    let history = read_history(&state.base_path);
+    let songs = get_songs(state);
+    let result = fs::create_dir_all(&directory).and_then(|_| match format {
+        ExportFormat::Csv => write_csv(state, &directory, &songs, &history),
+        ExportFormat::Json => write_json(state, &directory, &songs, &history),
+    });
+    match result {
+        Ok(()) => state.push_status(
+            StatusType::Info,
+            format!("Exported library to {}", directory.to_string_lossy()),
+        ),
+        Err(e) => state.push_status(StatusType::Error, format!("Could not export library:\n{e}")),
+    }
+}
+
+/// Playlists that were created by the user, the default playlists are exported as songs and
+/// history
+fn get_user_playlists(state: &State) -> impl Iterator<Item = &app::Playlist> {
+    state
+        .playlists
+        .iter()
+        .filter(|x| !util::is_default_playlist(&x.name))
+}
+
+fn to_csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn write_csv(
+    state: &State,
+    directory: &Path,
+    songs: &[ExportedSong],
+    history: &[(u64, String)],
+) -> io::Result<()> {
+    let mut text = "path,artist,title,album,duration_ms,plays,rating,note\n".to_string();
+    for exported in songs {
+        text += &format!(
+            "{},{},{},{},{},{},{},{}\n",
+            to_csv_field(&exported.song.path),
+            to_csv_field(&exported.song.artist),
+            to_csv_field(&exported.song.name),
+            to_csv_field(exported.album),
+            exported
+                .song
+                .duration
+                .map(|x| x.to_string())
+                .unwrap_or_default(),
+            exported.plays,
+            exported.rating.map(|x| x.to_string()).unwrap_or_default(),
+            to_csv_field(exported.note)
+        );
+    }
+    fs::write(directory.join("songs.csv"), text)?;
+
+    let mut text = "playlist,position,path\n".to_string();
+    for playlist in get_user_playlists(state) {
+        for (i, song) in playlist.songs.iter().enumerate() {
+            text += &format!(
+                "{},{},{}\n",
+                to_csv_field(&playlist.name),
+                i + 1,
+                to_csv_field(&song.path)
+            );
+        }
+    }
+    fs::write(directory.join("playlists.csv"), text)?;
+
+    let mut text = "unix_time,path\n".to_string();
+    for (time, path) in history {
+        text += &format!("{time},{}\n", to_csv_field(path));
+    }
+    fs::write(directory.join("history.csv"), text)
+}
+
+fn write_json(
+    state: &State,
+    directory: &Path,
+    songs: &[ExportedSong],
+    history: &[(u64, String)],
+) -> io::Result<()> {
+    let to_json_option = |x: Option<String>| x.unwrap_or_else(|| "null".to_string());
+    let songs: Vec<String> = songs
+        .iter()
+        .map(|x| {
+            format!(
+                "{{\"path\":{},\"artist\":{},\"title\":{},\"album\":{},\"duration_ms\":{},\"plays\":{},\"rating\":{},\"note\":{}}}",
+                util::to_json_string(&x.song.path),
+                util::to_json_string(&x.song.artist),
+                util::to_json_string(&x.song.name),
+                util::to_json_string(x.album),
+                to_json_option(x.song.duration.map(|x| x.to_string())),
+                x.plays,
+                to_json_option(x.rating.map(|x| x.to_string())),
+                util::to_json_string(x.note)
+            )
+        })
+        .collect();
+    let playlists: Vec<String> = get_user_playlists(state)
+        .map(|playlist| {
+            let paths: Vec<String> = playlist
+                .songs
+                .iter()
+                .map(|x| util::to_json_string(&x.path))
+                .collect();
+            format!(
+                "{{\"name\":{},\"songs\":[{}]}}",
+                util::to_json_string(&playlist.name),
+                paths.join(",")
+            )
+        })
+        .collect();
+    let history: Vec<String> = history
+        .iter()
+        .map(|(time, path)| {
+            format!(
+                "{{\"unix_time\":{time},\"path\":{}}}",
+                util::to_json_string(path)
+            )
+        })
+        .collect();
+    let text = format!(
+        "{{\"songs\":[{}],\"playlists\":[{}],\"history\":[{}]}}\n",
+        songs.join(","),
+        playlists.join(","),
+        history.join(",")
+    );
+    fs::write(directory.join("library.json"), text)
+}
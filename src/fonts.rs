@@ -0,0 +1,162 @@
+use std::{collections::BTreeSet, fs};
+
+use imgui::{FontAtlas, FontConfig, FontGlyphRanges, FontSource};
+
+use crate::app::State;
+
+const FONT_SIZE: f32 = 18.0;
+const SYMBOL_FONT_SIZE: f32 = 40.0;
+/// Characters below this are in the Latin blocks that the main font covers completely
+const FIRST_FALLBACK_CHARACTER: u32 = 0x250;
+
+/// Fonts of the system with CJK characters, symbols and emoji that are tried by default
+#[cfg(target_os = "windows")]
+const DEFAULT_FALLBACK_FONTS: &[&str] = &[
+    r"C:\Windows\Fonts\msyh.ttc",
+    r"C:\Windows\Fonts\YuGothM.ttc",
+    r"C:\Windows\Fonts\malgun.ttf",
+    r"C:\Windows\Fonts\seguisym.ttf",
+    r"C:\Windows\Fonts\seguiemj.ttf",
+];
+#[cfg(target_os = "macos")]
+const DEFAULT_FALLBACK_FONTS: &[&str] = &[
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/AppleSDGothicNeo.ttc",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const DEFAULT_FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoEmoji-Regular.ttf",
+];
+
+pub fn get_default_fallback_fonts() -> Vec<String> {
+    DEFAULT_FALLBACK_FONTS
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Fonts that are merged into the main font for the characters it doesn't contain. Only the
+/// characters that occur in the library are added to keep the font texture small, so the fonts
+/// are rebuilt when new ones show up. No fallback fonts are loaded until the first update.
+#[derive(Default)]
+pub struct FallbackFonts {
+    /// Paths from the settings that the fonts were loaded from, including unreadable ones
+    paths: Vec<String>,
+    data: Vec<Vec<u8>>,
+    characters: BTreeSet<char>,
+    /// Number of texts that were scanned for characters, checked to skip scanning
+    text_count: usize,
+}
+
+/// Returns the texts that are shown in the lists and panels.
+fn get_texts(state: &State) -> impl Iterator<Item = &str> {
+    let playlists = state.playlists.iter().flat_map(|playlist| {
+        std::iter::once(playlist.name.as_str()).chain(
+            playlist
+                .songs
+                .iter()
+                .flat_map(|x| [x.name.as_str(), x.artist.as_str()]),
+        )
+    });
+    let tags = state.tags.values().flat_map(|x| {
+        [&x.title, &x.artist, &x.album]
+            .into_iter()
+            .flatten()
+            .map(|x| x.as_str())
+    });
+    let lyrics = state.lyrics.iter().flatten().map(|x| x.text.as_str());
+    playlists
+        .chain(tags)
+        .chain(lyrics)
+        .chain(std::iter::once(state.song_search_text.as_str()))
+}
+
+/// Turns the characters into the zero terminated list of inclusive ranges that imgui expects.
+fn get_glyph_ranges(characters: &BTreeSet<char>) -> &'static [u32] {
+    let mut ranges: Vec<u32> = Vec::new();
+    for c in characters.iter().map(|x| *x as u32) {
+        match ranges.last_mut() {
+            Some(end) if *end + 1 == c => *end = c,
+            _ => ranges.extend([c, c]),
+        }
+    }
+    ranges.push(0);
+    // imgui reads the ranges whenever the atlas is built. They are only replaced when new
+    // characters show up, so leaking them is fine.
+    Vec::leak(ranges)
+}
+
+impl FallbackFonts {
+    /// Loads the fonts if their paths changed and collects new characters. Returns whether the
+    /// font atlas has to be rebuilt.
+    pub fn update(&mut self, state: &State) -> bool {
+        let mut is_changed = false;
+        if self.paths != state.settings.fallback_fonts {
+            self.paths = state.settings.fallback_fonts.clone();
+            self.data = self.paths.iter().filter_map(|x| fs::read(x).ok()).collect();
+            is_changed = true;
+        }
+
+        let text_count = state
+            .playlists
+            .iter()
+            .map(|x| x.songs.len() + 1)
+            .sum::<usize>()
+            + state.tags.len()
+            + state.lyrics.as_ref().map_or(0, |x| x.len())
+            + state.song_search_text.len();
+        if text_count != self.text_count {
+            self.text_count = text_count;
+            let character_count = self.characters.len();
+            self.characters.extend(
+                get_texts(state)
+                    .flat_map(|x| x.chars())
+                    .filter(|x| *x as u32 >= FIRST_FALLBACK_CHARACTER),
+            );
+            is_changed |= self.characters.len() != character_count;
+        }
+        is_changed && !self.data.is_empty()
+    }
+
+    /// Adds the main font with the fallback fonts merged into it and the font for large symbols.
+    /// The fonts are rasterized at the scale factor of the monitor to stay sharp.
+    pub fn add_to_atlas(&self, atlas: &mut FontAtlas, scale: f64) {
+        let font_size = FONT_SIZE * scale as f32;
+        let mut sources = vec![FontSource::TtfData {
+            data: include_bytes!("DejaVuSans.ttf"),
+            config: Some(FontConfig {
+                glyph_ranges: FontGlyphRanges::from_slice(&[1, 65535, 0]),
+                ..Default::default()
+            }),
+            size_pixels: font_size,
+        }];
+        if !self.characters.is_empty() {
+            let glyph_ranges = get_glyph_ranges(&self.characters);
+            // Glyphs that an earlier font already contains are skipped when merging
+            for data in &self.data {
+                sources.push(FontSource::TtfData {
+                    data,
+                    config: Some(FontConfig {
+                        glyph_ranges: FontGlyphRanges::from_slice(glyph_ranges),
+                        ..Default::default()
+                    }),
+                    size_pixels: font_size,
+                });
+            }
+        }
+        atlas.add_font(&sources);
+        atlas.add_font(&[FontSource::TtfData {
+            data: include_bytes!("NotoSansSymbols2-Regular.ttf"),
+            config: Some(FontConfig {
+                glyph_ranges: FontGlyphRanges::from_slice(&[1, 65535, 0]),
+                ..Default::default()
+            }),
+            size_pixels: SYMBOL_FONT_SIZE * scale as f32,
+        }]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+
+use glutin::{dpi::PhysicalPosition, event::Ime, window::Window};
+use imgui::sys;
+
+/// Position of the text cursor of the focused text field and its line height, set by imgui
+static INPUT_POSITION: Mutex<Option<([f32; 2], f32)>> = Mutex::new(None);
+
+unsafe extern "C" fn set_platform_ime_data(
+    _viewport: *mut sys::ImGuiViewport,
+    data: *mut sys::ImGuiPlatformImeData,
+) {
+    let data = &*data;
+    *INPUT_POSITION.lock().unwrap() = data
+        .WantVisible
+        .then_some(([data.InputPos.x, data.InputPos.y], data.InputLineHeight));
+}
+
+/// Returns the position of the text cursor and the line height while a text field is focused.
+pub fn get_input_position() -> Option<([f32; 2], f32)> {
+    *INPUT_POSITION.lock().unwrap()
+}
+
+/// Input methods for languages like Japanese, Chinese or Korean that compose characters from
+/// several key presses. Only enabled while a text field is focused so that the hotkeys still work.
+#[derive(Default)]
+pub struct InputMethod {
+    is_allowed: bool,
+    position: Option<[f32; 2]>,
+}
+
+pub fn init(io: &mut imgui::Io) -> InputMethod {
+    io.set_platform_ime_data_fn = Some(set_platform_ime_data);
+    InputMethod::default()
+}
+
+/// Passes the composed text to imgui and returns the text that is still being composed.
+pub fn handle_event(io: &mut imgui::Io, event: &Ime) -> String {
+    match event {
+        Ime::Preedit(text, _) => text.clone(),
+        Ime::Commit(text) => {
+            for c in text.chars() {
+                io.add_input_character(c);
+            }
+            String::new()
+        }
+        Ime::Enabled | Ime::Disabled => String::new(),
+    }
+}
+
+impl InputMethod {
+    /// Enables the input method while imgui wants text input and moves the candidate window
+    /// below the text cursor.
+    pub fn update(&mut self, window: &Window, wants_text_input: bool, hidpi_factor: f64) {
+        if self.is_allowed != wants_text_input {
+            self.is_allowed = wants_text_input;
+            window.set_ime_allowed(wants_text_input);
+        }
+        let position = get_input_position()
+            .filter(|_| wants_text_input)
+            .map(|(pos, line_height)| [pos[0], pos[1] + line_height]);
+        if position != self.position {
+            self.position = position;
+            if let Some([x, y]) = position {
+                window.set_ime_position(PhysicalPosition::new(
+                    x as f64 * hidpi_factor,
+                    y as f64 * hidpi_factor,
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::{Playlist, Song, State, StatusType, ALL_PLAYLIST_NAME},
+    download::{self, StreamingTrack},
+    playlist_file, util,
+};
+
+struct ImportedSong {
+    /// Path of the song in the other library
+    path: String,
+    artist: Option<String>,
+    name: Option<String>,
+}
+
+struct ImportedPlaylist {
+    name: String,
+    songs: Vec<ImportedSong>,
+}
+
+/// Returns the location of an iTunes or Rhythmbox library of the current user, if there is one.
+pub fn find_default_library() -> Option<String> {
+    let home = PathBuf::from(env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()?);
+    [
+        ".local/share/rhythmbox/playlists.xml",
+        "Music/iTunes/iTunes Music Library.xml",
+        "Music/iTunes/iTunes Library.xml",
+    ]
+    .iter()
+    .map(|x| home.join(x))
+    .find(|x| x.exists())
+    .map(|x| x.to_string_lossy().to_string())
+}
+
+fn read_itunes(path: &Path) -> Result<Vec<ImportedPlaylist>, String> {
+    let library = plist::Value::from_file(path).map_err(|e| e.to_string())?;
+    let library = library
+        .as_dictionary()
+        .ok_or("Not an iTunes library".to_string())?;
+    let tracks = library
+        .get("Tracks")
+        .and_then(|x| x.as_dictionary())
+        .ok_or("The library contains no tracks".to_string())?;
+
+    let mut playlists = Vec::new();
+    for playlist in library
+        .get("Playlists")
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_dictionary())
+    {
+        // Skip the whole library, built-in playlists like Music or Podcasts and folders
+        let is_special = ["Master", "Folder", "Smart Info"]
+            .iter()
+            .any(|x| playlist.contains_key(x))
+            || playlist.contains_key("Distinguished Kind");
+        let name = playlist.get("Name").and_then(|x| x.as_string());
+        let name = match name {
+            Some(name) if !is_special => name,
+            _ => continue,
+        };
+
+        let songs = playlist
+            .get("Playlist Items")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                let id = item.as_dictionary()?.get("Track ID")?.as_signed_integer()?;
+                let track = tracks.get(&id.to_string())?.as_dictionary()?;
+                let get_text = |key: &str| track.get(key).and_then(|x| x.as_string());
+                Some(ImportedSong {
+                    path: util::url_to_path(get_text("Location")?),
+                    artist: get_text("Artist").map(|x| x.to_string()),
+                    name: get_text("Name").map(|x| x.to_string()),
+                })
+            })
+            .collect();
+        playlists.push(ImportedPlaylist {
+            name: name.to_string(),
+            songs,
+        });
+    }
+    Ok(playlists)
+}
+
+fn get_attribute(element: &str, name: &str) -> Option<String> {
+    let start = element.find(&format!(" {name}=\""))? + name.len() + 3;
+    let end = start + element[start..].find('"')?;
+    Some(playlist_file::unescape_xml(&element[start..end]))
+}
+
+/// Reads the static playlists of Rhythmbox. Automatic playlists only contain their query.
+fn read_rhythmbox(path: &Path) -> Result<Vec<ImportedPlaylist>, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut playlists = Vec::new();
+    let mut rest = content.as_str();
+    while let Some(start) = rest.find("<playlist ") {
+        let tag_end = match rest[start..].find('>') {
+            Some(x) => start + x,
+            None => break,
+        };
+        // Empty playlists are written as self-closing elements
+        let end = if rest[..tag_end].ends_with('/') {
+            tag_end
+        } else {
+            rest[tag_end..]
+                .find("</playlist>")
+                .map_or(rest.len(), |x| tag_end + x)
+        };
+        let element = &rest[start..end];
+        rest = &rest[end..];
+
+        let tag_end = tag_end - start;
+        if get_attribute(&element[..tag_end], "type").as_deref() != Some("static") {
+            continue;
+        }
+        let name = match get_attribute(&element[..tag_end], "name") {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let mut songs = Vec::new();
+        let mut locations = &element[tag_end..];
+        while let Some(start) = locations.find("<location>") {
+            let start = start + "<location>".len();
+            let end = match locations[start..].find("</location>") {
+                Some(end) => start + end,
+                None => break,
+            };
+            songs.push(ImportedSong {
+                path: util::url_to_path(&playlist_file::unescape_xml(locations[start..end].trim())),
+                artist: None,
+                name: None,
+            });
+            locations = &locations[end..];
+        }
+        playlists.push(ImportedPlaylist { name, songs });
+    }
+    Ok(playlists)
+}
+
+/// Lookup of the local songs by their file name and by their artist and name
+struct LocalSongs<'a> {
+    by_file_name: HashMap<String, Vec<(&'a Song, String)>>,
+    by_tags: HashMap<(String, String), &'a Song>,
+}
+
+impl<'a> LocalSongs<'a> {
+    fn new(songs: &'a [Song]) -> LocalSongs<'a> {
+        let mut by_file_name: HashMap<String, Vec<(&Song, String)>> = HashMap::new();
+        let mut by_tags = HashMap::new();
+        for song in songs {
+            let path = song.path.replace('\\', "/").to_lowercase();
+            let file_name = path.rsplit('/').next().unwrap_or_default().to_string();
+            by_file_name
+                .entry(file_name)
+                .or_default()
+                .push((song, path));
+            by_tags
+                .entry((song.artist.to_lowercase(), song.name.to_lowercase()))
+                .or_insert(song);
+        }
+        LocalSongs {
+            by_file_name,
+            by_tags,
+        }
+    }
+
+    /// Finds the local song for a song of another library. Songs are matched by their file name,
+    /// preferring the one with the most matching parent folders, and otherwise by artist and name.
+    fn find(&self, imported: &ImportedSong) -> Option<&'a Song> {
+        let imported_path = imported.path.replace('\\', "/").to_lowercase();
+        let file_name = imported_path.rsplit('/').next().unwrap_or_default();
+        if let Some(candidates) = self.by_file_name.get(file_name) {
+            return candidates
+                .iter()
+                .max_by_key(|(_, path)| {
+                    path.rsplit('/')
+                        .zip(imported_path.rsplit('/'))
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                })
+                .map(|x| x.0);
+        }
+
+        let artist = imported.artist.as_ref()?.to_lowercase();
+        let name = imported.name.as_ref()?.to_lowercase();
+        self.by_tags.get(&(artist, name)).copied()
+    }
+}
+
+/// Imported playlists never replace existing ones, a number is added to the name if it is taken.
+fn get_unused_name(state: &State, name: &str) -> String {
+    let mut unused_name = name.to_string();
+    let mut number = 2;
+    while state.playlists.iter().any(|x| x.name == unused_name) {
+        unused_name = format!("{name} ({number})");
+        number += 1;
+    }
+    unused_name
+}
+
+/// Recreates the playlists of an iTunes library (`Library.xml`) or of Rhythmbox (`playlists.xml`)
+/// with the matching songs of the local library.
+pub fn import_library(state: &mut State, path: &str) {
+    let mut path = PathBuf::from(path.trim());
+    // The Rhythmbox database itself contains no playlists, they are stored next to it
+    if path.file_name().is_some_and(|x| x == "rhythmdb.xml") {
+        path.set_file_name("playlists.xml");
+    }
+    let is_rhythmbox = fs::read_to_string(&path).is_ok_and(|x| x.contains("<rhythmdb-playlists"));
+    let result = if is_rhythmbox {
+        read_rhythmbox(&path)
+    } else {
+        read_itunes(&path)
+    };
+    let imported_playlists = match result {
+        Ok(playlists) => playlists,
+        Err(e) => {
+            state.push_status(StatusType::Error, format!("Could not import library:\n{e}"));
+            return;
+        }
+    };
+
+    let all_songs = state
+        .playlists
+        .iter()
+        .find(|x| x.name == ALL_PLAYLIST_NAME)
+        .map_or(Vec::new(), |x| x.songs.clone());
+    let local_songs = LocalSongs::new(&all_songs);
+    let mut playlist_count = 0;
+    let mut song_count = 0;
+    let mut missing_count = 0;
+    for imported in imported_playlists {
+        let mut songs = Vec::new();
+        for imported_song in imported.songs.iter() {
+            match local_songs.find(imported_song) {
+                Some(song) => songs.push(song.clone()),
+                None => missing_count += 1,
+            }
+        }
+        if songs.is_empty() {
+            continue;
+        }
+
+        let name = get_unused_name(state, &imported.name);
+        song_count += songs.len();
+        playlist_count += 1;
+        let mut playlist = Playlist::new(name, songs);
+        playlist.original_hash = 0;
+        state.playlists.push(playlist);
+    }
+    state.sort_playlists();
+
+    state.push_status(
+        StatusType::Info,
+        format!(
+            "Imported {playlist_count} playlists with {song_count} songs, {missing_count} songs were not found"
+        ),
+    );
+}
+
+/// Splits CSV text into rows of fields, fields in quotes may contain commas, quotes and line
+/// breaks.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if is_quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => is_quoted = !is_quoted,
+            ',' if !is_quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !is_quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !is_quoted => (),
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Reads the tracks of a playlist that was exported as CSV, like by Exportify for Spotify or by
+/// TuneMyMusic. The columns are found by their names in the first row.
+fn read_csv(path: &Path) -> Result<Vec<StreamingTrack>, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let rows = parse_csv(content.trim_start_matches('\u{feff}'));
+    let Some(header) = rows.first() else {
+        return Err("The file is empty".to_string());
+    };
+    let find_column = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            header
+                .iter()
+                .position(|x| x.trim().eq_ignore_ascii_case(name))
+        })
+    };
+    let title_column = find_column(&["Track Name", "Title", "Name", "Song", "Track"])
+        .ok_or("The file has no column with the song titles")?;
+    let artist_column = find_column(&["Artist Name(s)", "Artist Name", "Artist", "Artists"]);
+    Ok(rows
+        .iter()
+        .skip(1)
+        .filter_map(|row| {
+            let title = row.get(title_column)?.trim();
+            (!title.is_empty()).then(|| StreamingTrack {
+                artist: artist_column
+                    .and_then(|x| row.get(x))
+                    .map_or(String::new(), |x| x.trim().to_string()),
+                title: title.to_string(),
+                url: None,
+            })
+        })
+        .collect())
+}
+
+/// Removes parts in parentheses or brackets like "(Official Video)" or "[Remastered]".
+fn remove_title_additions(title: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            c if depth == 0 => result.push(c),
+            _ => (),
+        }
+    }
+    result.trim().to_string()
+}
+
+/// Finds the local song of a track by its artist and title. Several artists are separated by
+/// commas or semicolons, the first one is also tried alone.
+fn find_track<'a>(local_songs: &LocalSongs<'a>, track: &StreamingTrack) -> Option<&'a Song> {
+    let first_artist = track
+        .artist
+        .split([',', ';'])
+        .next()
+        .unwrap_or_default()
+        .trim();
+    let cleaned_title = remove_title_additions(&track.title);
+    [track.artist.as_str(), first_artist]
+        .into_iter()
+        .flat_map(|artist| {
+            [
+                (artist, track.title.as_str()),
+                (artist, cleaned_title.as_str()),
+            ]
+        })
+        .find_map(|(artist, title)| {
+            local_songs.find(&ImportedSong {
+                path: String::new(),
+                artist: Some(artist.to_string()),
+                name: Some(title.to_string()),
+            })
+        })
+}
+
+/// Imports a playlist of a streaming service from a YouTube or YouTube Music playlist URL or from a
+/// CSV file.
+pub fn import_streaming_playlist(state: &mut State, input: &str) {
+    let input = input.trim();
+    if input.starts_with("http://") || input.starts_with("https://") {
+        if input.contains("spotify.com") {
+            state.push_status(
+                StatusType::Error,
+                "Spotify playlists can't be read directly, export the playlist as CSV (for example with Exportify) and import the file".to_string(),
+            );
+            return;
+        }
+        download::start_listing_playlist(state, input);
+        return;
+    }
+
+    let path = Path::new(input);
+    match read_csv(path) {
+        Ok(tracks) => {
+            let name = path
+                .file_stem()
+                .map_or("Imported playlist".to_string(), |x| {
+                    x.to_string_lossy().to_string()
+                });
+            import_streaming_tracks(state, &name, tracks);
+        }
+        Err(e) => state.push_status(
+            StatusType::Error,
+            format!("Could not import playlist:\n{e}"),
+        ),
+    }
+}
+
+/// Creates a playlist with the tracks that are found in the library. The missing tracks are
+/// offered for download, which adds them to the new playlist.
+pub fn import_streaming_tracks(state: &mut State, name: &str, tracks: Vec<StreamingTrack>) {
+    let all_songs = state
+        .playlists
+        .iter()
+        .find(|x| x.name == ALL_PLAYLIST_NAME)
+        .map_or(Vec::new(), |x| x.songs.clone());
+    let local_songs = LocalSongs::new(&all_songs);
+    let mut songs = Vec::new();
+    let mut missing_tracks = Vec::new();
+    for track in tracks {
+        match find_track(&local_songs, &track) {
+            Some(song) => songs.push(song.clone()),
+            None => missing_tracks.push(track),
+        }
+    }
+
+    let name = get_unused_name(state, name);
+    state.push_status(
+        StatusType::Info,
+        format!(
+            "Imported {name} with {} songs, {} songs were not found",
+            songs.len(),
+            missing_tracks.len()
+        ),
+    );
+    let mut playlist = Playlist::new(name.clone(), songs);
+    playlist.original_hash = 0;
+    state.playlists.push(playlist);
+    state.sort_playlists();
+    if !missing_tracks.is_empty() {
+        state.missing_streaming_tracks = Some((name, missing_tracks));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use glutin::event_loop::EventLoopProxy;
+
+use crate::cli::CliCommand;
+
+#[cfg(not(unix))]
+use std::net::{TcpListener as Listener, TcpStream as Stream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener as Listener, UnixStream as Stream};
+
+/// Port on the loopback interface that the running instance listens on where Unix sockets are not
+/// available
+#[cfg(not(unix))]
+const INSTANCE_ADDRESS: &str = "127.0.0.1:47811";
+
+#[cfg(unix)]
+fn get_socket_path() -> std::path::PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("implayer.sock")
+}
+
+#[cfg(unix)]
+fn connect() -> io::Result<Stream> {
+    Stream::connect(get_socket_path())
+}
+
+#[cfg(not(unix))]
+fn connect() -> io::Result<Stream> {
+    Stream::connect(INSTANCE_ADDRESS)
+}
+
+#[cfg(unix)]
+fn bind() -> io::Result<Listener> {
+    let path = get_socket_path();
+    // The socket file of an instance that crashed is left behind
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    Listener::bind(path)
+}
+
+#[cfg(not(unix))]
+fn bind() -> io::Result<Listener> {
+    Listener::bind(INSTANCE_ADDRESS)
+}
+
+/// Sends the commands to an instance that is already running, which shows its window if there are
+/// no commands. Returns false if no instance is running.
+pub fn send_to_running_instance(commands: &[CliCommand]) -> bool {
+    let Ok(mut stream) = connect() else {
+        return false;
+    };
+    let lines: Vec<String> = if commands.is_empty() {
+        vec![CliCommand::Show.to_line()]
+    } else {
+        commands.iter().map(|x| x.to_line()).collect()
+    };
+    for line in lines {
+        if writeln!(stream, "{line}").is_err() {
+            eprintln!("Could not send the command to the running instance");
+            break;
+        }
+    }
+    true
+}
+
+/// Receives the commands of instances that are started later in the background.
+pub fn listen(proxy: EventLoopProxy<()>) -> io::Result<Receiver<CliCommand>> {
+    let listener = bind()?;
+    let (command_tx, command_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|x| x.ok()) {
+            receive_commands(stream, &command_tx, &proxy);
+        }
+    });
+    Ok(command_rx)
+}
+
+fn receive_commands(
+    stream: impl Read,
+    command_tx: &Sender<CliCommand>,
+    proxy: &EventLoopProxy<()>,
+) {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if let Some(command) = CliCommand::parse_line(line.trim_end()) {
+            if command_tx.send(command).is_err() {
+                return;
+            }
+            // Wakes up the event loop so that the command is handled immediately
+            let _ = proxy.send_event(());
+        }
+    }
+}
@@ -0,0 +1,35 @@
+pub use implayer_core::job::*;
+
+use crate::app::{State, StatusType};
+
+/// Shows the progress and the errors of the job as statuses, the progress is labeled with the
+/// name. Returns the result once the job finished.
+pub fn update<T>(
+    state: &mut State,
+    get_job: fn(&mut State) -> &mut Job<T>,
+    name: &str,
+) -> Option<T> {
+    let mut result = None;
+    for message in get_job(state).receive() {
+        match message {
+            JobMessage::Progress { done, total, item } => {
+                state
+                    .status_queue
+                    .retain(|x| !matches!(x.r#type, StatusType::Progress));
+                let text = match item {
+                    Some(item) => format!("{name} {done}/{total}: {item}"),
+                    None => format!("{name}: {done}/{total}"),
+                };
+                state.push_status(StatusType::Progress, text);
+            }
+            JobMessage::Error(e) => state.push_status(StatusType::Error, e),
+            JobMessage::Finished(value) => {
+                state
+                    .status_queue
+                    .retain(|x| !matches!(x.r#type, StatusType::Progress));
+                result = Some(value);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+
+use imgui::{Key, Ui};
+
+/// Keys that only modify other keys and can't be bound on their own
+const MODIFIER_KEYS: [Key; 8] = [
+    Key::LeftCtrl,
+    Key::LeftShift,
+    Key::LeftAlt,
+    Key::LeftSuper,
+    Key::RightCtrl,
+    Key::RightShift,
+    Key::RightAlt,
+    Key::RightSuper,
+];
+
+/// Keys of the vim mode with their descriptions, they are handled separately from the keymap
+pub const VIM_KEYS: [(&str, &str); 7] = [
+    ("gg", "Select first song"),
+    ("G", "Select last song"),
+    ("/", "Focus search field"),
+    ("dd", "Remove selected songs"),
+    ("yy", "Copy selected songs"),
+    ("p", "Paste songs"),
+    ("x", "Play selected songs next"),
+];
+
+/// Actions that can be triggered with keyboard shortcuts
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    PlayPause,
+    Next,
+    Prev,
+    ToggleMute,
+    SelectPrevious,
+    SelectNext,
+    ExtendSelectionUp,
+    ExtendSelectionDown,
+    SwitchPane,
+    PlaySelection,
+    MoveSelectionUp,
+    MoveSelectionDown,
+    SelectAll,
+    RemoveSelection,
+    Cut,
+    Copy,
+    Paste,
+    Queue,
+    FocusSearch,
+    RenamePlaylist,
+    SavePlaylist,
+    SaveAllPlaylists,
+    ToggleLyrics,
+    ToggleNowPlaying,
+    ToggleAlwaysOnTop,
+    ShowShortcutHelp,
+}
+
+/// Area of the player that a command belongs to, used to group the shortcut help
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CommandGroup {
+    Playback,
+    Selection,
+    Editing,
+    Window,
+}
+
+impl CommandGroup {
+    pub const ALL: [CommandGroup; 4] = [
+        CommandGroup::Playback,
+        CommandGroup::Selection,
+        CommandGroup::Editing,
+        CommandGroup::Window,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandGroup::Playback => "Playback",
+            CommandGroup::Selection => "Selection",
+            CommandGroup::Editing => "Playlist editing",
+            CommandGroup::Window => "Window",
+        }
+    }
+}
+
+impl Command {
+    pub const ALL: [Command; 26] = [
+        Command::PlayPause,
+        Command::Next,
+        Command::Prev,
+        Command::ToggleMute,
+        Command::SelectPrevious,
+        Command::SelectNext,
+        Command::ExtendSelectionUp,
+        Command::ExtendSelectionDown,
+        Command::SwitchPane,
+        Command::PlaySelection,
+        Command::MoveSelectionUp,
+        Command::MoveSelectionDown,
+        Command::SelectAll,
+        Command::RemoveSelection,
+        Command::Cut,
+        Command::Copy,
+        Command::Paste,
+        Command::Queue,
+        Command::FocusSearch,
+        Command::RenamePlaylist,
+        Command::SavePlaylist,
+        Command::SaveAllPlaylists,
+        Command::ToggleLyrics,
+        Command::ToggleNowPlaying,
+        Command::ToggleAlwaysOnTop,
+        Command::ShowShortcutHelp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::PlayPause => "Resume/pause playback",
+            Command::Next => "Play next song",
+            Command::Prev => "Play previous song",
+            Command::ToggleMute => "Mute/unmute",
+            Command::SelectPrevious => "Select previous song or playlist",
+            Command::SelectNext => "Select next song or playlist",
+            Command::ExtendSelectionUp => "Extend selection up",
+            Command::ExtendSelectionDown => "Extend selection down",
+            Command::SwitchPane => "Switch between playlists and songs",
+            Command::PlaySelection => "Play selected song or playlist",
+            Command::MoveSelectionUp => "Move selected songs up",
+            Command::MoveSelectionDown => "Move selected songs down",
+            Command::SelectAll => "Select all songs",
+            Command::RemoveSelection => "Remove selected songs",
+            Command::Cut => "Cut selected songs",
+            Command::Copy => "Copy selected songs",
+            Command::Paste => "Paste songs",
+            Command::Queue => "Play selected songs next",
+            Command::FocusSearch => "Focus search field",
+            Command::RenamePlaylist => "Rename selected playlist",
+            Command::SavePlaylist => "Save selected playlist",
+            Command::SaveAllPlaylists => "Save all playlists",
+            Command::ToggleLyrics => "Toggle lyrics panel",
+            Command::ToggleNowPlaying => "Toggle Now Playing panel",
+            Command::ToggleAlwaysOnTop => "Toggle always on top",
+            Command::ShowShortcutHelp => "Show keyboard shortcuts",
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Command::PlayPause => "play_pause",
+            Command::Next => "next",
+            Command::Prev => "prev",
+            Command::ToggleMute => "toggle_mute",
+            Command::SelectPrevious => "select_previous",
+            Command::SelectNext => "select_next",
+            Command::ExtendSelectionUp => "extend_selection_up",
+            Command::ExtendSelectionDown => "extend_selection_down",
+            Command::SwitchPane => "switch_pane",
+            Command::PlaySelection => "play_selection",
+            Command::MoveSelectionUp => "move_selection_up",
+            Command::MoveSelectionDown => "move_selection_down",
+            Command::SelectAll => "select_all",
+            Command::RemoveSelection => "remove_selection",
+            Command::Cut => "cut",
+            Command::Copy => "copy",
+            Command::Paste => "paste",
+            Command::Queue => "queue",
+            Command::FocusSearch => "focus_search",
+            Command::RenamePlaylist => "rename_playlist",
+            Command::SavePlaylist => "save_playlist",
+            Command::SaveAllPlaylists => "save_all_playlists",
+            Command::ToggleLyrics => "toggle_lyrics",
+            Command::ToggleNowPlaying => "toggle_now_playing",
+            Command::ToggleAlwaysOnTop => "toggle_always_on_top",
+            Command::ShowShortcutHelp => "show_shortcut_help",
+        }
+    }
+
+    pub fn group(&self) -> CommandGroup {
+        match self {
+            Command::PlayPause
+            | Command::Next
+            | Command::Prev
+            | Command::ToggleMute
+            | Command::PlaySelection => CommandGroup::Playback,
+            Command::SelectPrevious
+            | Command::SelectNext
+            | Command::ExtendSelectionUp
+            | Command::ExtendSelectionDown
+            | Command::SelectAll
+            | Command::FocusSearch => CommandGroup::Selection,
+            Command::MoveSelectionUp
+            | Command::MoveSelectionDown
+            | Command::RemoveSelection
+            | Command::Cut
+            | Command::Copy
+            | Command::Paste
+            | Command::Queue
+            | Command::RenamePlaylist
+            | Command::SavePlaylist
+            | Command::SaveAllPlaylists => CommandGroup::Editing,
+            Command::SwitchPane
+            | Command::ToggleLyrics
+            | Command::ToggleNowPlaying
+            | Command::ToggleAlwaysOnTop
+            | Command::ShowShortcutHelp => CommandGroup::Window,
+        }
+    }
+
+    /// Whether holding the keys repeats the command, like moving through the list.
+    fn is_repeating(&self) -> bool {
+        matches!(
+            self,
+            Command::SelectPrevious
+                | Command::SelectNext
+                | Command::ExtendSelectionUp
+                | Command::ExtendSelectionDown
+                | Command::MoveSelectionUp
+                | Command::MoveSelectionDown
+        )
+    }
+
+    fn get_default_bindings(&self) -> Vec<KeyBinding> {
+        let plain = KeyBinding::new;
+        let ctrl = |key| KeyBinding {
+            ctrl: true,
+            ..KeyBinding::new(key)
+        };
+        let shift = |key| KeyBinding {
+            shift: true,
+            ..KeyBinding::new(key)
+        };
+        let alt = |key| KeyBinding {
+            alt: true,
+            ..KeyBinding::new(key)
+        };
+        match self {
+            Command::PlayPause => vec![plain(Key::Space)],
+            Command::Next => vec![ctrl(Key::RightArrow)],
+            Command::Prev => vec![ctrl(Key::LeftArrow)],
+            Command::ToggleMute => vec![plain(Key::M)],
+            Command::SelectPrevious => vec![plain(Key::UpArrow), plain(Key::K)],
+            Command::SelectNext => vec![plain(Key::DownArrow), plain(Key::J)],
+            Command::ExtendSelectionUp => vec![shift(Key::UpArrow)],
+            Command::ExtendSelectionDown => vec![shift(Key::DownArrow)],
+            Command::SwitchPane => vec![plain(Key::Tab)],
+            Command::PlaySelection => vec![plain(Key::Enter), plain(Key::KeypadEnter)],
+            Command::MoveSelectionUp => vec![alt(Key::UpArrow)],
+            Command::MoveSelectionDown => vec![alt(Key::DownArrow)],
+            Command::SelectAll => vec![ctrl(Key::A)],
+            Command::RemoveSelection => vec![plain(Key::Delete)],
+            Command::Cut => vec![ctrl(Key::X)],
+            Command::Copy => vec![ctrl(Key::C)],
+            Command::Paste => vec![ctrl(Key::V)],
+            // Available through x in the vim mode and the context menu
+            Command::Queue => vec![],
+            Command::FocusSearch => vec![ctrl(Key::F)],
+            Command::RenamePlaylist => vec![plain(Key::F2)],
+            Command::SavePlaylist => vec![ctrl(Key::S)],
+            Command::SaveAllPlaylists => vec![KeyBinding {
+                shift: true,
+                ..ctrl(Key::S)
+            }],
+            Command::ToggleLyrics => vec![plain(Key::L)],
+            Command::ToggleNowPlaying => vec![plain(Key::N)],
+            Command::ToggleAlwaysOnTop => vec![ctrl(Key::T)],
+            // The question mark on most keyboard layouts
+            Command::ShowShortcutHelp => vec![shift(Key::Slash)],
+        }
+    }
+}
+
+/// A key with the modifiers that have to be held, all other modifiers must be released
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: Key,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// Name of the key like `Space`, `F2` or `7`.
+fn get_key_name(key: Key) -> String {
+    let name = format!("{key:?}");
+    match name.strip_prefix("Alpha") {
+        Some(digit) => digit.to_string(),
+        None => name,
+    }
+}
+
+/// Keys that can be bound, without the modifiers, gamepad and mouse buttons.
+fn get_bindable_keys() -> impl Iterator<Item = Key> {
+    Key::VARIANTS
+        .into_iter()
+        .take_while(|x| *x != Key::GamepadStart)
+        .filter(|x| !MODIFIER_KEYS.contains(x))
+}
+
+impl KeyBinding {
+    fn new(key: Key) -> KeyBinding {
+        KeyBinding {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    /// Returns the binding like `Ctrl+Shift+S`.
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        for (is_held, modifier) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+        ] {
+            if is_held {
+                name.push_str(modifier);
+            }
+        }
+        name.push_str(&get_key_name(self.key));
+        name
+    }
+
+    /// Parses a binding like `Ctrl+Shift+S`, the modifiers can be in any order.
+    pub fn parse(text: &str) -> Option<KeyBinding> {
+        let mut parts: Vec<&str> = text.split('+').map(|x| x.trim()).collect();
+        let key_name = parts.pop()?;
+        let mut binding =
+            KeyBinding::new(get_bindable_keys().find(|x| get_key_name(*x) == key_name)?);
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" => binding.alt = true,
+                _ => return None,
+            }
+        }
+        Some(binding)
+    }
+
+    /// Returns the character that the key types if no modifier besides Shift is held.
+    pub fn get_character(&self) -> Option<char> {
+        if self.ctrl || self.alt {
+            return None;
+        }
+        let name = get_key_name(self.key);
+        match self.key {
+            Key::Space => Some(' '),
+            _ if name.len() == 1 => name.to_lowercase().chars().next(),
+            _ => None,
+        }
+    }
+
+    fn is_pressed(&self, ui: &Ui, is_repeating: bool) -> bool {
+        let io = ui.io();
+        io.key_ctrl == self.ctrl
+            && io.key_shift == self.shift
+            && io.key_alt == self.alt
+            && if is_repeating {
+                ui.is_key_pressed(self.key)
+            } else {
+                ui.is_key_pressed_no_repeat(self.key)
+            }
+    }
+}
+
+/// Returns the key that was pressed in this frame together with the held modifiers, used to
+/// record a new binding.
+pub fn get_pressed_binding(ui: &Ui) -> Option<KeyBinding> {
+    let key = get_bindable_keys().find(|x| ui.is_key_pressed_no_repeat(*x))?;
+    Some(KeyBinding {
+        key,
+        ctrl: ui.io().key_ctrl,
+        shift: ui.io().key_shift,
+        alt: ui.io().key_alt,
+    })
+}
+
+/// Key bindings of the commands, stored in the settings file as lines like
+/// `shortcut.play_pause=Space` with multiple bindings separated by commas
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: HashMap<Command, Vec<KeyBinding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            bindings: Command::ALL
+                .into_iter()
+                .map(|x| (x, x.get_default_bindings()))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn get(&self, command: Command) -> &[KeyBinding] {
+        self.bindings
+            .get(&command)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn set(&mut self, command: Command, bindings: Vec<KeyBinding>) {
+        self.bindings.insert(command, bindings);
+    }
+
+    /// Sets the bindings of the command from its settings value. Unknown commands and invalid
+    /// bindings are ignored.
+    pub fn parse_bindings(&mut self, command_key: &str, value: &str) {
+        if let Some(command) = Command::ALL.into_iter().find(|x| x.key() == command_key) {
+            let bindings = value.split(',').filter_map(KeyBinding::parse).collect();
+            self.set(command, bindings);
+        }
+    }
+
+    /// Returns the lines of the settings file.
+    pub fn to_settings_lines(&self) -> String {
+        Command::ALL
+            .iter()
+            .map(|command| {
+                let bindings: Vec<String> = self.get(*command).iter().map(|x| x.name()).collect();
+                format!("shortcut.{}={}\n", command.key(), bindings.join(","))
+            })
+            .collect()
+    }
+
+    /// Whether a binding of the command was pressed. Bindings that type characters are skipped
+    /// while the typed characters are used to search.
+    pub fn is_pressed(&self, ui: &Ui, command: Command, is_typing: bool) -> bool {
+        self.get(command).iter().any(|x| {
+            !(is_typing && x.get_character().is_some()) && x.is_pressed(ui, command.is_repeating())
+        })
+    }
+
+    /// Whether the character is the shortcut of a command when it is typed on its own.
+    pub fn is_character_bound(&self, c: char) -> bool {
+        self.bindings
+            .values()
+            .flatten()
+            .any(|x| !x.shift && x.get_character() == Some(c))
+    }
+
+    /// Returns the other commands that have the binding.
+    pub fn get_conflicts(&self, command: Command, binding: KeyBinding) -> Vec<Command> {
+        Command::ALL
+            .into_iter()
+            .filter(|x| *x != command && self.get(*x).contains(&binding))
+            .collect()
+    }
+}
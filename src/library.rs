@@ -0,0 +1,280 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::SystemTime,
+};
+
+use implayer_core::model;
+
+use crate::{
+    actions,
+    app::{self, Playlist, Song, State, StatusType},
+    play_counts, player,
+    playlist_file::{self, PlaylistFormat},
+    tags, util,
+};
+
+/// Songs listed in the error about songs that could not be read
+const UNREADABLE_SONGS_SHOWN: usize = 10;
+
+pub enum LibraryMessage {
+    /// Description of the current step, shown below the playlists
+    Progress(String),
+    Playlist(Playlist),
+    Error(String),
+    /// Songs of the All, All Unused and History playlists, the times the song files were created
+    /// and the play counts
+    Finished {
+        songs: Vec<Song>,
+        unused_songs: Vec<Song>,
+        history_songs: Vec<Song>,
+        added_times: HashMap<String, SystemTime>,
+        play_counts: HashMap<String, usize>,
+    },
+}
+
+/// Reads the song paths of the last entries of the history, newest first. The history file
+/// contains the oldest entries first and is shortened to `HISTORY_LIMIT` entries. It is read before
+/// the songs, so that songs played while loading are not read again.
+fn read_history(base_path: &str, sender: &Sender<LibraryMessage>) -> Vec<String> {
+    let history_path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(app::HISTORY_FILE_NAME);
+    let history = fs::read_to_string(&history_path).unwrap_or_default();
+    let history_paths: Vec<&str> = history
+        .lines()
+        .filter_map(|x| x.split_once('\t').map(|x| x.1))
+        .collect();
+    if history_paths.len() > app::HISTORY_LIMIT {
+        let lines: Vec<&str> = history.lines().collect();
+        if let Err(e) = fs::write(
+            &history_path,
+            lines[lines.len() - app::HISTORY_LIMIT..].join("\n") + "\n",
+        ) {
+            let _ = sender.send(LibraryMessage::Error(format!(
+                "Could not shorten the history:\n{e}"
+            )));
+        }
+    }
+    history_paths
+        .iter()
+        .rev()
+        .take(app::HISTORY_LIMIT)
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Returns the songs of the history entries, songs that don't exist anymore are kept as missing.
+fn get_history_songs(base_path: &str, history_paths: &[String], songs: &[Song]) -> Vec<Song> {
+    history_paths
+        .iter()
+        .map(|path| match songs.iter().find(|x| x.path == *path) {
+            Some(song) => song.clone(),
+            None => Song::new(PathBuf::from(base_path).join(path), base_path, None),
+        })
+        .collect()
+}
+
+/// Parses the playlists and reads the durations of the songs. Stops once the receiver is dropped.
+fn run(base_path: String, sender: Sender<LibraryMessage>) {
+    // Play counts that were not saved yet are counted before the history file is shortened
+    let play_counts =
+        play_counts::load(&base_path).unwrap_or_else(|| play_counts::count_history(&base_path));
+    let history_paths = read_history(&base_path, &sender);
+    let mut song_paths = Vec::new();
+    app::find_song_files(Path::new(&base_path), &mut song_paths);
+    song_paths.sort();
+    let mut songs: Vec<Song> = song_paths
+        .into_iter()
+        .map(|path| Song::new(path, &base_path, None))
+        .collect();
+
+    // Playlists that can't be read are skipped so that they are not overwritten
+    let mut playlist_paths: Vec<PathBuf> = match fs::read_dir(&base_path) {
+        Ok(entries) => entries
+            .filter_map(|x| x.ok())
+            .filter(|x| x.file_type().is_ok_and(|x| x.is_file()))
+            .map(|x| x.path())
+            .filter(|x| !playlist_file::is_backup(x) && PlaylistFormat::from_path(x).is_some())
+            .collect(),
+        Err(e) => {
+            let _ = sender.send(LibraryMessage::Error(format!(
+                "Could not read the music directory:\n{e}"
+            )));
+            Vec::new()
+        }
+    };
+    // Of playlists with the same name in several formats, the file that is saved to is loaded
+    playlist_paths.sort_by_key(|x| {
+        let format = PlaylistFormat::from_path(x);
+        let format_index = PlaylistFormat::ALL.iter().position(|x| Some(*x) == format);
+        (x.file_stem().map(|x| x.to_os_string()), format_index)
+    });
+    let mut playlist_names = HashSet::new();
+    // Paths of the songs in the playlists, the other songs are unused
+    let mut used_paths = HashSet::new();
+    for (i, path) in playlist_paths.iter().enumerate() {
+        let progress = format!("Loading playlists {}/{}", i + 1, playlist_paths.len());
+        if sender.send(LibraryMessage::Progress(progress)).is_err() {
+            return;
+        }
+        let name = path.file_stem().unwrap_or_default().to_os_string();
+        if !playlist_names.insert(name) {
+            let _ = sender.send(LibraryMessage::Error(format!(
+                "Skipped {} because another playlist file has the same name",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            )));
+            continue;
+        }
+        match playlist_file::load(&base_path, path, &mut songs) {
+            Ok(playlist) => {
+                used_paths.extend(model::get_song_paths([&playlist]));
+                if sender.send(LibraryMessage::Playlist(playlist)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = sender.send(LibraryMessage::Error(e));
+            }
+        }
+    }
+
+    // Songs that can't be read are left out, with the error of each path
+    let mut unused_songs = Vec::new();
+    let mut unreadable_songs: Vec<(String, String)> = Vec::new();
+    let progress = format!("Reading {} songs", songs.len());
+    if sender.send(LibraryMessage::Progress(progress)).is_err() {
+        return;
+    }
+    for song in songs.iter_mut() {
+        if used_paths.contains(&song.path) {
+            continue;
+        }
+        match player::get_duration(&Path::new(&base_path).join(&song.path)) {
+            Ok(duration) => song.duration = Some(duration),
+            Err(e) => {
+                unreadable_songs.push((song.path.clone(), e));
+                continue;
+            }
+        }
+        unused_songs.push(song.clone());
+    }
+    if !unreadable_songs.is_empty() {
+        let unreadable_paths: HashSet<&String> = unreadable_songs.iter().map(|x| &x.0).collect();
+        songs.retain(|x| !unreadable_paths.contains(&x.path));
+        let lines: Vec<String> = unreadable_songs
+            .iter()
+            .take(UNREADABLE_SONGS_SHOWN)
+            .map(|(path, e)| format!("{path}: {e}"))
+            .collect();
+        let _ = sender.send(LibraryMessage::Error(format!(
+            "Skipped {} songs that could not be read:\n{}",
+            unreadable_songs.len(),
+            lines.join("\n")
+        )));
+    }
+
+    let added_times = songs
+        .iter()
+        .filter_map(|song| {
+            let metadata = fs::metadata(Path::new(&base_path).join(&song.path)).ok()?;
+            // The creation time is not available on all file systems
+            let time = metadata.created().or_else(|_| metadata.modified()).ok()?;
+            Some((song.path.clone(), time))
+        })
+        .collect();
+    let history_songs = get_history_songs(&base_path, &history_paths, &songs);
+    let _ = sender.send(LibraryMessage::Finished {
+        songs,
+        unused_songs,
+        history_songs,
+        added_times,
+        play_counts,
+    });
+}
+
+/// Starts loading the playlists and songs of the music directory in the background. The default
+/// playlists stay empty until all songs are read.
+pub fn start_loading(state: &mut State) {
+    for name in [
+        app::ALL_PLAYLIST_NAME,
+        app::ALL_UNUSED_PLAYLIST_NAME,
+        app::HISTORY_PLAYLIST_NAME,
+    ] {
+        state
+            .playlists
+            .push(Playlist::new(name.to_string(), Vec::new()));
+    }
+    let base_path = state.base_path.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || run(base_path, sender));
+    state.library_rx = Some(receiver);
+    state.library_progress = "Loading library...".to_string();
+}
+
+/// Replaces the songs of the default playlist.
+fn set_default_playlist_songs(state: &mut State, name: &str, songs: Vec<Song>) {
+    if let Some(playlist) = state.playlists.iter_mut().find(|x| x.name == name) {
+        *playlist = Playlist::new(name.to_string(), songs);
+    }
+    state.mark_playlists_changed();
+}
+
+/// Adds the loaded playlists to the state.
+pub fn update(state: &mut State) {
+    let messages = match state.library_rx {
+        Some(ref receiver) => util::receive_all(receiver),
+        None => return,
+    };
+
+    for message in messages {
+        match message {
+            LibraryMessage::Progress(progress) => state.library_progress = progress,
+            LibraryMessage::Playlist(playlist) => {
+                state.playlists.push(playlist);
+                state.sort_playlists();
+            }
+            LibraryMessage::Error(e) => state.push_status(StatusType::Error, e),
+            LibraryMessage::Finished {
+                songs,
+                unused_songs,
+                history_songs,
+                added_times,
+                play_counts,
+            } => {
+                state.tags_rx = Some(tags::start_loading(
+                    &state.base_path,
+                    songs.iter().map(|x| x.path.clone()).collect(),
+                ));
+                set_default_playlist_songs(state, app::ALL_PLAYLIST_NAME, songs);
+                set_default_playlist_songs(state, app::ALL_UNUSED_PLAYLIST_NAME, unused_songs);
+                // Songs played while loading stay in front of the songs read from the history
+                let mut played_songs = state
+                    .playlists
+                    .iter()
+                    .find(|x| x.name == app::HISTORY_PLAYLIST_NAME)
+                    .map_or(Vec::new(), |x| x.songs.clone());
+                played_songs.extend(history_songs);
+                played_songs.truncate(app::HISTORY_LIMIT);
+                set_default_playlist_songs(state, app::HISTORY_PLAYLIST_NAME, played_songs);
+                // Songs played while loading were already counted
+                for (path, count) in play_counts {
+                    *state.play_counts.entry(path).or_insert(0) += count;
+                }
+                state.mark_song_data_changed();
+                play_counts::save(state);
+                // Songs added while loading keep their time
+                for (path, time) in added_times {
+                    state.song_added_times.entry(path).or_insert(time);
+                }
+                actions::update_recently_added(state);
+                state.sort_playlists();
+                state.library_rx = None;
+                state.library_progress.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{fs, fs::File, path::Path};
+
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+
+fn find_lyrics_tag(revision: &MetadataRevision) -> Option<String> {
+    revision
+        .tags()
+        .iter()
+        .find(|x| x.std_key == Some(StandardTagKey::Lyrics))
+        .map(|x| x.value.to_string())
+}
+
+fn read_embedded_lyrics(path: &Path) -> Option<String> {
+    let mss = symphonia::core::io::MediaSourceStream::new(
+        Box::new(File::open(path).ok()?),
+        Default::default(),
+    );
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .ok()?;
+
+    // Tags can be stored in front of the container (e.g. ID3) or inside of it
+    if let Some(lyrics) = probed
+        .metadata
+        .get()
+        .and_then(|x| x.current().and_then(find_lyrics_tag))
+    {
+        return Some(lyrics);
+    }
+    probed.format.metadata().current().and_then(find_lyrics_tag)
+}
+
+pub struct LyricsLine {
+    /// Milliseconds, only set for synced lyrics
+    pub time: Option<u64>,
+    pub text: String,
+}
+
+/// Parses a timestamp like `01:23.45` into milliseconds.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (minutes, seconds) = timestamp.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds.trim().parse::<f64>().ok()?;
+    Some(minutes * 60 * 1000 + (seconds * 1000.0) as u64)
+}
+
+/// Parses the content of an .lrc file. Lines can have multiple timestamps, like
+/// `[00:12.00][01:30.00]Chorus`, in which case they are repeated for each timestamp.
+fn parse_lrc(content: &str) -> Vec<LyricsLine> {
+    let mut offset = 0;
+    let mut lines = Vec::new();
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let mut line = line.trim();
+        let mut times = Vec::new();
+        let mut is_info_tag = false;
+        while let Some((tag, rest)) = line.strip_prefix('[').and_then(|x| x.split_once(']')) {
+            if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse::<i64>().unwrap_or(0);
+            }
+            if tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                is_info_tag = true;
+                break;
+            }
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            }
+            line = rest.trim_start();
+        }
+        if is_info_tag {
+            continue;
+        }
+
+        if times.is_empty() {
+            lines.push(LyricsLine {
+                time: None,
+                text: line.to_string(),
+            });
+        }
+        for time in times {
+            lines.push(LyricsLine {
+                time: Some(time),
+                text: line.to_string(),
+            });
+        }
+    }
+
+    // A positive offset shows the lyrics earlier
+    for line in lines.iter_mut() {
+        line.time = line.time.map(|x| (x as i64 - offset).max(0) as u64);
+    }
+    if lines.iter().any(|x| x.time.is_some()) {
+        lines.retain(|x| x.time.is_some());
+        lines.sort_by_key(|x| x.time);
+    }
+    lines
+}
+
+/// Loads the lyrics of the song from an .lrc file next to it or from its tags.
+pub fn load(song_path: &Path) -> Option<Vec<LyricsLine>> {
+    if let Ok(content) = fs::read_to_string(song_path.with_extension("lrc")) {
+        return Some(parse_lrc(&content));
+    }
+
+    // Embedded lyrics can be in the lrc format as well
+    read_embedded_lyrics(song_path).map(|x| parse_lrc(&x))
+}
+
+/// Returns the index of the line that is sung at the given position of synced lyrics.
+pub fn get_current_line(lines: &[LyricsLine], position: u64) -> Option<usize> {
+    lines
+        .iter()
+        .rposition(|x| x.time.is_some_and(|time| time <= position))
+}
@@ -3,19 +3,51 @@
 use std::time::{Duration, Instant};
 mod actions;
 mod app;
+mod cli;
 mod clipboard;
+mod cover;
+mod crash;
+mod decoder;
+mod dlna;
 mod download;
-mod output;
-mod player;
-mod resampler;
+mod duplicates;
+mod export;
+mod fonts;
+mod ime;
+mod import;
+mod instance;
+mod job;
+mod keymap;
+mod library;
+mod lyrics;
+mod notes;
+mod play_counts;
+mod power;
+mod ratings;
+mod remote;
+mod replaygain;
+mod search;
+mod settings;
+mod sync;
+mod tags;
+#[cfg(target_os = "windows")]
+mod taskbar;
+mod theme;
+mod tray;
 mod util;
+mod widgets;
 
+use actions::Action;
+use glow::HasContext;
 use glutin::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     WindowedContext,
 };
+use imgui_glow_renderer::TextureMap;
 use imgui_winit_support::WinitPlatform;
+use implayer_core::{player, playlist_file};
 
 const TITLE: &str = "Playlist Player";
 const FAST_REDRAW_MS_DELAY: u64 = 16;
@@ -24,27 +56,26 @@ const IDLE_REDRAW_MS_DELAY: u64 = 1000;
 type Window = WindowedContext<glutin::PossiblyCurrent>;
 
 fn main() {
-    let (event_loop, window) = create_window();
-    let (mut winit_platform, mut imgui_context) = imgui_init(&window);
-    imgui_context.style_mut().cell_padding = [0.0, 0.0];
-    imgui_context.style_mut().window_padding = [0.0, 0.0];
-    imgui_context.style_mut().window_border_size = 0.0;
-    imgui_context.style_mut().scrollbar_rounding = f32::MAX;
-
-    imgui_context.style_mut()[imgui::StyleColor::Text] = app::TEXT1;
-    imgui_context.style_mut()[imgui::StyleColor::FrameBg] = app::DARK2;
-    imgui_context.style_mut()[imgui::StyleColor::FrameBgHovered] = app::HOVERED_BG;
-    imgui_context.style_mut()[imgui::StyleColor::FrameBgActive] = app::ACTIVE_BG;
-    imgui_context.style_mut()[imgui::StyleColor::SliderGrab] = app::PRIMARY2;
-    imgui_context.style_mut()[imgui::StyleColor::SliderGrabActive] = app::PRIMARY2;
-    imgui_context.style_mut()[imgui::StyleColor::Button] = app::DARK2;
-    imgui_context.style_mut()[imgui::StyleColor::ButtonHovered] = app::HOVERED_BG;
-    imgui_context.style_mut()[imgui::StyleColor::ButtonActive] = app::ACTIVE_BG;
-    imgui_context.style_mut()[imgui::StyleColor::Header] = app::PRIMARY1;
-    imgui_context.style_mut()[imgui::StyleColor::HeaderHovered] = app::PRIMARY2;
-    imgui_context.style_mut()[imgui::StyleColor::HeaderActive] = app::PRIMARY1;
-
+    let arguments = match cli::parse() {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            println!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if instance::send_to_running_instance(&arguments.commands) {
+        return;
+    }
+    let base_path = app::get_base_path(arguments.directory);
+    crash::install_panic_hook(&base_path);
+    let settings = settings::load(&base_path);
+    let (event_loop, window) = create_window(&settings);
+    let mut fallback_fonts = fonts::FallbackFonts::default();
+    let (mut winit_platform, mut imgui_context) = imgui_init(&window, &fallback_fonts);
     let gl = glow_context(&window);
+    let mut input_method = ime::init(imgui_context.io_mut());
+    // Scale factor of the monitor that the fonts were built for
+    let mut font_scale = winit_platform.hidpi_factor();
 
     let mut ig_renderer = imgui_glow_renderer::AutoRenderer::initialize(gl, &mut imgui_context)
         .expect("failed to create renderer");
@@ -66,12 +97,48 @@ fn main() {
 
         let handle = match window.window().raw_window_handle() {
             raw_window_handle::RawWindowHandle::Win32(handle) => handle,
-            _ => panic!(),
+            _ => unreachable!("Windows only has Win32 window handles"),
         };
         Some(handle.hwnd)
     };
 
-    let mut state = app::initialize(hwnd);
+    let mut state = app::initialize(hwnd, base_path, settings);
+
+    let tray = match tray::init(state.theme.primary2) {
+        Ok(tray) => Some(tray),
+        Err(e) => {
+            eprintln!("Failed to create tray icon: {e}");
+            None
+        }
+    };
+
+    #[cfg(target_os = "windows")]
+    let mut taskbar = match hwnd.map(taskbar::init) {
+        Some(Ok(taskbar)) => Some(taskbar),
+        Some(Err(e)) => {
+            eprintln!("Failed to connect to the taskbar: {e}");
+            None
+        }
+        None => None,
+    };
+
+    // The window flag is updated when the setting is changed while drawing
+    let mut is_always_on_top = state.settings.always_on_top;
+    window.window().set_always_on_top(is_always_on_top);
+
+    let mut sleep_inhibitor = power::SleepInhibitor::default();
+    let mut remote_control = remote::RemoteControl::default();
+    // Wakes up the event loop when a remote control request or a command of another instance
+    // arrives
+    let event_loop_proxy = event_loop.create_proxy();
+    let instance_rx = match instance::listen(event_loop_proxy.clone()) {
+        Ok(instance_rx) => Some(instance_rx),
+        Err(e) => {
+            eprintln!("Failed to listen for other instances: {e}");
+            None
+        }
+    };
+    let mut commands = arguments.commands;
 
     let mut redraws_required = 0;
     let mut fast_redrawing = false;
@@ -84,7 +151,81 @@ fn main() {
                 // https://github.com/rust-windowing/winit/issues/1634
             }
             Event::MainEventsCleared => {
-                actions::handle_media_keys(&mut state);
+                commands.extend(actions::handle_media_keys(&mut state));
+                actions::handle_media_cover(&mut state);
+                actions::handle_renderer(&mut state);
+                // Checked here instead of while drawing since a hidden window is not redrawn
+                actions::handle_song_end(&mut state);
+                actions::handle_player_errors(&mut state);
+                app::update(&mut state);
+                sleep_inhibitor.update(state.is_playing);
+                remote_control.update(&mut state, &event_loop_proxy);
+                if let Some(instance_rx) = &instance_rx {
+                    commands.extend(instance_rx.try_iter());
+                }
+                // Commands refer to the playlists, so they wait until the library is loaded
+                let command_count = match state.library_rx {
+                    Some(_) => 0,
+                    None => commands.len(),
+                };
+                for command in commands.drain(..command_count) {
+                    match command {
+                        cli::CliCommand::Show => {
+                            window.window().set_visible(true);
+                            window.window().set_minimized(false);
+                            window.window().focus_window();
+                            redraws_required = 2;
+                        }
+                        cli::CliCommand::Play => {
+                            // Starts the selected playlist if no song was played yet
+                            if state.playing_song_index.is_some() {
+                                state.emit(Action::Resume);
+                            } else {
+                                state.emit(Action::StartPlaylist {
+                                    name: None,
+                                    shuffle: false,
+                                });
+                            }
+                        }
+                        cli::CliCommand::Pause => state.emit(Action::Pause),
+                        cli::CliCommand::Toggle => state.emit(Action::TogglePlayback),
+                        cli::CliCommand::Next => state.emit(Action::Next),
+                        cli::CliCommand::Prev => state.emit(Action::Prev),
+                        cli::CliCommand::Open(path) => state.emit(Action::OpenSongFile(path)),
+                        cli::CliCommand::PlayPlaylist { name, shuffle } => {
+                            state.emit(Action::StartPlaylist { name, shuffle })
+                        }
+                        cli::CliCommand::Volume(percent) => {
+                            state.emit(Action::SetVolumePercent(percent))
+                        }
+                    }
+                }
+                for action in tray::receive_actions() {
+                    match action {
+                        tray::TrayAction::Show => {
+                            window.window().set_visible(true);
+                            window.window().focus_window();
+                            redraws_required = 2;
+                        }
+                        tray::TrayAction::PlayPause => state.emit(Action::TogglePlayback),
+                        tray::TrayAction::Next => state.emit(Action::Next),
+                        tray::TrayAction::Exit => {
+                            request_exit(&mut state, &window, control_flow, &mut redraws_required)
+                        }
+                    }
+                }
+                #[cfg(target_os = "windows")]
+                if let Some(taskbar) = &mut taskbar {
+                    for action in taskbar.receive_actions() {
+                        match action {
+                            taskbar::TaskbarAction::Prev => state.emit(Action::Prev),
+                            taskbar::TaskbarAction::PlayPause => state.emit(Action::TogglePlayback),
+                            taskbar::TaskbarAction::Next => state.emit(Action::Next),
+                        }
+                    }
+                    taskbar.update(&state);
+                }
+                actions::dispatch_pending(&mut state);
                 if redraws_required > 0
                     || (fast_redrawing
                         && (Instant::now() - last_frame)
@@ -121,21 +262,31 @@ fn main() {
                 imgui_context.io_mut().update_delta_time(now - last_frame);
                 last_frame = now;
 
+                // Applied every frame to show changes of the theme like the accent color immediately
+                state.theme.apply(imgui_context.style_mut());
                 let ui = imgui_context.frame();
 
                 if false {
                     let mut a = true;
                     ui.show_demo_window(&mut a);
                 } else {
-                    let client_size = window.window().inner_size();
-                    fast_redrawing = app::draw(
-                        &ui,
-                        client_size.width as f32,
-                        client_size.height as f32,
-                        &mut state,
-                        scroll_delta,
-                    );
+                    // The layout uses logical pixels, so it grows with the scale factor
+                    let [width, height] = ui.io().display_size;
+                    fast_redrawing = app::draw(&ui, width, height, &mut state, scroll_delta);
+                    actions::dispatch_pending(&mut state);
                     scroll_delta = 0.0;
+                    input_method.update(
+                        window.window(),
+                        ui.io().want_text_input,
+                        winit_platform.hidpi_factor(),
+                    );
+                    if state.settings.always_on_top != is_always_on_top {
+                        is_always_on_top = state.settings.always_on_top;
+                        window.window().set_always_on_top(is_always_on_top);
+                    }
+                    if state.should_exit {
+                        *control_flow = ControlFlow::Exit;
+                    }
                 }
 
                 winit_platform.prepare_render(&ui, window.window());
@@ -147,11 +298,43 @@ fn main() {
                     .expect("error rendering imgui");
 
                 window.swap_buffers().unwrap();
+
+                // The texture can only be replaced while it is not used by the current frame
+                if let Some(cover) = state.loaded_cover.take() {
+                    update_cover_texture(&mut ig_renderer, &mut state, cover);
+                }
+                if !state.loaded_thumbnails.is_empty() {
+                    update_thumbnail_atlas(&mut ig_renderer, &mut state);
+                }
+                // The window was moved to a monitor with a different scale factor
+                let is_scale_changed = winit_platform.hidpi_factor() != font_scale;
+                if fallback_fonts.update(&state) || is_scale_changed {
+                    font_scale = winit_platform.hidpi_factor();
+                    rebuild_fonts(
+                        &mut ig_renderer,
+                        &mut imgui_context,
+                        &fallback_fonts,
+                        font_scale,
+                    );
+                }
+            }
+            Event::LoopDestroyed => {
+                save_window_geometry(&mut state, &window);
+                // Waiting for the background work may take a moment
+                window.window().set_visible(false);
+                actions::shutdown(&mut state);
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
-            } => *control_flow = ControlFlow::Exit,
+            } => {
+                if state.settings.close_to_tray && tray.is_some() {
+                    // Playback continues, the window is shown again through the tray icon
+                    window.window().set_visible(false);
+                } else {
+                    request_exit(&mut state, &window, control_flow, &mut redraws_required);
+                }
+            }
             event => {
                 // We may need to redraw twice after an event. The first draw may make changes to
                 // the GUI that are not reflected until the second draw. In some cases more redraws
@@ -162,6 +345,9 @@ fn main() {
                 if let Event::WindowEvent { ref event, .. } = event {
                     redraws_required = 2;
                     match event {
+                        WindowEvent::DroppedFile(path) => {
+                            state.dropped_paths.push(path.clone());
+                        }
                         WindowEvent::MouseWheel { delta, .. } => {
                             scroll_delta = match delta {
                                 winit::event::MouseScrollDelta::LineDelta(_, d) => *d,
@@ -169,6 +355,11 @@ fn main() {
                             };
                             skip_event_handling = true;
                         }
+                        WindowEvent::Ime(ime_event) => {
+                            state.ime_preedit =
+                                ime::handle_event(imgui_context.io_mut(), ime_event);
+                            skip_event_handling = true;
+                        }
                         _ => (),
                     };
                 }
@@ -180,11 +371,228 @@ fn main() {
     })
 }
 
-fn create_window() -> (EventLoop<()>, Window) {
+/// Exits the program or asks first if there are unsaved playlists.
+fn request_exit(
+    state: &mut app::State,
+    window: &Window,
+    control_flow: &mut ControlFlow,
+    redraws_required: &mut i32,
+) {
+    if state.playlists.iter().any(|x| x.has_changes()) {
+        // The window may be hidden in the tray
+        window.window().set_visible(true);
+        state.show_exit_confirmation = true;
+        *redraws_required = 2;
+    } else {
+        *control_flow = ControlFlow::Exit;
+    }
+}
+
+/// Remembers the position and size of the window for the next start.
+fn save_window_geometry(state: &mut app::State, window: &Window) {
+    let window = window.window();
+    state.settings.window_maximized = window.is_maximized();
+    // Keep the geometry from before maximizing to restore it when the window is unmaximized
+    if !state.settings.window_maximized {
+        if let Ok(position) = window.outer_position() {
+            state.settings.window_position = Some([position.x, position.y]);
+        }
+        let size = window.inner_size();
+        state.settings.window_size = Some([size.width, size.height]);
+    }
+    settings::save(state);
+}
+
+/// Replaces the texture of the cover that is shown in the Now Playing panel.
+fn update_cover_texture(
+    renderer: &mut imgui_glow_renderer::AutoRenderer,
+    state: &mut app::State,
+    cover: Option<cover::Cover>,
+) {
+    let gl = renderer.gl_context().clone();
+    if let Some((texture_id, _)) = state.cover_texture.take() {
+        if let Some(texture) = renderer.texture_map().gl_texture(texture_id) {
+            unsafe { gl.delete_texture(texture) };
+        }
+    }
+    let Some(cover) = cover else {
+        return;
+    };
+
+    let texture = unsafe {
+        let Ok(texture) = gl.create_texture() else {
+            return;
+        };
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            glow::LINEAR as i32,
+        );
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAG_FILTER,
+            glow::LINEAR as i32,
+        );
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA as i32,
+            cover.width as i32,
+            cover.height as i32,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            Some(&cover.rgba),
+        );
+        texture
+    };
+    if let Some(texture_id) = renderer.texture_map_mut().register(texture) {
+        state.cover_texture = Some((texture_id, [cover.width as f32, cover.height as f32]));
+    }
+}
+
+/// Copies the loaded thumbnails into their cells of the atlas of the album grid, which is
+/// created on first use.
+fn update_thumbnail_atlas(
+    renderer: &mut imgui_glow_renderer::AutoRenderer,
+    state: &mut app::State,
+) {
+    let gl = renderer.gl_context().clone();
+    let atlas_size = (cover::THUMBNAIL_SIZE * cover::ATLAS_CELLS) as i32;
+    let texture = match state
+        .thumbnail_atlas
+        .and_then(|x| renderer.texture_map().gl_texture(x))
+    {
+        Some(texture) => texture,
+        None => {
+            let texture = unsafe {
+                let Ok(texture) = gl.create_texture() else {
+                    state.loaded_thumbnails.clear();
+                    return;
+                };
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                gl.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MIN_FILTER,
+                    glow::LINEAR as i32,
+                );
+                gl.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MAG_FILTER,
+                    glow::LINEAR as i32,
+                );
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RGBA as i32,
+                    atlas_size,
+                    atlas_size,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    None,
+                );
+                texture
+            };
+            state.thumbnail_atlas = renderer.texture_map_mut().register(texture);
+            texture
+        }
+    };
+
+    unsafe {
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        for (cell, thumbnail) in state.loaded_thumbnails.drain(..) {
+            let cell = cell as u32;
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                ((cell % cover::ATLAS_CELLS) * cover::THUMBNAIL_SIZE) as i32,
+                ((cell / cover::ATLAS_CELLS) * cover::THUMBNAIL_SIZE) as i32,
+                thumbnail.width as i32,
+                thumbnail.height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(&thumbnail.rgba),
+            );
+        }
+    }
+}
+
+/// Builds the font atlas again with the current fallback fonts, characters and scale factor and
+/// replaces its texture.
+fn rebuild_fonts(
+    renderer: &mut imgui_glow_renderer::AutoRenderer,
+    imgui_context: &mut imgui::Context,
+    fallback_fonts: &fonts::FallbackFonts,
+    scale: f64,
+) {
+    let gl = renderer.gl_context().clone();
+    imgui_context.io_mut().font_global_scale = (1.0 / scale) as f32;
+    let atlas = imgui_context.fonts();
+    let old_texture_id = atlas.tex_id;
+    atlas.clear();
+    fallback_fonts.add_to_atlas(atlas, scale);
+    let atlas_texture = atlas.build_rgba32_texture();
+
+    let texture = unsafe {
+        let Ok(texture) = gl.create_texture() else {
+            return;
+        };
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            glow::LINEAR as i32,
+        );
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAG_FILTER,
+            glow::LINEAR as i32,
+        );
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA as i32,
+            atlas_texture.width as i32,
+            atlas_texture.height as i32,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            Some(atlas_texture.data),
+        );
+        texture
+    };
+    if let Some(texture_id) = renderer.texture_map_mut().register(texture) {
+        atlas.tex_id = texture_id;
+    }
+    if let Some(old_texture) = renderer.texture_map().gl_texture(old_texture_id) {
+        unsafe { gl.delete_texture(old_texture) };
+    }
+}
+
+fn create_window(settings: &settings::Settings) -> (EventLoop<()>, Window) {
     let event_loop = glutin::event_loop::EventLoop::new();
-    let window = glutin::window::WindowBuilder::new()
+    let mut window = glutin::window::WindowBuilder::new()
         .with_title(TITLE)
-        .with_inner_size(glutin::dpi::LogicalSize::new(1500, 780));
+        .with_maximized(settings.window_maximized);
+    window = match settings.window_size {
+        Some([width, height]) => window.with_inner_size(PhysicalSize::new(width, height)),
+        None => window.with_inner_size(LogicalSize::new(1500, 780)),
+    };
+    if let Some([x, y]) = settings.window_position {
+        // The monitor that the window was on may not be connected anymore
+        let is_on_monitor = event_loop.available_monitors().any(|monitor| {
+            let (position, size) = (monitor.position(), monitor.size());
+            x >= position.x
+                && y >= position.y
+                && x < position.x + size.width as i32
+                && y < position.y + size.height as i32
+        });
+        if is_on_monitor {
+            window = window.with_position(PhysicalPosition::new(x, y));
+        }
+    }
     let window = glutin::ContextBuilder::new()
         .with_vsync(false)
         .build_windowed(window, &event_loop)
@@ -201,7 +609,10 @@ fn glow_context(window: &Window) -> glow::Context {
     unsafe { glow::Context::from_loader_function(|s| window.get_proc_address(s).cast()) }
 }
 
-fn imgui_init(window: &Window) -> (WinitPlatform, imgui::Context) {
+fn imgui_init(
+    window: &Window,
+    fallback_fonts: &fonts::FallbackFonts,
+) -> (WinitPlatform, imgui::Context) {
     let mut imgui_context = imgui::Context::create();
     imgui_context.set_ini_filename(None);
 
@@ -212,28 +623,10 @@ fn imgui_init(window: &Window) -> (WinitPlatform, imgui::Context) {
         imgui_winit_support::HiDpiMode::Rounded,
     );
 
-    imgui_context
-        .fonts()
-        .add_font(&[imgui::FontSource::TtfData {
-            data: include_bytes!("DejaVuSans.ttf"),
-            config: Some(imgui::FontConfig {
-                glyph_ranges: imgui::FontGlyphRanges::from_slice(&[1, 65535, 0]),
-                ..Default::default()
-            }),
-            size_pixels: 18.0,
-        }]);
-    imgui_context
-        .fonts()
-        .add_font(&[imgui::FontSource::TtfData {
-            data: include_bytes!("NotoSansSymbols2-Regular.ttf"),
-            config: Some(imgui::FontConfig {
-                glyph_ranges: imgui::FontGlyphRanges::from_slice(&[1, 65535, 0]),
-                ..Default::default()
-            }),
-            size_pixels: 40.0,
-        }]);
-
-    imgui_context.io_mut().font_global_scale = (1.0 / winit_platform.hidpi_factor()) as f32;
+    // The fonts are rasterized in physical pixels and scaled back to logical pixels
+    let scale = winit_platform.hidpi_factor();
+    fallback_fonts.add_to_atlas(imgui_context.fonts(), scale);
+    imgui_context.io_mut().font_global_scale = (1.0 / scale) as f32;
 
     (winit_platform, imgui_context)
 }
@@ -0,0 +1,73 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::app::{self, State, StatusType};
+
+const NOTES_FILE_NAME: &str = "notes";
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Loads the notes of all songs from the data directory. Each line of the notes file contains the
+/// song path and its note separated by a tab.
+pub fn load(base_path: &str) -> HashMap<String, String> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(NOTES_FILE_NAME);
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| x.split_once('\t'))
+        .map(|(path, note)| (path.to_string(), unescape(note)))
+        .collect()
+}
+
+pub fn save(state: &mut State) {
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let mut notes: Vec<(&String, &String)> = state.notes.iter().collect();
+    notes.sort();
+    let content: String = notes
+        .iter()
+        .map(|(path, note)| format!("{path}\t{}\n", escape(note)))
+        .collect();
+
+    let result = fs::create_dir_all(&data_path)
+        .and_then(|_| fs::write(data_path.join(NOTES_FILE_NAME), content));
+    if let Err(e) = result {
+        state.push_status(StatusType::Error, format!("Could not save notes:\n{e}"));
+    }
+}
+
+/// Sets the note of the songs, an empty note removes it.
+pub fn set_note(state: &mut State, paths: &[String], note: &str) {
+    let note = note.trim();
+    for path in paths {
+        if note.is_empty() {
+            state.notes.remove(path);
+        } else {
+            state.notes.insert(path.clone(), note.to_string());
+        }
+    }
+    state.mark_song_data_changed();
+    save(state);
+}
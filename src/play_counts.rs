@@ -0,0 +1,63 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::app::{self, State, StatusType};
+
+const PLAY_COUNTS_FILE_NAME: &str = "play_counts";
+
+/// Loads the number of plays of each song from the data directory. Each line of the play counts
+/// file contains the song path and its number of plays separated by a tab. Returns `None` if the
+/// file doesn't exist yet.
+pub fn load(base_path: &str) -> Option<HashMap<String, usize>> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(PLAY_COUNTS_FILE_NAME);
+    let content = fs::read_to_string(path).ok()?;
+    Some(
+        content
+            .lines()
+            .filter_map(|x| x.split_once('\t'))
+            .filter_map(|(path, count)| Some((path.to_string(), count.trim().parse().ok()?)))
+            .collect(),
+    )
+}
+
+/// Counts the plays in the history file. Used once to fill the play counts file, since the
+/// history file is shortened to the last entries.
+pub fn count_history(base_path: &str) -> HashMap<String, usize> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(app::HISTORY_FILE_NAME);
+    let mut play_counts = HashMap::new();
+    for line in fs::read_to_string(path).unwrap_or_default().lines() {
+        if let Some((_, path)) = line.split_once('\t') {
+            *play_counts.entry(path.to_string()).or_insert(0) += 1;
+        }
+    }
+    play_counts
+}
+
+pub fn save(state: &mut State) {
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let mut play_counts: Vec<(&String, &usize)> = state.play_counts.iter().collect();
+    play_counts.sort();
+    let content: String = play_counts
+        .iter()
+        .map(|(path, count)| format!("{path}\t{count}\n"))
+        .collect();
+
+    let result = fs::create_dir_all(&data_path)
+        .and_then(|_| fs::write(data_path.join(PLAY_COUNTS_FILE_NAME), content));
+    if let Err(e) = result {
+        state.push_status(
+            StatusType::Error,
+            format!("Could not save play counts:\n{e}"),
+        );
+    }
+}
+
+/// Counts a play of the song.
+pub fn add_play(state: &mut State, path: &str) {
+    *state.play_counts.entry(path.to_string()).or_insert(0) += 1;
+    state.mark_song_data_changed();
+    save(state);
+}
@@ -0,0 +1,87 @@
+/// Keeps the computer from going to sleep while a song is playing. The display may still turn off.
+#[derive(Default)]
+pub struct SleepInhibitor {
+    is_inhibited: bool,
+    /// The inhibition ends when the connection is closed
+    #[cfg(target_os = "linux")]
+    connection: Option<(dbus::blocking::Connection, u32)>,
+}
+
+#[cfg(target_os = "linux")]
+const DBUS_NAME: &str = "org.freedesktop.PowerManagement.Inhibit";
+#[cfg(target_os = "linux")]
+const DBUS_PATH: &str = "/org/freedesktop/PowerManagement/Inhibit";
+#[cfg(target_os = "linux")]
+const DBUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+impl SleepInhibitor {
+    /// Inhibits sleep when playback starts and releases it when playback is paused or stopped.
+    pub fn update(&mut self, is_playing: bool) {
+        if is_playing == self.is_inhibited {
+            return;
+        }
+        // Not retried on failure, the state is only checked when it changes
+        self.is_inhibited = is_playing;
+        let result = if is_playing {
+            self.inhibit()
+        } else {
+            self.release()
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to change the sleep inhibition: {e}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn inhibit(&mut self) -> Result<(), dbus::Error> {
+        let connection = dbus::blocking::Connection::new_session()?;
+        let (cookie,): (u32,) = connection
+            .with_proxy(DBUS_NAME, DBUS_PATH, DBUS_TIMEOUT)
+            .method_call(DBUS_NAME, "Inhibit", ("ImPlayer", "Playing music"))?;
+        self.connection = Some((connection, cookie));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn release(&mut self) -> Result<(), dbus::Error> {
+        if let Some((connection, cookie)) = self.connection.take() {
+            connection
+                .with_proxy(DBUS_NAME, DBUS_PATH, DBUS_TIMEOUT)
+                .method_call::<(), _, _, _>(DBUS_NAME, "UnInhibit", (cookie,))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn inhibit(&mut self) -> windows::core::Result<()> {
+        use windows::Win32::System::Power::{
+            SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+        };
+
+        // The state belongs to the main thread, which keeps running until the process exits
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) }.0 == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn release(&mut self) -> windows::core::Result<()> {
+        use windows::Win32::System::Power::{SetThreadExecutionState, ES_CONTINUOUS};
+
+        if unsafe { SetThreadExecutionState(ES_CONTINUOUS) }.0 == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    fn inhibit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    fn release(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::app::{self, State, StatusType};
+
+const RATINGS_FILE_NAME: &str = "ratings";
+
+/// Loads the ratings that were set in the player from the data directory. Each line of the
+/// ratings file contains the song path and its number of stars separated by a tab.
+pub fn load(base_path: &str) -> HashMap<String, u8> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(RATINGS_FILE_NAME);
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| x.split_once('\t'))
+        .filter_map(|(path, stars)| {
+            Some((path.to_string(), stars.trim().parse::<u8>().ok()?.min(5)))
+        })
+        .collect()
+}
+
+pub fn save(state: &mut State) {
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let mut ratings: Vec<(&String, &u8)> = state.ratings.iter().collect();
+    ratings.sort();
+    let content: String = ratings
+        .iter()
+        .map(|(path, stars)| format!("{path}\t{stars}\n"))
+        .collect();
+
+    let result = fs::create_dir_all(&data_path)
+        .and_then(|_| fs::write(data_path.join(RATINGS_FILE_NAME), content));
+    if let Err(e) = result {
+        state.push_status(StatusType::Error, format!("Could not save ratings:\n{e}"));
+    }
+}
+
+/// Sets the rating of the song, which takes precedence over the rating in its tags.
+pub fn set_rating(state: &mut State, path: &str, stars: u8) {
+    state.ratings.insert(path.to_string(), stars.min(5));
+    state.mark_song_data_changed();
+    save(state);
+}
@@ -0,0 +1,460 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use glutin::event_loop::EventLoopProxy;
+
+use crate::{
+    actions::{self, Action},
+    app::{self, Song, State, StatusType},
+    util,
+};
+
+/// Address that the server listens on by default, it is only reachable from this computer
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+/// Time between checks whether the server was stopped while no connection arrives
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// Time that a connection waits for the request or for the player to handle it
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum size of the request line and the headers in bytes
+const MAX_HEADER_SIZE: u64 = 8192;
+
+/// Request that is handled on the main thread since it accesses the state
+struct Request {
+    method: String,
+    /// Path segments, `/playlists/2/play` is `["playlists", "2", "play"]`
+    path: Vec<String>,
+    query: Vec<(String, String)>,
+    response_tx: Sender<Response>,
+}
+
+struct Response {
+    status: &'static str,
+    /// JSON text
+    body: String,
+}
+
+impl Response {
+    fn ok(body: String) -> Response {
+        Response {
+            status: "200 OK",
+            body,
+        }
+    }
+
+    fn done() -> Response {
+        Response::ok("{\"ok\":true}".to_string())
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response {
+            status,
+            body: format!("{{\"error\":{}}}", util::to_json_string(message)),
+        }
+    }
+}
+
+/// HTTP server to control the player from scripts or other devices. It runs while it is enabled
+/// in the settings and is restarted when the address changes.
+#[derive(Default)]
+pub struct RemoteControl {
+    /// Address that the server was started with, even if it could not listen on it
+    address: Option<String>,
+    is_stopped: Arc<AtomicBool>,
+    request_rx: Option<Receiver<Request>>,
+}
+
+impl RemoteControl {
+    /// Starts or stops the server according to the settings and answers the received requests.
+    pub fn update(&mut self, state: &mut State, proxy: &EventLoopProxy<()>) {
+        let address = state
+            .settings
+            .remote_control
+            .then(|| state.settings.remote_control_address.clone());
+        if address != self.address {
+            self.stop();
+            if let Some(address) = &address {
+                match start(address, proxy.clone()) {
+                    Ok((request_rx, is_stopped)) => {
+                        self.request_rx = Some(request_rx);
+                        self.is_stopped = is_stopped;
+                    }
+                    Err(e) => state.push_status(
+                        StatusType::Error,
+                        format!("Could not start the remote control on {address}:\n{e}"),
+                    ),
+                }
+            }
+            self.address = address;
+        }
+
+        if let Some(request_rx) = &self.request_rx {
+            for request in util::receive_all(request_rx) {
+                let response = handle_request(state, &request);
+                let _ = request.response_tx.send(response);
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.is_stopped.store(true, Ordering::Relaxed);
+        self.request_rx = None;
+    }
+}
+
+impl Drop for RemoteControl {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn start(
+    address: &str,
+    proxy: EventLoopProxy<()>,
+) -> io::Result<(Receiver<Request>, Arc<AtomicBool>)> {
+    let listener = TcpListener::bind(address)?;
+    // Checking the flag in between lets the thread end and free the address when stopped
+    listener.set_nonblocking(true)?;
+    let (request_tx, request_rx) = mpsc::channel();
+    let is_stopped = Arc::new(AtomicBool::new(false));
+    let thread_is_stopped = is_stopped.clone();
+    thread::spawn(move || {
+        while !thread_is_stopped.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let _ = handle_connection(stream, &request_tx, &proxy);
+                }
+                Err(_) => thread::sleep(ACCEPT_INTERVAL),
+            }
+        }
+    });
+    Ok((request_rx, is_stopped))
+}
+
+/// Reads the request, waits for the main thread to handle it and writes the response.
+fn handle_connection(
+    mut stream: TcpStream,
+    request_tx: &Sender<Request>,
+    proxy: &EventLoopProxy<()>,
+) -> io::Result<()> {
+    // The stream inherits the non-blocking mode of the listener on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let local_address = stream.local_addr()?.ip().to_canonical();
+    // Requests have no body, so nothing after the headers is read
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_HEADER_SIZE));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Browsers send the origin with requests of web pages, which must not control the player
+    let mut has_origin = false;
+    let mut host = None;
+    let mut is_complete = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            break;
+        }
+        if header.trim().is_empty() {
+            is_complete = true;
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_lowercase().as_str() {
+                "origin" => has_origin = true,
+                "host" => host = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+    }
+    if !is_complete {
+        return write_response(
+            &mut stream,
+            Response::error(
+                "431 Request Header Fields Too Large",
+                "The request headers are too large or incomplete",
+            ),
+        );
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => {
+            return write_response(
+                &mut stream,
+                Response::error("400 Bad Request", "Bad request"),
+            )
+        }
+    };
+    if has_origin {
+        return write_response(
+            &mut stream,
+            Response::error("403 Forbidden", "Requests from web pages are not allowed"),
+        );
+    }
+    if !host.is_some_and(|x| is_allowed_host(&x, local_address)) {
+        return write_response(
+            &mut stream,
+            Response::error(
+                "403 Forbidden",
+                "The Host header must be localhost or the address of the player",
+            ),
+        );
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (response_tx, response_rx) = mpsc::channel();
+    let request = Request {
+        method: method.to_uppercase(),
+        path: path
+            .split('/')
+            .filter(|x| !x.is_empty())
+            .map(util::percent_decode)
+            .collect(),
+        query: query
+            .split('&')
+            .filter_map(|x| x.split_once('='))
+            .map(|(key, value)| (util::percent_decode(key), util::percent_decode(value)))
+            .collect(),
+        response_tx,
+    };
+    if request_tx.send(request).is_err() {
+        return Ok(());
+    }
+    // Wakes up the event loop so that the request is handled without waiting for the next frame
+    let _ = proxy.send_event(());
+    let response = response_rx.recv_timeout(TIMEOUT).unwrap_or_else(|_| {
+        Response::error("503 Service Unavailable", "The player did not respond")
+    });
+    write_response(&mut stream, response)
+}
+
+/// Whether the Host header names this computer or the address that the connection arrived at.
+/// Other names may only resolve to this computer to let web pages reach the server.
+fn is_allowed_host(host: &str, local_address: IpAddr) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback() || ip.to_canonical() == local_address)
+}
+
+fn write_response(stream: &mut TcpStream, response: Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn to_json_option<T: ToString>(value: Option<T>) -> String {
+    value.map_or("null".to_string(), |x| x.to_string())
+}
+
+fn song_to_json(index: usize, song: &Song) -> String {
+    format!(
+        "{{\"index\":{index},\"name\":{},\"artist\":{},\"path\":{},\"duration_ms\":{},\"exists\":{}}}",
+        util::to_json_string(&song.name),
+        util::to_json_string(&song.artist),
+        util::to_json_string(&song.path),
+        to_json_option(song.duration),
+        song.exists
+    )
+}
+
+fn get_status(state: &State) -> String {
+    let playing = state
+        .playing_playlist_index
+        .zip(state.playing_song_index)
+        .map(|(playlist_index, song_index)| {
+            let playlist = &state.playlists[playlist_index];
+            format!(
+                "{{\"playlist_index\":{playlist_index},\"playlist\":{},\"song\":{}}}",
+                util::to_json_string(&playlist.name),
+                song_to_json(song_index, &playlist.songs[song_index])
+            )
+        });
+    let volume = (state.volume - app::MIN_VOLUME) / (app::MAX_VOLUME - app::MIN_VOLUME) * 100.0;
+    format!(
+        "{{\"is_playing\":{},\"playing\":{},\"position_ms\":{},\"volume\":{},\"is_muted\":{},\"queue_length\":{}}}",
+        state.is_playing,
+        to_json_option(playing),
+        *state.position.lock().unwrap(),
+        volume.round(),
+        state.volume == app::MIN_VOLUME,
+        state.queued_songs.len()
+    )
+}
+
+fn get_playlists(state: &State) -> String {
+    let playlists: Vec<String> = state
+        .playlists
+        .iter()
+        .enumerate()
+        .map(|(i, playlist)| {
+            let duration_sum: u64 = playlist.songs.iter().map(|x| x.duration.unwrap_or(0)).sum();
+            format!(
+                "{{\"index\":{i},\"name\":{},\"song_count\":{},\"duration_ms\":{duration_sum},\"has_changes\":{}}}",
+                util::to_json_string(&playlist.name),
+                playlist.songs.len(),
+                playlist.has_changes()
+            )
+        })
+        .collect();
+    format!("[{}]", playlists.join(","))
+}
+
+fn get_queue(state: &State) -> String {
+    let songs: Vec<String> = state
+        .queued_songs
+        .iter()
+        .map(|(playlist_index, path)| {
+            format!(
+                "{{\"playlist_index\":{playlist_index},\"path\":{}}}",
+                util::to_json_string(path)
+            )
+        })
+        .collect();
+    format!("[{}]", songs.join(","))
+}
+
+fn get_query_value<T: std::str::FromStr>(request: &Request, key: &str) -> Option<T> {
+    request
+        .query
+        .iter()
+        .find(|x| x.0 == key)
+        .and_then(|x| x.1.trim().parse().ok())
+}
+
+/// Parses the index of a playlist in the path if the playlist exists.
+fn get_playlist_index(state: &State, segment: &str) -> Option<usize> {
+    segment.parse().ok().filter(|x| *x < state.playlists.len())
+}
+
+fn handle_request(state: &mut State, request: &Request) -> Response {
+    let path: Vec<&str> = request.path.iter().map(|x| x.as_str()).collect();
+    match (request.method.as_str(), path.as_slice()) {
+        ("GET", ["status"]) => Response::ok(get_status(state)),
+        ("POST", ["play"]) => {
+            actions::dispatch(state, Action::Resume);
+            Response::done()
+        }
+        ("POST", ["pause"]) => {
+            actions::dispatch(state, Action::Pause);
+            Response::done()
+        }
+        ("POST", ["toggle"]) => {
+            actions::dispatch(state, Action::TogglePlayback);
+            Response::done()
+        }
+        ("POST", ["next"]) => {
+            actions::dispatch(state, Action::Next);
+            Response::done()
+        }
+        ("POST", ["prev"]) => {
+            actions::dispatch(state, Action::Prev);
+            Response::done()
+        }
+        ("POST", ["seek"]) => match get_query_value(request, "position") {
+            Some(_) if state.playing_song_index.is_none() => {
+                Response::error("409 Conflict", "No song is playing")
+            }
+            Some(position) => {
+                actions::dispatch(state, Action::Seek(position));
+                Response::done()
+            }
+            None => Response::error("400 Bad Request", "Expected ?position=<milliseconds>"),
+        },
+        ("POST", ["volume"]) => match get_query_value::<f32>(request, "value") {
+            Some(percent) if percent.is_finite() => {
+                actions::dispatch(state, Action::SetVolumePercent(percent));
+                Response::done()
+            }
+            _ => Response::error("400 Bad Request", "Expected ?value=<0 to 100>"),
+        },
+        ("POST", ["mute"]) => {
+            actions::dispatch(state, Action::ToggleMute);
+            Response::done()
+        }
+        ("GET", ["playlists"]) => Response::ok(get_playlists(state)),
+        ("GET", ["playlists", index]) => match get_playlist_index(state, index) {
+            Some(i) => {
+                let songs: Vec<String> = state.playlists[i]
+                    .songs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, song)| song_to_json(i, song))
+                    .collect();
+                Response::ok(format!("[{}]", songs.join(",")))
+            }
+            None => Response::error("404 Not Found", "Playlist not found"),
+        },
+        ("POST", ["playlists", index, "play"]) => {
+            let Some(playlist_index) = get_playlist_index(state, index) else {
+                return Response::error("404 Not Found", "Playlist not found");
+            };
+            let song_index = get_query_value(request, "song").unwrap_or(0);
+            match state.playlists[playlist_index].songs.get(song_index) {
+                Some(song) if song.exists => {
+                    actions::dispatch(
+                        state,
+                        Action::Play {
+                            playlist_index,
+                            song_index,
+                        },
+                    );
+                    Response::done()
+                }
+                Some(_) => Response::error("409 Conflict", "The song file does not exist"),
+                None => Response::error("404 Not Found", "Song not found"),
+            }
+        }
+        ("GET", ["queue"]) => Response::ok(get_queue(state)),
+        ("POST", ["queue"]) => {
+            let playlist_index =
+                get_query_value(request, "playlist").filter(|x: &usize| *x < state.playlists.len());
+            let song = playlist_index.zip(get_query_value(request, "song")).filter(
+                |(playlist_index, song_index): &(usize, usize)| {
+                    *song_index < state.playlists[*playlist_index].songs.len()
+                },
+            );
+            match song {
+                Some((playlist_index, song_index)) => {
+                    actions::dispatch(
+                        state,
+                        Action::QueueSong {
+                            playlist_index,
+                            song_index,
+                        },
+                    );
+                    Response::done()
+                }
+                None => Response::error(
+                    "400 Bad Request",
+                    "Expected ?playlist=<index>&song=<index> of an existing song",
+                ),
+            }
+        }
+        ("DELETE", ["queue"]) => {
+            actions::dispatch(state, Action::ClearQueue);
+            Response::done()
+        }
+        _ => Response::error("404 Not Found", "Unknown endpoint"),
+    }
+}
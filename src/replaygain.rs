@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ebur128::{EbuR128, Mode};
+use lofty::{config::WriteOptions, prelude::*, tag::Tag};
+
+use crate::{
+    app::{State, StatusType},
+    decoder::SampleDecoder,
+    job::{self, JobMessage, JobSender},
+};
+
+/// ReplayGain 2.0 reference loudness in LUFS
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Measures the loudness and the sample peak of a song.
+fn analyze(path: &Path) -> Option<(EbuR128, f64)> {
+    let mut decoder = SampleDecoder::open(path)?;
+    let mut ebur128 = EbuR128::new(
+        decoder.channels,
+        decoder.sample_rate,
+        Mode::I | Mode::SAMPLE_PEAK | Mode::HISTOGRAM,
+    )
+    .ok()?;
+
+    let mut sample_buf = None;
+    while let Some(samples) = decoder.next_samples::<f32>(&mut sample_buf) {
+        ebur128.add_frames_f32(samples).ok()?;
+    }
+
+    let mut peak = 0.0_f64;
+    for channel in 0..decoder.channels {
+        peak = peak.max(ebur128.sample_peak(channel).ok()?);
+    }
+    Some((ebur128, peak))
+}
+
+fn write_tags(path: &Path, track: (f64, f64), album: Option<(f64, f64)>) -> Result<(), String> {
+    let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file.primary_tag_mut().unwrap();
+
+    tag.insert_text(ItemKey::ReplayGainTrackGain, format!("{:.2} dB", track.0));
+    tag.insert_text(ItemKey::ReplayGainTrackPeak, format!("{:.6}", track.1));
+    if let Some((gain, peak)) = album {
+        tag.insert_text(ItemKey::ReplayGainAlbumGain, format!("{gain:.2} dB"));
+        tag.insert_text(ItemKey::ReplayGainAlbumPeak, format!("{peak:.6}"));
+    }
+    tagged_file
+        .save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())
+}
+
+/// Songs are given as their path and the artist and name of their album. Album gain is calculated
+/// over all songs of an album, albums of different artists with the same name are kept apart by
+/// their album artist or otherwise their artist.
+fn run(
+    songs: Vec<(String, Option<(String, String)>)>,
+    base_path: String,
+    sender: JobSender<usize>,
+) {
+    let mut results = Vec::new();
+    for (i, (path, album)) in songs.iter().enumerate() {
+        match analyze(&PathBuf::from(&base_path).join(path)) {
+            Some(result) => results.push((path, album, result)),
+            None => {
+                let _ = sender.send(JobMessage::Error(format!("Could not analyze \"{path}\"")));
+            }
+        }
+        let progress = JobMessage::Progress {
+            done: i + 1,
+            total: songs.len(),
+            item: None,
+        };
+        if sender.send(progress).is_err() {
+            return;
+        }
+    }
+
+    let mut albums: HashMap<&(String, String), Vec<usize>> = HashMap::new();
+    for (i, (_, album, _)) in results.iter().enumerate() {
+        if let Some(album) = album {
+            albums.entry(album).or_default().push(i);
+        }
+    }
+    let album_gains: HashMap<&(String, String), (f64, f64)> = albums
+        .iter()
+        .filter_map(|(album, indices)| {
+            let loudness =
+                EbuR128::loudness_global_multiple(indices.iter().map(|i| &results[*i].2 .0))
+                    .ok()?;
+            let peak = indices.iter().map(|i| results[*i].2 .1).fold(0.0, f64::max);
+            Some((*album, (REFERENCE_LOUDNESS - loudness, peak)))
+        })
+        .collect();
+
+    let mut written_count = 0;
+    for (path, album, (ebur128, peak)) in results.iter() {
+        let loudness = match ebur128.loudness_global() {
+            Ok(loudness) if loudness.is_finite() => loudness,
+            _ => continue,
+        };
+        let album_gain = album
+            .as_ref()
+            .and_then(|x| album_gains.get(x).copied())
+            .filter(|x| x.0.is_finite());
+        match write_tags(
+            &PathBuf::from(&base_path).join(path),
+            (REFERENCE_LOUDNESS - loudness, *peak),
+            album_gain,
+        ) {
+            Ok(()) => written_count += 1,
+            Err(e) => {
+                let _ = sender.send(JobMessage::Error(format!(
+                    "Could not write tags of \"{path}\":\n{e}"
+                )));
+            }
+        }
+    }
+    let _ = sender.send(JobMessage::Finished(written_count));
+}
+
+/// Starts calculating the ReplayGain values of the songs in the background and writes them into
+/// their tags.
+pub fn scan(state: &mut State, paths: Vec<String>) {
+    if state.replaygain_job.is_running() {
+        return;
+    }
+    let mut songs: Vec<(String, Option<(String, String)>)> = Vec::new();
+    for path in paths {
+        if songs.iter().any(|x| x.0 == path) || !Path::new(&state.base_path).join(&path).exists() {
+            continue;
+        }
+        let album = state.tags.get(&path).and_then(|x| {
+            let artist = x.album_artist.as_ref().or(x.artist.as_ref());
+            Some((artist.cloned().unwrap_or_default(), x.album.clone()?))
+        });
+        songs.push((path, album));
+    }
+    let base_path = state.base_path.clone();
+    let sender = state.replaygain_job.start();
+    state.spawn_writer(move || run(songs, base_path, sender));
+
+    state.push_status(StatusType::Info, "Scanning ReplayGain...".to_string());
+}
+
+pub fn update(state: &mut State) {
+    if let Some(count) = job::update(state, |x| &mut x.replaygain_job, "Scanning ReplayGain") {
+        state.push_status(
+            StatusType::Info,
+            format!("Wrote ReplayGain tags of {count} songs"),
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::app::{Song, State};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Splits the operator like `>=` off the front of the value.
+    fn parse(value: &str) -> (Comparison, &str) {
+        for (operator, comparison) in [
+            (">=", Comparison::GreaterOrEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">", Comparison::Greater),
+            ("<", Comparison::Less),
+            ("=", Comparison::Equal),
+        ] {
+            if let Some(value) = value.strip_prefix(operator) {
+                return (comparison, value.trim());
+            }
+        }
+        (Comparison::Equal, value)
+    }
+
+    fn compare<T: PartialOrd>(&self, a: T, b: T) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Note,
+    Path,
+}
+
+/// A single `key:value` term of the search
+enum Condition {
+    /// Case insensitive substring of the field
+    Text(TextField, String),
+    /// Seconds
+    Duration(Comparison, u64),
+    Rating(Comparison, u8),
+    Plays(Comparison, usize),
+    Missing(bool),
+}
+
+/// Search text split into free text, which is matched fuzzily, and conditions that all have to
+/// be met, like `artist:queen duration:>5:00 rating:>=4 missing:no`
+#[derive(Default)]
+pub struct Query {
+    /// Remaining text without the conditions
+    pub text: String,
+    conditions: Vec<Condition>,
+}
+
+/// Parses a duration like `5:00`, `1:02:03` or `90` into seconds.
+fn parse_duration(value: &str) -> Option<u64> {
+    let mut seconds = 0;
+    for part in value.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    Some(seconds)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "yes" | "true" => Some(true),
+        "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_condition(key: &str, value: &str) -> Option<Condition> {
+    let text_field = match key {
+        "title" | "song" => Some(TextField::Title),
+        "artist" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "genre" => Some(TextField::Genre),
+        "note" => Some(TextField::Note),
+        "path" => Some(TextField::Path),
+        _ => None,
+    };
+    if let Some(field) = text_field {
+        return Some(Condition::Text(field, value.to_lowercase()));
+    }
+
+    let (comparison, value) = Comparison::parse(value);
+    match key {
+        "duration" => Some(Condition::Duration(comparison, parse_duration(value)?)),
+        "rating" => Some(Condition::Rating(comparison, value.parse().ok()?)),
+        "plays" => Some(Condition::Plays(comparison, value.parse().ok()?)),
+        "missing" => Some(Condition::Missing(parse_bool(value)?)),
+        _ => None,
+    }
+}
+
+/// Splits the text at whitespace, except inside of quotes like `artist:"pink floyd"`.
+fn split_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut is_quoted = false;
+    for c in text.chars() {
+        if c == '"' {
+            is_quoted = !is_quoted;
+        } else if c.is_whitespace() && !is_quoted {
+            if !term.is_empty() {
+                terms.push(std::mem::take(&mut term));
+            }
+        } else {
+            term.push(c);
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    terms
+}
+
+/// Parses the search text. Terms with an unknown key or an invalid value are kept as free text.
+pub fn parse(search_text: &str) -> Query {
+    let mut query = Query::default();
+    let mut text_terms = Vec::new();
+    for term in split_terms(search_text) {
+        let condition = term
+            .split_once(':')
+            .and_then(|(key, value)| parse_condition(&key.trim().to_lowercase(), value.trim()));
+        match condition {
+            Some(condition) => query.conditions.push(condition),
+            None => text_terms.push(term),
+        }
+    }
+    query.text = text_terms.join(" ");
+    query
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.conditions.is_empty()
+    }
+
+    /// Whether the song matches the free text by its name, artist or note and meets all
+    /// conditions.
+    pub fn is_matching(&self, state: &State, song: &Song) -> bool {
+        if !self.text.is_empty()
+            && song.get_match_score(&self.text).is_none()
+            && !state
+                .notes
+                .get(&song.path)
+                .is_some_and(|x| x.to_lowercase().contains(&self.text.to_lowercase()))
+        {
+            return false;
+        }
+        self.conditions
+            .iter()
+            .all(|x| is_condition_met(x, state, song))
+    }
+}
+
+fn is_condition_met(condition: &Condition, state: &State, song: &Song) -> bool {
+    let tags = state.tags.get(&song.path);
+    match condition {
+        Condition::Text(field, value) => {
+            let texts: Vec<&str> = match field {
+                TextField::Title => vec![
+                    &song.name,
+                    tags.and_then(|x| x.title.as_deref()).unwrap_or_default(),
+                ],
+                TextField::Artist => vec![
+                    &song.artist,
+                    tags.and_then(|x| x.artist.as_deref()).unwrap_or_default(),
+                ],
+                TextField::Album => tags.and_then(|x| x.album.as_deref()).into_iter().collect(),
+                TextField::Genre => tags
+                    .map(|x| x.genres.iter().map(|x| x.as_str()).collect())
+                    .unwrap_or_default(),
+                TextField::Note => state
+                    .notes
+                    .get(&song.path)
+                    .map(|x| x.as_str())
+                    .into_iter()
+                    .collect(),
+                TextField::Path => vec![&song.path],
+            };
+            texts.iter().any(|x| x.to_lowercase().contains(value))
+        }
+        Condition::Duration(comparison, duration) => song
+            .duration
+            .is_some_and(|x| comparison.compare(x / 1000, *duration)),
+        Condition::Rating(comparison, rating) => {
+            comparison.compare(state.get_rating(&song.path).unwrap_or(0), *rating)
+        }
+        Condition::Plays(comparison, plays) => comparison.compare(
+            state.play_counts.get(&song.path).copied().unwrap_or(0),
+            *plays,
+        ),
+        Condition::Missing(is_missing) => song.exists != *is_missing,
+    }
+}
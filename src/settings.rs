@@ -0,0 +1,445 @@
+use std::{collections::HashMap, env, fs, path::Path};
+
+use crate::{
+    app::{self, Column, State, StatusType},
+    download::Postprocessor,
+    fonts,
+    keymap::Keymap,
+    remote,
+};
+
+const SETTINGS_FILE_NAME: &str = "settings";
+
+/// What happens to files outside of the music directory that are dropped onto the window
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum DroppedFilesAction {
+    /// Add the songs with their absolute paths
+    #[default]
+    Add,
+    Copy,
+    Move,
+}
+
+impl DroppedFilesAction {
+    pub const ALL: [DroppedFilesAction; 3] = [
+        DroppedFilesAction::Add,
+        DroppedFilesAction::Copy,
+        DroppedFilesAction::Move,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DroppedFilesAction::Add => "Add in place",
+            DroppedFilesAction::Copy => "Copy into the music directory",
+            DroppedFilesAction::Move => "Move into the music directory",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            DroppedFilesAction::Add => "add",
+            DroppedFilesAction::Copy => "copy",
+            DroppedFilesAction::Move => "move",
+        }
+    }
+}
+
+/// Tag by which consecutive songs are grouped below a header in the song list
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SongGrouping {
+    #[default]
+    None,
+    Album,
+    Artist,
+}
+
+impl SongGrouping {
+    pub const ALL: [SongGrouping; 3] = [
+        SongGrouping::None,
+        SongGrouping::Album,
+        SongGrouping::Artist,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SongGrouping::None => "No grouping",
+            SongGrouping::Album => "Group by album",
+            SongGrouping::Artist => "Group by artist",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            SongGrouping::None => "none",
+            SongGrouping::Album => "album",
+            SongGrouping::Artist => "artist",
+        }
+    }
+}
+
+/// Options that are kept between sessions
+pub struct Settings {
+    /// Write changed playlists to disk shortly after the last change
+    pub auto_save: bool,
+    /// Add songs dropped on a playlist to its end instead of its beginning
+    pub drop_at_end: bool,
+    /// Hide the window in the tray when it is closed and keep playing
+    pub close_to_tray: bool,
+    /// Keep the window above other windows
+    pub always_on_top: bool,
+    /// Scroll the song list to the playing song when the song changes
+    pub follow_playing_song: bool,
+    /// Show the remaining time of the playing song instead of its duration
+    pub show_remaining_time: bool,
+    /// Vim-style keys like `gg`, `dd` and `/` in addition to the keymap
+    pub vim_mode: bool,
+    /// Scroll the cut off texts of the hovered and the playing song instead of only truncating them
+    pub scroll_long_titles: bool,
+    /// Run the HTTP server to control the player from other programs
+    pub remote_control: bool,
+    /// Address and port that the remote control listens on
+    pub remote_control_address: String,
+    /// Folder on a mounted device that playlists are synced to
+    pub sync_path: String,
+    /// Names of the playlists that are synced to the device
+    pub sync_playlists: Vec<String>,
+    /// Outer position of the window in physical pixels when it was last closed
+    pub window_position: Option<[i32; 2]>,
+    /// Inner size of the window in physical pixels when it was last closed
+    pub window_size: Option<[u32; 2]>,
+    /// The position and size are those before the window was maximized
+    pub window_maximized: bool,
+    pub dropped_files_action: DroppedFilesAction,
+    pub song_grouping: SongGrouping,
+    /// Songs created within this number of days are shown in the Recently Added playlist
+    pub recently_added_days: u64,
+    /// Hue of the accent colors in degrees, the colors of the theme file are used if it is not set
+    pub accent_hue: Option<f32>,
+    /// Name or path of the yt-dlp program
+    pub yt_dlp_path: String,
+    /// Name or path of the aacgain program
+    pub aacgain_path: String,
+    /// Name or path of the mp3gain program
+    pub mp3gain_path: String,
+    /// Name or path of the ffmpeg program
+    pub ffmpeg_path: String,
+    /// Proxy URL that yt-dlp connects through
+    pub download_proxy: String,
+    /// Maximum download rate like "500K" or "2M"
+    pub download_rate_limit: String,
+    /// Netscape cookies file for downloads that require a login
+    pub cookies_file: String,
+    /// Number of downloads that run at the same time
+    pub parallel_downloads: usize,
+    /// Visible columns of the song list
+    pub song_columns: Vec<Column>,
+    /// Relative widths of the columns that share the width of the song list, 1 by default
+    pub column_weights: HashMap<Column, f32>,
+    /// Steps that are run on downloaded songs in this order
+    pub postprocessors: Vec<Postprocessor>,
+    /// Font files whose glyphs are used for characters that the main font doesn't contain
+    pub fallback_fonts: Vec<String>,
+    pub keymap: Keymap,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            auto_save: false,
+            drop_at_end: false,
+            close_to_tray: false,
+            always_on_top: false,
+            follow_playing_song: false,
+            show_remaining_time: false,
+            vim_mode: false,
+            scroll_long_titles: false,
+            remote_control: false,
+            remote_control_address: remote::DEFAULT_ADDRESS.to_string(),
+            sync_path: String::new(),
+            sync_playlists: Vec::new(),
+            window_position: None,
+            window_size: None,
+            window_maximized: false,
+            dropped_files_action: DroppedFilesAction::default(),
+            song_grouping: SongGrouping::default(),
+            recently_added_days: 30,
+            accent_hue: None,
+            yt_dlp_path: "yt-dlp".to_string(),
+            aacgain_path: "aacgain".to_string(),
+            mp3gain_path: "mp3gain".to_string(),
+            ffmpeg_path: "ffmpeg".to_string(),
+            download_proxy: String::new(),
+            download_rate_limit: String::new(),
+            cookies_file: String::new(),
+            parallel_downloads: 3,
+            song_columns: vec![Column::Song, Column::Artist, Column::Duration],
+            column_weights: HashMap::new(),
+            postprocessors: vec![Postprocessor::Aacgain],
+            fallback_fonts: fonts::get_default_fallback_fonts(),
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn get_column_weight(&self, column: Column) -> f32 {
+        self.column_weights.get(&column).copied().unwrap_or(1.0)
+    }
+}
+
+/// Loads the settings from the data directory. Each line of the settings file contains a key and
+/// its value separated by `=`, unknown keys are ignored.
+pub fn load(base_path: &str) -> Settings {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(SETTINGS_FILE_NAME);
+    let mut settings = Settings::default();
+    for (key, value) in fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| x.split_once('='))
+    {
+        let value = value.trim();
+        match key.trim() {
+            "auto_save" => settings.auto_save = value == "true",
+            "drop_at_end" => settings.drop_at_end = value == "true",
+            "close_to_tray" => settings.close_to_tray = value == "true",
+            "always_on_top" => settings.always_on_top = value == "true",
+            "follow_playing_song" => settings.follow_playing_song = value == "true",
+            "show_remaining_time" => settings.show_remaining_time = value == "true",
+            "vim_mode" => settings.vim_mode = value == "true",
+            "scroll_long_titles" => settings.scroll_long_titles = value == "true",
+            "remote_control" => settings.remote_control = value == "true",
+            "remote_control_address" => {
+                if !value.is_empty() {
+                    settings.remote_control_address = value.to_string();
+                }
+            }
+            "sync_path" => settings.sync_path = value.to_string(),
+            // Playlist names are separated by tabs since they may contain commas
+            "sync_playlists" => {
+                settings.sync_playlists = value
+                    .split('\t')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_string())
+                    .collect()
+            }
+            "window_position" => {
+                if let Some((x, y)) = value.split_once(',') {
+                    if let (Ok(x), Ok(y)) = (x.trim().parse(), y.trim().parse()) {
+                        settings.window_position = Some([x, y]);
+                    }
+                }
+            }
+            "window_size" => {
+                let size: Vec<u32> = value
+                    .split(',')
+                    .filter_map(|x| x.trim().parse().ok())
+                    .collect();
+                if let [width, height] = size[..] {
+                    if width > 0 && height > 0 {
+                        settings.window_size = Some([width, height]);
+                    }
+                }
+            }
+            "window_maximized" => settings.window_maximized = value == "true",
+            "dropped_files_action" => {
+                settings.dropped_files_action = DroppedFilesAction::ALL
+                    .into_iter()
+                    .find(|x| x.key() == value)
+                    .unwrap_or_default()
+            }
+            "song_grouping" => {
+                settings.song_grouping = SongGrouping::ALL
+                    .into_iter()
+                    .find(|x| x.key() == value)
+                    .unwrap_or_default()
+            }
+            "recently_added_days" => {
+                if let Ok(days) = value.parse() {
+                    settings.recently_added_days = days;
+                }
+            }
+            "accent_hue" => {
+                settings.accent_hue = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|x| x.is_finite())
+                    .map(|x| x.rem_euclid(360.0))
+            }
+            "yt_dlp_path" => settings.yt_dlp_path = value.to_string(),
+            "aacgain_path" => settings.aacgain_path = value.to_string(),
+            "mp3gain_path" => settings.mp3gain_path = value.to_string(),
+            "ffmpeg_path" => settings.ffmpeg_path = value.to_string(),
+            "download_proxy" => settings.download_proxy = value.to_string(),
+            "download_rate_limit" => settings.download_rate_limit = value.to_string(),
+            "cookies_file" => settings.cookies_file = value.to_string(),
+            "parallel_downloads" => {
+                if let Ok(count) = value.parse::<usize>() {
+                    settings.parallel_downloads = count.max(1);
+                }
+            }
+            "song_columns" => {
+                let columns: Vec<Column> = Column::ALL
+                    .into_iter()
+                    .filter(|x| value.split(',').any(|y| y.trim() == x.key()))
+                    .collect();
+                if !columns.is_empty() {
+                    settings.song_columns = columns;
+                }
+            }
+            "column_weights" => {
+                for (key, weight) in value.split(',').filter_map(|x| x.split_once(':')) {
+                    let column = Column::ALL.into_iter().find(|x| x.key() == key.trim());
+                    if let (Some(column), Ok(weight)) = (column, weight.trim().parse::<f32>()) {
+                        if weight.is_finite() && weight > 0.0 {
+                            settings.column_weights.insert(column, weight);
+                        }
+                    }
+                }
+            }
+            "postprocessors" => {
+                settings.postprocessors = value
+                    .split(',')
+                    .filter_map(|x| Postprocessor::ALL.into_iter().find(|y| y.key() == x.trim()))
+                    .collect()
+            }
+            // Separated like the PATH variable since the paths may contain commas
+            "fallback_fonts" => {
+                settings.fallback_fonts = env::split_paths(value)
+                    .filter(|x| !x.as_os_str().is_empty())
+                    .map(|x| x.to_string_lossy().to_string())
+                    .collect()
+            }
+            key => {
+                if let Some(command) = key.strip_prefix("shortcut.") {
+                    settings.keymap.parse_bindings(command, value);
+                }
+            }
+        }
+    }
+    settings
+}
+
+pub fn save(state: &mut State) {
+    let data_path = Path::new(&state.base_path).join(app::DATA_DIRECTORY);
+    let settings = &state.settings;
+    let lines: Vec<(&str, String)> = vec![
+        ("auto_save", settings.auto_save.to_string()),
+        ("drop_at_end", settings.drop_at_end.to_string()),
+        ("close_to_tray", settings.close_to_tray.to_string()),
+        ("always_on_top", settings.always_on_top.to_string()),
+        (
+            "follow_playing_song",
+            settings.follow_playing_song.to_string(),
+        ),
+        (
+            "show_remaining_time",
+            settings.show_remaining_time.to_string(),
+        ),
+        ("vim_mode", settings.vim_mode.to_string()),
+        (
+            "scroll_long_titles",
+            settings.scroll_long_titles.to_string(),
+        ),
+        ("remote_control", settings.remote_control.to_string()),
+        (
+            "remote_control_address",
+            settings.remote_control_address.clone(),
+        ),
+        ("sync_path", settings.sync_path.clone()),
+        ("sync_playlists", settings.sync_playlists.join("\t")),
+        (
+            "window_position",
+            settings
+                .window_position
+                .map(|[x, y]| format!("{x},{y}"))
+                .unwrap_or_default(),
+        ),
+        (
+            "window_size",
+            settings
+                .window_size
+                .map(|[width, height]| format!("{width},{height}"))
+                .unwrap_or_default(),
+        ),
+        ("window_maximized", settings.window_maximized.to_string()),
+        (
+            "dropped_files_action",
+            settings.dropped_files_action.key().to_string(),
+        ),
+        ("song_grouping", settings.song_grouping.key().to_string()),
+        (
+            "recently_added_days",
+            settings.recently_added_days.to_string(),
+        ),
+        (
+            "accent_hue",
+            settings
+                .accent_hue
+                .map(|x| format!("{x:.1}"))
+                .unwrap_or_default(),
+        ),
+        ("yt_dlp_path", settings.yt_dlp_path.clone()),
+        ("aacgain_path", settings.aacgain_path.clone()),
+        ("mp3gain_path", settings.mp3gain_path.clone()),
+        ("ffmpeg_path", settings.ffmpeg_path.clone()),
+        ("download_proxy", settings.download_proxy.clone()),
+        ("download_rate_limit", settings.download_rate_limit.clone()),
+        ("cookies_file", settings.cookies_file.clone()),
+        (
+            "parallel_downloads",
+            settings.parallel_downloads.to_string(),
+        ),
+        (
+            "song_columns",
+            settings
+                .song_columns
+                .iter()
+                .map(|x| x.key())
+                .collect::<Vec<&str>>()
+                .join(","),
+        ),
+        (
+            "column_weights",
+            Column::ALL
+                .iter()
+                .filter_map(|x| {
+                    Some(format!(
+                        "{}:{:.3}",
+                        x.key(),
+                        settings.column_weights.get(x)?
+                    ))
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
+        (
+            "postprocessors",
+            settings
+                .postprocessors
+                .iter()
+                .map(|x| x.key())
+                .collect::<Vec<&str>>()
+                .join(","),
+        ),
+        (
+            "fallback_fonts",
+            env::join_paths(&settings.fallback_fonts)
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        ),
+    ];
+    let mut content: String = lines
+        .iter()
+        .map(|(key, value)| format!("{key}={value}\n"))
+        .collect();
+    content.push_str(&settings.keymap.to_settings_lines());
+
+    let result = fs::create_dir_all(&data_path)
+        .and_then(|_| fs::write(data_path.join(SETTINGS_FILE_NAME), content));
+    if let Err(e) = result {
+        state.push_status(StatusType::Error, format!("Could not save settings:\n{e}"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+};
+
+use crate::{
+    app::{State, StatusType},
+    util,
+};
+
+/// Folder on the device for songs from outside of the music directory
+const EXTERNAL_DIRECTORY: &str = "External";
+
+pub enum SyncMessage {
+    /// Number of handled songs, number of all songs and the path of the current song
+    Progress(usize, usize, String),
+    Error(String),
+    /// Number of copied songs and number of songs that were already on the device
+    Finished(usize, usize),
+}
+
+/// Returns the path of the song relative to the folder on the device. Songs keep their folders
+/// from the music directory so that playlists from several syncs share the files.
+fn get_device_path(song_path: &str) -> String {
+    let path = Path::new(song_path);
+    if path.is_absolute() {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        format!("{EXTERNAL_DIRECTORY}/{file_name}")
+    } else {
+        song_path.replace('\\', "/")
+    }
+}
+
+/// Copies without the permissions since MTP mounts reject them.
+fn copy_file(source: &Path, destination: &Path) -> io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut source = File::open(source)?;
+    let mut destination = File::create(destination)?;
+    io::copy(&mut source, &mut destination)?;
+    Ok(())
+}
+
+/// Copies the songs that are not on the device yet and writes the playlist files. Files with the
+/// same size are assumed to be unchanged.
+fn run(
+    base_path: String,
+    target: PathBuf,
+    song_paths: Vec<String>,
+    playlist_files: Vec<(String, String)>,
+    sender: Sender<SyncMessage>,
+) {
+    let mut copied_count = 0;
+    let mut skipped_count = 0;
+    for (i, song_path) in song_paths.iter().enumerate() {
+        if sender
+            .send(SyncMessage::Progress(
+                i,
+                song_paths.len(),
+                song_path.clone(),
+            ))
+            .is_err()
+        {
+            return;
+        }
+        let source = Path::new(&base_path).join(song_path);
+        let destination = target.join(get_device_path(song_path));
+        let is_synced = match (fs::metadata(&source), fs::metadata(&destination)) {
+            (Ok(source), Ok(destination)) => source.len() == destination.len(),
+            _ => false,
+        };
+        if is_synced {
+            skipped_count += 1;
+            continue;
+        }
+        match copy_file(&source, &destination) {
+            Ok(()) => copied_count += 1,
+            Err(e) => {
+                // A partially copied file would be taken as synced next time if its size matched
+                let _ = fs::remove_file(&destination);
+                let _ = sender.send(SyncMessage::Error(format!(
+                    "Could not copy \"{song_path}\":\n{e}"
+                )));
+            }
+        }
+    }
+
+    for (file_name, content) in playlist_files {
+        if let Err(e) = fs::write(target.join(&file_name), content) {
+            let _ = sender.send(SyncMessage::Error(format!(
+                "Could not write \"{file_name}\":\n{e}"
+            )));
+        }
+    }
+    let _ = sender.send(SyncMessage::Finished(copied_count, skipped_count));
+}
+
+/// Starts copying the checked playlists and their songs to the folder of the device in the
+/// background. Each playlist is written as an m3u file with paths relative to the folder.
+pub fn start(state: &mut State) {
+    if state.sync_rx.is_some() {
+        return;
+    }
+    let target = PathBuf::from(state.settings.sync_path.trim());
+    if !target.is_dir() {
+        state.push_status(
+            StatusType::Error,
+            format!(
+                "{} is not a folder, is the device mounted?",
+                target.to_string_lossy()
+            ),
+        );
+        return;
+    }
+
+    let mut song_paths = Vec::new();
+    let mut added_paths = HashSet::new();
+    let mut playlist_files = Vec::new();
+    for playlist in state
+        .playlists
+        .iter()
+        .filter(|x| state.settings.sync_playlists.contains(&x.name))
+    {
+        let mut content = "#EXTM3U".to_string();
+        for song in playlist.songs.iter().filter(|x| x.exists) {
+            if added_paths.insert(&song.path) {
+                song_paths.push(song.path.clone());
+            }
+            content += &format!(
+                "\n#EXTINF:{},{} - {}\n{}",
+                song.duration.unwrap_or(0) / 1000,
+                song.artist,
+                song.name,
+                get_device_path(&song.path)
+            );
+        }
+        let file_name = format!("{}.m3u", util::sanitize_file_name(&playlist.name));
+        playlist_files.push((file_name, content + "\n"));
+    }
+    if playlist_files.is_empty() {
+        state.push_status(StatusType::Error, "No playlists are checked".to_string());
+        return;
+    }
+
+    let base_path = state.base_path.clone();
+    let (sender, receiver) = mpsc::channel();
+    state.spawn_writer(move || run(base_path, target, song_paths, playlist_files, sender));
+    state.sync_rx = Some(receiver);
+    state.sync_progress = None;
+}
+
+/// Stops the sync after the file that is being copied.
+pub fn cancel(state: &mut State) {
+    // The thread stops once it can't send its progress anymore
+    state.sync_rx = None;
+    state.sync_progress = None;
+    state.push_status(StatusType::Info, "Sync canceled".to_string());
+}
+
+pub fn update(state: &mut State) {
+    let messages = match state.sync_rx {
+        Some(ref receiver) => util::receive_all(receiver),
+        None => return,
+    };
+
+    for message in messages {
+        match message {
+            SyncMessage::Progress(done, total, path) => {
+                state.sync_progress = Some((done, total, path));
+            }
+            SyncMessage::Error(e) => state.push_status(StatusType::Error, e),
+            SyncMessage::Finished(copied_count, skipped_count) => {
+                state.push_status(
+                    StatusType::Info,
+                    format!(
+                        "Synced playlists, copied {copied_count} songs, {skipped_count} songs were already on the device"
+                    ),
+                );
+                state.sync_rx = None;
+                state.sync_progress = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+
+/// Tags read from a song file. Songs without tags use the file name for their artist and name.
+#[derive(Clone, Default, Hash)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<String>,
+    pub date: Option<String>,
+    pub genres: Vec<String>,
+    /// Rating from 0 to 5 stars
+    pub rating: Option<u8>,
+}
+
+/// Converts ratings stored as 1-5 stars or as percentages like in Vorbis comments to stars.
+fn parse_rating(value: &str) -> Option<u8> {
+    let rating: u32 = value.split('/').next()?.trim().parse().ok()?;
+    let stars = match rating {
+        0..=5 => rating,
+        _ => (rating.min(100) + 10) / 20,
+    };
+    Some(stars as u8)
+}
+
+/// Converts the 0-255 rating of an ID3 POPM frame to stars like Windows Media Player, which writes
+/// 1, 64, 128, 196 and 255 for 1 to 5 stars.
+fn parse_popm_rating(value: &str) -> Option<u8> {
+    let stars = match value.parse::<u8>().ok()? {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        224..=255 => 5,
+    };
+    Some(stars)
+}
+
+fn apply_revision(tags: &mut Tags, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        let value = tag.value.to_string().trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => tags.title = Some(value),
+            Some(StandardTagKey::Artist) => tags.artist = Some(value),
+            Some(StandardTagKey::Album) => tags.album = Some(value),
+            Some(StandardTagKey::AlbumArtist) => tags.album_artist = Some(value),
+            Some(StandardTagKey::TrackNumber) => tags.track_number = Some(value),
+            Some(StandardTagKey::Date) => tags.date = Some(value),
+            // POPM frames are keyed by the frame and the email of the rating user
+            Some(StandardTagKey::Rating) if tag.key.starts_with("POPM") => {
+                tags.rating = parse_popm_rating(&value)
+            }
+            Some(StandardTagKey::Rating) => tags.rating = parse_rating(&value),
+            Some(StandardTagKey::Genre) => {
+                for genre in value.split(';') {
+                    let genre = genre.trim().to_string();
+                    if !genre.is_empty() && !tags.genres.contains(&genre) {
+                        tags.genres.push(genre);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn read(path: &Path) -> Option<Tags> {
+    let mss = symphonia::core::io::MediaSourceStream::new(
+        Box::new(File::open(path).ok()?),
+        Default::default(),
+    );
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .ok()?;
+
+    let mut tags = Tags::default();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|x| x.current()) {
+        apply_revision(&mut tags, revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        apply_revision(&mut tags, revision);
+    }
+    Some(tags)
+}
+
+/// Reads the tags of all songs in the background. Songs are identified by their path relative to
+/// the base path.
+pub fn start_loading(base_path: &str, song_paths: Vec<String>) -> Receiver<(String, Tags)> {
+    let base_path = PathBuf::from(base_path);
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for song_path in song_paths {
+            if let Some(tags) = read(&base_path.join(&song_path)) {
+                if sender.send((song_path, tags)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
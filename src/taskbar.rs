@@ -0,0 +1,292 @@
+use std::{
+    ffi,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+        System::Com::{
+            CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+        },
+        UI::{
+            Shell::{
+                DefSubclassProc, ITaskbarList3, SetWindowSubclass, TaskbarList, TBPFLAG,
+                TBPF_NOPROGRESS, TBPF_NORMAL, TBPF_PAUSED, THBF_ENABLED, THBN_CLICKED, THB_FLAGS,
+                THB_ICON, THB_TOOLTIP, THUMBBUTTON,
+            },
+            WindowsAndMessaging::{CreateIcon, RegisterWindowMessageW, HICON, WM_COMMAND},
+        },
+    },
+};
+
+use crate::app::State;
+
+const PREV_ID: u32 = 1;
+const PLAY_PAUSE_ID: u32 = 2;
+const NEXT_ID: u32 = 3;
+const ICON_SIZE: usize = 16;
+/// Progress is only updated when it changes by this fraction to avoid calls in every frame
+const PROGRESS_STEPS: u64 = 1000;
+
+/// Ids of the thumbnail buttons that were clicked, filled by the window procedure
+static CLICKED_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+/// Message that is sent when the taskbar button was created, e.g. after Explorer restarted
+static BUTTON_CREATED_MESSAGE: AtomicU32 = AtomicU32::new(0);
+static IS_BUTTON_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Actions that are triggered through the thumbnail toolbar
+pub enum TaskbarAction {
+    Prev,
+    PlayPause,
+    Next,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProgressState {
+    None,
+    Normal(u64),
+    Paused(u64),
+}
+
+/// Progress on the taskbar button and buttons in the thumbnail of the window
+pub struct Taskbar {
+    list: ITaskbarList3,
+    hwnd: HWND,
+    prev_icon: HICON,
+    play_icon: HICON,
+    pause_icon: HICON,
+    next_icon: HICON,
+    has_buttons: bool,
+    is_playing: bool,
+    progress: ProgressState,
+}
+
+unsafe extern "system" fn window_procedure(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _id: usize,
+    _data: usize,
+) -> LRESULT {
+    if message == WM_COMMAND && (wparam.0 >> 16) as u32 & 0xffff == THBN_CLICKED {
+        CLICKED_IDS.lock().unwrap().push(wparam.0 as u32 & 0xffff);
+    } else if message == BUTTON_CREATED_MESSAGE.load(Ordering::Relaxed) {
+        IS_BUTTON_CREATED.store(true, Ordering::Relaxed);
+    }
+    DefSubclassProc(hwnd, message, wparam, lparam)
+}
+
+/// Draws a white symbol into an icon since there are no icon files.
+fn create_icon(is_inside: impl Fn(f32, f32) -> bool) -> Result<HICON, String> {
+    let mut bgra = Vec::with_capacity(ICON_SIZE * ICON_SIZE * 4);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let alpha = if is_inside(x as f32 + 0.5, y as f32 + 0.5) {
+                u8::MAX
+            } else {
+                0
+            };
+            bgra.extend_from_slice(&[u8::MAX, u8::MAX, u8::MAX, alpha]);
+        }
+    }
+    // The mask is unused since the colors have an alpha channel
+    let mask = vec![0u8; ICON_SIZE * ICON_SIZE / 8];
+    unsafe {
+        CreateIcon(
+            HINSTANCE::default(),
+            ICON_SIZE as i32,
+            ICON_SIZE as i32,
+            1,
+            32,
+            mask.as_ptr(),
+            bgra.as_ptr(),
+        )
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Whether the point is inside a triangle pointing right (or left) between the x positions.
+fn is_in_triangle(x: f32, y: f32, left: f32, right: f32, is_pointing_right: bool) -> bool {
+    let size = ICON_SIZE as f32;
+    let distance_to_tip = if is_pointing_right {
+        right - x
+    } else {
+        x - left
+    };
+    let half_height = distance_to_tip / (right - left) * size * 0.35;
+    x >= left && x <= right && (y - size / 2.0).abs() <= half_height
+}
+
+fn is_in_bar(x: f32, y: f32, left: f32, right: f32) -> bool {
+    let size = ICON_SIZE as f32;
+    x >= left && x <= right && y >= size * 0.15 && y <= size * 0.85
+}
+
+fn create_button(id: u32, icon: HICON, tooltip: &str) -> THUMBBUTTON {
+    let mut button = THUMBBUTTON {
+        dwMask: THB_ICON | THB_TOOLTIP | THB_FLAGS,
+        iId: id,
+        hIcon: icon,
+        dwFlags: THBF_ENABLED,
+        ..Default::default()
+    };
+    for (i, c) in tooltip
+        .encode_utf16()
+        .take(button.szTip.len() - 1)
+        .enumerate()
+    {
+        button.szTip[i] = c;
+    }
+    button
+}
+
+/// Connects to the taskbar button of the window.
+pub fn init(hwnd: *mut ffi::c_void) -> Result<Taskbar, String> {
+    let hwnd = HWND(hwnd);
+    let list: ITaskbarList3 = unsafe {
+        // COM may already be initialized by the window library, which is fine
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).map_err(|e| e.to_string())?
+    };
+    unsafe {
+        list.HrInit().map_err(|e| e.to_string())?;
+        BUTTON_CREATED_MESSAGE.store(
+            RegisterWindowMessageW(w!("TaskbarButtonCreated")),
+            Ordering::Relaxed,
+        );
+        if !SetWindowSubclass(hwnd, Some(window_procedure), 0, 0).as_bool() {
+            return Err("Could not receive the clicks of the thumbnail buttons".to_string());
+        }
+    }
+
+    let size = ICON_SIZE as f32;
+    let mut taskbar = Taskbar {
+        list,
+        hwnd,
+        prev_icon: create_icon(|x, y| {
+            is_in_bar(x, y, size * 0.15, size * 0.3)
+                || is_in_triangle(x, y, size * 0.3, size * 0.85, false)
+        })?,
+        play_icon: create_icon(|x, y| is_in_triangle(x, y, size * 0.2, size * 0.85, true))?,
+        pause_icon: create_icon(|x, y| {
+            is_in_bar(x, y, size * 0.2, size * 0.4) || is_in_bar(x, y, size * 0.6, size * 0.8)
+        })?,
+        next_icon: create_icon(|x, y| {
+            is_in_triangle(x, y, size * 0.15, size * 0.7, true)
+                || is_in_bar(x, y, size * 0.7, size * 0.85)
+        })?,
+        has_buttons: false,
+        is_playing: false,
+        progress: ProgressState::None,
+    };
+    // Fails if the taskbar button doesn't exist yet, then they are added once it is created
+    taskbar.add_buttons();
+    Ok(taskbar)
+}
+
+impl Taskbar {
+    fn get_buttons(&self) -> [THUMBBUTTON; 3] {
+        let (play_pause_icon, play_pause_tooltip) = if self.is_playing {
+            (self.pause_icon, "Pause")
+        } else {
+            (self.play_icon, "Play")
+        };
+        [
+            create_button(PREV_ID, self.prev_icon, "Previous song"),
+            create_button(PLAY_PAUSE_ID, play_pause_icon, play_pause_tooltip),
+            create_button(NEXT_ID, self.next_icon, "Next song"),
+        ]
+    }
+
+    fn add_buttons(&mut self) {
+        self.has_buttons = unsafe {
+            self.list
+                .ThumbBarAddButtons(self.hwnd, &self.get_buttons())
+                .is_ok()
+        };
+    }
+
+    fn set_progress_state(&self, flag: TBPFLAG) {
+        let _ = unsafe { self.list.SetProgressState(self.hwnd, flag) };
+    }
+
+    /// Shows the progress of the downloads or otherwise of the playing song and updates the
+    /// play/pause button.
+    pub fn update(&mut self, state: &State) {
+        if IS_BUTTON_CREATED.swap(false, Ordering::Relaxed) {
+            // The buttons and progress have to be set again for a new taskbar button
+            self.is_playing = state.is_playing;
+            self.add_buttons();
+            self.progress = ProgressState::None;
+            self.set_progress_state(TBPF_NOPROGRESS);
+        } else if self.has_buttons && self.is_playing != state.is_playing {
+            self.is_playing = state.is_playing;
+            let _ = unsafe {
+                self.list
+                    .ThumbBarUpdateButtons(self.hwnd, &self.get_buttons())
+            };
+        }
+
+        let song_fraction = state
+            .playing_playlist_index
+            .zip(state.playing_song_index)
+            .and_then(|(playlist_index, song_index)| {
+                state.playlists[playlist_index].songs[song_index].duration
+            })
+            .filter(|x| *x > 0)
+            .map(|x| *state.position.lock().unwrap() as f32 / x as f32);
+        let progress = if !state.download_jobs.is_empty() {
+            let fraction = state
+                .download_jobs
+                .iter()
+                .map(|x| x.get_fraction())
+                .sum::<f32>()
+                / state.download_jobs.len() as f32;
+            ProgressState::Normal((fraction.clamp(0.0, 1.0) * PROGRESS_STEPS as f32) as u64)
+        } else if let Some(fraction) = song_fraction {
+            let steps = (fraction.clamp(0.0, 1.0) * PROGRESS_STEPS as f32) as u64;
+            if state.is_playing {
+                ProgressState::Normal(steps)
+            } else {
+                ProgressState::Paused(steps)
+            }
+        } else {
+            ProgressState::None
+        };
+        if progress == self.progress {
+            return;
+        }
+        self.progress = progress;
+        match progress {
+            ProgressState::None => self.set_progress_state(TBPF_NOPROGRESS),
+            ProgressState::Normal(steps) | ProgressState::Paused(steps) => {
+                let flag = if matches!(progress, ProgressState::Paused(_)) {
+                    TBPF_PAUSED
+                } else {
+                    TBPF_NORMAL
+                };
+                self.set_progress_state(flag);
+                let _ = unsafe { self.list.SetProgressValue(self.hwnd, steps, PROGRESS_STEPS) };
+            }
+        }
+    }
+
+    /// Returns the actions that were triggered since the last call.
+    pub fn receive_actions(&self) -> Vec<TaskbarAction> {
+        std::mem::take(&mut *CLICKED_IDS.lock().unwrap())
+            .into_iter()
+            .filter_map(|id| match id {
+                PREV_ID => Some(TaskbarAction::Prev),
+                PLAY_PAUSE_ID => Some(TaskbarAction::PlayPause),
+                NEXT_ID => Some(TaskbarAction::Next),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,238 @@
+use std::{fs, path::Path};
+
+use imgui::{Style, StyleColor};
+
+use crate::{app, util};
+
+const THEME_FILE_NAME: &str = "theme.toml";
+/// Only the hue of the accent colors can be chosen, their saturation and brightness are fixed
+const ACCENT_SATURATION: f32 = 1.0;
+const PRIMARY1_VALUE: f32 = 0.50;
+const PRIMARY2_VALUE: f32 = 0.61;
+const DRAG_ALPHA: f32 = 0.85;
+
+/// Colors and sizes of the user interface, which can be changed in the theme file
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub text1: [f32; 4],
+    /// Text of less important information like durations and folders
+    pub text2: [f32; 4],
+    /// Background colors from darkest to brightest
+    pub dark1: [f32; 4],
+    pub dark2: [f32; 4],
+    pub dark3: [f32; 4],
+    pub dark4: [f32; 4],
+    pub dark5: [f32; 4],
+    pub dark6: [f32; 4],
+    pub dark7: [f32; 4],
+    /// Accent color of selections and headers
+    pub primary1: [f32; 4],
+    /// Accent color of hovered selections and sliders
+    pub primary2: [f32; 4],
+    pub playing: [f32; 4],
+    pub not_existing: [f32; 4],
+    pub drag: [f32; 4],
+    pub info: [f32; 4],
+    pub error: [f32; 4],
+    /// Rounding of buttons, textboxes and sliders
+    pub frame_rounding: f32,
+    pub popup_rounding: f32,
+    pub scrollbar_rounding: f32,
+    /// Padding inside of buttons and textboxes
+    pub frame_padding: [f32; 2],
+    /// Padding inside of context menus and popups
+    pub popup_padding: [f32; 2],
+    pub item_spacing: [f32; 2],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            text1: [0.80, 0.80, 0.80, 1.0],
+            text2: [0.50, 0.50, 0.50, 1.0],
+            dark1: [0.07, 0.07, 0.07, 1.0],
+            dark2: [0.11, 0.11, 0.11, 1.0],
+            dark3: [0.13, 0.13, 0.13, 1.0],
+            dark4: [0.14, 0.14, 0.14, 1.0],
+            dark5: [0.15, 0.15, 0.15, 1.0],
+            dark6: [0.17, 0.17, 0.17, 1.0],
+            dark7: [0.20, 0.20, 0.20, 1.0],
+            primary1: [0.00, 0.28, 0.50, 1.0],
+            primary2: [0.00, 0.34, 0.61, 1.0],
+            playing: [0.00, 0.70, 0.00, 1.0],
+            not_existing: [0.70, 0.00, 0.00, 1.0],
+            drag: [0.00, 0.28, 0.50, 0.85],
+            info: [0.0, 0.2, 0.4, 1.0],
+            error: [0.4, 0.0, 0.0, 1.0],
+            frame_rounding: 0.0,
+            popup_rounding: 0.0,
+            scrollbar_rounding: f32::MAX,
+            frame_padding: [4.0, 3.0],
+            popup_padding: [4.0, 10.0],
+            item_spacing: [8.0, 4.0],
+        }
+    }
+}
+
+impl Theme {
+    pub fn hovered_bg(&self) -> [f32; 4] {
+        self.dark5
+    }
+
+    pub fn active_bg(&self) -> [f32; 4] {
+        self.dark6
+    }
+
+    /// Hue of the accent colors in degrees
+    pub fn accent_hue(&self) -> f32 {
+        util::rgb_to_hue(self.primary1)
+    }
+
+    /// Sets the accent colors of selections, sliders and dragged items to the hue.
+    pub fn set_accent_hue(&mut self, hue: f32) {
+        let [r, g, b] = util::hsv_to_rgb(hue, ACCENT_SATURATION, PRIMARY1_VALUE);
+        self.primary1 = [r, g, b, 1.0];
+        self.drag = [r, g, b, DRAG_ALPHA];
+        let [r, g, b] = util::hsv_to_rgb(hue, ACCENT_SATURATION, PRIMARY2_VALUE);
+        self.primary2 = [r, g, b, 1.0];
+    }
+
+    fn color_mut(&mut self, name: &str) -> Option<&mut [f32; 4]> {
+        Some(match name {
+            "text1" => &mut self.text1,
+            "text2" => &mut self.text2,
+            "dark1" => &mut self.dark1,
+            "dark2" => &mut self.dark2,
+            "dark3" => &mut self.dark3,
+            "dark4" => &mut self.dark4,
+            "dark5" => &mut self.dark5,
+            "dark6" => &mut self.dark6,
+            "dark7" => &mut self.dark7,
+            "primary1" => &mut self.primary1,
+            "primary2" => &mut self.primary2,
+            "playing" => &mut self.playing,
+            "not_existing" => &mut self.not_existing,
+            "drag" => &mut self.drag,
+            "info" => &mut self.info,
+            "error" => &mut self.error,
+            _ => return None,
+        })
+    }
+
+    fn size_mut(&mut self, name: &str) -> Option<&mut f32> {
+        Some(match name {
+            "frame_rounding" => &mut self.frame_rounding,
+            "popup_rounding" => &mut self.popup_rounding,
+            "scrollbar_rounding" => &mut self.scrollbar_rounding,
+            _ => return None,
+        })
+    }
+
+    fn padding_mut(&mut self, name: &str) -> Option<&mut [f32; 2]> {
+        Some(match name {
+            "frame_padding" => &mut self.frame_padding,
+            "popup_padding" => &mut self.popup_padding,
+            "item_spacing" => &mut self.item_spacing,
+            _ => return None,
+        })
+    }
+
+    /// Sets the colors and sizes of the imgui style that are used by the default widgets.
+    pub fn apply(&self, style: &mut Style) {
+        style.cell_padding = [0.0, 0.0];
+        style.window_padding = [0.0, 0.0];
+        style.window_border_size = 0.0;
+        style.frame_rounding = self.frame_rounding;
+        style.grab_rounding = self.frame_rounding;
+        style.popup_rounding = self.popup_rounding;
+        style.scrollbar_rounding = self.scrollbar_rounding;
+        style.frame_padding = self.frame_padding;
+        style.item_spacing = self.item_spacing;
+
+        style[StyleColor::Text] = self.text1;
+        style[StyleColor::FrameBg] = self.dark2;
+        style[StyleColor::FrameBgHovered] = self.hovered_bg();
+        style[StyleColor::FrameBgActive] = self.active_bg();
+        style[StyleColor::SliderGrab] = self.primary2;
+        style[StyleColor::SliderGrabActive] = self.primary2;
+        style[StyleColor::Button] = self.dark2;
+        style[StyleColor::ButtonHovered] = self.hovered_bg();
+        style[StyleColor::ButtonActive] = self.active_bg();
+        style[StyleColor::Header] = self.primary1;
+        style[StyleColor::HeaderHovered] = self.primary2;
+        style[StyleColor::HeaderActive] = self.primary1;
+    }
+}
+
+/// Parses a color written as `"#rrggbb"`, `"#rrggbbaa"` or an array of 3 or 4 numbers between 0
+/// and 1.
+fn parse_color(value: &toml::Value) -> Option<[f32; 4]> {
+    match value {
+        toml::Value::String(text) => {
+            let hex = text.strip_prefix('#')?;
+            if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+                return None;
+            }
+            let mut color = [1.0; 4];
+            for (i, component) in color.iter_mut().take(hex.len() / 2).enumerate() {
+                *component = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()? as f32 / 255.0;
+            }
+            Some(color)
+        }
+        toml::Value::Array(values) => match parse_numbers(values)?[..] {
+            [r, g, b] => Some([r, g, b, 1.0]),
+            [r, g, b, a] => Some([r, g, b, a]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_number(value: &toml::Value) -> Option<f32> {
+    match value {
+        toml::Value::Float(x) => Some(*x as f32),
+        toml::Value::Integer(x) => Some(*x as f32),
+        _ => None,
+    }
+}
+
+fn parse_numbers(values: &[toml::Value]) -> Option<Vec<f32>> {
+    values.iter().map(parse_number).collect()
+}
+
+/// Loads the theme from the data directory. The theme file contains a `[colors]` table and a
+/// `[style]` table, missing values keep their default and unknown keys are ignored.
+pub fn load(base_path: &str) -> Result<Theme, String> {
+    let path = Path::new(base_path)
+        .join(app::DATA_DIRECTORY)
+        .join(THEME_FILE_NAME);
+    let mut theme = Theme::default();
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(theme);
+    };
+    let table: toml::Table = content.parse().map_err(|e| format!("{e}"))?;
+
+    if let Some(colors) = table.get("colors").and_then(|x| x.as_table()) {
+        for (key, value) in colors {
+            if let Some(color) = theme.color_mut(key) {
+                *color = parse_color(value).ok_or_else(|| format!("Invalid color of {key}"))?;
+            }
+        }
+    }
+    if let Some(style) = table.get("style").and_then(|x| x.as_table()) {
+        for (key, value) in style {
+            if let Some(size) = theme.size_mut(key) {
+                *size = parse_number(value)
+                    .filter(|x| *x >= 0.0)
+                    .ok_or_else(|| format!("Invalid value of {key}"))?;
+            } else if let Some(padding) = theme.padding_mut(key) {
+                *padding = value
+                    .as_array()
+                    .and_then(|x| parse_numbers(x))
+                    .and_then(|x| x.try_into().ok())
+                    .ok_or_else(|| format!("Invalid value of {key}"))?;
+            }
+        }
+    }
+    Ok(theme)
+}
@@ -0,0 +1,116 @@
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+const SHOW_ID: &str = "show";
+const PLAY_PAUSE_ID: &str = "play_pause";
+const NEXT_ID: &str = "next";
+const EXIT_ID: &str = "exit";
+const ICON_SIZE: u32 = 32;
+
+/// Actions that are triggered through the tray icon
+pub enum TrayAction {
+    Show,
+    PlayPause,
+    Next,
+    Exit,
+}
+
+/// Keeps the tray icon alive, it is removed once this is dropped
+pub struct Tray {
+    #[cfg(not(target_os = "linux"))]
+    _icon: TrayIcon,
+}
+
+/// Draws a play symbol since there is no icon file.
+fn create_icon(color: [f32; 4]) -> Result<Icon, String> {
+    let [r, g, b, _] = color.map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8);
+    let size = ICON_SIZE as f32;
+    let (left, right) = (size * 0.2, size * 0.9);
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            // The triangle gets narrower towards its tip at the right
+            let half_height = (right - x as f32) / (right - left) * size * 0.4;
+            let is_inside = x as f32 >= left && (y as f32 + 0.5 - size / 2.0).abs() <= half_height;
+            rgba.extend_from_slice(&[r, g, b, if is_inside { u8::MAX } else { 0 }]);
+        }
+    }
+    Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).map_err(|e| e.to_string())
+}
+
+fn build_icon(color: [f32; 4]) -> Result<TrayIcon, String> {
+    let menu = Menu::new();
+    menu.append_items(&[
+        &MenuItem::with_id(SHOW_ID, "Show window", true, None),
+        &MenuItem::with_id(PLAY_PAUSE_ID, "Play/Pause", true, None),
+        &MenuItem::with_id(NEXT_ID, "Next song", true, None),
+        &PredefinedMenuItem::separator(),
+        &MenuItem::with_id(EXIT_ID, "Exit", true, None),
+    ])
+    .map_err(|e| e.to_string())?;
+    TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(crate::TITLE)
+        .with_icon(create_icon(color)?)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Adds an icon in the accent color to the system tray.
+pub fn init(color: [f32; 4]) -> Result<Tray, String> {
+    #[cfg(target_os = "linux")]
+    {
+        // On Linux the tray icon needs a running gtk main loop on the thread that created it
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            if let Err(e) = gtk::init() {
+                let _ = result_tx.send(Err(e.to_string()));
+                return;
+            }
+            match build_icon(color) {
+                Ok(_icon) => {
+                    let _ = result_tx.send(Ok(()));
+                    gtk::main();
+                }
+                Err(e) => {
+                    let _ = result_tx.send(Err(e));
+                }
+            }
+        });
+        result_rx.recv().map_err(|e| e.to_string())??;
+        Ok(Tray {})
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    Ok(Tray {
+        _icon: build_icon(color)?,
+    })
+}
+
+/// Returns the actions that were triggered since the last call.
+pub fn receive_actions() -> Vec<TrayAction> {
+    let mut actions = Vec::new();
+    while let Ok(event) = MenuEvent::receiver().try_recv() {
+        match event.id.0.as_str() {
+            SHOW_ID => actions.push(TrayAction::Show),
+            PLAY_PAUSE_ID => actions.push(TrayAction::PlayPause),
+            NEXT_ID => actions.push(TrayAction::Next),
+            EXIT_ID => actions.push(TrayAction::Exit),
+            _ => (),
+        }
+    }
+    // Clicks on the icon itself are not reported on Linux, there only the menu opens
+    while let Ok(event) = TrayIconEvent::receiver().try_recv() {
+        if let TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        } = event
+        {
+            actions.push(TrayAction::Show);
+        }
+    }
+    actions
+}
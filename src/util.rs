@@ -1,4 +1,16 @@
-use std::sync::mpsc::Receiver;
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+pub use implayer_core::model::{is_default_playlist, is_music_file};
+pub use implayer_core::util::{
+    fuzzy_match, get_playlist_folders, percent_decode, percent_encode, receive_all,
+};
 
 pub fn ms_to_string(milli_seconds: u64) -> String {
     let mut result = String::new();
@@ -28,6 +40,93 @@ pub fn ms_to_string(milli_seconds: u64) -> String {
     result
 }
 
+pub fn time_ago_to_string(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .map_or(0, |x| x.as_secs());
+
+    if seconds < 60 {
+        "just now".to_string()
+    } else if seconds < 60 * 60 {
+        format!("{} min ago", seconds / 60)
+    } else if seconds < 60 * 60 * 24 {
+        format!("{} h ago", seconds / (60 * 60))
+    } else {
+        format!("{} days ago", seconds / (60 * 60 * 24))
+    }
+}
+
+/// Words in parentheses or brackets of a song name that mark them as video or upload information
+const FILE_NAME_NOISE_WORDS: [&str; 11] = [
+    "official",
+    "video",
+    "audio",
+    "lyric",
+    "lyrics",
+    "visualizer",
+    "visualiser",
+    "hd",
+    "hq",
+    "4k",
+    "mv",
+];
+
+fn is_youtube_id(text: &str) -> bool {
+    text.len() == 11
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Removes upload information like `(Official Video)` or the `[dQw4w9WgXcQ]` IDs of yt-dlp from a
+/// file name without extension and replaces characters that are not allowed in file names.
+pub fn sanitize_file_name(file_name: &str) -> String {
+    let mut result = String::new();
+    let mut rest = file_name;
+    while let Some(start) = rest.find(['(', '[']) {
+        let closing = if rest[start..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let end = match rest[start..].find(closing) {
+            Some(end) => start + end,
+            None => break,
+        };
+        let content = &rest[start + 1..end];
+        let is_noise = (closing == ']' && is_youtube_id(content))
+            || content
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|x| FILE_NAME_NOISE_WORDS.contains(&x.to_lowercase().as_str()));
+
+        result += &rest[..start];
+        if !is_noise {
+            result += &rest[start..=end];
+        }
+        rest = &rest[end + 1..];
+    }
+    result += rest;
+
+    // yt-dlp replaces illegal characters with similar looking full width characters
+    let result: String = result
+        .chars()
+        .filter_map(|c| match c {
+            '/' | '\\' | '⧸' | '⧹' | '／' | '＼' | '|' | '｜' | ':' | '：' => Some('-'),
+            '"' | '＂' => Some('\''),
+            '?' | '？' | '*' | '＊' | '<' | '＜' | '>' | '＞' => None,
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+
+    result
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .trim_end_matches(['-', '.', ' '])
+        .to_string()
+}
+
 pub fn add_pos(first: [f32; 2], second: [f32; 2]) -> [f32; 2] {
     [first[0] + second[0], first[1] + second[1]]
 }
@@ -46,19 +145,173 @@ pub fn is_point_in_rect(point: [f32; 2], rect_min: [f32; 2], rect_max: [f32; 2])
     true
 }
 
-pub fn is_default_playlist(playlist_name: &str) -> bool {
-    playlist_name == crate::app::ALL_PLAYLIST_NAME
-        || playlist_name == crate::app::ALL_UNUSED_PLAYLIST_NAME
+/// Returns the name of a playlist without its folders.
+pub fn get_playlist_short_name(playlist_name: &str) -> &str {
+    match playlist_name.rsplit_once('.') {
+        Some((folders, name)) if !folders.is_empty() && !name.is_empty() => name,
+        _ => playlist_name,
+    }
+}
+
+/// Shuffles the items randomly, using the random keys of the standard library hasher as source
+/// of randomness.
+pub fn shuffle<T>(items: &mut [T]) {
+    let random_state = RandomState::new();
+    for i in (1..items.len()).rev() {
+        let j = (random_state.hash_one(i) % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
 }
 
 pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
     start + t * (end - start)
 }
 
-pub fn receive_all<T>(receiver: &Receiver<T>) -> Vec<T> {
-    let mut result = Vec::new();
-    while let Ok(value) = receiver.try_recv() {
-        result.push(value);
+/// Converts a color with a hue in degrees and a saturation and value between 0 and 1 to RGB.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as usize {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let m = value - chroma;
+    [r + m, g + m, b + m]
+}
+
+/// Returns the hue of an RGB color in degrees, 0 for gray colors.
+pub fn rgb_to_hue(color: [f32; 4]) -> f32 {
+    let [r, g, b, _] = color;
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+    if chroma <= 0.0 {
+        return 0.0;
+    }
+    let sector = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    sector * 60.0
+}
+
+/// Returns the full path of the program. Names without a directory are looked up in `PATH`.
+pub fn find_executable(program: &str) -> Option<String> {
+    let program = program.trim();
+    if program.is_empty() {
+        return None;
+    }
+    let extensions: &[&str] = if cfg!(target_os = "windows") {
+        &["", "exe", "cmd", "bat"]
+    } else {
+        &[""]
+    };
+    let find_file = |path: PathBuf| {
+        extensions
+            .iter()
+            .map(|x| {
+                let mut file_path = path.clone().into_os_string();
+                if !x.is_empty() {
+                    file_path.push(format!(".{x}"));
+                }
+                PathBuf::from(file_path)
+            })
+            .find(|x| x.is_file())
+    };
+
+    let path = Path::new(program);
+    let found_path = if path.components().count() > 1 {
+        find_file(path.to_path_buf())
+    } else {
+        std::env::split_paths(&std::env::var_os("PATH")?).find_map(|x| find_file(x.join(path)))
+    };
+    found_path.map(|x| x.to_string_lossy().to_string())
+}
+
+/// Opens the folder containing the file in the file manager with the file selected, if supported.
+pub fn reveal_in_file_manager(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        // explorer returns a non-zero exit code even on success
+        Command::new("explorer")
+            .arg(format!("/select,{}", path.display()))
+            .spawn()?;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        Command::new("open").arg("-R").arg(path).spawn()?;
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        use std::process::Stdio;
+
+        let path = path.canonicalize()?;
+        let uri = format!("file://{}", percent_encode(&path.to_string_lossy()));
+        let parent = path.parent().unwrap_or(&path).to_path_buf();
+        // Not every file manager implements the FileManager1 interface, fall back to opening the
+        // folder without selection
+        let mut dbus_send = Command::new("dbus-send")
+            .arg("--session")
+            .arg("--print-reply")
+            .arg("--dest=org.freedesktop.FileManager1")
+            .arg("--type=method_call")
+            .arg("/org/freedesktop/FileManager1")
+            .arg("org.freedesktop.FileManager1.ShowItems")
+            .arg(format!("array:string:{uri}"))
+            .arg("string:")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        std::thread::spawn(move || {
+            if !dbus_send.wait().is_ok_and(|x| x.success()) {
+                let _ = Command::new("xdg-open")
+                    .arg(parent)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Converts a `file://` URL into a path.
+pub fn url_to_path(url: &str) -> String {
+    let path = url
+        .strip_prefix("file://")
+        .map(|x| x.strip_prefix("localhost").unwrap_or(x))
+        .unwrap_or(url);
+    let path = percent_decode(path);
+    // Windows paths are written as /C:/...
+    if path.get(2..3) == Some(":") {
+        path[1..].to_string()
+    } else {
+        path
+    }
+}
+
+/// Quotes and escapes the text as a JSON string.
+pub fn to_json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
     }
+    result.push('"');
     result
 }
@@ -0,0 +1,418 @@
+use std::time::{Duration, Instant};
+
+use imgui::*;
+
+use crate::{theme::Theme, util};
+
+/// Speed of scrolling texts in pixels per second
+const MARQUEE_SPEED: f32 = 40.0;
+/// Seconds that scrolling texts stay at their start and end
+const MARQUEE_PAUSE: f32 = 1.5;
+
+pub struct ScrollInfo {
+    pub is_scrolling: bool,
+    pub scroll_start_time: Instant,
+    pub scroll_duration: Duration,
+    pub scroll_target_y: f32,
+}
+
+impl Default for ScrollInfo {
+    fn default() -> Self {
+        ScrollInfo {
+            is_scrolling: false,
+            scroll_start_time: Instant::now(),
+            scroll_duration: Duration::from_millis(200),
+            scroll_target_y: 0.0,
+        }
+    }
+}
+
+pub struct RowResponse {
+    pub clicked: bool,
+    pub double_clicked: bool,
+    pub right_clicked: bool,
+}
+
+/// Draws a selectable spanning the whole row. The background is drawn behind the selectable so
+/// that the hover and selection colors stay visible. Row contents are drawn by the caller
+/// afterwards using `same_line`.
+pub fn draw_row(ui: &Ui, selected: bool, background: Option<[f32; 4]>) -> RowResponse {
+    let mut clicked = false;
+    match background {
+        Some(color) => {
+            let draw_list = ui.get_window_draw_list();
+            draw_list.channels_split(2, |channel| {
+                channel.set_current(1);
+                clicked = ui
+                    .selectable_config("")
+                    .selected(selected)
+                    .allow_double_click(true)
+                    .build();
+                channel.set_current(0);
+                draw_list
+                    .add_rect(ui.item_rect_min(), ui.item_rect_max(), color)
+                    .filled(true)
+                    .build();
+            });
+        }
+        None => {
+            clicked = ui
+                .selectable_config("")
+                .selected(selected)
+                .allow_double_click(true)
+                .build();
+        }
+    }
+
+    RowResponse {
+        clicked,
+        double_clicked: clicked && ui.is_mouse_double_clicked(MouseButton::Left),
+        right_clicked: ui.is_item_clicked_with_button(MouseButton::Right),
+    }
+}
+
+/// How items that are dragged over a list row are dropped.
+#[derive(Clone, Copy)]
+pub enum DropKind {
+    /// The items are inserted above or below the row, depending on the mouse position
+    Between,
+    /// The items are added into the row, e.g. a playlist, at its end or its beginning
+    Into { at_end: bool },
+}
+
+/// A row of a list drawn by `draw_list_row`.
+pub struct ListRow {
+    pub selected: bool,
+    pub background: Option<[f32; 4]>,
+    /// How dragged items are dropped onto the row, None while nothing can be dropped
+    pub drop_kind: Option<DropKind>,
+}
+
+pub struct ListRowResponse {
+    pub clicked: bool,
+    pub double_clicked: bool,
+    pub right_clicked: bool,
+    pub is_visible: bool,
+    /// Whether a drag started on the row
+    pub is_drag_started: bool,
+    /// Whether dragged items are over the row
+    pub is_drop_hovered: bool,
+    /// Set when dragged items are released over the row, whether they go below it
+    pub dropped_below: Option<bool>,
+}
+
+/// Draws a selectable list row and handles dragging from it and dropping onto it. While items are
+/// dragged over the row, the insertion line shows where they would be dropped.
+pub fn draw_list_row(ui: &Ui, theme: &Theme, row: &ListRow) -> ListRowResponse {
+    let response = draw_row(ui, row.selected, row.background);
+    let is_visible = ui.is_item_visible();
+    let mut is_drop_hovered = false;
+    let mut dropped_below = None;
+    if let Some(drop_kind) = row
+        .drop_kind
+        .filter(|_| is_visible && self::is_drop_hovered(ui))
+    {
+        is_drop_hovered = true;
+        let is_below = match drop_kind {
+            DropKind::Between => is_mouse_in_lower_half(ui),
+            DropKind::Into { at_end } => at_end,
+        };
+        if ui.is_mouse_released(MouseButton::Left) {
+            dropped_below = Some(is_below);
+        } else {
+            if let DropKind::Into { .. } = drop_kind {
+                draw_drop_highlight(ui, theme);
+            }
+            draw_insertion_line(ui, theme, is_below);
+        }
+    }
+
+    ListRowResponse {
+        clicked: response.clicked,
+        double_clicked: response.double_clicked,
+        right_clicked: response.right_clicked,
+        is_visible,
+        is_drag_started: is_drag_started(ui),
+        is_drop_hovered,
+        dropped_below,
+    }
+}
+
+/// Draws a scrolling list whose rows all have the same height. Only the rows in view are drawn by
+/// `draw_row`, the others only take up their space.
+pub fn draw_virtual_list(
+    ui: &Ui,
+    id: &str,
+    size: [f32; 2],
+    scroll_delta: f32,
+    scroll_info: &mut ScrollInfo,
+    count: usize,
+    mut draw_row: impl FnMut(usize),
+) {
+    ui.child_window(id).size(size).build(|| {
+        apply_smooth_scrolling(ui, scroll_delta, scroll_info);
+        let clipper = ListClipper::new(count as i32).begin(ui);
+        for i in clipper.iter() {
+            let token = ui.push_id_usize(i as usize);
+            draw_row(i as usize);
+            token.pop();
+        }
+    });
+}
+
+/// Returns the background color of a striped list row.
+pub fn stripe_color(row: usize, even: [f32; 4], odd: [f32; 4]) -> [f32; 4] {
+    if row % 2 == 1 {
+        odd
+    } else {
+        even
+    }
+}
+
+/// Whether a drag started on the last drawn row.
+pub fn is_drag_started(ui: &Ui) -> bool {
+    ui.is_mouse_dragging(MouseButton::Left)
+        && ui.is_item_visible()
+        && util::is_point_in_rect(
+            util::sub_pos(ui.io().mouse_pos, ui.mouse_drag_delta()),
+            util::add_pos(ui.item_rect_min(), [0.0, 1.0]),
+            ui.item_rect_max(),
+        )
+}
+
+/// Whether something is currently dragged over the last drawn row.
+pub fn is_drop_hovered(ui: &Ui) -> bool {
+    ui.is_item_visible()
+        && util::is_point_in_rect(
+            ui.io().mouse_pos,
+            util::add_pos(ui.item_rect_min(), [0.0, 1.0]),
+            ui.item_rect_max(),
+        )
+}
+
+pub fn draw_drop_highlight(ui: &Ui, theme: &Theme) {
+    ui.get_window_draw_list()
+        .add_rect(
+            util::add_pos(ui.item_rect_min(), [4.0, 1.0]),
+            util::sub_pos(ui.item_rect_max(), [4.0, 0.0]),
+            theme.drag,
+        )
+        .build();
+}
+
+/// Draws a line at the top or bottom of the last drawn row to show where dropped items are inserted.
+pub fn draw_insertion_line(ui: &Ui, theme: &Theme, is_below: bool) {
+    draw_insertion_line_at_rect(ui, theme, ui.item_rect_min(), ui.item_rect_max(), is_below);
+}
+
+/// Draws the insertion line at the top or bottom of a row that was drawn earlier.
+pub fn draw_insertion_line_at_rect(
+    ui: &Ui,
+    theme: &Theme,
+    rect_min: [f32; 2],
+    rect_max: [f32; 2],
+    is_below: bool,
+) {
+    let y = if is_below {
+        rect_max[1]
+    } else {
+        rect_min[1] + 1.0
+    };
+    ui.get_window_draw_list()
+        .add_line([rect_min[0] + 4.0, y], [rect_max[0] - 4.0, y], theme.drag)
+        .thickness(2.0)
+        .build();
+}
+
+/// Whether the mouse is in the lower half of the last drawn row.
+pub fn is_mouse_in_lower_half(ui: &Ui) -> bool {
+    ui.io().mouse_pos[1] > (ui.item_rect_min()[1] + ui.item_rect_max()[1]) / 2.0
+}
+
+/// Draws the number of dragged items next to the mouse cursor.
+pub fn draw_drag_indicator(ui: &Ui, theme: &Theme, count: usize) {
+    ui.get_foreground_draw_list()
+        .add_circle(ui.io().mouse_pos, 10.0, theme.drag)
+        .filled(true)
+        .build();
+    ui.get_foreground_draw_list().add_text(
+        util::add_pos(ui.io().mouse_pos, [5.0, -20.0]),
+        theme.text1,
+        count.to_string(),
+    );
+}
+
+pub fn scroll_to_item(ui: &Ui) {
+    if !ui.is_item_visible() {
+        ui.set_scroll_here_y();
+    }
+}
+
+pub fn apply_smooth_scrolling(ui: &Ui, scroll_delta: f32, scroll_info: &mut ScrollInfo) {
+    let current_time = Instant::now();
+    let elapsed_time = current_time.duration_since(scroll_info.scroll_start_time);
+
+    if scroll_info.is_scrolling {
+        if elapsed_time < scroll_info.scroll_duration {
+            let progress = elapsed_time.as_secs_f32() / scroll_info.scroll_duration.as_secs_f32();
+            let mut scroll_y = util::lerp(ui.scroll_y(), scroll_info.scroll_target_y, progress);
+
+            // Clamp values here and to ensure smooth movements at the top/bottom. Clamping the
+            // target value when it is set results in an unsmooth transition.
+            if scroll_y < 0.0 || scroll_y > ui.scroll_max_y() {
+                scroll_y = scroll_y.clamp(0.0, ui.scroll_max_y());
+                scroll_info.scroll_target_y =
+                    scroll_info.scroll_target_y.clamp(0.0, ui.scroll_max_y());
+            }
+
+            ui.set_scroll_y(scroll_y);
+        } else {
+            scroll_info.is_scrolling = false;
+        }
+    }
+
+    // Update scroll target when scrolling with the mouse wheel
+    if ui.is_window_hovered() {
+        if scroll_delta != 0.0 {
+            scroll_info.scroll_target_y = if scroll_info.is_scrolling {
+                scroll_info.scroll_target_y - scroll_delta * 66.0
+            } else {
+                ui.scroll_y() - scroll_delta * 66.0
+            };
+            scroll_info.scroll_start_time = Instant::now();
+            scroll_info.is_scrolling = true;
+        }
+    }
+}
+
+/// Draws a speaker into the square, with sound waves or crossed out if it is muted.
+pub fn draw_speaker_icon(ui: &Ui, min: [f32; 2], size: f32, color: [f32; 4], is_muted: bool) {
+    let draw_list = ui.get_window_draw_list();
+    let point = |x: f32, y: f32| [min[0] + x * size, min[1] + y * size];
+    draw_list
+        .add_rect(point(0.1, 0.38), point(0.3, 0.62), color)
+        .filled(true)
+        .build();
+    draw_list
+        .add_polyline(
+            vec![
+                point(0.3, 0.38),
+                point(0.5, 0.2),
+                point(0.5, 0.8),
+                point(0.3, 0.62),
+            ],
+            color,
+        )
+        .filled(true)
+        .build();
+    if is_muted {
+        draw_list
+            .add_line(point(0.62, 0.38), point(0.86, 0.62), color)
+            .thickness(1.5)
+            .build();
+        draw_list
+            .add_line(point(0.62, 0.62), point(0.86, 0.38), color)
+            .thickness(1.5)
+            .build();
+    } else {
+        for (x, height) in [(0.62, 0.14), (0.76, 0.26)] {
+            draw_list
+                .add_bezier_curve(
+                    point(x, 0.5 - height),
+                    point(x + 0.08, 0.5 - height / 2.0),
+                    point(x + 0.08, 0.5 + height / 2.0),
+                    point(x, 0.5 + height),
+                    color,
+                )
+                .thickness(1.5)
+                .build();
+        }
+    }
+}
+
+/// Draws the text like `draw_truncated_text` with the characters at the positions in the color.
+pub fn draw_highlighted_text(
+    ui: &Ui,
+    text: &str,
+    width: f32,
+    positions: &[usize],
+    color: [f32; 4],
+) {
+    draw_truncated_text(ui, text, width);
+    let start = ui.item_rect_min();
+
+    // Characters that are cut off by the ellipsis are not highlighted
+    let visible_width = if ui.calc_text_size(text)[0] <= width {
+        width
+    } else {
+        width - ui.calc_text_size("...")[0]
+    };
+    let draw_list = ui.get_window_draw_list();
+    for (i, c) in text.chars().enumerate() {
+        if !positions.contains(&i) {
+            continue;
+        }
+        let offset = ui.calc_text_size(text.chars().take(i).collect::<String>())[0];
+        let mut buffer = [0; 4];
+        let c: &str = c.encode_utf8(&mut buffer);
+        if offset + ui.calc_text_size(c)[0] > visible_width {
+            break;
+        }
+        draw_list.add_text([start[0] + offset, start[1]], color, c);
+    }
+}
+
+/// Draws the text like `draw_truncated_text`, but a text that doesn't fit scrolls back and forth
+/// instead of being cut off. `elapsed` is the time in seconds since it started scrolling. Returns
+/// whether the text scrolls.
+pub fn draw_scrolling_text(ui: &Ui, text: &str, width: f32, elapsed: f32) -> bool {
+    let text_width = ui.calc_text_size(text)[0];
+    if text_width <= width {
+        ui.text(text);
+        return false;
+    }
+
+    let overflow = text_width - width;
+    let scroll_time = overflow / MARQUEE_SPEED;
+    let time = elapsed % (2.0 * (MARQUEE_PAUSE + scroll_time));
+    let offset = if time < MARQUEE_PAUSE {
+        0.0
+    } else if time < MARQUEE_PAUSE + scroll_time {
+        (time - MARQUEE_PAUSE) * MARQUEE_SPEED
+    } else if time < 2.0 * MARQUEE_PAUSE + scroll_time {
+        overflow
+    } else {
+        overflow - (time - 2.0 * MARQUEE_PAUSE - scroll_time) * MARQUEE_SPEED
+    };
+
+    let min = ui.cursor_screen_pos();
+    let size = [width, ui.text_line_height()];
+    let draw_list = ui.get_window_draw_list();
+    draw_list.with_clip_rect_intersect(min, util::add_pos(min, size), || {
+        draw_list.add_text(
+            [min[0] - offset, min[1]],
+            ui.style_color(StyleColor::Text),
+            text,
+        );
+    });
+    ui.dummy(size);
+    true
+}
+
+pub fn draw_truncated_text(ui: &Ui, text: &str, width: f32) {
+    if ui.calc_text_size(text)[0] <= width {
+        ui.text(text);
+        return;
+    }
+
+    let ellipsis = "...";
+    let ellipsis_width = ui.calc_text_size(ellipsis)[0];
+    // Shorten the text by one character at a time without copying it
+    for (end, _) in text.char_indices().skip(1).rev() {
+        let part = &text[..end];
+        if ellipsis_width + ui.calc_text_size(part)[0] <= width {
+            ui.text(format!("{part}{ellipsis}"));
+            return;
+        }
+    }
+}